@@ -0,0 +1,61 @@
+use drop_lib::lexer::Lexer;
+use drop_lib::parser::fast::ArenaParser;
+use drop_lib::parser::Parser;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+fn source(functions: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        source.push_str(&format!(
+            "
+            @memo
+            fn f{i}(a: int, (b, c), d = {i}, ...rest) -> int requires a > 0 {{
+                let (x, y) = (a * 2 + b, \"value {i}\");
+                for n in 0..=a step 2 {{
+                    if n == d {{ x = x + n; }} else {{ print(y, n: n); }}
+                }}
+                try {{ throw err(\"failed\"); }} catch (e) {{ print(e); }}
+                return x ?? rest.len();
+            }}
+            "
+        ));
+    }
+    source
+}
+
+fn measure(name: &str, mut parse: impl FnMut() -> usize) -> Duration {
+    let mut best = Duration::MAX;
+    let mut size = 0;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        size = parse();
+        best = best.min(start.elapsed());
+    }
+    println!("{name:<8} {best:>12.2?} ({size} statements)");
+    best
+}
+
+fn main() {
+    let source = source(2000);
+    println!("parsing {} bytes, best of {ITERATIONS}", source.len());
+    let current = measure("current", || {
+        let (tokens, spans) = Lexer::lex_spanned(&source);
+        let mut parser = Parser::with_spans(&tokens, &spans);
+        let program = parser.parse();
+        assert!(parser.errors.is_empty());
+        program.len()
+    });
+    let arena = measure("arena", || {
+        let lexemes = Lexer::scan(&source);
+        let mut parser = ArenaParser::new(&lexemes);
+        let program = parser.parse().lower();
+        assert!(parser.errors.is_empty());
+        program.len()
+    });
+    println!(
+        "speedup  {:>12.2}x",
+        current.as_secs_f64() / arena.as_secs_f64()
+    );
+}
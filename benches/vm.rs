@@ -0,0 +1,213 @@
+use drop_lib::compiler::Compiler;
+use drop_lib::lexer::Lexer;
+use drop_lib::optimizer::Optimizer;
+use drop_lib::parser::Parser;
+use drop_lib::vm::opcode::{Scope, Value};
+use drop_lib::vm::VM;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 5;
+
+const FIB: &str = "
+    fn fib(n) {
+        if n <= 1 {
+            return 1;
+        }
+        return fib(n - 1) + fib(n - 2);
+    }
+    let result = fib(24);
+";
+
+const GLOBAL_LOOP: &str = "
+    let total = 0;
+    let i = 0;
+    while i < 200000 {
+        let square = i * i;
+        total = total + square / (i + 1);
+        i = i + 1;
+    }
+    let result = total;
+";
+
+const LOCAL_LOOP: &str = "
+    fn count(limit) {
+        let total = 0;
+        let i = 0;
+        while i < limit {
+            let square = i * i;
+            total = total + square / (i + 1);
+            i = i + 1;
+        }
+        return total;
+    }
+    let result = count(200000);
+";
+
+const FOR_LOOP: &str = "
+    fn count(limit) {
+        let total = 0;
+        for i in 0..limit {
+            let square = i * i;
+            total = total + square / (i + 1);
+        }
+        return total;
+    }
+    let result = count(200000);
+";
+
+fn measure(name: &str, source: &str) -> Duration {
+    let (tokens, spans) = Lexer::lex_spanned(source);
+    let mut parser = Parser::with_spans(&tokens, &spans);
+    let program = parser.parse();
+    assert!(parser.errors.is_empty());
+    let program = Optimizer::new().optimize(program);
+    let mut compiler = Compiler::new();
+    compiler.compile(&program);
+    assert!(compiler.errors.is_empty());
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..ITERATIONS {
+        let mut vm = VM::new(compiler.codes.clone());
+        let start = Instant::now();
+        assert_eq!(vm.run(), Ok(()));
+        best = best.min(start.elapsed());
+        result = vm.get_global("result");
+    }
+    let result = match result {
+        Some(Value::Int(value)) => value,
+        other => panic!("unexpected result {:?}", other),
+    };
+    println!("{name:<8} {best:>12.2?} (result {result})");
+    best
+}
+
+fn int(value: Option<Value>) -> i64 {
+    match value {
+        Some(Value::Int(value)) => value,
+        other => panic!("unexpected value {:?}", other),
+    }
+}
+
+// Name resolution as the VM did it before locals got slots: every access clones the
+// name out of the opcode and probes each scope's HashMap on the way up the chain.
+fn load_name(scope: &Rc<RefCell<Scope>>, name: String) -> i64 {
+    let mut scope = scope.clone();
+    loop {
+        if let Some(value) = scope.borrow().variables.get(&name) {
+            return int(value.clone());
+        }
+        let parent = scope.borrow().parent.clone().unwrap();
+        scope = parent;
+    }
+}
+
+fn store_name(scope: &Rc<RefCell<Scope>>, name: String, value: i64) {
+    let mut scope = scope.clone();
+    loop {
+        if let Some(slot) = scope.borrow_mut().variables.get_mut(&name) {
+            *slot = Some(Value::Int(value));
+            return;
+        }
+        let parent = scope.borrow().parent.clone().unwrap();
+        scope = parent;
+    }
+}
+
+fn load_slot(scope: &Rc<RefCell<Scope>>, depth: usize, slot: usize) -> i64 {
+    let mut scope = scope.clone();
+    for _ in 0..depth {
+        let parent = scope.borrow().parent.clone().unwrap();
+        scope = parent;
+    }
+    let value = scope.borrow().slots[slot].clone();
+    int(value)
+}
+
+fn store_slot(scope: &Rc<RefCell<Scope>>, depth: usize, slot: usize, value: i64) {
+    let mut scope = scope.clone();
+    for _ in 0..depth {
+        let parent = scope.borrow().parent.clone().unwrap();
+        scope = parent;
+    }
+    scope.borrow_mut().store(slot, Some(Value::Int(value)));
+}
+
+// LOCAL_LOOP's variable traffic, with `limit`, `total` and `i` in the function scope and
+// `square` in a fresh block scope per iteration.
+fn by_name(limit: i64) -> i64 {
+    let function = Rc::new(RefCell::new(Scope::new()));
+    for (name, value) in [("limit", limit), ("total", 0), ("i", 0)] {
+        let value = Some(Value::Int(value));
+        function
+            .borrow_mut()
+            .variables
+            .insert(name.to_string(), value);
+    }
+    while load_name(&function, "i".to_string()) < load_name(&function, "limit".to_string()) {
+        let block = Rc::new(RefCell::new(Scope::new_child(function.clone())));
+        let i = load_name(&block, "i".to_string());
+        let square = i * load_name(&block, "i".to_string());
+        block
+            .borrow_mut()
+            .variables
+            .insert("square".to_string(), None);
+        store_name(&block, "square".to_string(), square);
+        let total = load_name(&block, "total".to_string())
+            + load_name(&block, "square".to_string()) / (load_name(&block, "i".to_string()) + 1);
+        store_name(&block, "total".to_string(), total);
+        store_name(
+            &block,
+            "i".to_string(),
+            load_name(&block, "i".to_string()) + 1,
+        );
+    }
+    load_name(&function, "total".to_string())
+}
+
+fn by_slot(limit: i64) -> i64 {
+    let slots = [limit, 0, 0].map(|value| Some(Value::Int(value))).to_vec();
+    let function = Rc::new(RefCell::new(Scope::with_slots(slots)));
+    while load_slot(&function, 0, 2) < load_slot(&function, 0, 0) {
+        let block = Rc::new(RefCell::new(Scope::new_child(function.clone())));
+        let square = load_slot(&block, 1, 2) * load_slot(&block, 1, 2);
+        store_slot(&block, 0, 0, square);
+        let total =
+            load_slot(&block, 1, 1) + load_slot(&block, 0, 0) / (load_slot(&block, 1, 2) + 1);
+        store_slot(&block, 1, 1, total);
+        store_slot(&block, 1, 2, load_slot(&block, 1, 2) + 1);
+    }
+    load_slot(&function, 0, 1)
+}
+
+fn measure_lookup(name: &str, run: fn(i64) -> i64) -> Duration {
+    let mut best = Duration::MAX;
+    let mut result = 0;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        result = run(200000);
+        best = best.min(start.elapsed());
+    }
+    println!("{name:<8} {best:>12.2?} (result {result})");
+    best
+}
+
+fn main() {
+    println!("running programs, best of {ITERATIONS}");
+    measure("fib", FIB);
+    let global = measure("global", GLOBAL_LOOP);
+    let local = measure("local", LOCAL_LOOP);
+    measure("for", FOR_LOOP);
+    println!(
+        "speedup  {:>12.2}x (locals over globals)",
+        global.as_secs_f64() / local.as_secs_f64()
+    );
+    println!("resolving the local loop's variables, best of {ITERATIONS}");
+    let names = measure_lookup("names", by_name);
+    let slots = measure_lookup("slots", by_slot);
+    println!(
+        "speedup  {:>12.2}x (slots over names)",
+        names.as_secs_f64() / slots.as_secs_f64()
+    );
+}
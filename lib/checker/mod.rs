@@ -0,0 +1,751 @@
+use crate::parser::ast::{
+    Arg, BinOp, Contract, Decorator, Expr, Ident, Literal, Param, Pattern, Program, Stmt, Type,
+    UnaryOp,
+};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub struct TypeError {
+    pub message: String,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Signature {
+    params: Vec<Param>,
+    ret: Type,
+}
+
+#[derive(Clone, Debug)]
+enum Binding {
+    Variable(Type, bool),
+    Function(Signature),
+}
+
+struct Frame {
+    bindings: HashMap<String, Binding>,
+    function: bool,
+}
+
+impl Frame {
+    fn new(function: bool) -> Self {
+        Self {
+            bindings: HashMap::new(),
+            function,
+        }
+    }
+}
+
+pub struct Checker {
+    pub errors: Vec<TypeError>,
+    frames: Vec<Frame>,
+    returns: Vec<(String, Option<Type>)>,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn compatible(expected: Type, actual: Type) -> bool {
+    expected == Type::Any || actual == Type::Any || expected == actual
+}
+
+fn numeric(ty: Type) -> bool {
+    matches!(ty, Type::Int | Type::Bool | Type::Any)
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self {
+            errors: vec![],
+            frames: vec![Frame::new(false)],
+            returns: vec![],
+        }
+    }
+
+    pub fn check(&mut self, program: &Program) {
+        self.check_stmts(program);
+    }
+
+    fn check_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts.iter() {
+            if let Stmt::FnStmt(ident, params, ret, body, decorators, _) = stmt {
+                self.declare_fn(ident, params, ret, body, decorators);
+            }
+        }
+        for stmt in stmts.iter() {
+            self.check_stmt(stmt);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::LetStmt(pattern, ty, expr) => match pattern {
+                Pattern::Ident(ident) => self.check_let(ident, ty, expr),
+                Pattern::Tuple(_) => {
+                    self.check_destructure(pattern, ty, expr);
+                }
+            },
+            Stmt::ReturnStmt(expr, _) => self.check_return(expr),
+            Stmt::ExprStmt(expr) => {
+                self.check_expr(expr);
+            }
+            Stmt::BlockStmt(stmts) => self.check_block(stmts),
+            Stmt::IfStmt(condition, body, alt) => {
+                self.check_condition(condition);
+                self.check_stmt(body);
+                if let Some(alt) = alt {
+                    self.check_stmt(alt);
+                }
+            }
+            Stmt::WhileStmt(condition, body) => {
+                let mut assigned = vec![];
+                collect_assigned(body, &mut assigned);
+                for name in assigned.iter() {
+                    self.widen(name);
+                }
+                self.check_condition(condition);
+                self.check_stmt(body);
+            }
+            Stmt::ForStmt(pattern, iterable, body) => {
+                let mut assigned = vec![];
+                collect_assigned(body, &mut assigned);
+                for name in assigned.iter() {
+                    self.widen(name);
+                }
+                let ty = self.check_expr(iterable);
+                let item = match ty {
+                    Type::Range => Type::Int,
+                    Type::String => Type::String,
+                    Type::List | Type::Tuple | Type::Any => Type::Any,
+                    _ => {
+                        self.error(format!("Cannot iterate over {}", ty));
+                        Type::Any
+                    }
+                };
+                self.frames.push(Frame::new(false));
+                match pattern {
+                    Pattern::Ident(ident) => self.declare(&ident.0, Binding::Variable(item, false)),
+                    Pattern::Tuple(_) => self.declare_pattern(pattern),
+                }
+                self.check_stmt(body);
+                self.frames.pop();
+            }
+            Stmt::FnStmt(ident, params, ret, body, decorators, contracts) => {
+                for decorator in decorators.iter() {
+                    for arg in decorator.args.iter() {
+                        match arg {
+                            Arg::Positional(expr) | Arg::Named(_, expr) => {
+                                self.check_expr(expr);
+                            }
+                        }
+                    }
+                }
+                self.check_fn(ident, params, ret, body, decorators, contracts)
+            }
+            Stmt::TryStmt(body, handler, finally) => {
+                self.check_stmt(body);
+                if let Some((ident, catch_body)) = handler {
+                    self.frames.push(Frame::new(false));
+                    self.declare(&ident.0, Binding::Variable(Type::Any, false));
+                    self.check_stmt(catch_body);
+                    self.frames.pop();
+                }
+                if let Some(finally) = finally {
+                    self.check_stmt(finally);
+                }
+            }
+            Stmt::ThrowStmt(expr) | Stmt::DeferStmt(expr) => {
+                self.check_expr(expr);
+            }
+            Stmt::YieldStmt(expr) => {
+                if let Some(expr) = expr {
+                    self.check_expr(expr);
+                }
+            }
+            Stmt::MacroStmt(..) | Stmt::ErrorStmt(_) => (),
+        }
+    }
+
+    fn check_block(&mut self, stmts: &[Stmt]) {
+        self.frames.push(Frame::new(false));
+        self.check_stmts(stmts);
+        self.frames.pop();
+    }
+
+    fn check_let(&mut self, ident: &Ident, ty: &Option<Type>, expr: &Option<Expr>) {
+        let actual = expr.as_ref().map(|expr| self.check_expr(expr));
+        match (ty, actual) {
+            (Some(ty), Some(actual)) => {
+                if !compatible(*ty, actual) {
+                    self.error(format!(
+                        "Cannot initialize variable '{}' of type {} with {}",
+                        ident.0, ty, actual
+                    ));
+                }
+                self.declare(&ident.0, Binding::Variable(*ty, true));
+            }
+            (Some(ty), None) => self.declare(&ident.0, Binding::Variable(*ty, true)),
+            (None, Some(actual)) => self.declare(&ident.0, Binding::Variable(actual, false)),
+            (None, None) => self.declare(&ident.0, Binding::Variable(Type::Any, false)),
+        }
+    }
+
+    fn check_destructure(&mut self, pattern: &Pattern, ty: &Option<Type>, expr: &Option<Expr>) {
+        if let Some(ty) = ty {
+            if !compatible(Type::Tuple, *ty) {
+                self.error(format!(
+                    "Tuple pattern '{}' cannot have type {}",
+                    pattern, ty
+                ));
+            }
+        }
+        if let Some(expr) = expr {
+            self.check_shape(pattern, expr);
+        }
+        self.declare_pattern(pattern);
+    }
+
+    fn check_shape(&mut self, pattern: &Pattern, expr: &Expr) -> Type {
+        let patterns = match pattern {
+            Pattern::Ident(_) => return self.check_expr(expr),
+            Pattern::Tuple(patterns) => patterns,
+        };
+        if let Expr::TupleExpr(exprs) = expr {
+            if exprs.len() != patterns.len() {
+                self.error(format!(
+                    "Cannot destructure a tuple of {} elements into {} names",
+                    exprs.len(),
+                    patterns.len()
+                ));
+            }
+            for (pattern, expr) in patterns.iter().zip(exprs.iter()) {
+                self.check_shape(pattern, expr);
+            }
+            return Type::Tuple;
+        }
+        let actual = self.check_expr(expr);
+        if !compatible(Type::Tuple, actual) {
+            self.error(format!(
+                "Cannot destructure {} into {} names, expected a tuple",
+                actual,
+                patterns.len()
+            ));
+        }
+        actual
+    }
+
+    fn declare_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Ident(ident) => self.declare(&ident.0, Binding::Variable(Type::Any, false)),
+            Pattern::Tuple(patterns) => {
+                for pattern in patterns.iter() {
+                    self.declare_pattern(pattern);
+                }
+            }
+        }
+    }
+
+    fn check_return(&mut self, expr: &Option<Expr>) {
+        let actual = match expr {
+            Some(expr) => self.check_expr(expr),
+            None => return,
+        };
+        if let Some((name, Some(ret))) = self.returns.last().cloned() {
+            if !compatible(ret, actual) {
+                self.error(format!(
+                    "Function '{}' should return {}, found {}",
+                    name, ret, actual
+                ));
+            }
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Expr) {
+        let ty = self.check_expr(condition);
+        if !numeric(ty) {
+            self.error(format!(
+                "Expression in condition should be bool or int, found {}",
+                ty
+            ));
+        }
+    }
+
+    fn declare_fn(
+        &mut self,
+        ident: &Ident,
+        params: &[Param],
+        ret: &Option<Type>,
+        body: &Stmt,
+        decorators: &[Decorator],
+    ) {
+        if decorators.iter().any(|decorator| !decorator.is_builtin()) {
+            self.declare(&ident.0, Binding::Variable(Type::Function, false));
+            return;
+        }
+        let body = match body {
+            Stmt::BlockStmt(block) => block,
+            _ => unreachable!(),
+        };
+        let signature = Signature {
+            params: params.to_vec(),
+            ret: match contains_yield(body) {
+                true => Type::Generator,
+                false => ret.unwrap_or(Type::Any),
+            },
+        };
+        self.declare(&ident.0, Binding::Function(signature));
+    }
+
+    fn check_fn(
+        &mut self,
+        ident: &Ident,
+        params: &[Param],
+        ret: &Option<Type>,
+        body: &Stmt,
+        decorators: &[Decorator],
+        contracts: &[Contract],
+    ) {
+        self.declare_fn(ident, params, ret, body, decorators);
+        let body = match body {
+            Stmt::BlockStmt(block) => block,
+            _ => unreachable!(),
+        };
+        self.frames.push(Frame::new(true));
+        for param in params.iter() {
+            if let Some(default) = &param.default {
+                let actual = self.check_expr(default);
+                if let Some(ty) = param.ty {
+                    if !compatible(ty, actual) {
+                        self.error(format!(
+                            "Default value of parameter '{}' should be {}, found {}",
+                            param.name.0, ty, actual
+                        ));
+                    }
+                }
+            }
+            if let Some(pattern) = &param.pattern {
+                if let Some(ty) = param.ty {
+                    if !compatible(Type::Tuple, ty) {
+                        self.error(format!(
+                            "Tuple pattern '{}' cannot have type {}",
+                            pattern, ty
+                        ));
+                    }
+                }
+                self.declare_pattern(pattern);
+                continue;
+            }
+            let binding = match (param.variadic, param.ty) {
+                (true, _) => Binding::Variable(Type::List, true),
+                (false, Some(ty)) => Binding::Variable(ty, true),
+                (false, None) => Binding::Variable(Type::Any, false),
+            };
+            self.declare(&param.name.0, binding);
+        }
+        for contract in contracts.iter() {
+            match contract {
+                Contract::Requires(expr) => self.check_condition(expr),
+                Contract::Ensures(expr) => {
+                    self.frames.push(Frame::new(false));
+                    let binding = Binding::Variable(ret.unwrap_or(Type::Any), ret.is_some());
+                    self.declare("result", binding);
+                    self.check_condition(expr);
+                    self.frames.pop();
+                }
+            }
+        }
+        self.returns.push((ident.0.to_string(), *ret));
+        self.check_stmts(body);
+        self.returns.pop();
+        self.frames.pop();
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::LiteralExpr(literal) => match literal {
+                Literal::IntLiteral(_) => Type::Int,
+                Literal::BoolLiteral(_) => Type::Bool,
+                Literal::StringLiteral(_) => Type::String,
+                Literal::NilLiteral => Type::Nil,
+            },
+            Expr::IdentExpr(ident) => match self.lookup(&ident.0) {
+                Some(Binding::Variable(ty, _)) => ty,
+                Some(Binding::Function(_)) => Type::Function,
+                None => Type::Any,
+            },
+            Expr::AssignmentExpr(pattern @ Pattern::Tuple(_), expr) => {
+                let actual = self.check_shape(pattern, expr);
+                let mut names = vec![];
+                collect_pattern(pattern, &mut names);
+                for name in names.iter() {
+                    self.widen(name);
+                }
+                actual
+            }
+            Expr::AssignmentExpr(Pattern::Ident(ident), expr) => {
+                let actual = self.check_expr(expr);
+                match self.lookup(&ident.0) {
+                    Some(Binding::Variable(ty, true)) if !compatible(ty, actual) => {
+                        self.error(format!(
+                            "Cannot assign {} to variable '{}' of type {}",
+                            actual, ident.0, ty
+                        ));
+                    }
+                    Some(Binding::Variable(ty, false)) if ty != actual => self.widen(&ident.0),
+                    _ => (),
+                }
+                actual
+            }
+            Expr::UnaryExpr(op, expr) => {
+                let ty = self.check_expr(expr);
+                match op {
+                    UnaryOp::UnaryPlus => ty,
+                    UnaryOp::UnaryMinus => {
+                        if !numeric(ty) {
+                            self.error(format!("Cannot negate {}", ty));
+                        }
+                        Type::Int
+                    }
+                    UnaryOp::Not => {
+                        if !numeric(ty) {
+                            self.error(format!("Cannot apply '!' to {}", ty));
+                        }
+                        Type::Bool
+                    }
+                }
+            }
+            Expr::BinExpr(l_expr, op, r_expr) => {
+                let left = self.check_expr(l_expr);
+                let right = self.check_expr(r_expr);
+                self.check_binary(left, op, right)
+            }
+            Expr::CallExpr(func, args) => self.check_call(func, args),
+            Expr::PropagateExpr(expr) => {
+                let ty = self.check_expr(expr);
+                if !compatible(Type::Result, ty) {
+                    self.error(format!("The '?' operator expects result, found {}", ty));
+                }
+                Type::Any
+            }
+            Expr::SpawnExpr(func, args) => {
+                self.check_call(func, args);
+                Type::Nil
+            }
+            Expr::CoalesceExpr(l_expr, r_expr) => {
+                let left = self.check_expr(l_expr);
+                let right = self.check_expr(r_expr);
+                match left {
+                    Type::Nil => right,
+                    _ if left == right => left,
+                    _ => Type::Any,
+                }
+            }
+            Expr::OptionalCallExpr(func, args) => {
+                self.check_call(func, args);
+                Type::Any
+            }
+            Expr::MethodCallExpr(receiver, _, args, _) => {
+                self.check_expr(receiver);
+                for arg in args.iter() {
+                    match arg {
+                        Arg::Positional(expr) | Arg::Named(_, expr) => {
+                            self.check_expr(expr);
+                        }
+                    }
+                }
+                Type::Any
+            }
+            Expr::TupleExpr(exprs) => {
+                for expr in exprs.iter() {
+                    self.check_expr(expr);
+                }
+                Type::Tuple
+            }
+            Expr::RangeExpr(start, end, _, step) => {
+                let mut bounds = vec![self.check_expr(start), self.check_expr(end)];
+                if let Some(step) = step {
+                    bounds.push(self.check_expr(step));
+                }
+                for ty in bounds {
+                    if !compatible(Type::Int, ty) {
+                        self.error(format!("Range bounds and step should be int, found {}", ty));
+                    }
+                }
+                Type::Range
+            }
+            Expr::IndexExpr(target, index) => {
+                let target = self.check_expr(target);
+                let index = self.check_expr(index);
+                if !matches!(index, Type::Int | Type::Range | Type::Any) {
+                    self.error(format!("Cannot index with {}", index));
+                }
+                match target {
+                    Type::String => Type::String,
+                    Type::List | Type::Tuple if index == Type::Range => target,
+                    Type::List | Type::Tuple | Type::Any => Type::Any,
+                    _ => {
+                        self.error(format!("Cannot index into {}", target));
+                        Type::Any
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_binary(&mut self, left: Type, op: &BinOp, right: Type) -> Type {
+        let (valid, result) = match op {
+            BinOp::Plus => match (left, right) {
+                (Type::String, Type::String) => (true, Type::String),
+                (Type::Any, Type::String) | (Type::String, Type::Any) => (true, Type::Any),
+                (Type::Any, Type::Any) => (true, Type::Any),
+                _ => (numeric(left) && numeric(right), Type::Int),
+            },
+            BinOp::Minus | BinOp::Multiply | BinOp::Divide => {
+                (numeric(left) && numeric(right), Type::Int)
+            }
+            BinOp::Less
+            | BinOp::Greater
+            | BinOp::LessEqual
+            | BinOp::GreaterEqual
+            | BinOp::Equal
+            | BinOp::NotEqual => {
+                let nil = matches!(op, BinOp::Equal | BinOp::NotEqual)
+                    && (left == Type::Nil || right == Type::Nil);
+                let valid = nil
+                    || (numeric(left) && numeric(right))
+                    || (compatible(Type::String, left) && compatible(Type::String, right));
+                (valid, Type::Bool)
+            }
+        };
+        if !valid {
+            self.error(format!("Cannot apply {:?} to {} and {}", op, left, right));
+        }
+        result
+    }
+
+    fn check_call(&mut self, func: &Expr, args: &[Arg]) -> Type {
+        let mut actuals = vec![];
+        for arg in args.iter() {
+            match arg {
+                Arg::Positional(expr) => actuals.push((None, self.check_expr(expr))),
+                Arg::Named(ident, expr) => {
+                    actuals.push((Some(ident.0.to_string()), self.check_expr(expr)))
+                }
+            }
+        }
+        let (name, signature) = match func {
+            Expr::IdentExpr(ident) => match self.lookup(&ident.0) {
+                Some(Binding::Function(signature)) => (ident.0.to_string(), signature),
+                _ => return Type::Any,
+            },
+            _ => {
+                self.check_expr(func);
+                return Type::Any;
+            }
+        };
+        let fixed: Vec<&Param> = signature
+            .params
+            .iter()
+            .filter(|param| !param.variadic)
+            .collect();
+        let mut position = 0;
+        for (arg_name, actual) in actuals {
+            let param = match arg_name {
+                Some(arg_name) => fixed.iter().find(|param| param.name.0 == arg_name),
+                None => {
+                    position += 1;
+                    fixed.get(position - 1)
+                }
+            };
+            if let Some(Param {
+                name: param_name,
+                ty: Some(ty),
+                ..
+            }) = param
+            {
+                if !compatible(*ty, actual) {
+                    self.error(format!(
+                        "Argument '{}' of '{}' should be {}, found {}",
+                        param_name.0, name, ty, actual
+                    ));
+                }
+            }
+        }
+        signature.ret
+    }
+
+    fn declare(&mut self, name: &str, binding: Binding) {
+        self.frames
+            .last_mut()
+            .unwrap()
+            .bindings
+            .insert(name.to_string(), binding);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        let mut crossed = false;
+        for frame in self.frames.iter().rev() {
+            if let Some(binding) = frame.bindings.get(name) {
+                return match binding {
+                    Binding::Variable(_, false) if crossed => {
+                        Some(Binding::Variable(Type::Any, false))
+                    }
+                    _ => Some(binding.clone()),
+                };
+            }
+            crossed = crossed || frame.function;
+        }
+        None
+    }
+
+    fn widen(&mut self, name: &str) {
+        for frame in self.frames.iter_mut().rev() {
+            if let Some(binding) = frame.bindings.get_mut(name) {
+                if let Binding::Variable(ty, false) = binding {
+                    *ty = Type::Any;
+                }
+                return;
+            }
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(TypeError { message })
+    }
+}
+
+fn collect_assigned(stmt: &Stmt, names: &mut Vec<String>) {
+    match stmt {
+        Stmt::LetStmt(_, _, Some(expr))
+        | Stmt::ExprStmt(expr)
+        | Stmt::ThrowStmt(expr)
+        | Stmt::DeferStmt(expr) => collect_assigned_expr(expr, names),
+        Stmt::ReturnStmt(Some(expr), _) | Stmt::YieldStmt(Some(expr)) => {
+            collect_assigned_expr(expr, names)
+        }
+        Stmt::BlockStmt(stmts) => {
+            for stmt in stmts.iter() {
+                collect_assigned(stmt, names);
+            }
+        }
+        Stmt::IfStmt(condition, body, alt) => {
+            collect_assigned_expr(condition, names);
+            collect_assigned(body, names);
+            if let Some(alt) = alt {
+                collect_assigned(alt, names);
+            }
+        }
+        Stmt::WhileStmt(condition, body) => {
+            collect_assigned_expr(condition, names);
+            collect_assigned(body, names);
+        }
+        Stmt::ForStmt(_, iterable, body) => {
+            collect_assigned_expr(iterable, names);
+            collect_assigned(body, names);
+        }
+        Stmt::TryStmt(body, handler, finally) => {
+            collect_assigned(body, names);
+            if let Some((_, catch_body)) = handler {
+                collect_assigned(catch_body, names);
+            }
+            if let Some(finally) = finally {
+                collect_assigned(finally, names);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn collect_pattern(pattern: &Pattern, names: &mut Vec<String>) {
+    match pattern {
+        Pattern::Ident(ident) => names.push(ident.0.to_string()),
+        Pattern::Tuple(patterns) => {
+            for pattern in patterns.iter() {
+                collect_pattern(pattern, names);
+            }
+        }
+    }
+}
+
+fn collect_assigned_expr(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::AssignmentExpr(pattern, expr) => {
+            collect_pattern(pattern, names);
+            collect_assigned_expr(expr, names);
+        }
+        Expr::TupleExpr(exprs) => {
+            for expr in exprs.iter() {
+                collect_assigned_expr(expr, names);
+            }
+        }
+        Expr::RangeExpr(start, end, _, step) => {
+            collect_assigned_expr(start, names);
+            collect_assigned_expr(end, names);
+            if let Some(step) = step {
+                collect_assigned_expr(step, names);
+            }
+        }
+        Expr::IndexExpr(target, index) => {
+            collect_assigned_expr(target, names);
+            collect_assigned_expr(index, names);
+        }
+        Expr::UnaryExpr(_, expr) | Expr::PropagateExpr(expr) => collect_assigned_expr(expr, names),
+        Expr::BinExpr(l_expr, _, r_expr) => {
+            collect_assigned_expr(l_expr, names);
+            collect_assigned_expr(r_expr, names);
+        }
+        Expr::CoalesceExpr(l_expr, r_expr) => {
+            collect_assigned_expr(l_expr, names);
+            collect_assigned_expr(r_expr, names);
+        }
+        Expr::CallExpr(func, args)
+        | Expr::SpawnExpr(func, args)
+        | Expr::OptionalCallExpr(func, args)
+        | Expr::MethodCallExpr(func, _, args, _) => {
+            collect_assigned_expr(func, names);
+            for arg in args.iter() {
+                match arg {
+                    Arg::Positional(expr) | Arg::Named(_, expr) => {
+                        collect_assigned_expr(expr, names)
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+fn contains_yield(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Stmt::YieldStmt(_) => true,
+        Stmt::BlockStmt(stmts) => contains_yield(stmts),
+        Stmt::IfStmt(_, body, alt) => {
+            contains_yield(std::slice::from_ref(body))
+                || alt
+                    .as_ref()
+                    .is_some_and(|alt| contains_yield(std::slice::from_ref(alt)))
+        }
+        Stmt::WhileStmt(_, body) | Stmt::ForStmt(_, _, body) => {
+            contains_yield(std::slice::from_ref(body))
+        }
+        Stmt::TryStmt(body, handler, finally) => {
+            contains_yield(std::slice::from_ref(body))
+                || handler
+                    .as_ref()
+                    .is_some_and(|(_, body)| contains_yield(std::slice::from_ref(body)))
+                || finally
+                    .as_ref()
+                    .is_some_and(|body| contains_yield(std::slice::from_ref(body)))
+        }
+        _ => false,
+    })
+}
@@ -1,8 +1,47 @@
-use crate::parser::ast::{BinOp, Expr, Ident, Literal, Program, Stmt, UnaryOp};
-use crate::vm::opcode::{Opcode, Value};
+use crate::optimizer::truth;
+use crate::parser::ast::visit::{walk_expr, Visitor};
+use crate::parser::ast::{
+    Arg, BinOp, Contract, Decorator, Expr, Ident, Literal, Param, Pattern, Program, Stmt, UnaryOp,
+};
+use crate::vm::opcode::{Capture, Local, Opcode, Parameter, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub message: String,
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
 
 pub struct Compiler {
     pub codes: Vec<Opcode>,
+    pub errors: Vec<CompileError>,
+    in_function: bool,
+    generator: bool,
+    returns_value: bool,
+    handlers: usize,
+    blocks: usize,
+    finally_blocks: Vec<(usize, Vec<Stmt>)>,
+    ensures: Vec<Contract>,
+    signature: String,
+    arguments: Vec<String>,
+    scopes: Vec<LocalScope>,
+    boundary: usize,
+    upvalues: Vec<String>,
+    siblings: Vec<String>,
+    exits: Vec<usize>,
+}
+
+#[derive(Clone, Default)]
+struct LocalScope {
+    names: HashMap<String, (usize, bool)>,
+    siblings: Vec<String>,
 }
 
 impl Default for Compiler {
@@ -13,56 +52,400 @@ impl Default for Compiler {
 
 impl Compiler {
     pub fn new() -> Self {
-        Self { codes: vec![] }
+        Self {
+            codes: vec![],
+            errors: vec![],
+            in_function: false,
+            generator: false,
+            returns_value: false,
+            handlers: 0,
+            blocks: 0,
+            finally_blocks: vec![],
+            ensures: vec![],
+            signature: String::new(),
+            arguments: vec![],
+            scopes: vec![],
+            boundary: 0,
+            upvalues: vec![],
+            siblings: vec![],
+            exits: vec![],
+        }
+    }
+
+    fn nested(&self) -> Compiler {
+        let mut sub_compiler = Compiler::new();
+        sub_compiler.in_function = true;
+        sub_compiler.scopes = self.scopes.clone();
+        for scope in sub_compiler.scopes.iter_mut() {
+            for (_, visible) in scope.names.values_mut() {
+                *visible = true;
+            }
+        }
+        sub_compiler.boundary = sub_compiler.scopes.len();
+        sub_compiler
+    }
+
+    fn enter_scope(&mut self, mut names: Vec<(String, bool)>, stmts: &[Stmt]) -> bool {
+        names.extend(declarations(stmts));
+        let deferred = stmts.iter().any(|stmt| matches!(stmt, Stmt::DeferStmt(_)));
+        if names.is_empty() && !deferred {
+            return false;
+        }
+        self.push_scope(names, siblings(stmts));
+        self.emit(Opcode::BeginScope);
+        true
     }
+
+    fn push_scope(&mut self, names: Vec<(String, bool)>, siblings: Vec<String>) {
+        let mut scope = LocalScope {
+            names: HashMap::new(),
+            siblings,
+        };
+        for (name, visible) in names {
+            let slot = scope.names.len();
+            let entry = scope.names.entry(name).or_insert((slot, false));
+            entry.1 |= visible;
+        }
+        self.scopes.push(scope);
+    }
+
+    fn exit_scope(&mut self, scoped: bool) {
+        if scoped {
+            self.scopes.pop();
+            self.emit(Opcode::EndScope);
+        }
+    }
+
+    fn lookup(&self, name: &str, visible: bool) -> Option<(usize, usize)> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, scope)| match scope.names.get(name) {
+                Some(&(slot, seen)) if seen || !visible => Some((index, slot)),
+                _ => None,
+            })
+    }
+
+    fn local(&self, index: usize, slot: usize, name: &str) -> Local {
+        Local::new(self.scopes.len() - 1 - index, slot, name)
+    }
+
+    fn resolve(&self, name: &str) -> Option<Local> {
+        match self.lookup(name, true) {
+            Some((index, slot)) if index >= self.boundary => Some(self.local(index, slot, name)),
+            _ => None,
+        }
+    }
+
+    fn upvalue(&mut self, name: &str) -> usize {
+        match self.upvalues.iter().position(|upvalue| upvalue == name) {
+            Some(index) => index,
+            None => {
+                self.upvalues.push(name.to_string());
+                self.upvalues.len() - 1
+            }
+        }
+    }
+
+    fn capture(&mut self, name: &str) -> Capture {
+        match self.lookup(name, false) {
+            Some((index, slot)) if index >= self.boundary => {
+                Capture::Local(self.local(index, slot, name))
+            }
+            _ => match self.sibling(name) {
+                Some(index) => Capture::Sibling(index),
+                None => Capture::Upvalue(self.upvalue(name)),
+            },
+        }
+    }
+
+    fn sibling(&self, name: &str) -> Option<usize> {
+        self.siblings.iter().position(|sibling| sibling == name)
+    }
+
+    fn captures(&mut self, upvalues: &[String]) -> Vec<Capture> {
+        upvalues.iter().map(|name| self.capture(name)).collect()
+    }
+
+    fn declare(&mut self, name: &str) -> Option<usize> {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                let slot = scope.names.len();
+                let entry = scope.names.entry(name.to_string()).or_insert((slot, false));
+                entry.1 = true;
+                Some(entry.0)
+            }
+            None => {
+                self.emit(Opcode::Register(name.to_string()));
+                None
+            }
+        }
+    }
+
+    fn load(&mut self, name: &str) {
+        if let Some(local) = self.resolve(name) {
+            self.emit(Opcode::LoadLocal(local));
+        } else if let Some(index) = self.sibling(name) {
+            self.emit(Opcode::LoadSibling(index));
+        } else if self.lookup(name, true).is_some() {
+            let index = self.upvalue(name);
+            self.emit(Opcode::LoadUpvalue(index, Rc::from(name)));
+        } else {
+            self.emit(Opcode::Load(name.to_string()));
+        }
+    }
+
+    fn store(&mut self, name: &str) {
+        if let Some(local) = self.resolve(name) {
+            self.emit(Opcode::StoreLocal(local));
+        } else if self.lookup(name, true).is_some() {
+            let index = self.upvalue(name);
+            self.emit(Opcode::StoreUpvalue(index));
+        } else {
+            self.emit(Opcode::Store(name.to_string()));
+        }
+    }
+
     pub fn compile(&mut self, program: &Program) {
+        let siblings = match self.scopes.is_empty() {
+            true => vec![],
+            false => siblings(program),
+        };
         for stmt in program.iter() {
-            self.compile_stmt(stmt)
+            match stmt {
+                Stmt::FnStmt(ident, params, _, body, _, contracts)
+                    if siblings.contains(&ident.0) =>
+                {
+                    self.make_fn(ident, params, body, contracts)
+                }
+                Stmt::FnStmt(..) => self.visit_stmt(stmt),
+                _ => (),
+            }
+        }
+        if !siblings.is_empty() {
+            self.emit(Opcode::MakeSiblings(siblings.len()));
+            for name in siblings.iter().rev() {
+                self.declare(name);
+                self.store(name);
+                self.emit(Opcode::Pop);
+            }
+        }
+        for stmt in program.iter() {
+            match stmt {
+                Stmt::FnStmt(ident, _, _, _, decorators, _) => {
+                    self.compile_decorators(ident, decorators)
+                }
+                stmt => self.visit_stmt(stmt),
+            }
         }
     }
 
-    fn compile_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::ExprStmt(expr) => {
-                self.compile_expr(expr);
-                self.emit(Opcode::Pop)
+    fn compile_while(&mut self, condition: &Expr, body: &Stmt) {
+        let start_pos = self.codes.len();
+        let backpatch = match truth(condition) {
+            Some(true) => None,
+            _ => {
+                self.visit_expr(condition);
+                self.emit(Opcode::JumpIfFalse(0));
+                Some(self.codes.len() - 1)
             }
-            Stmt::LetStmt(ident, expr) => self.compile_let(ident, expr),
-            Stmt::FnStmt(ident, params, body) => self.compile_fn(ident, params, body),
-            Stmt::BlockStmt(stmts) => self.compile_block(stmts),
-            Stmt::IfStmt(condition, body, alt) => self.compile_if(condition, body, alt),
-            Stmt::ReturnStmt(expr) => self.compile_ret(expr),
-            Stmt::WhileStmt(condition, body) => self.compile_while(condition, body),
+        };
+        let body = match body {
+            Stmt::BlockStmt(block) => block,
+            _ => unreachable!(),
+        };
+        self.compile_block(body);
+        self.emit(Opcode::Jump(start_pos));
+        if let Some(backpatch) = backpatch {
+            let pos = self.codes.len();
+            self.codes[backpatch] = Opcode::JumpIfFalse(pos);
         }
     }
 
-    fn compile_while(&mut self, condition: &Expr, body: &Box<Stmt>) {
+    fn compile_for(&mut self, pattern: &Pattern, iterable: &Expr, body: &Stmt) {
+        self.visit_expr(iterable);
+        self.emit(Opcode::Push(Value::Int(0)));
         let start_pos = self.codes.len();
-        self.compile_expr(condition);
-        self.emit(Opcode::JumpIfFalse(0));
-        let backpatch = self.codes.len() - 1;
-        let body = match body.as_ref() {
+        self.emit(Opcode::IterNext(0));
+        let body = match body {
             Stmt::BlockStmt(block) => block,
             _ => unreachable!(),
         };
-        self.compile_block(body);
+        let scoped = self.enter_scope(pattern_names(pattern, false), body);
+        self.compile_bind(pattern, true);
+        self.blocks += 1;
+        self.compile(body);
+        self.blocks -= 1;
+        self.exit_scope(scoped);
         self.emit(Opcode::Jump(start_pos));
         let pos = self.codes.len();
-        self.codes[backpatch] = Opcode::JumpIfFalse(pos);
+        self.codes[start_pos] = Opcode::IterNext(pos);
     }
 
     fn compile_ret(&mut self, expr: &Option<Expr>) {
         if let Some(expression) = expr {
-            self.compile_expr(expression);
+            self.returns_value = true;
+            self.visit_expr(expression);
+        }
+        if !self.ensures.is_empty() {
+            if expr.is_none() {
+                self.emit(Opcode::Push(Value::None));
+            }
+            self.emit(Opcode::SkipContracts(0));
+            let backpatch = self.codes.len() - 1;
+            self.declare("result");
+            self.store("result");
+            let ensures = self.ensures.clone();
+            self.arguments.push("result".to_string());
+            self.compile_contracts(&ensures);
+            self.arguments.pop();
+            let pos = self.codes.len();
+            self.codes[backpatch] = Opcode::SkipContracts(pos);
+        }
+        self.emit_return();
+    }
+
+    fn compile_contracts(&mut self, contracts: &[Contract]) {
+        for contract in contracts.iter() {
+            let (Contract::Requires(expr) | Contract::Ensures(expr)) = contract;
+            self.visit_expr(expr);
+            let locals = self
+                .arguments
+                .iter()
+                .filter_map(|name| match self.resolve(&entry(name)) {
+                    Some(local) => Some(Local {
+                        name: Rc::from(name.as_str()),
+                        ..local
+                    }),
+                    None => self.resolve(name),
+                })
+                .collect();
+            self.emit(Opcode::Contract(
+                format!("{} {} failed", self.signature, contract),
+                locals,
+            ));
+        }
+    }
+
+    fn compile_yield(&mut self, expr: &Option<Expr>) {
+        if !self.in_function {
+            self.error("Yield should live in a function");
+            return;
+        }
+        self.generator = true;
+        match expr {
+            Some(expression) => self.visit_expr(expression),
+            None => self.emit(Opcode::Push(Value::None)),
+        }
+        self.emit(Opcode::Yield);
+    }
+
+    fn compile_defer(&mut self, expr: &Expr) {
+        if !self.in_function && self.blocks == 0 {
+            self.error("Defer should live in a block or a function");
+            return;
+        }
+        let mut sub_compiler = self.nested();
+        sub_compiler.scopes.push(LocalScope::default());
+        sub_compiler.visit_expr(expr);
+        sub_compiler.emit(Opcode::Return);
+        self.errors.append(&mut sub_compiler.errors);
+        let captures = self.captures(&sub_compiler.upvalues);
+        self.emit(Opcode::Defer(sub_compiler.codes.into(), captures));
+    }
+
+    fn emit_return(&mut self) {
+        let handlers = self.handlers;
+        let finally_blocks = self.finally_blocks.clone();
+        for (depth, (level, block)) in finally_blocks.iter().enumerate().rev() {
+            for _ in *level..self.handlers {
+                self.emit(Opcode::PopHandler);
+            }
+            // A return inside the finally block only runs the outer ones.
+            self.handlers = *level;
+            self.finally_blocks.truncate(depth);
+            self.compile_block(block);
         }
+        self.handlers = handlers;
+        self.finally_blocks = finally_blocks;
         self.emit(Opcode::Return);
     }
 
-    fn compile_if(&mut self, condition: &Expr, body: &Box<Stmt>, alt: &Option<Box<Stmt>>) {
-        self.compile_expr(condition);
+    fn compile_try(
+        &mut self,
+        body: &Stmt,
+        handler: &Option<(Ident, Box<Stmt>)>,
+        finally: &Option<Box<Stmt>>,
+    ) {
+        let body = match body {
+            Stmt::BlockStmt(block) => block,
+            _ => unreachable!(),
+        };
+        let finally = finally.as_ref().map(|content| match content.as_ref() {
+            Stmt::BlockStmt(block) => block,
+            _ => unreachable!(),
+        });
+        if let Some(block) = finally {
+            self.finally_blocks.push((self.handlers, block.clone()));
+        }
+        self.emit(Opcode::PushHandler(0));
+        let mut backpatch1 = self.codes.len() - 1;
+        self.handlers += 1;
+        self.compile_block(body);
+        self.handlers -= 1;
+        self.emit(Opcode::PopHandler);
+        if let Some((ident, catch_body)) = handler {
+            self.emit(Opcode::Jump(0));
+            let backpatch2 = self.codes.len() - 1;
+            let pos1 = self.codes.len();
+            self.codes[backpatch1] = Opcode::PushHandler(pos1);
+            let mut backpatch3 = None;
+            if finally.is_some() {
+                self.emit(Opcode::PushHandler(0));
+                backpatch3 = Some(self.codes.len() - 1);
+                self.handlers += 1;
+            }
+            let catch_body = match catch_body.as_ref() {
+                Stmt::BlockStmt(block) => block,
+                _ => unreachable!(),
+            };
+            let scoped = self.enter_scope(vec![(ident.0.to_string(), false)], catch_body);
+            self.compile_bind(&Pattern::Ident(ident.clone()), true);
+            self.blocks += 1;
+            self.compile(catch_body);
+            self.blocks -= 1;
+            self.exit_scope(scoped);
+            if finally.is_some() {
+                self.handlers -= 1;
+                self.emit(Opcode::PopHandler);
+            }
+            let pos2 = self.codes.len();
+            self.codes[backpatch2] = Opcode::Jump(pos2);
+            backpatch1 = match backpatch3 {
+                Some(backpatch3) => backpatch3,
+                None => return,
+            };
+        }
+        if let Some(block) = finally {
+            self.finally_blocks.pop();
+            self.compile_block(block);
+            self.emit(Opcode::Jump(0));
+            let backpatch4 = self.codes.len() - 1;
+            let pos3 = self.codes.len();
+            self.codes[backpatch1] = Opcode::PushHandler(pos3);
+            self.compile_block(block);
+            self.emit(Opcode::Throw);
+            let pos4 = self.codes.len();
+            self.codes[backpatch4] = Opcode::Jump(pos4);
+        }
+    }
+
+    fn compile_if(&mut self, condition: &Expr, body: &Stmt, alt: &Option<Box<Stmt>>) {
+        self.visit_expr(condition);
         self.emit(Opcode::JumpIfFalse(0));
         let backpatch1 = self.codes.len() - 1;
-        let body = match body.as_ref() {
+        let body = match body {
             Stmt::BlockStmt(block) => block,
             _ => unreachable!(),
         };
@@ -84,86 +467,320 @@ impl Compiler {
             None => {
                 let pos1 = self.codes.len();
                 self.codes[backpatch1] = Opcode::JumpIfFalse(pos1);
-            },
+            }
         };
     }
 
     fn compile_block(&mut self, stmts: &Vec<Stmt>) {
-        self.emit(Opcode::BeginScope);
-        let mut sub_compiler = Compiler::new();
-        sub_compiler.compile(stmts);
-        self.codes.append(&mut sub_compiler.codes);
-        self.emit(Opcode::EndScope);
+        let scoped = self.enter_scope(vec![], stmts);
+        self.blocks += 1;
+        self.compile(stmts);
+        self.blocks -= 1;
+        self.exit_scope(scoped);
     }
 
-    fn compile_fn(&mut self, ident: &Ident, params: &Vec<Expr>, body: &Box<Stmt>) {
-        let mut param_names = vec![];
-        for param in params {
-            match param {
-                Expr::IdentExpr(name) => {
-                    param_names.push(name.0.to_string());
-                }
-                _ => unreachable!(),
-            }
-        }
-        let body = match body.as_ref() {
+    fn compile_fn(&mut self, ident: &Ident, params: &[Param], body: &Stmt, contracts: &[Contract]) {
+        self.make_fn(ident, params, body, contracts);
+        self.compile_bind(&Pattern::Ident(ident.clone()), true);
+    }
+
+    fn make_fn(&mut self, ident: &Ident, params: &[Param], body: &Stmt, contracts: &[Contract]) {
+        let body = match body {
             Stmt::BlockStmt(block) => block,
             _ => unreachable!(),
         };
-        let mut sub_compiler = Compiler::new();
+        let mut sub_compiler = self.nested();
+        if let Some(scope) = self.scopes.last() {
+            if scope.siblings.contains(&ident.0) {
+                sub_compiler.siblings = scope.siblings.clone();
+            }
+        }
+        let mut names: Vec<(String, bool)> = params
+            .iter()
+            .map(|param| (param.name.0.to_string(), true))
+            .collect();
+        for param in params {
+            if let Some(pattern) = &param.pattern {
+                names.extend(pattern_names(pattern, false));
+            }
+        }
+        names.extend(declarations(body));
+        sub_compiler.push_scope(names, siblings(body));
+        let mut parameters = vec![];
+        for (slot, param) in params.iter().enumerate() {
+            let name = param.name.0.to_string();
+            if let Some(default) = &param.default {
+                sub_compiler.emit(Opcode::JumpIfAssigned(slot, 0));
+                let backpatch = sub_compiler.codes.len() - 1;
+                sub_compiler.visit_expr(default);
+                sub_compiler.emit(Opcode::StoreLocal(Local::new(0, slot, &name)));
+                sub_compiler.emit(Opcode::Pop);
+                let pos = sub_compiler.codes.len();
+                sub_compiler.codes[backpatch] = Opcode::JumpIfAssigned(slot, pos);
+            }
+            if let Some(pattern) = &param.pattern {
+                sub_compiler.emit(Opcode::LoadLocal(Local::new(0, slot, &name)));
+                sub_compiler.compile_bind(pattern, true);
+            }
+            parameters.push(Parameter {
+                name,
+                has_default: param.default.is_some(),
+                variadic: param.variadic,
+            });
+        }
+        let names: Vec<String> = parameters.iter().map(|param| param.to_string()).collect();
+        sub_compiler.signature = format!("{}({})", ident.0, names.join(", "));
+        sub_compiler.arguments = parameters.iter().map(|param| param.name.clone()).collect();
+        let (requires, ensures): (Vec<Contract>, Vec<Contract>) = contracts
+            .iter()
+            .cloned()
+            .partition(|contract| matches!(contract, Contract::Requires(_)));
+        if !requires.is_empty() {
+            sub_compiler.emit(Opcode::SkipContracts(0));
+            let backpatch = sub_compiler.codes.len() - 1;
+            sub_compiler.compile_contracts(&requires);
+            let pos = sub_compiler.codes.len();
+            sub_compiler.codes[backpatch] = Opcode::SkipContracts(pos);
+        }
+        if !ensures.is_empty() {
+            sub_compiler.emit(Opcode::SkipContracts(0));
+            let backpatch = sub_compiler.codes.len() - 1;
+            for name in sub_compiler.arguments.clone() {
+                sub_compiler.load(&name);
+                sub_compiler.declare(&entry(&name));
+                sub_compiler.store(&entry(&name));
+                sub_compiler.emit(Opcode::Pop);
+            }
+            let pos = sub_compiler.codes.len();
+            sub_compiler.codes[backpatch] = Opcode::SkipContracts(pos);
+        }
+        sub_compiler.ensures = ensures;
         sub_compiler.compile(body);
-        self.emit(Opcode::StoreFunction(
+        if sub_compiler.generator && sub_compiler.returns_value {
+            sub_compiler.error("Cannot return a value from a generator");
+        }
+        self.errors.append(&mut sub_compiler.errors);
+        let captures = self.captures(&sub_compiler.upvalues);
+        self.emit(Opcode::MakeFunction(
             ident.0.to_string(),
-            param_names,
-            sub_compiler.codes,
+            parameters,
+            sub_compiler.codes.into(),
+            sub_compiler.generator,
+            captures,
         ));
     }
 
-    fn compile_let(&mut self, ident: &Ident, expr: &Option<Expr>) {
-        if let Some(expression) = expr {
-            self.compile_expr(expression);
-            self.emit(Opcode::Register(ident.0.to_string()));
-            self.emit(Opcode::Store(ident.0.to_string()));
+    fn compile_decorators(&mut self, ident: &Ident, decorators: &[Decorator]) {
+        let sibling = match decorators.is_empty() {
+            true => None,
+            false => self.scopes.last().and_then(|scope| {
+                scope
+                    .siblings
+                    .iter()
+                    .position(|sibling| *sibling == ident.0)
+            }),
+        };
+        if sibling.is_some() {
+            self.load(&ident.0);
+        }
+        for decorator in decorators.iter().rev() {
+            if decorator.is_builtin() {
+                if decorator
+                    .args
+                    .iter()
+                    .any(|arg| matches!(arg, Arg::Named(..)))
+                {
+                    self.error("Built-in decorators do not take named arguments");
+                    continue;
+                }
+                let limit = match decorator.name.0.as_str() {
+                    "deprecated" => 1,
+                    _ => 0,
+                };
+                if decorator.args.len() > limit {
+                    self.error(&format!(
+                        "Decorator '@{}' takes at most {} arguments but {} were given",
+                        decorator.name.0,
+                        limit,
+                        decorator.args.len()
+                    ));
+                    continue;
+                }
+                self.load(&ident.0);
+                let (positional, _) = self.compile_args(&decorator.args);
+                self.emit(Opcode::Decorate(decorator.name.0.to_string(), positional));
+            } else {
+                self.load(&ident.0);
+                let (positional, names) = self.compile_args(&decorator.args);
+                self.load(&decorator.name.0);
+                self.emit(Opcode::Call(positional + 1, names));
+            }
+            self.store(&ident.0);
             self.emit(Opcode::Pop);
-        } else {
-            self.emit(Opcode::Register(ident.0.to_string()));
+        }
+        if let Some(index) = sibling {
+            self.load(&ident.0);
+            self.emit(Opcode::BindSibling(index));
+        }
+    }
+
+    fn compile_let(&mut self, pattern: &Pattern, expr: &Option<Expr>) {
+        match (pattern, expr) {
+            (_, Some(expression)) => {
+                self.visit_expr(expression);
+                self.compile_bind(pattern, true);
+            }
+            (Pattern::Ident(ident), None) => {
+                if let Some(slot) = self.declare(&ident.0) {
+                    self.emit(Opcode::RegisterLocal(slot));
+                }
+            }
+            (Pattern::Tuple(_), None) => {
+                self.error("Destructuring declaration needs an initializer")
+            }
+        }
+    }
+
+    fn compile_bind(&mut self, pattern: &Pattern, declare: bool) {
+        match pattern {
+            Pattern::Ident(ident) => {
+                if declare {
+                    self.declare(&ident.0);
+                }
+                self.store(&ident.0);
+                self.emit(Opcode::Pop);
+            }
+            Pattern::Tuple(patterns) => {
+                self.emit(Opcode::Unpack(patterns.len()));
+                for pattern in patterns {
+                    self.compile_bind(pattern, declare);
+                }
+            }
+        }
+    }
+
+    fn compile_coalesce(&mut self, l_expr: &Expr, r_expr: &Expr) {
+        self.visit_expr(l_expr);
+        self.emit(Opcode::JumpIfNotNil(0));
+        let backpatch = self.codes.len() - 1;
+        self.visit_expr(r_expr);
+        let pos = self.codes.len();
+        self.codes[backpatch] = Opcode::JumpIfNotNil(pos);
+    }
+
+    fn compile_chain(&mut self, expr: &Expr) {
+        let outer = std::mem::take(&mut self.exits);
+        self.compile_link(expr);
+        let exits = std::mem::replace(&mut self.exits, outer);
+        let pos = self.codes.len();
+        for exit in exits {
+            self.codes[exit] = Opcode::JumpIfNil(pos);
         }
     }
 
-    fn compile_expr(&mut self, expr: &Expr) {
+    fn compile_link(&mut self, expr: &Expr) {
         match expr {
-            Expr::LiteralExpr(literal) => self.compile_literal(literal),
-            Expr::IdentExpr(ident) => self.compile_ident(ident),
-            Expr::BinExpr(l_expr, op, r_expr) => self.compile_binary(l_expr, op, r_expr),
-            Expr::UnaryExpr(op, expr) => self.compile_unary(op, expr),
-            Expr::AssignmentExpr(ident, expr) => self.compile_assignment(ident, expr),
             Expr::CallExpr(func, args) => self.compile_call(func, args),
+            Expr::OptionalCallExpr(func, args) => self.compile_optional_call(func, args),
+            Expr::MethodCallExpr(receiver, name, args, optional) => {
+                self.compile_method_call(receiver, name, args, *optional)
+            }
+            Expr::IndexExpr(target, index) => {
+                self.compile_link(target);
+                self.visit_expr(index);
+                self.emit(Opcode::Index);
+            }
+            expr => self.visit_expr(expr),
         }
     }
 
-    fn compile_call(&mut self, func: &Box<Expr>, args: &Vec<Expr>) {
-        for arg in args {
-            self.compile_expr(arg);
+    fn compile_exit(&mut self) {
+        self.emit(Opcode::JumpIfNil(0));
+        self.exits.push(self.codes.len() - 1);
+    }
+
+    fn compile_method_call(&mut self, receiver: &Expr, name: &Ident, args: &[Arg], optional: bool) {
+        self.compile_link(receiver);
+        if optional {
+            self.compile_exit();
         }
-        match func.as_ref() {
-            Expr::CallExpr(expr, new_args) => {
-                self.compile_call(expr, new_args);
-            }
-            Expr::IdentExpr(ident) => {
-                self.compile_ident(ident);
+        for arg in args {
+            match arg {
+                Arg::Positional(expr) => self.visit_expr(expr),
+                Arg::Named(_, _) => self.error("Methods do not take named arguments"),
             }
-            _ => {
-                panic!("The function call should lead by ident")
+        }
+        self.emit(Opcode::CallMethod(name.0.to_string(), args.len()));
+    }
+
+    fn compile_optional_call(&mut self, func: &Expr, args: &[Arg]) {
+        self.compile_link(func);
+        self.compile_exit();
+        let (positional, names) = self.compile_args(args);
+        self.emit(Opcode::Roll(positional + names.len()));
+        self.emit(Opcode::Call(positional, names));
+    }
+
+    fn compile_spawn(&mut self, func: &Expr, args: &[Arg]) {
+        let (positional, names) = self.compile_args(args);
+        self.visit_expr(func);
+        self.emit(Opcode::Spawn(positional, names));
+    }
+
+    fn compile_propagate(&mut self, expr: &Expr) {
+        if !self.in_function {
+            self.error("The '?' operator can only be used inside a function");
+            return;
+        }
+        self.visit_expr(expr);
+        self.emit(Opcode::Propagate(0));
+        let backpatch = self.codes.len() - 1;
+        self.emit_return();
+        let pos = self.codes.len();
+        self.codes[backpatch] = Opcode::Propagate(pos);
+    }
+
+    fn compile_call(&mut self, func: &Expr, args: &[Arg]) {
+        if optional_link(func) {
+            self.compile_link(func);
+            let (positional, names) = self.compile_args(args);
+            self.emit(Opcode::Roll(positional + names.len()));
+            self.emit(Opcode::Call(positional, names));
+            return;
+        }
+        let (positional, names) = self.compile_args(args);
+        self.compile_link(func);
+        self.emit(Opcode::Call(positional, names));
+    }
+    fn compile_args(&mut self, args: &[Arg]) -> (usize, Vec<String>) {
+        let mut positional = 0;
+        let mut names = vec![];
+        for arg in args {
+            match arg {
+                Arg::Positional(expr) => {
+                    self.visit_expr(expr);
+                    positional += 1;
+                }
+                Arg::Named(ident, expr) => {
+                    self.visit_expr(expr);
+                    names.push(ident.0.to_string());
+                }
             }
         }
-        self.emit(Opcode::Call(args.len()));
+        (positional, names)
     }
-    fn compile_assignment(&mut self, ident: &Ident, expr: &Box<Expr>) {
-        self.compile_expr(expr);
-        self.emit(Opcode::Store(ident.0.to_string()));
+    fn compile_assignment(&mut self, pattern: &Pattern, expr: &Expr) {
+        self.visit_expr(expr);
+        match pattern {
+            Pattern::Ident(ident) => self.store(&ident.0),
+            Pattern::Tuple(_) => {
+                self.emit(Opcode::Dup);
+                self.compile_bind(pattern, false);
+            }
+        }
     }
-    fn compile_unary(&mut self, op: &UnaryOp, expr: &Box<Expr>) {
-        self.compile_expr(expr);
+    fn compile_unary(&mut self, op: &UnaryOp, expr: &Expr) {
+        self.visit_expr(expr);
         match op {
             UnaryOp::Not => self.emit(Opcode::Not),
             UnaryOp::UnaryMinus => self.emit(Opcode::Negate),
@@ -171,8 +788,8 @@ impl Compiler {
         }
     }
     fn compile_binary(&mut self, l_expr: &Expr, op: &BinOp, r_expr: &Expr) {
-        self.compile_expr(l_expr);
-        self.compile_expr(r_expr);
+        self.visit_expr(l_expr);
+        self.visit_expr(r_expr);
         match op {
             BinOp::Plus => self.emit(Opcode::Add),
             BinOp::Minus => self.emit(Opcode::Subtract),
@@ -187,9 +804,145 @@ impl Compiler {
         }
     }
     fn compile_ident(&mut self, ident: &Ident) {
-        self.emit(Opcode::Load(ident.0.to_string()));
+        self.load(&ident.0);
+    }
+    fn error(&mut self, message: &str) {
+        self.errors.push(CompileError {
+            message: message.to_string(),
+        })
+    }
+    fn emit(&mut self, code: Opcode) {
+        self.codes.push(code)
+    }
+}
+
+fn entry(name: &str) -> String {
+    format!("{}@entry", name)
+}
+
+fn declarations(stmts: &[Stmt]) -> Vec<(String, bool)> {
+    let mut names = vec![];
+    for stmt in stmts {
+        match stmt {
+            Stmt::LetStmt(pattern, ..) => names.extend(pattern_names(pattern, false)),
+            Stmt::FnStmt(ident, ..) => names.push((ident.0.to_string(), true)),
+            _ => (),
+        }
+    }
+    names
+}
+
+fn siblings(stmts: &[Stmt]) -> Vec<String> {
+    let mut assigned = Assigned(HashSet::new());
+    assigned.visit_program(stmts);
+    let mut declared: HashMap<String, usize> = HashMap::new();
+    for (name, _) in declarations(stmts) {
+        *declared.entry(name).or_default() += 1;
+    }
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::FnStmt(ident, ..)
+                if declared[&ident.0] == 1 && !assigned.0.contains(&ident.0) =>
+            {
+                Some(ident.0.to_string())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+struct Assigned(HashSet<String>);
+
+impl Visitor for Assigned {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::AssignmentExpr(pattern, _) = expr {
+            self.0.extend(
+                pattern_names(pattern, false)
+                    .into_iter()
+                    .map(|(name, _)| name),
+            );
+        }
+        walk_expr(self, expr);
+    }
+}
+
+fn optional_link(expr: &Expr) -> bool {
+    match expr {
+        Expr::OptionalCallExpr(..) | Expr::MethodCallExpr(_, _, _, true) => true,
+        Expr::CallExpr(inner, _) | Expr::MethodCallExpr(inner, ..) | Expr::IndexExpr(inner, _) => {
+            optional_link(inner)
+        }
+        _ => false,
+    }
+}
+
+fn pattern_names(pattern: &Pattern, visible: bool) -> Vec<(String, bool)> {
+    match pattern {
+        Pattern::Ident(ident) => vec![(ident.0.to_string(), visible)],
+        Pattern::Tuple(patterns) => patterns
+            .iter()
+            .flat_map(|pattern| pattern_names(pattern, visible))
+            .collect(),
+    }
+}
+
+impl Visitor for Compiler {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::ExprStmt(expr) => {
+                self.visit_expr(expr);
+                self.emit(Opcode::Pop)
+            }
+            Stmt::LetStmt(pattern, _, expr) => self.compile_let(pattern, expr),
+            Stmt::FnStmt(ident, params, _, body, _, contracts) => {
+                self.compile_fn(ident, params, body, contracts)
+            }
+            Stmt::BlockStmt(stmts) => self.compile_block(stmts),
+            Stmt::IfStmt(condition, body, alt) => self.compile_if(condition, body, alt),
+            Stmt::ReturnStmt(expr, _) => self.compile_ret(expr),
+            Stmt::WhileStmt(condition, body) => self.compile_while(condition, body),
+            Stmt::ForStmt(pattern, iterable, body) => self.compile_for(pattern, iterable, body),
+            Stmt::TryStmt(body, handler, finally) => self.compile_try(body, handler, finally),
+            Stmt::ThrowStmt(expr) => {
+                self.visit_expr(expr);
+                self.emit(Opcode::Throw);
+            }
+            Stmt::YieldStmt(expr) => self.compile_yield(expr),
+            Stmt::DeferStmt(expr) => self.compile_defer(expr),
+            Stmt::MacroStmt(ident, ..) => {
+                self.error(&format!("Unexpanded macro '{}' at {}", ident.0, ident.1))
+            }
+            Stmt::ErrorStmt(span) => self.error(&format!("Syntax error at {}", span)),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IdentExpr(ident) => self.compile_ident(ident),
+            Expr::BinExpr(l_expr, op, r_expr) => self.compile_binary(l_expr, op, r_expr),
+            Expr::UnaryExpr(op, expr) => self.compile_unary(op, expr),
+            Expr::AssignmentExpr(pattern, expr) => self.compile_assignment(pattern, expr),
+            Expr::PropagateExpr(expr) => self.compile_propagate(expr),
+            Expr::SpawnExpr(func, args) => self.compile_spawn(func, args),
+            Expr::CoalesceExpr(l_expr, r_expr) => self.compile_coalesce(l_expr, r_expr),
+            Expr::CallExpr(..)
+            | Expr::OptionalCallExpr(..)
+            | Expr::MethodCallExpr(..)
+            | Expr::IndexExpr(..) => self.compile_chain(expr),
+            Expr::LiteralExpr(_) => walk_expr(self, expr),
+            Expr::RangeExpr(_, _, inclusive, step) => {
+                walk_expr(self, expr);
+                self.emit(Opcode::BuildRange(*inclusive, step.is_some()));
+            }
+            Expr::TupleExpr(exprs) => {
+                walk_expr(self, expr);
+                self.emit(Opcode::BuildTuple(exprs.len()));
+            }
+        }
     }
-    fn compile_literal(&mut self, literal: &Literal) {
+
+    fn visit_literal(&mut self, literal: &Literal) {
         match literal {
             Literal::BoolLiteral(val) => {
                 self.emit(Opcode::Push(Value::Boolean(*val)));
@@ -200,9 +953,9 @@ impl Compiler {
             Literal::StringLiteral(val) => {
                 self.emit(Opcode::Push(Value::String(val.to_string())));
             }
+            Literal::NilLiteral => {
+                self.emit(Opcode::Push(Value::None));
+            }
         }
     }
-    fn emit(&mut self, code: Opcode) {
-        self.codes.push(code)
-    }
 }
@@ -1,5 +1,11 @@
+pub mod checker;
 pub mod compiler;
+pub mod expander;
+pub mod formatter;
+pub mod json;
 pub mod lexer;
+pub mod optimizer;
 pub mod parser;
+pub mod resolver;
 mod test;
 pub mod vm;
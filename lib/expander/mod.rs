@@ -0,0 +1,477 @@
+use crate::lexer::token::Span;
+use crate::parser::ast::fold::{fold_expr, fold_param, fold_pattern, fold_stmt};
+use crate::parser::ast::visit::{walk_program, Visitor};
+use crate::parser::ast::{Arg, Expr, Fold, Ident, Literal, Param, Pattern, Program, Stmt};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug)]
+pub struct ExpandError {
+    pub message: String,
+    pub span: Span,
+    pub name: String,
+    pub definition: Span,
+}
+
+impl Display for ExpandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} (macro '{}' defined at {})",
+            self.span, self.message, self.name, self.definition
+        )
+    }
+}
+
+#[derive(Clone)]
+struct Macro {
+    name: Ident,
+    params: Vec<Ident>,
+    body: Vec<Stmt>,
+}
+
+pub struct Expander {
+    pub errors: Vec<ExpandError>,
+    macros: HashMap<String, Macro>,
+    taken: HashSet<String>,
+    locals: Vec<HashSet<String>>,
+    depth: usize,
+}
+
+impl Default for Expander {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Expander {
+    pub fn new() -> Self {
+        Self {
+            errors: vec![],
+            macros: HashMap::new(),
+            taken: HashSet::new(),
+            locals: vec![],
+            depth: 0,
+        }
+    }
+
+    pub fn expand(&mut self, program: Program) -> Program {
+        let mut names = Names(HashSet::new());
+        walk_program(&mut names, &program);
+        self.taken.extend(names.0);
+        let mut stmts = vec![];
+        for stmt in program {
+            match stmt {
+                Stmt::MacroStmt(name, params, body) => self.define(name, params, *body),
+                stmt => stmts.push(stmt),
+            }
+        }
+        self.fold_program(stmts)
+    }
+
+    fn define(&mut self, name: Ident, params: Vec<Ident>, body: Stmt) {
+        let body = match body {
+            Stmt::BlockStmt(stmts) => stmts,
+            stmt => vec![stmt],
+        };
+        if let Some(previous) = self.macros.get(&name.0) {
+            let message = format!("Macro '{}' is already defined", name.0);
+            let definition = previous.name.1;
+            self.error(message, name.1, &name.0, definition);
+            return;
+        }
+        self.macros
+            .insert(name.0.clone(), Macro { name, params, body });
+    }
+
+    fn error(&mut self, message: String, span: Span, name: &str, definition: Span) {
+        self.errors.push(ExpandError {
+            message,
+            span,
+            name: name.to_string(),
+            definition,
+        });
+    }
+
+    fn lookup(&self, callee: &Expr) -> Option<Macro> {
+        match callee {
+            Expr::IdentExpr(ident) => self.macros.get(&ident.0).cloned(),
+            _ => None,
+        }
+    }
+
+    fn instantiate(&mut self, call: &Ident, definition: &Macro, args: Vec<Arg>) -> Option<Program> {
+        let fail = |expander: &mut Self, message: String| {
+            expander.error(message, call.1, &definition.name.0, definition.name.1);
+            None
+        };
+        if self.depth >= MAX_DEPTH {
+            let message = format!(
+                "Macro expansion of '{}' exceeded the maximum depth of {}",
+                call.0, MAX_DEPTH
+            );
+            return fail(self, message);
+        }
+        if args.len() != definition.params.len() {
+            let message = format!(
+                "Macro '{}' expects {} arguments, found {}",
+                call.0,
+                definition.params.len(),
+                args.len()
+            );
+            return fail(self, message);
+        }
+        let mut params = HashMap::new();
+        for (param, arg) in definition.params.iter().zip(args) {
+            match arg {
+                Arg::Positional(expr) => params.insert(param.0.clone(), expr),
+                Arg::Named(name, _) => {
+                    let message = format!(
+                        "Macro '{}' does not take named argument '{}'",
+                        call.0, name.0
+                    );
+                    return fail(self, message);
+                }
+            };
+        }
+        let mut template = Template {
+            params,
+            scopes: vec![],
+            taken: &mut self.taken,
+            free: vec![],
+            errors: vec![],
+        };
+        let body = template.fold_program(definition.body.clone());
+        let free = std::mem::take(&mut template.free);
+        for ident in free {
+            let shadowed = self.locals.iter().any(|scope| scope.contains(&ident.0));
+            if shadowed && !self.macros.contains_key(&ident.0) {
+                template.errors.push(format!(
+                    "Macro '{}' refers to '{}', which is shadowed at the call site",
+                    call.0, ident.0
+                ));
+            }
+        }
+        if !template.errors.is_empty() {
+            for message in template.errors {
+                self.error(message, call.1, &definition.name.0, definition.name.1);
+            }
+            return None;
+        }
+        self.depth += 1;
+        let body = self.fold_program(body);
+        self.depth -= 1;
+        Some(body)
+    }
+}
+
+impl Fold for Expander {
+    fn fold_program(&mut self, program: Program) -> Program {
+        let mut stmts = vec![];
+        for stmt in program {
+            match stmt {
+                Stmt::ExprStmt(Expr::CallExpr(callee, args)) if self.lookup(&callee).is_some() => {
+                    let definition = self.lookup(&callee).unwrap();
+                    let call = match *callee {
+                        Expr::IdentExpr(ident) => ident,
+                        _ => unreachable!("macro calls are plain names"),
+                    };
+                    stmts.extend(
+                        self.instantiate(&call, &definition, args)
+                            .unwrap_or_default(),
+                    );
+                }
+                Stmt::MacroStmt(name, ..) => {
+                    let message = "Macros can only be defined at the top level".to_string();
+                    self.error(message, name.1, &name.0, name.1);
+                }
+                stmt => stmts.push(self.fold_stmt(stmt)),
+            }
+        }
+        stmts
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::BlockStmt(stmts) => {
+                self.locals
+                    .push(hoisted(&stmts).map(|ident| ident.0.clone()).collect());
+                let stmts = self.fold_program(stmts);
+                self.locals.pop();
+                Stmt::BlockStmt(stmts)
+            }
+            Stmt::LetStmt(pattern, ty, init) => {
+                let init = init.map(|init| self.fold_expr(init));
+                if let Some(scope) = self.locals.last_mut() {
+                    collect_pattern(&pattern, &mut |ident: &Ident| {
+                        scope.insert(ident.0.clone());
+                    });
+                }
+                Stmt::LetStmt(pattern, ty, init)
+            }
+            Stmt::FnStmt(_, ref params, ..) => {
+                let mut scope = HashSet::new();
+                for param in params {
+                    collect_param(param, &mut |ident: &Ident| {
+                        scope.insert(ident.0.clone());
+                    });
+                }
+                self.locals.push(scope);
+                let stmt = fold_stmt(self, stmt);
+                self.locals.pop();
+                stmt
+            }
+            Stmt::ForStmt(pattern, iterable, body) => {
+                let iterable = self.fold_expr(iterable);
+                let mut scope = HashSet::new();
+                collect_pattern(&pattern, &mut |ident: &Ident| {
+                    scope.insert(ident.0.clone());
+                });
+                self.locals.push(scope);
+                let body = self.fold_stmt(*body);
+                self.locals.pop();
+                Stmt::ForStmt(pattern, iterable, Box::from(body))
+            }
+            Stmt::TryStmt(body, handler, finally) => {
+                let body = self.fold_stmt(*body);
+                let handler = handler.map(|(ident, handler)| {
+                    self.locals.push(HashSet::from([ident.0.clone()]));
+                    let handler = self.fold_stmt(*handler);
+                    self.locals.pop();
+                    (ident, Box::from(handler))
+                });
+                let finally = finally.map(|finally| Box::from(self.fold_stmt(*finally)));
+                Stmt::TryStmt(Box::from(body), handler, finally)
+            }
+            stmt => fold_stmt(self, stmt),
+        }
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::CallExpr(callee, args) if self.lookup(&callee).is_some() => {
+                let definition = self.lookup(&callee).unwrap();
+                let call = match *callee {
+                    Expr::IdentExpr(ident) => ident,
+                    _ => unreachable!("macro calls are plain names"),
+                };
+                match self.instantiate(&call, &definition, args) {
+                    Some(mut body) if matches!(body.as_slice(), [Stmt::ExprStmt(_)]) => {
+                        match body.pop() {
+                            Some(Stmt::ExprStmt(expr)) => expr,
+                            _ => unreachable!(),
+                        }
+                    }
+                    Some(_) => {
+                        let message = format!(
+                            "Macro '{}' expands to statements and cannot be used as an expression",
+                            call.0
+                        );
+                        self.error(message, call.1, &definition.name.0, definition.name.1);
+                        Expr::LiteralExpr(Literal::NilLiteral)
+                    }
+                    None => Expr::LiteralExpr(Literal::NilLiteral),
+                }
+            }
+            expr => fold_expr(self, expr),
+        }
+    }
+}
+
+fn hoisted(stmts: &[Stmt]) -> impl Iterator<Item = &Ident> {
+    stmts.iter().filter_map(|stmt| match stmt {
+        Stmt::FnStmt(ident, ..) => Some(ident),
+        _ => None,
+    })
+}
+
+fn collect_param(param: &Param, collect: &mut impl FnMut(&Ident)) {
+    match &param.pattern {
+        Some(pattern) => collect_pattern(pattern, collect),
+        None => collect(&param.name),
+    }
+}
+
+fn collect_pattern(pattern: &Pattern, collect: &mut impl FnMut(&Ident)) {
+    match pattern {
+        Pattern::Ident(ident) => collect(ident),
+        Pattern::Tuple(patterns) => {
+            for pattern in patterns {
+                collect_pattern(pattern, collect);
+            }
+        }
+    }
+}
+
+struct Names(HashSet<String>);
+
+impl Visitor for Names {
+    fn visit_ident(&mut self, ident: &Ident) {
+        self.0.insert(ident.0.clone());
+    }
+}
+
+struct Template<'a> {
+    params: HashMap<String, Expr>,
+    scopes: Vec<HashMap<String, String>>,
+    taken: &'a mut HashSet<String>,
+    free: Vec<Ident>,
+    errors: Vec<String>,
+}
+
+impl Template<'_> {
+    fn bind(&mut self, ident: &Ident) {
+        if self.params.contains_key(&ident.0) {
+            return;
+        }
+        let name = (1..)
+            .map(|n| format!("{}_{}", ident.0, n))
+            .find(|name| !self.taken.contains(name))
+            .unwrap();
+        self.taken.insert(name.clone());
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(ident.0.clone(), name);
+        }
+    }
+
+    fn bind_pattern(&mut self, pattern: &Pattern) {
+        let mut idents = vec![];
+        collect_pattern(pattern, &mut |ident: &Ident| idents.push(ident.clone()));
+        for ident in idents.iter() {
+            self.bind(ident);
+        }
+    }
+
+    fn rename(&mut self, ident: Ident) -> Ident {
+        if let Some(arg) = self.params.get(&ident.0) {
+            return match arg {
+                Expr::IdentExpr(arg) => arg.clone(),
+                _ => {
+                    self.errors.push(format!(
+                        "Macro argument for '{}' must be a name because it is bound in the macro body",
+                        ident.0
+                    ));
+                    ident
+                }
+            };
+        }
+        match self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&ident.0))
+        {
+            Some(name) => Ident(name.clone(), ident.1),
+            None => {
+                self.free.push(ident.clone());
+                ident
+            }
+        }
+    }
+
+    fn scoped<T>(&mut self, fold: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(HashMap::new());
+        let result = fold(self);
+        self.scopes.pop();
+        result
+    }
+}
+
+impl Fold for Template<'_> {
+    fn fold_program(&mut self, program: Program) -> Program {
+        self.scoped(|template| {
+            let idents: Vec<Ident> = hoisted(&program).cloned().collect();
+            for ident in idents.iter() {
+                template.bind(ident);
+            }
+            program
+                .into_iter()
+                .map(|stmt| template.fold_stmt(stmt))
+                .collect()
+        })
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::LetStmt(pattern, ty, init) => {
+                let init = init.map(|init| self.fold_expr(init));
+                self.bind_pattern(&pattern);
+                Stmt::LetStmt(self.fold_pattern(pattern), ty, init)
+            }
+            Stmt::FnStmt(ident, params, ret, body, decorators, contracts) => {
+                let ident = self.rename(ident);
+                let decorators = decorators
+                    .into_iter()
+                    .map(|decorator| self.fold_decorator(decorator))
+                    .collect();
+                self.scoped(|template| {
+                    for param in params.iter() {
+                        match &param.pattern {
+                            Some(pattern) => template.bind_pattern(pattern),
+                            None => template.bind(&param.name),
+                        }
+                    }
+                    let params = params
+                        .into_iter()
+                        .map(|param| template.fold_param(param))
+                        .collect();
+                    let body = template.fold_stmt(*body);
+                    let contracts = contracts
+                        .into_iter()
+                        .map(|contract| template.fold_contract(contract))
+                        .collect();
+                    Stmt::FnStmt(ident, params, ret, Box::from(body), decorators, contracts)
+                })
+            }
+            Stmt::ForStmt(pattern, iterable, body) => {
+                let iterable = self.fold_expr(iterable);
+                self.scoped(|template| {
+                    template.bind_pattern(&pattern);
+                    let pattern = template.fold_pattern(pattern);
+                    let body = template.fold_stmt(*body);
+                    Stmt::ForStmt(pattern, iterable, Box::from(body))
+                })
+            }
+            Stmt::TryStmt(body, handler, finally) => {
+                let body = self.fold_stmt(*body);
+                let handler = handler.map(|(ident, handler)| {
+                    self.scoped(|template| {
+                        template.bind(&ident);
+                        let ident = template.rename(ident);
+                        (ident, Box::from(template.fold_stmt(*handler)))
+                    })
+                });
+                let finally = finally.map(|finally| Box::from(self.fold_stmt(*finally)));
+                Stmt::TryStmt(Box::from(body), handler, finally)
+            }
+            stmt => fold_stmt(self, stmt),
+        }
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::IdentExpr(ident) => match self.params.get(&ident.0) {
+                Some(arg) => arg.clone(),
+                None => Expr::IdentExpr(self.rename(ident)),
+            },
+            expr => fold_expr(self, expr),
+        }
+    }
+
+    fn fold_pattern(&mut self, pattern: Pattern) -> Pattern {
+        match pattern {
+            Pattern::Ident(ident) => Pattern::Ident(self.rename(ident)),
+            pattern => fold_pattern(self, pattern),
+        }
+    }
+
+    fn fold_param(&mut self, param: Param) -> Param {
+        let name = match param.pattern {
+            Some(_) => param.name,
+            None => self.rename(param.name),
+        };
+        fold_param(self, Param { name, ..param })
+    }
+}
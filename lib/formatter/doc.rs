@@ -0,0 +1,147 @@
+pub enum Doc {
+    Text(String),
+    Line,
+    SoftLine,
+    HardLine,
+    Comment(String, bool),
+    Indent(Box<Doc>),
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+impl Doc {
+    pub fn text(text: &str) -> Doc {
+        Doc::Text(text.to_string())
+    }
+
+    pub fn indent(doc: Doc) -> Doc {
+        Doc::Indent(Box::from(doc))
+    }
+
+    pub fn group(doc: Doc) -> Doc {
+        Doc::Group(Box::from(doc))
+    }
+}
+
+const INDENT: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+pub fn print(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    let mut pending_indent = None;
+    let mut broken: Option<Option<usize>> = None;
+    let mut stack = vec![(0, Mode::Break, doc)];
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) => {
+                let mut text = text.as_str();
+                if let Some(resume) = broken.take() {
+                    out.push('\n');
+                    pending_indent = Some(resume.unwrap_or(indent + INDENT));
+                    text = text.trim_start();
+                }
+                if let Some(indent) = pending_indent.take() {
+                    out.push_str(&" ".repeat(indent));
+                    column = indent;
+                }
+                out.push_str(text);
+                column += text.chars().count();
+            }
+            Doc::Comment(text, own_line) => {
+                let start = pending_indent.is_some() || out.is_empty() || out.ends_with('\n');
+                if let Some(resume) = broken.take() {
+                    out.push('\n');
+                    pending_indent = Some(resume.unwrap_or(indent + INDENT));
+                } else if *own_line && !start {
+                    out.truncate(out.trim_end_matches(' ').len());
+                    out.push('\n');
+                    pending_indent = Some(indent + INDENT);
+                }
+                broken = Some(None);
+                match pending_indent.take() {
+                    Some(indent) => {
+                        out.push_str(&" ".repeat(indent));
+                        column = indent;
+                        broken = Some(Some(indent));
+                    }
+                    None => {
+                        let trimmed = out.trim_end_matches(' ').len();
+                        column -= out.len() - trimmed;
+                        out.truncate(trimmed);
+                        // After a closing brace, an `else` or `catch` lines up with the brace.
+                        let line = &out[out.rfind('\n').map_or(0, |i| i + 1)..];
+                        if line.trim_start() == "}" {
+                            broken = Some(Some(line.len() - 1));
+                        }
+                        out.push(' ');
+                        column += 1;
+                    }
+                }
+                out.push_str(text);
+                column += text.chars().count();
+            }
+            Doc::Line if mode == Mode::Flat && broken.is_none() => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::SoftLine if mode == Mode::Flat && broken.is_none() => (),
+            Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                out.push('\n');
+                column = 0;
+                pending_indent = Some(indent);
+                broken = None;
+            }
+            Doc::Indent(doc) => stack.push((indent + INDENT, mode, doc)),
+            Doc::Group(doc) => {
+                let remaining = width as isize - column.max(pending_indent.unwrap_or(0)) as isize;
+                let mode = match mode == Mode::Flat || fits(remaining, doc, &stack) {
+                    true => Mode::Flat,
+                    false => Mode::Break,
+                };
+                stack.push((indent, mode, doc));
+            }
+            Doc::Concat(docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent, mode, doc));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn fits(mut remaining: isize, doc: &Doc, rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut stack = vec![(Mode::Flat, doc)];
+    let mut rest = rest.iter().rev();
+    loop {
+        if remaining < 0 {
+            return false;
+        }
+        let (mode, doc) = match stack.pop() {
+            Some(entry) => entry,
+            None => match rest.next() {
+                Some((_, mode, doc)) => (*mode, *doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(text) => remaining -= text.chars().count() as isize,
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => return true,
+            Doc::Line => remaining -= 1,
+            Doc::SoftLine => (),
+            Doc::HardLine | Doc::Comment(..) => return mode == Mode::Break,
+            Doc::Indent(doc) | Doc::Group(doc) => stack.push((mode, doc)),
+            Doc::Concat(docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((mode, doc));
+                }
+            }
+        }
+    }
+}
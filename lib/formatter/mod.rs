@@ -0,0 +1,644 @@
+use crate::lexer::token::TokenKind;
+use crate::lexer::Lexer;
+use crate::parser::ast::Program;
+use crate::parser::cst::typed::*;
+use crate::parser::cst::{CstParser, Element, Node, NodeKind};
+use crate::parser::ParseError;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+mod doc;
+
+use doc::Doc;
+
+const WIDTH: usize = 80;
+
+pub struct Formatter {
+    width: usize,
+    pub errors: Vec<ParseError>,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Formatter::with_width(WIDTH)
+    }
+
+    pub fn with_width(width: usize) -> Self {
+        Formatter {
+            width,
+            errors: vec![],
+        }
+    }
+
+    pub fn format(&mut self, source: &str) -> String {
+        let lexemes = Lexer::scan_lossless(source);
+        let mut parser = CstParser::new(&lexemes);
+        let root = parser.parse();
+        if !parser.errors.is_empty() {
+            self.errors.append(&mut parser.errors);
+            return source.to_string();
+        }
+        let items = stmts(&root, false);
+        if items.is_empty() {
+            return String::new();
+        }
+        let mut out = doc::print(&Doc::Concat(items), self.width);
+        out.push('\n');
+        out
+    }
+
+    pub fn format_program(&mut self, program: &Program) -> String {
+        let source: Vec<String> = program.iter().map(|stmt| stmt.to_string()).collect();
+        self.format(&source.join("\n"))
+    }
+}
+
+struct Comment {
+    anchor: usize,
+    own_line: bool,
+    text: String,
+}
+
+#[derive(Default)]
+struct Trivia {
+    tokens: usize,
+    newline: bool,
+    comments: Vec<Comment>,
+}
+
+impl Trivia {
+    fn collect(&mut self, node: &Node, attach: bool) {
+        for child in node.children.iter() {
+            match child {
+                Element::Token(token) => match token.kind {
+                    TokenKind::Whitespace => self.newline |= token.text.contains('\n'),
+                    TokenKind::Comment if attach => self.comments.push(Comment {
+                        anchor: self.tokens,
+                        own_line: self.newline || self.tokens == 0,
+                        text: token.text.trim_end().to_string(),
+                    }),
+                    TokenKind::Comment => (),
+                    _ => {
+                        self.tokens += 1;
+                        self.newline = false;
+                    }
+                },
+                Element::Node(node) => self.collect(
+                    node,
+                    attach && !matches!(node.kind, NodeKind::Block | NodeKind::TypeRef),
+                ),
+            }
+        }
+    }
+}
+
+fn weave(doc: Doc, seen: &mut usize, comments: &mut Peekable<IntoIter<Comment>>) -> Doc {
+    match doc {
+        Doc::Text(text) => {
+            let tokens = Lexer::scan(&text).len() - 1;
+            let mut docs = vec![];
+            while let Some(comment) = comments
+                .next_if(|comment| comment.own_line && comment.anchor <= *seen && tokens > 0)
+            {
+                docs.push(Doc::Comment(comment.text, true));
+            }
+            docs.push(Doc::Text(text));
+            *seen += tokens;
+            while let Some(comment) =
+                comments.next_if(|comment| !comment.own_line && comment.anchor <= *seen)
+            {
+                docs.push(Doc::Comment(comment.text, false));
+            }
+            Doc::Concat(docs)
+        }
+        Doc::Indent(doc) => Doc::indent(weave(*doc, seen, comments)),
+        Doc::Group(doc) => Doc::group(weave(*doc, seen, comments)),
+        Doc::Concat(docs) => Doc::Concat(
+            docs.into_iter()
+                .map(|doc| weave(doc, seen, comments))
+                .collect(),
+        ),
+        doc => doc,
+    }
+}
+
+fn stmts(node: &Node, opened: bool) -> Vec<Doc> {
+    let mut docs = vec![];
+    let mut newlines = 0;
+    let mut previous = opened;
+    let mut first = true;
+    for child in node.children.iter() {
+        let item = match child {
+            Element::Token(token) => match token.kind {
+                TokenKind::Whitespace => {
+                    newlines += token.text.matches('\n').count();
+                    continue;
+                }
+                TokenKind::Comment if previous && newlines == 0 => {
+                    docs.push(Doc::text(" "));
+                    docs.push(Doc::text(token.text.trim_end()));
+                    continue;
+                }
+                TokenKind::Comment => Doc::text(token.text.trim_end()),
+                _ => continue,
+            },
+            Element::Node(node) => stmt(node),
+        };
+        if previous {
+            docs.push(Doc::HardLine);
+            if newlines > 1 && !first {
+                docs.push(Doc::HardLine);
+            }
+        }
+        docs.push(item);
+        previous = true;
+        first = false;
+        newlines = 0;
+    }
+    docs
+}
+
+fn block(block: Block) -> Doc {
+    let items = stmts(block.syntax(), true);
+    if items.is_empty() {
+        return Doc::text("{}");
+    }
+    Doc::Concat(vec![
+        Doc::text("{"),
+        Doc::indent(Doc::Concat(items)),
+        Doc::HardLine,
+        Doc::text("}"),
+    ])
+}
+
+fn stmt(node: &Node) -> Doc {
+    let stmt = match AnyStmt::cast(node) {
+        Some(stmt) => stmt,
+        None => return Doc::Text(node.text()),
+    };
+    let mut trivia = Trivia::default();
+    trivia.collect(node, true);
+    if trivia.comments.is_empty() {
+        return any_stmt(stmt);
+    }
+    let mut comments = trivia.comments.into_iter().peekable();
+    let mut docs = vec![];
+    while let Some(comment) = comments.next_if(|comment| comment.anchor == 0) {
+        docs.push(Doc::Comment(comment.text, comment.own_line));
+    }
+    docs.push(weave(any_stmt(stmt), &mut 0, &mut comments));
+    docs.extend(comments.map(|comment| Doc::Comment(comment.text, comment.own_line)));
+    Doc::Concat(docs)
+}
+
+fn any_stmt(stmt: AnyStmt) -> Doc {
+    let mut docs = vec![];
+    match stmt {
+        AnyStmt::Let(stmt) => {
+            docs.push(Doc::text("let "));
+            docs.extend(stmt.pattern().map(pattern));
+            if let Some(ty) = stmt.ty() {
+                docs.push(Doc::text(": "));
+                docs.push(Doc::Text(ty.syntax().text()));
+            }
+            if let Some(init) = stmt.init() {
+                docs.push(Doc::text(" = "));
+                docs.push(expr(init));
+            }
+            docs.push(Doc::text(";"));
+        }
+        AnyStmt::Return(stmt) => keyword_stmt(&mut docs, "return", stmt.expr()),
+        AnyStmt::Expr(stmt) => {
+            docs.extend(stmt.expr().map(expr));
+            docs.push(Doc::text(";"));
+        }
+        AnyStmt::Block(stmt) => docs.push(block(stmt)),
+        AnyStmt::If(stmt) => if_stmt(&mut docs, stmt),
+        AnyStmt::While(stmt) => {
+            docs.push(Doc::text("while "));
+            docs.extend(stmt.condition().map(expr));
+            docs.push(Doc::text(" "));
+            docs.extend(stmt.body().map(block));
+        }
+        AnyStmt::For(stmt) => {
+            docs.push(Doc::text("for "));
+            docs.extend(stmt.pattern().map(pattern));
+            docs.push(Doc::text(" in "));
+            docs.extend(stmt.iterable().map(expr));
+            docs.push(Doc::text(" "));
+            docs.extend(stmt.body().map(block));
+        }
+        AnyStmt::Fn(stmt) => fn_stmt(&mut docs, stmt),
+        AnyStmt::Try(stmt) => {
+            docs.push(Doc::text("try "));
+            docs.extend(stmt.body().map(block));
+            if let Some(clause) = stmt.catch_clause() {
+                docs.push(Doc::Text(format!(" catch ({}) ", clause.name().text)));
+                docs.extend(clause.body().map(block));
+            }
+            if let Some(clause) = stmt.finally_clause() {
+                docs.push(Doc::text(" finally "));
+                docs.extend(clause.body().map(block));
+            }
+        }
+        AnyStmt::Throw(stmt) => keyword_stmt(&mut docs, "throw", stmt.expr()),
+        AnyStmt::Yield(stmt) => keyword_stmt(&mut docs, "yield", stmt.expr()),
+        AnyStmt::Defer(stmt) => keyword_stmt(&mut docs, "defer", stmt.expr()),
+        AnyStmt::Macro(stmt) => {
+            docs.extend(
+                stmt.name()
+                    .map(|name| Doc::Text(format!("macro {}", name.text))),
+            );
+            let params = stmt.params().map(|param| Doc::Text(param.text.clone()));
+            docs.push(list(params.collect()));
+            docs.push(Doc::text(" "));
+            docs.extend(stmt.body().map(block));
+        }
+        AnyStmt::Error(stmt) => docs.push(Doc::Text(stmt.syntax().text())),
+    }
+    Doc::Concat(docs)
+}
+
+fn keyword_stmt(docs: &mut Vec<Doc>, keyword: &str, value: Option<AnyExpr>) {
+    docs.push(Doc::text(keyword));
+    if let Some(value) = value {
+        docs.push(Doc::text(" "));
+        docs.push(expr(value));
+    }
+    docs.push(Doc::text(";"));
+}
+
+fn if_stmt(docs: &mut Vec<Doc>, stmt: IfStmt) {
+    docs.push(Doc::text("if "));
+    docs.extend(stmt.condition().map(expr));
+    docs.push(Doc::text(" "));
+    docs.extend(stmt.then_branch().map(block));
+    match stmt.else_branch() {
+        Some(AnyStmt::If(branch)) => {
+            docs.push(Doc::text(" else "));
+            if_stmt(docs, branch);
+        }
+        Some(AnyStmt::Block(branch)) => {
+            docs.push(Doc::text(" else "));
+            docs.push(block(branch));
+        }
+        _ => (),
+    }
+}
+
+fn fn_stmt(docs: &mut Vec<Doc>, stmt: FnStmt) {
+    for decorator in stmt.decorators() {
+        docs.push(Doc::Text(format!("@{}", decorator.name().text)));
+        docs.extend(decorator.args().map(args));
+        docs.push(Doc::HardLine);
+    }
+    docs.push(Doc::Text(format!("fn {}", stmt.name().text)));
+    if let Some(params) = stmt.params() {
+        docs.push(list(params.params().map(param).collect()));
+    }
+    if let Some(ty) = stmt.ret() {
+        docs.push(Doc::text(" -> "));
+        docs.push(Doc::Text(ty.syntax().text()));
+    }
+    for contract in stmt.contracts() {
+        docs.push(Doc::text(match contract.is_requires() {
+            true => " requires ",
+            false => " ensures ",
+        }));
+        docs.extend(contract.expr().map(expr));
+    }
+    docs.push(Doc::text(" "));
+    docs.extend(stmt.body().map(block));
+}
+
+fn param(param: Param) -> Doc {
+    let mut docs = vec![];
+    if param.variadic() {
+        docs.push(Doc::text("..."));
+    }
+    match param.name() {
+        Some(name) => docs.push(Doc::Text(name.text.clone())),
+        None => docs.extend(param.pattern().map(pattern)),
+    }
+    if let Some(ty) = param.ty() {
+        docs.push(Doc::text(": "));
+        docs.push(Doc::Text(ty.syntax().text()));
+    }
+    if let Some(default) = param.default() {
+        docs.push(Doc::text(" = "));
+        docs.push(expr(default));
+    }
+    Doc::Concat(docs)
+}
+
+fn list(items: Vec<Doc>) -> Doc {
+    if items.is_empty() {
+        return Doc::text("()");
+    }
+    let mut inner = vec![Doc::SoftLine];
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 {
+            inner.push(Doc::text(","));
+            inner.push(Doc::Line);
+        }
+        inner.push(item);
+    }
+    Doc::group(Doc::Concat(vec![
+        Doc::text("("),
+        Doc::indent(Doc::Concat(inner)),
+        Doc::SoftLine,
+        Doc::text(")"),
+    ]))
+}
+
+fn args(args: ArgList) -> Doc {
+    list(
+        args.args()
+            .map(|arg| match arg {
+                AnyArg::Positional(arg) => Doc::Concat(arg.expr().map(expr).into_iter().collect()),
+                AnyArg::Named(arg) => {
+                    let mut docs = vec![];
+                    if let Some(name) = arg.name() {
+                        docs.push(Doc::Text(format!("{}: ", name.text)));
+                    }
+                    docs.extend(arg.value().map(expr));
+                    Doc::Concat(docs)
+                }
+            })
+            .collect(),
+    )
+}
+
+fn tuple(items: Vec<Doc>) -> Doc {
+    let single = items.len() == 1;
+    let mut docs = vec![Doc::text("(")];
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 {
+            docs.push(Doc::text(", "));
+        }
+        docs.push(item);
+    }
+    if single {
+        docs.push(Doc::text(","));
+    }
+    docs.push(Doc::text(")"));
+    Doc::Concat(docs)
+}
+
+fn operator(node: &Node) -> String {
+    node.tokens()
+        .next()
+        .map(|token| token.text.clone())
+        .unwrap_or_default()
+}
+
+fn expr(expr: AnyExpr) -> Doc {
+    let mut docs = vec![];
+    match expr {
+        AnyExpr::Name(expr) => docs.push(Doc::Text(expr.name().text.clone())),
+        AnyExpr::Literal(expr) => docs.push(Doc::Text(expr.token().text.clone())),
+        AnyExpr::Paren(expr) => {
+            docs.push(Doc::text("("));
+            docs.extend(expr.expr().map(self::expr));
+            docs.push(Doc::text(")"));
+        }
+        AnyExpr::Tuple(expr) => docs.push(tuple(expr.exprs().map(self::expr).collect())),
+        AnyExpr::Assign(expr) => {
+            docs.extend(expr.target().map(self::expr));
+            docs.push(Doc::text(" = "));
+            docs.extend(expr.value().map(self::expr));
+        }
+        AnyExpr::Binary(_) | AnyExpr::Coalesce(_) => {
+            let mut operands = expr.syntax().nodes().filter_map(AnyExpr::cast);
+            docs.extend(operands.next().map(self::expr));
+            docs.push(Doc::Text(format!(" {} ", operator(expr.syntax()))));
+            docs.extend(operands.next().map(self::expr));
+        }
+        AnyExpr::Unary(expr) => {
+            docs.push(Doc::Text(operator(expr.syntax())));
+            docs.extend(expr.operand().map(self::expr));
+        }
+        AnyExpr::Range(expr) => {
+            docs.extend(expr.start().map(self::expr));
+            docs.push(Doc::Text(operator(expr.syntax())));
+            docs.extend(expr.end().map(self::expr));
+            if let Some(step) = expr.step() {
+                docs.push(Doc::text(" step "));
+                docs.push(self::expr(step));
+            }
+        }
+        AnyExpr::Call(expr) => {
+            docs.extend(expr.callee().map(self::expr));
+            docs.extend(expr.args().map(args));
+        }
+        AnyExpr::Spawn(expr) => {
+            docs.push(Doc::text("spawn "));
+            docs.extend(
+                expr.syntax()
+                    .nodes()
+                    .find_map(AnyExpr::cast)
+                    .map(self::expr),
+            );
+        }
+        AnyExpr::OptionalCall(expr) => {
+            docs.extend(expr.callee().map(self::expr));
+            docs.push(Doc::text("?."));
+            docs.extend(expr.args().map(args));
+        }
+        AnyExpr::MethodCall(expr) => {
+            docs.extend(expr.receiver().map(self::expr));
+            docs.push(Doc::text(match expr.optional() {
+                true => "?.",
+                false => ".",
+            }));
+            docs.push(Doc::Text(expr.name().text.clone()));
+            docs.extend(expr.args().map(args));
+        }
+        AnyExpr::Index(expr) => {
+            docs.extend(expr.target().map(self::expr));
+            docs.push(Doc::text("["));
+            docs.extend(expr.index().map(self::expr));
+            docs.push(Doc::text("]"));
+        }
+        AnyExpr::Propagate(expr) => {
+            docs.extend(expr.expr().map(self::expr));
+            docs.push(Doc::text("?"));
+        }
+    }
+    Doc::Concat(docs)
+}
+
+fn pattern(pattern: AnyPattern) -> Doc {
+    match pattern {
+        AnyPattern::Name(pattern) => Doc::Text(pattern.name().text.clone()),
+        AnyPattern::Tuple(pattern) => tuple(pattern.patterns().map(self::pattern).collect()),
+        AnyPattern::Paren(pattern) => Doc::Concat(vec![
+            Doc::text("("),
+            Doc::Concat(pattern.pattern().map(self::pattern).into_iter().collect()),
+            Doc::text(")"),
+        ]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format(s: &str) -> String {
+        let mut formatter = Formatter::new();
+        let result = formatter.format(s);
+        assert_eq!(formatter.errors.len(), 0);
+        result
+    }
+
+    #[test]
+    fn test_formatter_1() {
+        let s = "\
+// adds two numbers
+fn add(a,b)   ->int requires a>0 {   // trailing comment
+  return a+b;
+
+
+  // dangling comment
+}
+let  x=add( 1 ,2 );
+if x==3{print(x);}else if x<3 {print(-x);} else {}
+for (i,j) in zip(1..10,1..=20 step 2){print(i,sep:\"\");}
+";
+        let expected = "\
+// adds two numbers
+fn add(a, b) -> int requires a > 0 { // trailing comment
+    return a + b;
+
+    // dangling comment
+}
+let x = add(1, 2);
+if x == 3 {
+    print(x);
+} else if x < 3 {
+    print(-x);
+} else {}
+for (i, j) in zip(1..10, 1..=20 step 2) {
+    print(i, sep: \"\");
+}
+";
+        assert_eq!(format(s), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn test_formatter_2() {
+        let s = "fn f() { let value = some_function(first_argument, second_argument, third_argument(1, 2)); }";
+        let expected = "\
+fn f() {
+    let value = some_function(
+        first_argument,
+        second_argument,
+        third_argument(1, 2)
+    );
+}
+";
+        assert_eq!(format(s), expected);
+        assert_eq!(format(expected), expected);
+        let narrow = "\
+fn f() {
+    let value = some_function(
+        first_argument,
+        second_argument,
+        third_argument(
+            1,
+            2
+        )
+    );
+}
+";
+        assert_eq!(Formatter::with_width(24).format(s), narrow);
+    }
+
+    #[test]
+    fn test_formatter_3() {
+        let s = "let x = f(1, // one\n  2);\n\n\n\nprint(x);";
+        assert_eq!(
+            format(s),
+            "let x = f(\n    1, // one\n    2\n);\n\nprint(x);\n"
+        );
+        let mut formatter = Formatter::new();
+        assert_eq!(formatter.format("let x = ;"), "let x = ;");
+        assert_eq!(formatter.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_formatter_4() {
+        let s = "\
+fn   add(a,  // first
+    b)->int{
+  let total=a+ // why
+     b;
+  return total;}
+let y  =  add(
+    // leading
+    3,4);
+let z =
+  // five
+  5;
+";
+        let expected = "\
+fn add(
+    a, // first
+    b
+) -> int {
+    let total = a + // why
+        b;
+    return total;
+}
+let y = add(
+    // leading
+    3,
+    4
+);
+let z =
+    // five
+    5;
+";
+        assert_eq!(format(s), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn test_formatter_5() {
+        let s = "\
+fn h(x) {
+  if x {f();} // then
+  else {g();}
+  try {f();} // body
+  catch (e) {g();} // handler
+  finally {}
+}
+";
+        let expected = "\
+fn h(x) {
+    if x {
+        f();
+    } // then
+    else {
+        g();
+    }
+    try {
+        f();
+    } // body
+    catch (e) {
+        g();
+    } // handler
+    finally {}
+}
+";
+        assert_eq!(format(s), expected);
+        assert_eq!(format(expected), expected);
+    }
+}
@@ -0,0 +1,489 @@
+use crate::json::{error, FromJson, Json, JsonError, ToJson};
+use crate::lexer::token::Span;
+use crate::parser::ast::{
+    Arg, BinOp, Contract, Decorator, Expr, Ident, Literal, Param, Pattern, Stmt, Type, UnaryOp,
+};
+
+const BLOCK: &[&str] = &["BlockStmt"];
+
+fn node(kind: &str, mut fields: Vec<(&str, Json)>) -> Json {
+    fields.insert(0, ("kind", Json::String(kind.to_string())));
+    Json::object(fields)
+}
+
+fn field<T: FromJson>(json: &Json, key: &str) -> Result<T, JsonError> {
+    T::from_json(json.get(key)?)
+}
+
+// The compiler and checker expect bodies to be blocks, as the parser always produces.
+fn block(json: &Json, key: &str, kinds: &[&str]) -> Result<Box<Stmt>, JsonError> {
+    let body = json.get(key)?;
+    match body.kind()? {
+        kind if kinds.contains(&kind) => Ok(Box::new(Stmt::from_json(body)?)),
+        kind => error(format!(
+            "Expected {} for '{}', found {}",
+            kinds.join(" or "),
+            key,
+            kind
+        )),
+    }
+}
+
+fn optional_block(json: &Json, key: &str, kinds: &[&str]) -> Result<Option<Box<Stmt>>, JsonError> {
+    match json.get(key)? {
+        Json::Null => Ok(None),
+        _ => block(json, key, kinds).map(Some),
+    }
+}
+
+impl ToJson for Ident {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("name", Json::String(self.0.clone())),
+            ("span", self.1.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Ident {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Ident(
+            json.get("name")?.as_str()?.to_string(),
+            field(json, "span")?,
+        ))
+    }
+}
+
+impl ToJson for Type {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+
+impl FromJson for Type {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        let name = json.as_str()?;
+        match Type::from_name(name) {
+            Some(ty) => Ok(ty),
+            None => error(format!("Unknown type '{}'", name)),
+        }
+    }
+}
+
+impl ToJson for UnaryOp {
+    fn to_json(&self) -> Json {
+        let symbol = match self {
+            UnaryOp::UnaryPlus => "+",
+            UnaryOp::UnaryMinus => "-",
+            UnaryOp::Not => "!",
+        };
+        Json::String(symbol.to_string())
+    }
+}
+
+impl FromJson for UnaryOp {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json.as_str()? {
+            "+" => Ok(UnaryOp::UnaryPlus),
+            "-" => Ok(UnaryOp::UnaryMinus),
+            "!" => Ok(UnaryOp::Not),
+            op => error(format!("Unknown unary operator '{}'", op)),
+        }
+    }
+}
+
+impl ToJson for BinOp {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+
+impl FromJson for BinOp {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json.as_str()? {
+            "+" => Ok(BinOp::Plus),
+            "-" => Ok(BinOp::Minus),
+            "/" => Ok(BinOp::Divide),
+            "*" => Ok(BinOp::Multiply),
+            "<" => Ok(BinOp::Less),
+            ">" => Ok(BinOp::Greater),
+            "<=" => Ok(BinOp::LessEqual),
+            ">=" => Ok(BinOp::GreaterEqual),
+            "==" => Ok(BinOp::Equal),
+            "!=" => Ok(BinOp::NotEqual),
+            op => error(format!("Unknown binary operator '{}'", op)),
+        }
+    }
+}
+
+impl ToJson for Literal {
+    fn to_json(&self) -> Json {
+        match self {
+            Literal::IntLiteral(value) => node("IntLiteral", vec![("value", Json::Number(*value))]),
+            Literal::BoolLiteral(value) => node("BoolLiteral", vec![("value", Json::Bool(*value))]),
+            Literal::StringLiteral(value) => node(
+                "StringLiteral",
+                vec![("value", Json::String(value.clone()))],
+            ),
+            Literal::NilLiteral => node("NilLiteral", vec![]),
+        }
+    }
+}
+
+impl FromJson for Literal {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json.kind()? {
+            "IntLiteral" => Ok(Literal::IntLiteral(json.get("value")?.as_i64()?)),
+            "BoolLiteral" => Ok(Literal::BoolLiteral(json.get("value")?.as_bool()?)),
+            "StringLiteral" => Ok(Literal::StringLiteral(
+                json.get("value")?.as_str()?.to_string(),
+            )),
+            "NilLiteral" => Ok(Literal::NilLiteral),
+            kind => error(format!("Unknown literal kind '{}'", kind)),
+        }
+    }
+}
+
+impl ToJson for Pattern {
+    fn to_json(&self) -> Json {
+        match self {
+            Pattern::Ident(ident) => node("IdentPattern", vec![("ident", ident.to_json())]),
+            Pattern::Tuple(patterns) => {
+                node("TuplePattern", vec![("patterns", patterns.to_json())])
+            }
+        }
+    }
+}
+
+impl FromJson for Pattern {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json.kind()? {
+            "IdentPattern" => Ok(Pattern::Ident(field(json, "ident")?)),
+            "TuplePattern" => Ok(Pattern::Tuple(field(json, "patterns")?)),
+            kind => error(format!("Unknown pattern kind '{}'", kind)),
+        }
+    }
+}
+
+impl ToJson for Arg {
+    fn to_json(&self) -> Json {
+        match self {
+            Arg::Positional(expr) => node("PositionalArg", vec![("value", expr.to_json())]),
+            Arg::Named(name, expr) => node(
+                "NamedArg",
+                vec![("name", name.to_json()), ("value", expr.to_json())],
+            ),
+        }
+    }
+}
+
+impl FromJson for Arg {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json.kind()? {
+            "PositionalArg" => Ok(Arg::Positional(field(json, "value")?)),
+            "NamedArg" => Ok(Arg::Named(field(json, "name")?, field(json, "value")?)),
+            kind => error(format!("Unknown argument kind '{}'", kind)),
+        }
+    }
+}
+
+impl ToJson for Param {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("name", self.name.to_json()),
+            ("pattern", self.pattern.to_json()),
+            ("type", self.ty.to_json()),
+            ("default", self.default.to_json()),
+            ("variadic", Json::Bool(self.variadic)),
+        ])
+    }
+}
+
+impl FromJson for Param {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Param {
+            name: field(json, "name")?,
+            pattern: field(json, "pattern")?,
+            ty: field(json, "type")?,
+            default: field(json, "default")?,
+            variadic: json.get("variadic")?.as_bool()?,
+        })
+    }
+}
+
+impl ToJson for Decorator {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("name", self.name.to_json()),
+            ("args", self.args.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Decorator {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Decorator {
+            name: field(json, "name")?,
+            args: field(json, "args")?,
+        })
+    }
+}
+
+impl ToJson for Contract {
+    fn to_json(&self) -> Json {
+        match self {
+            Contract::Requires(expr) => node("Requires", vec![("expr", expr.to_json())]),
+            Contract::Ensures(expr) => node("Ensures", vec![("expr", expr.to_json())]),
+        }
+    }
+}
+
+impl FromJson for Contract {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json.kind()? {
+            "Requires" => Ok(Contract::Requires(field(json, "expr")?)),
+            "Ensures" => Ok(Contract::Ensures(field(json, "expr")?)),
+            kind => error(format!("Unknown contract kind '{}'", kind)),
+        }
+    }
+}
+
+impl ToJson for Expr {
+    fn to_json(&self) -> Json {
+        match self {
+            Expr::IdentExpr(ident) => node("IdentExpr", vec![("ident", ident.to_json())]),
+            Expr::LiteralExpr(literal) => node("LiteralExpr", vec![("literal", literal.to_json())]),
+            Expr::AssignmentExpr(pattern, value) => node(
+                "AssignmentExpr",
+                vec![("target", pattern.to_json()), ("value", value.to_json())],
+            ),
+            Expr::UnaryExpr(op, operand) => node(
+                "UnaryExpr",
+                vec![("op", op.to_json()), ("operand", operand.to_json())],
+            ),
+            Expr::BinExpr(lhs, op, rhs) => node(
+                "BinExpr",
+                vec![
+                    ("lhs", lhs.to_json()),
+                    ("op", op.to_json()),
+                    ("rhs", rhs.to_json()),
+                ],
+            ),
+            Expr::CallExpr(callee, args) => node(
+                "CallExpr",
+                vec![("callee", callee.to_json()), ("args", args.to_json())],
+            ),
+            Expr::PropagateExpr(expr) => node("PropagateExpr", vec![("expr", expr.to_json())]),
+            Expr::SpawnExpr(callee, args) => node(
+                "SpawnExpr",
+                vec![("callee", callee.to_json()), ("args", args.to_json())],
+            ),
+            Expr::CoalesceExpr(lhs, rhs) => node(
+                "CoalesceExpr",
+                vec![("lhs", lhs.to_json()), ("rhs", rhs.to_json())],
+            ),
+            Expr::OptionalCallExpr(callee, args) => node(
+                "OptionalCallExpr",
+                vec![("callee", callee.to_json()), ("args", args.to_json())],
+            ),
+            Expr::MethodCallExpr(receiver, method, args, optional) => node(
+                "MethodCallExpr",
+                vec![
+                    ("receiver", receiver.to_json()),
+                    ("method", method.to_json()),
+                    ("args", args.to_json()),
+                    ("optional", Json::Bool(*optional)),
+                ],
+            ),
+            Expr::TupleExpr(items) => node("TupleExpr", vec![("items", items.to_json())]),
+            Expr::RangeExpr(start, end, inclusive, step) => node(
+                "RangeExpr",
+                vec![
+                    ("start", start.to_json()),
+                    ("end", end.to_json()),
+                    ("inclusive", Json::Bool(*inclusive)),
+                    ("step", step.to_json()),
+                ],
+            ),
+            Expr::IndexExpr(target, index) => node(
+                "IndexExpr",
+                vec![("target", target.to_json()), ("index", index.to_json())],
+            ),
+        }
+    }
+}
+
+impl FromJson for Expr {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        let expr = match json.kind()? {
+            "IdentExpr" => Expr::IdentExpr(field(json, "ident")?),
+            "LiteralExpr" => Expr::LiteralExpr(field(json, "literal")?),
+            "AssignmentExpr" => Expr::AssignmentExpr(field(json, "target")?, field(json, "value")?),
+            "UnaryExpr" => Expr::UnaryExpr(field(json, "op")?, field(json, "operand")?),
+            "BinExpr" => {
+                Expr::BinExpr(field(json, "lhs")?, field(json, "op")?, field(json, "rhs")?)
+            }
+            "CallExpr" => Expr::CallExpr(field(json, "callee")?, field(json, "args")?),
+            "PropagateExpr" => Expr::PropagateExpr(field(json, "expr")?),
+            "SpawnExpr" => Expr::SpawnExpr(field(json, "callee")?, field(json, "args")?),
+            "CoalesceExpr" => Expr::CoalesceExpr(field(json, "lhs")?, field(json, "rhs")?),
+            "OptionalCallExpr" => {
+                Expr::OptionalCallExpr(field(json, "callee")?, field(json, "args")?)
+            }
+            "MethodCallExpr" => Expr::MethodCallExpr(
+                field(json, "receiver")?,
+                field(json, "method")?,
+                field(json, "args")?,
+                json.get("optional")?.as_bool()?,
+            ),
+            "TupleExpr" => Expr::TupleExpr(field(json, "items")?),
+            "RangeExpr" => Expr::RangeExpr(
+                field(json, "start")?,
+                field(json, "end")?,
+                json.get("inclusive")?.as_bool()?,
+                field(json, "step")?,
+            ),
+            "IndexExpr" => Expr::IndexExpr(field(json, "target")?, field(json, "index")?),
+            kind => return error(format!("Unknown expression kind '{}'", kind)),
+        };
+        Ok(expr)
+    }
+}
+
+// Spans are only serialised where the AST keeps them: identifiers, return statements
+// and error statements. Other statements and expressions carry no position.
+impl ToJson for Stmt {
+    fn to_json(&self) -> Json {
+        match self {
+            Stmt::LetStmt(pattern, ty, init) => node(
+                "LetStmt",
+                vec![
+                    ("pattern", pattern.to_json()),
+                    ("type", ty.to_json()),
+                    ("init", init.to_json()),
+                ],
+            ),
+            Stmt::ReturnStmt(value, span) => node(
+                "ReturnStmt",
+                vec![("value", value.to_json()), ("span", span.to_json())],
+            ),
+            Stmt::ExprStmt(expr) => node("ExprStmt", vec![("expr", expr.to_json())]),
+            Stmt::BlockStmt(stmts) => node("BlockStmt", vec![("stmts", stmts.to_json())]),
+            Stmt::IfStmt(condition, then_branch, else_branch) => node(
+                "IfStmt",
+                vec![
+                    ("condition", condition.to_json()),
+                    ("then", then_branch.to_json()),
+                    ("else", else_branch.to_json()),
+                ],
+            ),
+            Stmt::WhileStmt(condition, body) => node(
+                "WhileStmt",
+                vec![("condition", condition.to_json()), ("body", body.to_json())],
+            ),
+            Stmt::ForStmt(pattern, iterable, body) => node(
+                "ForStmt",
+                vec![
+                    ("pattern", pattern.to_json()),
+                    ("iterable", iterable.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            Stmt::FnStmt(name, params, ret, body, decorators, contracts) => node(
+                "FnStmt",
+                vec![
+                    ("name", name.to_json()),
+                    ("params", params.to_json()),
+                    ("return_type", ret.to_json()),
+                    ("body", body.to_json()),
+                    ("decorators", decorators.to_json()),
+                    ("contracts", contracts.to_json()),
+                ],
+            ),
+            Stmt::TryStmt(body, handler, finally) => {
+                let handler = match handler {
+                    Some((name, body)) => {
+                        Json::object(vec![("name", name.to_json()), ("body", body.to_json())])
+                    }
+                    None => Json::Null,
+                };
+                node(
+                    "TryStmt",
+                    vec![
+                        ("body", body.to_json()),
+                        ("catch", handler),
+                        ("finally", finally.to_json()),
+                    ],
+                )
+            }
+            Stmt::ThrowStmt(expr) => node("ThrowStmt", vec![("expr", expr.to_json())]),
+            Stmt::YieldStmt(value) => node("YieldStmt", vec![("value", value.to_json())]),
+            Stmt::DeferStmt(expr) => node("DeferStmt", vec![("expr", expr.to_json())]),
+            Stmt::MacroStmt(name, params, body) => node(
+                "MacroStmt",
+                vec![
+                    ("name", name.to_json()),
+                    ("params", params.to_json()),
+                    ("body", body.to_json()),
+                ],
+            ),
+            Stmt::ErrorStmt(span) => node("ErrorStmt", vec![("span", span.to_json())]),
+        }
+    }
+}
+
+impl FromJson for Stmt {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        let stmt = match json.kind()? {
+            "LetStmt" => Stmt::LetStmt(
+                field(json, "pattern")?,
+                field(json, "type")?,
+                field(json, "init")?,
+            ),
+            "ReturnStmt" => Stmt::ReturnStmt(field(json, "value")?, field(json, "span")?),
+            "ExprStmt" => Stmt::ExprStmt(field(json, "expr")?),
+            "BlockStmt" => Stmt::BlockStmt(field(json, "stmts")?),
+            "IfStmt" => Stmt::IfStmt(
+                field(json, "condition")?,
+                block(json, "then", BLOCK)?,
+                optional_block(json, "else", &["BlockStmt", "IfStmt"])?,
+            ),
+            "WhileStmt" => Stmt::WhileStmt(field(json, "condition")?, block(json, "body", BLOCK)?),
+            "ForStmt" => Stmt::ForStmt(
+                field(json, "pattern")?,
+                field(json, "iterable")?,
+                block(json, "body", BLOCK)?,
+            ),
+            "FnStmt" => Stmt::FnStmt(
+                field(json, "name")?,
+                field(json, "params")?,
+                field(json, "return_type")?,
+                block(json, "body", BLOCK)?,
+                field(json, "decorators")?,
+                field(json, "contracts")?,
+            ),
+            "TryStmt" => {
+                let handler = match json.get("catch")? {
+                    Json::Null => None,
+                    handler => Some((field(handler, "name")?, block(handler, "body", BLOCK)?)),
+                };
+                Stmt::TryStmt(
+                    block(json, "body", BLOCK)?,
+                    handler,
+                    optional_block(json, "finally", BLOCK)?,
+                )
+            }
+            "ThrowStmt" => Stmt::ThrowStmt(field(json, "expr")?),
+            "YieldStmt" => Stmt::YieldStmt(field(json, "value")?),
+            "DeferStmt" => Stmt::DeferStmt(field(json, "expr")?),
+            "MacroStmt" => Stmt::MacroStmt(
+                field(json, "name")?,
+                field(json, "params")?,
+                block(json, "body", BLOCK)?,
+            ),
+            "ErrorStmt" => Stmt::ErrorStmt(field::<Span>(json, "span")?),
+            kind => return error(format!("Unknown statement kind '{}'", kind)),
+        };
+        Ok(stmt)
+    }
+}
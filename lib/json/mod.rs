@@ -0,0 +1,604 @@
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+mod ast;
+mod token;
+
+pub use token::{tokens_from_json, tokens_to_json};
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct JsonError {
+    pub message: String,
+}
+
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+pub trait FromJson: Sized {
+    fn from_json(json: &Json) -> Result<Self, JsonError>;
+}
+
+const MAX_DEPTH: usize = 512;
+
+fn error<T>(message: String) -> Result<T, JsonError> {
+    Err(JsonError { message })
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn parse(input: &str) -> Result<Json, JsonError> {
+        let mut reader = Reader {
+            chars: input.char_indices().peekable(),
+            input,
+            depth: 0,
+        };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        match reader.chars.next() {
+            Some((offset, c)) => error(format!("Unexpected '{}' at offset {}", c, offset)),
+            None => Ok(value),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<&Json, JsonError> {
+        match self {
+            Json::Object(fields) => match fields.iter().find(|(name, _)| name == key) {
+                Some((_, value)) => Ok(value),
+                None => error(format!("Missing field '{}'", key)),
+            },
+            _ => error(format!("Expected an object with field '{}'", key)),
+        }
+    }
+
+    pub fn kind(&self) -> Result<&str, JsonError> {
+        self.get("kind")?.as_str()
+    }
+
+    pub fn as_str(&self) -> Result<&str, JsonError> {
+        match self {
+            Json::String(value) => Ok(value),
+            _ => error(format!("Expected a string, found {}", self)),
+        }
+    }
+
+    pub fn as_i64(&self) -> Result<i64, JsonError> {
+        match self {
+            Json::Number(value) => Ok(*value),
+            _ => error(format!("Expected a number, found {}", self)),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, JsonError> {
+        match self {
+            Json::Bool(value) => Ok(*value),
+            _ => error(format!("Expected a boolean, found {}", self)),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Json], JsonError> {
+        match self {
+            Json::Array(items) => Ok(items),
+            _ => error(format!("Expected an array, found {}", self)),
+        }
+    }
+
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth + 1);
+        match self {
+            Json::Array(items) if !items.is_empty() => {
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(",\n");
+                    }
+                    out.push_str(&indent);
+                    item.write_pretty(out, depth + 1);
+                }
+                out.push('\n');
+                out.push_str(&"  ".repeat(depth));
+                out.push(']');
+            }
+            Json::Object(fields) if !fields.is_empty() => {
+                out.push_str("{\n");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(",\n");
+                    }
+                    out.push_str(&indent);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write_pretty(out, depth + 1);
+                }
+                out.push('\n');
+                out.push_str(&"  ".repeat(depth));
+                out.push('}');
+            }
+            _ => out.push_str(&self.to_string()),
+        }
+    }
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(value) => {
+                let mut out = String::new();
+                write_string(&mut out, value);
+                write!(f, "{}", out)
+            }
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", Json::String(key.clone()), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    input: &'a str,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some((_, ' ' | '\n' | '\r' | '\t')) = self.chars.peek() {
+            self.chars.next();
+        }
+    }
+
+    fn offset(&mut self) -> usize {
+        match self.chars.peek() {
+            Some((offset, _)) => *offset,
+            None => self.input.len(),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        let offset = self.offset();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((_, c)) => error(format!(
+                "Expected '{}' but found '{}' at offset {}",
+                expected, c, offset
+            )),
+            None => error(format!("Expected '{}' but found end of input", expected)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        let offset = self.offset();
+        match self.chars.peek() {
+            Some((_, '{' | '[')) if self.depth == MAX_DEPTH => error(format!(
+                "Nesting deeper than {} levels at offset {}",
+                MAX_DEPTH, offset
+            )),
+            Some((_, '{')) => self.nested(Self::object),
+            Some((_, '[')) => self.nested(Self::array),
+            Some((_, '"')) => Ok(Json::String(self.string()?)),
+            Some((_, '-' | '0'..='9')) => self.number(),
+            Some((_, 'a'..='z')) => {
+                let word = self.word();
+                match word {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => error(format!("Unknown literal '{}' at offset {}", word, offset)),
+                }
+            }
+            Some((_, c)) => error(format!("Unexpected '{}' at offset {}", c, offset)),
+            None => error("Unexpected end of input".to_string()),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Json, JsonError>,
+    ) -> Result<Json, JsonError> {
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn word(&mut self) -> &'a str {
+        let start = self.offset();
+        while let Some((_, 'a'..='z')) = self.chars.peek() {
+            self.chars.next();
+        }
+        &self.input[start..self.offset()]
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.offset();
+        if let Some((_, '-')) = self.chars.peek() {
+            self.chars.next();
+        }
+        while let Some((_, '0'..='9')) = self.chars.peek() {
+            self.chars.next();
+        }
+        if let Some((offset, '.' | 'e' | 'E')) = self.chars.peek() {
+            return error(format!(
+                "Only integer numbers are supported (at offset {})",
+                offset
+            ));
+        }
+        let text = &self.input[start..self.offset()];
+        match text.parse() {
+            Ok(value) => Ok(Json::Number(value)),
+            Err(_) => error(format!("Invalid number '{}' at offset {}", text, start)),
+        }
+    }
+
+    fn hex(&mut self) -> Result<u32, JsonError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            match self.chars.next().and_then(|(_, c)| c.to_digit(16)) {
+                Some(digit) => value = value * 16 + digit,
+                None => return error("Invalid unicode escape".to_string()),
+            }
+        }
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            let c = match self.chars.next() {
+                Some((_, '"')) => return Ok(value),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => '"',
+                    Some((_, '\\')) => '\\',
+                    Some((_, '/')) => '/',
+                    Some((_, 'b')) => '\u{8}',
+                    Some((_, 'f')) => '\u{c}',
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 't')) => '\t',
+                    Some((_, 'u')) => {
+                        let mut code = self.hex()?;
+                        if (0xd800..0xdc00).contains(&code) {
+                            let low = match (self.chars.next(), self.chars.next()) {
+                                (Some((_, '\\')), Some((_, 'u'))) => self.hex()?,
+                                _ => return error("Unpaired surrogate escape".to_string()),
+                            };
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return error("Unpaired surrogate escape".to_string());
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        match char::from_u32(code) {
+                            Some(c) => c,
+                            None => return error("Invalid unicode escape".to_string()),
+                        }
+                    }
+                    Some((offset, c)) => {
+                        return error(format!("Invalid escape '\\{}' at offset {}", c, offset))
+                    }
+                    None => return error("Unterminated string".to_string()),
+                },
+                Some((_, c)) => c,
+                None => return error("Unterminated string".to_string()),
+            };
+            value.push(c);
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if let Some((_, ']')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some((_, ',')) => self.chars.next(),
+                _ => break,
+            };
+        }
+        self.expect(']')?;
+        Ok(Json::Array(items))
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.expect('{')?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if let Some((_, '}')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some((_, ',')) => self.chars.next(),
+                _ => break,
+            };
+        }
+        self.expect('}')?;
+        Ok(Json::Object(fields))
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        match self {
+            Some(value) => value.to_json(),
+            None => Json::Null,
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Box<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().to_json()
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        json.as_array()?.iter().map(T::from_json).collect()
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Null => Ok(None),
+            json => Ok(Some(T::from_json(json)?)),
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Box<T> {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Box::from(T::from_json(json)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_json_1() {
+        let source =
+            r#" {"a": [1, -2, true, null], "b\n": "x\"\\\u00e9\ud83d\ude00", "c": {}, "d": []} "#;
+        let value = Json::parse(source).unwrap();
+        assert_eq!(
+            value,
+            Json::object(vec![
+                (
+                    "a",
+                    Json::Array(vec![
+                        Json::Number(1),
+                        Json::Number(-2),
+                        Json::Bool(true),
+                        Json::Null
+                    ])
+                ),
+                ("b\n", Json::String("x\"\\\u{e9}\u{1f600}".to_string())),
+                ("c", Json::Object(vec![])),
+                ("d", Json::Array(vec![])),
+            ])
+        );
+        assert_eq!(
+            value.to_string(),
+            "{\"a\":[1,-2,true,null],\"b\\n\":\"x\\\"\\\\\u{e9}\u{1f600}\",\"c\":{},\"d\":[]}"
+        );
+        assert_eq!(Json::parse(&value.to_string()), Ok(value.clone()));
+        assert_eq!(Json::parse(&value.pretty()), Ok(value.clone()));
+        assert_eq!(
+            value.get("a").unwrap().pretty(),
+            "[\n  1,\n  -2,\n  true,\n  null\n]"
+        );
+    }
+
+    #[test]
+    fn test_json_2() {
+        let messages: Vec<String> = ["[1, 2", "{\"a\" 1}", "1.5", "nul", "\"\\q\"", "[] x"]
+            .iter()
+            .map(|source| Json::parse(source).unwrap_err().message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Expected ']' but found end of input",
+                "Expected ':' but found '1' at offset 5",
+                "Only integer numbers are supported (at offset 1)",
+                "Unknown literal 'nul' at offset 0",
+                "Invalid escape '\\q' at offset 2",
+                "Unexpected 'x' at offset 3",
+            ]
+        );
+        let value = Json::object(vec![("kind", Json::String("X".to_string()))]);
+        assert_eq!(value.kind(), Ok("X"));
+        let nested = format!("{}{}", "[".repeat(512), "]".repeat(512));
+        assert!(Json::parse(&nested).is_ok());
+        assert_eq!(
+            Json::parse(&"[".repeat(100_000)),
+            Err(JsonError {
+                message: "Nesting deeper than 512 levels at offset 512".to_string()
+            })
+        );
+        assert_eq!(
+            value.get("y").unwrap_err().message,
+            "Missing field 'y'".to_string()
+        );
+    }
+
+    #[test]
+    fn test_json_3() {
+        use crate::lexer::token::{Span, Token};
+        use crate::lexer::Lexer;
+        use crate::parser::ast::Program;
+        use crate::parser::Parser;
+
+        let source = "let x = \"hi\";\nprint(x?.len(), sep: nil);";
+        let (tokens, spans) = Lexer::lex_spanned(source);
+        let json = tokens_to_json(&tokens, &spans);
+        assert_eq!(
+            json.as_array().unwrap()[..2]
+                .iter()
+                .map(|token| token.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "{\"kind\":\"Let\",\"span\":{\"line\":1,\"column\":1}}",
+                "{\"kind\":\"Ident\",\"value\":\"x\",\"span\":{\"line\":1,\"column\":5}}",
+            ]
+        );
+        let parsed = Json::parse(&json.pretty()).unwrap();
+        assert_eq!(
+            tokens_from_json(&parsed),
+            Ok((tokens.clone(), spans.clone()))
+        );
+        assert_eq!(
+            Token::from_json(&Json::parse("{\"kind\":\"Bogus\"}").unwrap()),
+            Err(JsonError {
+                message: "Unknown token kind 'Bogus'".to_string()
+            })
+        );
+        assert_eq!(
+            Span::from_json(&Json::parse("{\"line\":-1,\"column\":0}").unwrap()),
+            Err(JsonError {
+                message: "Span line must not be negative".to_string()
+            })
+        );
+
+        let program = Parser::with_spans(&tokens, &spans).parse();
+        let json = program.to_json();
+        assert_eq!(
+            json.as_array().unwrap()[0].to_string(),
+            "{\"kind\":\"LetStmt\",\"pattern\":{\"kind\":\"IdentPattern\",\"ident\":\
+             {\"name\":\"x\",\"span\":{\"line\":1,\"column\":5}}},\"type\":null,\"init\":\
+             {\"kind\":\"LiteralExpr\",\"literal\":{\"kind\":\"StringLiteral\",\"value\":\"hi\"}}}"
+        );
+        let parsed = Json::parse(&json.pretty()).unwrap();
+        assert_eq!(Program::from_json(&parsed), Ok(program));
+        let broken =
+            Json::parse("[{\"kind\":\"ExprStmt\",\"expr\":{\"kind\":\"BinExpr\"}}]").unwrap();
+        assert_eq!(
+            Program::from_json(&broken).unwrap_err().message,
+            "Missing field 'lhs'"
+        );
+        let expr = Json::parse(
+            "{\"kind\":\"ExprStmt\",\"expr\":{\"kind\":\"IdentExpr\",\
+             \"ident\":{\"name\":\"x\",\"span\":{\"line\":1,\"column\":1}}}}",
+        )
+        .unwrap();
+        let messages: Vec<String> = [
+            ("fn f() { return 1; }", "body"),
+            ("while true { f(); }", "body"),
+            ("try { f(); } finally { f(); }", "finally"),
+            ("if true { f(); } else { f(); }", "else"),
+        ]
+        .iter()
+        .map(|(source, key)| {
+            let (tokens, spans) = Lexer::lex_spanned(source);
+            let mut json = Parser::with_spans(&tokens, &spans).parse().to_json();
+            if let Json::Array(stmts) = &mut json {
+                if let Json::Object(fields) = &mut stmts[0] {
+                    for (name, value) in fields.iter_mut() {
+                        if name == key {
+                            *value = expr.clone();
+                        }
+                    }
+                }
+            }
+            Program::from_json(&json).unwrap_err().message
+        })
+        .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Expected BlockStmt for 'body', found ExprStmt",
+                "Expected BlockStmt for 'body', found ExprStmt",
+                "Expected BlockStmt for 'finally', found ExprStmt",
+                "Expected BlockStmt or IfStmt for 'else', found ExprStmt",
+            ]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::json::{error, FromJson, Json, JsonError, ToJson};
+use crate::lexer::token::{Span, Token, Tokens};
+
+macro_rules! unit_tokens {
+    ($($name:ident),* $(,)?) => {
+        fn unit_name(token: &Token) -> Option<&'static str> {
+            match token {
+                $(Token::$name => Some(stringify!($name)),)*
+                _ => None,
+            }
+        }
+
+        fn unit_token(name: &str) -> Option<Token> {
+            match name {
+                $(stringify!($name) => Some(Token::$name),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+unit_tokens!(
+    Illegal,
+    EOF,
+    Assign,
+    Plus,
+    Minus,
+    Divide,
+    Multiply,
+    Not,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    Comma,
+    Colon,
+    Question,
+    DoubleQuestion,
+    QuestionDot,
+    At,
+    SemiColon,
+    Dot,
+    Ellipsis,
+    DotDot,
+    DotDotEqual,
+    Arrow,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Function,
+    Let,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Return,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    Yield,
+    Spawn,
+    Defer,
+    Nil,
+);
+
+impl ToJson for Span {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("line", Json::Number(self.line as i64)),
+            ("column", Json::Number(self.column as i64)),
+        ])
+    }
+}
+
+impl FromJson for Span {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        let position = |key| match usize::try_from(json.get(key)?.as_i64()?) {
+            Ok(value) => Ok(value),
+            Err(_) => error(format!("Span {} must not be negative", key)),
+        };
+        Ok(Span {
+            line: position("line")?,
+            column: position("column")?,
+        })
+    }
+}
+
+impl ToJson for Token {
+    fn to_json(&self) -> Json {
+        let (kind, value) = match self {
+            Token::Ident(name) => ("Ident", Json::String(name.clone())),
+            Token::IntLiteral(value) => ("IntLiteral", Json::Number(*value)),
+            Token::BooleanLiteral(value) => ("BooleanLiteral", Json::Bool(*value)),
+            Token::StringLiteral(value) => ("StringLiteral", Json::String(value.clone())),
+            token => {
+                let kind = unit_name(token).expect("every token kind has a name");
+                return Json::object(vec![("kind", Json::String(kind.to_string()))]);
+            }
+        };
+        Json::object(vec![
+            ("kind", Json::String(kind.to_string())),
+            ("value", value),
+        ])
+    }
+}
+
+impl FromJson for Token {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        let token = match json.kind()? {
+            "Ident" => Token::Ident(json.get("value")?.as_str()?.to_string()),
+            "IntLiteral" => Token::IntLiteral(json.get("value")?.as_i64()?),
+            "BooleanLiteral" => Token::BooleanLiteral(json.get("value")?.as_bool()?),
+            "StringLiteral" => Token::StringLiteral(json.get("value")?.as_str()?.to_string()),
+            kind => match unit_token(kind) {
+                Some(token) => token,
+                None => return error(format!("Unknown token kind '{}'", kind)),
+            },
+        };
+        Ok(token)
+    }
+}
+
+pub fn tokens_to_json(tokens: &[Token], spans: &[Span]) -> Json {
+    Json::Array(
+        tokens
+            .iter()
+            .zip(spans.iter())
+            .map(|(token, span)| {
+                let mut json = token.to_json();
+                if let Json::Object(fields) = &mut json {
+                    fields.push(("span".to_string(), span.to_json()));
+                }
+                json
+            })
+            .collect(),
+    )
+}
+
+pub fn tokens_from_json(json: &Json) -> Result<(Tokens, Vec<Span>), JsonError> {
+    let mut tokens = vec![];
+    let mut spans = vec![];
+    for item in json.as_array()? {
+        tokens.push(Token::from_json(item)?);
+        spans.push(Span::from_json(item.get("span")?)?);
+    }
+    Ok((tokens, spans))
+}
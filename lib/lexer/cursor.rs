@@ -1,20 +1,32 @@
 use std::str::Chars;
 
+use crate::lexer::token::Span;
+
 pub struct Cursor<'a> {
     chars: Chars<'a>,
+    line: usize,
+    column: usize,
 }
 
 impl<'a> Cursor<'a> {
     pub fn new(input: &'a str) -> Self {
         Self {
             chars: input.chars(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
         }
     }
 
     pub fn peek_first(&self) -> Option<char> {
         self.chars.clone().next()
     }
-    #[allow(unused)]
     pub fn peak_second(&self) -> Option<char> {
         let mut iter = self.chars.clone();
         iter.next();
@@ -22,6 +34,15 @@ impl<'a> Cursor<'a> {
     }
 
     pub fn next(&mut self) -> Option<char> {
-        self.chars.next()
+        let c = self.chars.next();
+        match c {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some(_) => self.column += 1,
+            None => (),
+        }
+        c
     }
 }
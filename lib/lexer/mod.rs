@@ -1,44 +1,104 @@
 mod cursor;
+mod scanner;
 pub mod token;
 
 use crate::lexer::cursor::Cursor;
+use crate::lexer::scanner::Scanner;
 use crate::lexer::token::*;
 
 pub struct Lexer;
 
 impl Lexer {
     pub fn lex_tokens(input: &str) -> Tokens {
+        Self::lex_spanned(input).0
+    }
+
+    pub fn scan(input: &str) -> Vec<Lexeme<'_>> {
+        Scanner::new(input).scan(false)
+    }
+
+    pub fn scan_lossless(input: &str) -> Vec<Lexeme<'_>> {
+        Scanner::new(input).scan(true)
+    }
+
+    pub fn lex_spanned(input: &str) -> (Tokens, Vec<Span>) {
         let mut input_chars = Cursor::new(input);
         let mut tokens = vec![];
+        let mut spans = vec![];
         loop {
             match input_chars.peek_first() {
                 None => {
+                    spans.push(input_chars.span());
                     tokens.push(Self::lex_token(&mut input_chars));
                     break;
                 }
                 Some(c) if c.is_ascii_whitespace() => {
                     input_chars.next();
                 }
+                Some('/') if input_chars.peak_second() == Some('/') => {
+                    while !matches!(input_chars.peek_first(), Some('\n') | None) {
+                        input_chars.next();
+                    }
+                }
                 _ => {
+                    spans.push(input_chars.span());
                     tokens.push(Self::lex_token(&mut input_chars));
                 }
             }
         }
-        tokens
+        (tokens, spans)
     }
 
     fn lex_token(input: &mut Cursor) -> Token {
         match input.next() {
             Some(';') => Token::SemiColon,
             Some(',') => Token::Comma,
+            Some(':') => Token::Colon,
+            Some('@') => Token::At,
+            Some('?') => match input.peek_first() {
+                Some('?') => {
+                    input.next();
+                    Token::DoubleQuestion
+                }
+                Some('.') => {
+                    input.next();
+                    Token::QuestionDot
+                }
+                _ => Token::Question,
+            },
             Some('(') => Token::LParen,
             Some(')') => Token::RParen,
             Some('{') => Token::LBrace,
             Some('}') => Token::RBrace,
+            Some('[') => Token::LBracket,
+            Some(']') => Token::RBracket,
             Some('+') => Token::Plus,
-            Some('-') => Token::Minus,
+            Some('-') => match input.peek_first() {
+                Some('>') => {
+                    input.next();
+                    Token::Arrow
+                }
+                _ => Token::Minus,
+            },
             Some('/') => Token::Divide,
             Some('*') => Token::Multiply,
+            Some('.') => match (input.peek_first(), input.peak_second()) {
+                (Some('.'), Some('.')) => {
+                    input.next();
+                    input.next();
+                    Token::Ellipsis
+                }
+                (Some('.'), Some('=')) => {
+                    input.next();
+                    input.next();
+                    Token::DotDotEqual
+                }
+                (Some('.'), _) => {
+                    input.next();
+                    Token::DotDot
+                }
+                _ => Token::Dot,
+            },
             Some('=') => match input.peek_first() {
                 Some('=') => {
                     input.next();
@@ -104,6 +164,15 @@ impl Lexer {
                     "else" => Token::Else,
                     "while" => Token::While,
                     "for" => Token::For,
+                    "in" => Token::In,
+                    "try" => Token::Try,
+                    "catch" => Token::Catch,
+                    "finally" => Token::Finally,
+                    "throw" => Token::Throw,
+                    "yield" => Token::Yield,
+                    "spawn" => Token::Spawn,
+                    "defer" => Token::Defer,
+                    "nil" => Token::Nil,
                     "true" => Token::BooleanLiteral(true),
                     "false" => Token::BooleanLiteral(false),
                     _ => Token::Ident(s),
@@ -227,4 +296,39 @@ mod test {
         ];
         assert_eq!(result, expected_result);
     }
+
+    #[test]
+    fn test_lexer_2() {
+        let source = "fn f(a) -> int { let s = \"héllo\"; return a..=10 ?? nil; }\n\"open\n$ 99999999999999999999";
+        let (tokens, spans) = Lexer::lex_spanned(source);
+        let lexemes = Lexer::scan(source);
+        let scanned: Vec<Token> = lexemes.iter().map(|lexeme| lexeme.to_token()).collect();
+        let scanned_spans: Vec<Span> = lexemes.iter().map(|lexeme| lexeme.span).collect();
+        assert_eq!(scanned, tokens);
+        assert_eq!(scanned_spans, spans);
+        assert_eq!(lexemes[11].text, "\"héllo\"");
+    }
+
+    #[test]
+    fn test_lexer_3() {
+        let source = "let a = 4 / 2; // halve it\n// a / b\nprint(a);";
+        let tokens = Lexer::lex_tokens(source);
+        assert_eq!(tokens.len(), 13);
+        assert_eq!(tokens[4], Token::Divide);
+        assert_eq!(tokens[7], Token::Ident("print".to_owned()));
+        let lexemes = Lexer::scan_lossless(source);
+        let text: String = lexemes.iter().map(|lexeme| lexeme.text).collect();
+        assert_eq!(text, source);
+        let comments: Vec<&str> = lexemes
+            .iter()
+            .filter(|lexeme| lexeme.kind == TokenKind::Comment)
+            .map(|lexeme| lexeme.text)
+            .collect();
+        assert_eq!(comments, vec!["// halve it", "// a / b"]);
+        let significant: Vec<Token> = Lexer::scan(source)
+            .iter()
+            .map(|lexeme| lexeme.to_token())
+            .collect();
+        assert_eq!(significant, tokens);
+    }
 }
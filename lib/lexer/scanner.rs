@@ -0,0 +1,210 @@
+use crate::lexer::token::{Lexeme, Span, TokenKind};
+use std::str::CharIndices;
+
+pub struct Scanner<'src> {
+    source: &'src str,
+    chars: CharIndices<'src>,
+    line: usize,
+    column: usize,
+}
+
+impl<'src> Scanner<'src> {
+    pub fn new(source: &'src str) -> Self {
+        Self {
+            source,
+            chars: source.char_indices(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    pub fn scan(mut self, trivia: bool) -> Vec<Lexeme<'src>> {
+        let mut lexemes = vec![];
+        loop {
+            let lexeme = self.scan_lexeme();
+            if trivia || !lexeme.kind.is_trivia() {
+                lexemes.push(lexeme);
+            }
+            if lexeme.kind == TokenKind::EOF {
+                return lexemes;
+            }
+        }
+    }
+
+    fn offset(&self) -> usize {
+        match self.chars.clone().next() {
+            Some((offset, _)) => offset,
+            None => self.source.len(),
+        }
+    }
+
+    fn peek_first(&self) -> Option<char> {
+        self.chars.clone().next().map(|(_, c)| c)
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.chars.clone().nth(1).map(|(_, c)| c)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next().map(|(_, c)| c);
+        match c {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some(_) => self.column += 1,
+            None => (),
+        }
+        c
+    }
+
+    fn follow(&mut self, expected: char, matched: TokenKind, otherwise: TokenKind) -> TokenKind {
+        match self.peek_first() == Some(expected) {
+            true => {
+                self.next();
+                matched
+            }
+            false => otherwise,
+        }
+    }
+
+    fn scan_lexeme(&mut self) -> Lexeme<'src> {
+        let span = Span {
+            line: self.line,
+            column: self.column,
+        };
+        let start = self.offset();
+        let kind = match self.scan_trivia() {
+            Some(kind) => kind,
+            None => self.scan_kind(),
+        };
+        Lexeme {
+            kind,
+            text: &self.source[start..self.offset()],
+            span,
+        }
+    }
+
+    fn scan_trivia(&mut self) -> Option<TokenKind> {
+        match (self.peek_first(), self.peek_second()) {
+            (Some(c), _) if c.is_ascii_whitespace() => {
+                while matches!(self.peek_first(), Some(c) if c.is_ascii_whitespace()) {
+                    self.next();
+                }
+                Some(TokenKind::Whitespace)
+            }
+            (Some('/'), Some('/')) => {
+                while !matches!(self.peek_first(), Some('\n') | None) {
+                    self.next();
+                }
+                Some(TokenKind::Comment)
+            }
+            _ => None,
+        }
+    }
+
+    fn scan_kind(&mut self) -> TokenKind {
+        match self.next() {
+            Some(';') => TokenKind::SemiColon,
+            Some(',') => TokenKind::Comma,
+            Some(':') => TokenKind::Colon,
+            Some('@') => TokenKind::At,
+            Some('?') => match self.peek_first() {
+                Some('?') => {
+                    self.next();
+                    TokenKind::DoubleQuestion
+                }
+                Some('.') => {
+                    self.next();
+                    TokenKind::QuestionDot
+                }
+                _ => TokenKind::Question,
+            },
+            Some('(') => TokenKind::LParen,
+            Some(')') => TokenKind::RParen,
+            Some('{') => TokenKind::LBrace,
+            Some('}') => TokenKind::RBrace,
+            Some('[') => TokenKind::LBracket,
+            Some(']') => TokenKind::RBracket,
+            Some('+') => TokenKind::Plus,
+            Some('-') => self.follow('>', TokenKind::Arrow, TokenKind::Minus),
+            Some('/') => TokenKind::Divide,
+            Some('*') => TokenKind::Multiply,
+            Some('.') => match (self.peek_first(), self.peek_second()) {
+                (Some('.'), Some('.')) => {
+                    self.next();
+                    self.next();
+                    TokenKind::Ellipsis
+                }
+                (Some('.'), Some('=')) => {
+                    self.next();
+                    self.next();
+                    TokenKind::DotDotEqual
+                }
+                (Some('.'), _) => {
+                    self.next();
+                    TokenKind::DotDot
+                }
+                _ => TokenKind::Dot,
+            },
+            Some('=') => self.follow('=', TokenKind::Equal, TokenKind::Assign),
+            Some('!') => self.follow('=', TokenKind::NotEqual, TokenKind::Not),
+            Some('<') => self.follow('=', TokenKind::LessEqual, TokenKind::Less),
+            Some('>') => self.follow('=', TokenKind::GreaterEqual, TokenKind::Greater),
+            Some('"') => loop {
+                match self.next() {
+                    Some('\n') | Some('\r') | None => return TokenKind::Illegal,
+                    Some('"') => return TokenKind::StringLiteral,
+                    Some(_) => (),
+                }
+            },
+            Some(c) if c.is_alphabetic() || c.eq(&'_') => {
+                let start = self.offset() - c.len_utf8();
+                while let Some(ch) = self.peek_first() {
+                    if ch.is_alphabetic() || ch.is_ascii_digit() || ch.eq(&'_') {
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                match &self.source[start..self.offset()] {
+                    "fn" => TokenKind::Function,
+                    "let" => TokenKind::Let,
+                    "return" => TokenKind::Return,
+                    "if" => TokenKind::If,
+                    "else" => TokenKind::Else,
+                    "while" => TokenKind::While,
+                    "for" => TokenKind::For,
+                    "in" => TokenKind::In,
+                    "try" => TokenKind::Try,
+                    "catch" => TokenKind::Catch,
+                    "finally" => TokenKind::Finally,
+                    "throw" => TokenKind::Throw,
+                    "yield" => TokenKind::Yield,
+                    "spawn" => TokenKind::Spawn,
+                    "defer" => TokenKind::Defer,
+                    "nil" => TokenKind::Nil,
+                    "true" | "false" => TokenKind::BooleanLiteral,
+                    _ => TokenKind::Ident,
+                }
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let start = self.offset() - 1;
+                while let Some(ch) = self.peek_first() {
+                    if ch.is_ascii_digit() {
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                match self.source[start..self.offset()].parse::<i64>() {
+                    Ok(_) => TokenKind::IntLiteral,
+                    Err(_) => TokenKind::Illegal,
+                }
+            }
+            None => TokenKind::EOF,
+            _ => TokenKind::Illegal,
+        }
+    }
+}
@@ -1,4 +1,16 @@
 pub type Tokens = Vec<Token>;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum Token {
     Illegal,
@@ -23,12 +35,24 @@ pub enum Token {
     NotEqual,
 
     Comma,
+    Colon,
+    Question,
+    DoubleQuestion,
+    QuestionDot,
+    At,
     SemiColon,
+    Dot,
+    Ellipsis,
+    DotDot,
+    DotDotEqual,
+    Arrow,
 
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
 
     Function,
     Let,
@@ -36,5 +60,156 @@ pub enum Token {
     Else,
     While,
     For,
+    In,
     Return,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    Yield,
+    Spawn,
+    Defer,
+    Nil,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TokenKind {
+    Illegal,
+    EOF,
+    Whitespace,
+    Comment,
+
+    Ident,
+    IntLiteral,
+    BooleanLiteral,
+    StringLiteral,
+
+    Assign,
+    Plus,
+    Minus,
+    Divide,
+    Multiply,
+    Not,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+
+    Comma,
+    Colon,
+    Question,
+    DoubleQuestion,
+    QuestionDot,
+    At,
+    SemiColon,
+    Dot,
+    Ellipsis,
+    DotDot,
+    DotDotEqual,
+    Arrow,
+
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+
+    Function,
+    Let,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Return,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    Yield,
+    Spawn,
+    Defer,
+    Nil,
+}
+
+impl TokenKind {
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Whitespace | TokenKind::Comment)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Lexeme<'src> {
+    pub kind: TokenKind,
+    pub text: &'src str,
+    pub span: Span,
+}
+
+impl<'src> Lexeme<'src> {
+    pub fn to_token(&self) -> Token {
+        match self.kind {
+            TokenKind::Illegal | TokenKind::Whitespace | TokenKind::Comment => Token::Illegal,
+            TokenKind::EOF => Token::EOF,
+            TokenKind::Ident => Token::Ident(self.text.to_string()),
+            TokenKind::IntLiteral => match self.text.parse::<i64>() {
+                Ok(value) => Token::IntLiteral(value),
+                Err(_) => Token::Illegal,
+            },
+            TokenKind::BooleanLiteral => Token::BooleanLiteral(self.text == "true"),
+            TokenKind::StringLiteral => Token::StringLiteral(self.string_value().to_string()),
+            TokenKind::Assign => Token::Assign,
+            TokenKind::Plus => Token::Plus,
+            TokenKind::Minus => Token::Minus,
+            TokenKind::Divide => Token::Divide,
+            TokenKind::Multiply => Token::Multiply,
+            TokenKind::Not => Token::Not,
+            TokenKind::Less => Token::Less,
+            TokenKind::Greater => Token::Greater,
+            TokenKind::LessEqual => Token::LessEqual,
+            TokenKind::GreaterEqual => Token::GreaterEqual,
+            TokenKind::Equal => Token::Equal,
+            TokenKind::NotEqual => Token::NotEqual,
+            TokenKind::Comma => Token::Comma,
+            TokenKind::Colon => Token::Colon,
+            TokenKind::Question => Token::Question,
+            TokenKind::DoubleQuestion => Token::DoubleQuestion,
+            TokenKind::QuestionDot => Token::QuestionDot,
+            TokenKind::At => Token::At,
+            TokenKind::SemiColon => Token::SemiColon,
+            TokenKind::Dot => Token::Dot,
+            TokenKind::Ellipsis => Token::Ellipsis,
+            TokenKind::DotDot => Token::DotDot,
+            TokenKind::DotDotEqual => Token::DotDotEqual,
+            TokenKind::Arrow => Token::Arrow,
+            TokenKind::LParen => Token::LParen,
+            TokenKind::RParen => Token::RParen,
+            TokenKind::LBrace => Token::LBrace,
+            TokenKind::RBrace => Token::RBrace,
+            TokenKind::LBracket => Token::LBracket,
+            TokenKind::RBracket => Token::RBracket,
+            TokenKind::Function => Token::Function,
+            TokenKind::Let => Token::Let,
+            TokenKind::If => Token::If,
+            TokenKind::Else => Token::Else,
+            TokenKind::While => Token::While,
+            TokenKind::For => Token::For,
+            TokenKind::In => Token::In,
+            TokenKind::Return => Token::Return,
+            TokenKind::Try => Token::Try,
+            TokenKind::Catch => Token::Catch,
+            TokenKind::Finally => Token::Finally,
+            TokenKind::Throw => Token::Throw,
+            TokenKind::Yield => Token::Yield,
+            TokenKind::Spawn => Token::Spawn,
+            TokenKind::Defer => Token::Defer,
+            TokenKind::Nil => Token::Nil,
+        }
+    }
+
+    pub fn string_value(&self) -> &'src str {
+        &self.text[1..self.text.len() - 1]
+    }
 }
@@ -0,0 +1,188 @@
+use crate::parser::ast::fold::{fold_expr, fold_program, fold_stmt};
+use crate::parser::ast::visit::{walk_stmt, Visitor};
+use crate::parser::ast::{BinOp, Expr, Fold, Literal, Program, Stmt, UnaryOp};
+use std::cmp::Ordering;
+
+#[derive(Default)]
+pub struct Optimizer;
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn optimize(&mut self, program: Program) -> Program {
+        self.fold_program(program)
+    }
+}
+
+impl Fold for Optimizer {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_program(self, program)
+            .into_iter()
+            .filter(|stmt| !matches!(stmt, Stmt::BlockStmt(stmts) if stmts.is_empty()))
+            .collect()
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+        match fold_stmt(self, stmt) {
+            Stmt::IfStmt(condition, body, alt) => match truth(&condition) {
+                Some(true) if !alt.as_deref().is_some_and(yields) => *body,
+                Some(false) if !yields(&body) => match alt {
+                    Some(alt) => *alt,
+                    None => Stmt::BlockStmt(vec![]),
+                },
+                _ => Stmt::IfStmt(condition, body, alt),
+            },
+            Stmt::WhileStmt(condition, body) if truth(&condition) == Some(false) => {
+                match yields(&body) {
+                    true => Stmt::WhileStmt(condition, body),
+                    false => Stmt::BlockStmt(vec![]),
+                }
+            }
+            stmt => stmt,
+        }
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        match fold_expr(self, expr) {
+            Expr::UnaryExpr(UnaryOp::UnaryPlus, expr) => *expr,
+            Expr::UnaryExpr(op, expr) => match (op, *expr) {
+                (op, Expr::LiteralExpr(literal)) => match unary(op, &literal) {
+                    Some(literal) => Expr::LiteralExpr(literal),
+                    None => Expr::UnaryExpr(op, Box::from(Expr::LiteralExpr(literal))),
+                },
+                (UnaryOp::Not, Expr::BinExpr(l_expr, op, r_expr)) => match negate(op) {
+                    Some(op) => Expr::BinExpr(l_expr, op, r_expr),
+                    None => {
+                        Expr::UnaryExpr(UnaryOp::Not, Box::from(Expr::BinExpr(l_expr, op, r_expr)))
+                    }
+                },
+                (op, expr) => Expr::UnaryExpr(op, Box::from(expr)),
+            },
+            Expr::BinExpr(l_expr, op, r_expr) => match (*l_expr, *r_expr) {
+                (Expr::LiteralExpr(l), Expr::LiteralExpr(r)) => match binary(&l, op, &r) {
+                    Some(literal) => Expr::LiteralExpr(literal),
+                    None => Expr::BinExpr(
+                        Box::from(Expr::LiteralExpr(l)),
+                        op,
+                        Box::from(Expr::LiteralExpr(r)),
+                    ),
+                },
+                (l_expr, r_expr) => simplify(l_expr, op, r_expr),
+            },
+            expr => expr,
+        }
+    }
+}
+
+pub(crate) fn truth(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::LiteralExpr(Literal::BoolLiteral(value)) => Some(*value),
+        Expr::LiteralExpr(Literal::IntLiteral(value)) => Some(*value != 0),
+        _ => None,
+    }
+}
+
+struct Yields(bool);
+
+impl Visitor for Yields {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::YieldStmt(_) => self.0 = true,
+            Stmt::FnStmt(..) => (),
+            stmt => walk_stmt(self, stmt),
+        }
+    }
+}
+
+fn yields(stmt: &Stmt) -> bool {
+    let mut yields = Yields(false);
+    yields.visit_stmt(stmt);
+    yields.0
+}
+
+fn number(literal: &Literal) -> Option<i64> {
+    match literal {
+        Literal::IntLiteral(value) => Some(*value),
+        Literal::BoolLiteral(value) => Some(*value as i64),
+        _ => None,
+    }
+}
+
+fn unary(op: UnaryOp, literal: &Literal) -> Option<Literal> {
+    match (op, literal) {
+        (UnaryOp::Not, Literal::BoolLiteral(value)) => Some(Literal::BoolLiteral(!value)),
+        (UnaryOp::Not, Literal::IntLiteral(value)) => Some(Literal::BoolLiteral(*value == 0)),
+        (UnaryOp::UnaryMinus, literal) => number(literal)?.checked_neg().map(Literal::IntLiteral),
+        _ => None,
+    }
+}
+
+fn binary(l: &Literal, op: BinOp, r: &Literal) -> Option<Literal> {
+    match (l, op, r) {
+        (Literal::StringLiteral(l), BinOp::Plus, Literal::StringLiteral(r)) => {
+            Some(Literal::StringLiteral(format!("{}{}", l, r)))
+        }
+        (Literal::NilLiteral, BinOp::Equal | BinOp::NotEqual, _)
+        | (_, BinOp::Equal | BinOp::NotEqual, Literal::NilLiteral) => {
+            Some(Literal::BoolLiteral((l == r) == (op == BinOp::Equal)))
+        }
+        (Literal::StringLiteral(l), op, Literal::StringLiteral(r)) => compare(op, l.cmp(r)),
+        _ => {
+            let (l, r) = (number(l)?, number(r)?);
+            match op {
+                BinOp::Plus => l.checked_add(r).map(Literal::IntLiteral),
+                BinOp::Minus => l.checked_sub(r).map(Literal::IntLiteral),
+                BinOp::Multiply => l.checked_mul(r).map(Literal::IntLiteral),
+                BinOp::Divide => l.checked_div(r).map(Literal::IntLiteral),
+                op => compare(op, l.cmp(&r)),
+            }
+        }
+    }
+}
+
+fn compare(op: BinOp, ordering: Ordering) -> Option<Literal> {
+    let result = match op {
+        BinOp::Less => ordering.is_lt(),
+        BinOp::Greater => ordering.is_gt(),
+        BinOp::LessEqual => ordering.is_le(),
+        BinOp::GreaterEqual => ordering.is_ge(),
+        BinOp::Equal => ordering.is_eq(),
+        BinOp::NotEqual => ordering.is_ne(),
+        _ => return None,
+    };
+    Some(Literal::BoolLiteral(result))
+}
+
+fn negate(op: BinOp) -> Option<BinOp> {
+    match op {
+        BinOp::Less => Some(BinOp::GreaterEqual),
+        BinOp::Greater => Some(BinOp::LessEqual),
+        BinOp::LessEqual => Some(BinOp::Greater),
+        BinOp::GreaterEqual => Some(BinOp::Less),
+        BinOp::Equal => Some(BinOp::NotEqual),
+        BinOp::NotEqual => Some(BinOp::Equal),
+        _ => None,
+    }
+}
+
+fn integral(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::LiteralExpr(Literal::IntLiteral(_))
+            | Expr::UnaryExpr(UnaryOp::UnaryMinus, _)
+            | Expr::BinExpr(_, BinOp::Minus | BinOp::Multiply | BinOp::Divide, _)
+    )
+}
+
+fn simplify(l_expr: Expr, op: BinOp, r_expr: Expr) -> Expr {
+    let unit = |expr: &Expr, unit: i64| matches!(expr, Expr::LiteralExpr(Literal::IntLiteral(value)) if *value == unit);
+    match op {
+        BinOp::Plus | BinOp::Minus if unit(&r_expr, 0) && integral(&l_expr) => l_expr,
+        BinOp::Multiply | BinOp::Divide if unit(&r_expr, 1) && integral(&l_expr) => l_expr,
+        BinOp::Plus if unit(&l_expr, 0) && integral(&r_expr) => r_expr,
+        BinOp::Multiply if unit(&l_expr, 1) && integral(&r_expr) => r_expr,
+        op => Expr::BinExpr(Box::from(l_expr), op, Box::from(r_expr)),
+    }
+}
@@ -0,0 +1,527 @@
+use crate::lexer::token::Span;
+use crate::parser::ast::{
+    Arg, BinOp, Contract, Decorator, Expr, Ident, Literal, Param, Pattern, Program, Stmt, Type,
+    UnaryOp,
+};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+pub struct Interner<'src> {
+    map: HashMap<&'src str, Symbol>,
+    names: Vec<&'src str>,
+}
+
+impl<'src> Interner<'src> {
+    pub fn intern(&mut self, name: &'src str) -> Symbol {
+        if let Some(symbol) = self.map.get(name) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.map.insert(name, symbol);
+        self.names.push(name);
+        symbol
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &'src str {
+        self.names[symbol.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct Name {
+    pub symbol: Symbol,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct ExprId(u32);
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct StmtId(u32);
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct PatternId(u32);
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct FnId(u32);
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct Items<T> {
+    start: u32,
+    len: u32,
+    marker: PhantomData<T>,
+}
+
+impl<T> Clone for Items<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Items<T> {}
+
+impl<T> Default for Items<T> {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            len: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Items<T> {
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn range(&self) -> std::ops::Range<usize> {
+        self.start as usize..(self.start + self.len) as usize
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum ExprNode<'src> {
+    Ident(Name),
+    Int(i64),
+    Bool(bool),
+    Str(&'src str),
+    Nil,
+    Assign(PatternId, ExprId),
+    Unary(UnaryOp, ExprId),
+    Binary(ExprId, BinOp, ExprId),
+    Call(ExprId, Items<ArgNode>),
+    Propagate(ExprId),
+    Spawn(ExprId, Items<ArgNode>),
+    Coalesce(ExprId, ExprId),
+    OptionalCall(ExprId, Items<ArgNode>),
+    MethodCall(ExprId, Name, Items<ArgNode>, bool),
+    Tuple(Items<ExprId>),
+    Range(ExprId, ExprId, bool, Option<ExprId>),
+    Index(ExprId, ExprId),
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum StmtNode {
+    Let(PatternId, Option<Type>, Option<ExprId>),
+    Return(Option<ExprId>, Span),
+    Expr(ExprId),
+    Block(Items<StmtId>),
+    If(ExprId, StmtId, Option<StmtId>),
+    While(ExprId, StmtId),
+    For(PatternId, ExprId, StmtId),
+    Fn(FnId),
+    Try(StmtId, Option<(Name, StmtId)>, Option<StmtId>),
+    Throw(ExprId),
+    Yield(Option<ExprId>),
+    Defer(ExprId),
+    Macro(Name, Items<PatternId>, StmtId),
+    Error(Span),
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum PatternNode {
+    Ident(Name),
+    Tuple(Items<PatternId>),
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct ArgNode {
+    pub name: Option<Name>,
+    pub value: ExprId,
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct ParamNode {
+    pub name: Option<Symbol>,
+    pub span: Span,
+    pub pattern: Option<PatternId>,
+    pub ty: Option<Type>,
+    pub default: Option<ExprId>,
+    pub variadic: bool,
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct DecoratorNode {
+    pub name: Name,
+    pub args: Items<ArgNode>,
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum ContractNode {
+    Requires(ExprId),
+    Ensures(ExprId),
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct FnNode {
+    pub name: Name,
+    pub params: Items<ParamNode>,
+    pub ret: Option<Type>,
+    pub body: StmtId,
+    pub decorators: Items<DecoratorNode>,
+    pub contracts: Items<ContractNode>,
+}
+
+#[derive(Default)]
+pub struct Ast<'src> {
+    pub interner: Interner<'src>,
+    pub root: Items<StmtId>,
+    exprs: Vec<ExprNode<'src>>,
+    stmts: Vec<StmtNode>,
+    patterns: Vec<PatternNode>,
+    fns: Vec<FnNode>,
+    stmt_lists: Vec<StmtId>,
+    expr_lists: Vec<ExprId>,
+    pattern_lists: Vec<PatternId>,
+    args: Vec<ArgNode>,
+    params: Vec<ParamNode>,
+    decorators: Vec<DecoratorNode>,
+    contracts: Vec<ContractNode>,
+}
+
+fn commit<T: Copy>(pool: &mut Vec<T>, scratch: &mut Vec<T>, start: usize) -> Items<T> {
+    let items = Items {
+        start: pool.len() as u32,
+        len: (scratch.len() - start) as u32,
+        marker: PhantomData,
+    };
+    pool.extend(scratch.drain(start..));
+    items
+}
+
+impl<'src> Ast<'src> {
+    pub fn node_count(&self) -> usize {
+        self.exprs.len() + self.stmts.len() + self.patterns.len()
+    }
+
+    pub fn expr(&self, id: ExprId) -> &ExprNode<'src> {
+        &self.exprs[id.0 as usize]
+    }
+
+    pub fn stmt(&self, id: StmtId) -> &StmtNode {
+        &self.stmts[id.0 as usize]
+    }
+
+    pub fn pattern(&self, id: PatternId) -> &PatternNode {
+        &self.patterns[id.0 as usize]
+    }
+
+    pub fn function(&self, id: FnId) -> &FnNode {
+        &self.fns[id.0 as usize]
+    }
+
+    pub fn stmt_list(&self, items: Items<StmtId>) -> &[StmtId] {
+        &self.stmt_lists[items.range()]
+    }
+
+    pub fn expr_list(&self, items: Items<ExprId>) -> &[ExprId] {
+        &self.expr_lists[items.range()]
+    }
+
+    pub fn pattern_list(&self, items: Items<PatternId>) -> &[PatternId] {
+        &self.pattern_lists[items.range()]
+    }
+
+    pub fn arg_list(&self, items: Items<ArgNode>) -> &[ArgNode] {
+        &self.args[items.range()]
+    }
+
+    pub fn param_list(&self, items: Items<ParamNode>) -> &[ParamNode] {
+        &self.params[items.range()]
+    }
+
+    pub fn decorator_list(&self, items: Items<DecoratorNode>) -> &[DecoratorNode] {
+        &self.decorators[items.range()]
+    }
+
+    pub fn contract_list(&self, items: Items<ContractNode>) -> &[ContractNode] {
+        &self.contracts[items.range()]
+    }
+
+    pub(super) fn alloc_expr(&mut self, node: ExprNode<'src>) -> ExprId {
+        self.exprs.push(node);
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    pub(super) fn replace_expr(&mut self, id: ExprId, node: ExprNode<'src>) {
+        self.exprs[id.0 as usize] = node;
+    }
+
+    pub(super) fn alloc_stmt(&mut self, node: StmtNode) -> StmtId {
+        self.stmts.push(node);
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+
+    pub(super) fn alloc_pattern(&mut self, node: PatternNode) -> PatternId {
+        self.patterns.push(node);
+        PatternId(self.patterns.len() as u32 - 1)
+    }
+
+    pub(super) fn alloc_fn(&mut self, node: FnNode) -> FnId {
+        self.fns.push(node);
+        FnId(self.fns.len() as u32 - 1)
+    }
+
+    pub(super) fn function_mut(&mut self, id: FnId) -> &mut FnNode {
+        &mut self.fns[id.0 as usize]
+    }
+
+    pub(super) fn commit_stmts(
+        &mut self,
+        scratch: &mut Vec<StmtId>,
+        start: usize,
+    ) -> Items<StmtId> {
+        commit(&mut self.stmt_lists, scratch, start)
+    }
+
+    pub(super) fn commit_exprs(
+        &mut self,
+        scratch: &mut Vec<ExprId>,
+        start: usize,
+    ) -> Items<ExprId> {
+        commit(&mut self.expr_lists, scratch, start)
+    }
+
+    pub(super) fn commit_patterns(
+        &mut self,
+        scratch: &mut Vec<PatternId>,
+        start: usize,
+    ) -> Items<PatternId> {
+        commit(&mut self.pattern_lists, scratch, start)
+    }
+
+    pub(super) fn commit_args(
+        &mut self,
+        scratch: &mut Vec<ArgNode>,
+        start: usize,
+    ) -> Items<ArgNode> {
+        commit(&mut self.args, scratch, start)
+    }
+
+    pub(super) fn commit_params(
+        &mut self,
+        scratch: &mut Vec<ParamNode>,
+        start: usize,
+    ) -> Items<ParamNode> {
+        commit(&mut self.params, scratch, start)
+    }
+
+    pub(super) fn commit_decorators(
+        &mut self,
+        scratch: &mut Vec<DecoratorNode>,
+        start: usize,
+    ) -> Items<DecoratorNode> {
+        commit(&mut self.decorators, scratch, start)
+    }
+
+    pub(super) fn commit_contracts(
+        &mut self,
+        scratch: &mut Vec<ContractNode>,
+        start: usize,
+    ) -> Items<ContractNode> {
+        commit(&mut self.contracts, scratch, start)
+    }
+
+    pub fn lower(&self) -> Program {
+        self.stmt_list(self.root)
+            .iter()
+            .map(|stmt| self.lower_stmt(*stmt))
+            .collect()
+    }
+
+    fn lower_name(&self, name: Name) -> Ident {
+        Ident(self.interner.resolve(name.symbol).to_string(), name.span)
+    }
+
+    fn lower_stmt(&self, id: StmtId) -> Stmt {
+        match *self.stmt(id) {
+            StmtNode::Let(pattern, ty, expr) => Stmt::LetStmt(
+                self.lower_pattern(pattern),
+                ty,
+                expr.map(|expr| self.lower_expr(expr)),
+            ),
+            StmtNode::Return(expr, span) => {
+                Stmt::ReturnStmt(expr.map(|expr| self.lower_expr(expr)), span)
+            }
+            StmtNode::Expr(expr) => Stmt::ExprStmt(self.lower_expr(expr)),
+            StmtNode::Block(stmts) => Stmt::BlockStmt(
+                self.stmt_list(stmts)
+                    .iter()
+                    .map(|stmt| self.lower_stmt(*stmt))
+                    .collect(),
+            ),
+            StmtNode::If(condition, then_branch, else_branch) => Stmt::IfStmt(
+                self.lower_expr(condition),
+                Box::from(self.lower_stmt(then_branch)),
+                else_branch.map(|stmt| Box::from(self.lower_stmt(stmt))),
+            ),
+            StmtNode::While(condition, body) => {
+                Stmt::WhileStmt(self.lower_expr(condition), Box::from(self.lower_stmt(body)))
+            }
+            StmtNode::For(pattern, iterable, body) => Stmt::ForStmt(
+                self.lower_pattern(pattern),
+                self.lower_expr(iterable),
+                Box::from(self.lower_stmt(body)),
+            ),
+            StmtNode::Fn(id) => self.lower_fn(self.function(id)),
+            StmtNode::Try(body, handler, finally) => Stmt::TryStmt(
+                Box::from(self.lower_stmt(body)),
+                handler
+                    .map(|(name, stmt)| (self.lower_name(name), Box::from(self.lower_stmt(stmt)))),
+                finally.map(|stmt| Box::from(self.lower_stmt(stmt))),
+            ),
+            StmtNode::Throw(expr) => Stmt::ThrowStmt(self.lower_expr(expr)),
+            StmtNode::Yield(expr) => Stmt::YieldStmt(expr.map(|expr| self.lower_expr(expr))),
+            StmtNode::Defer(expr) => Stmt::DeferStmt(self.lower_expr(expr)),
+            StmtNode::Macro(name, params, body) => Stmt::MacroStmt(
+                self.lower_name(name),
+                self.pattern_list(params)
+                    .iter()
+                    .map(|param| match self.lower_pattern(*param) {
+                        Pattern::Ident(ident) => ident,
+                        Pattern::Tuple(_) => unreachable!("macro parameters are names"),
+                    })
+                    .collect(),
+                Box::from(self.lower_stmt(body)),
+            ),
+            StmtNode::Error(span) => Stmt::ErrorStmt(span),
+        }
+    }
+
+    fn lower_fn(&self, node: &FnNode) -> Stmt {
+        let params = self
+            .param_list(node.params)
+            .iter()
+            .map(|param| {
+                let pattern = param.pattern.map(|pattern| self.lower_pattern(pattern));
+                let name = match (param.name, &pattern) {
+                    (Some(symbol), _) => self.interner.resolve(symbol).to_string(),
+                    (None, Some(pattern)) => pattern.to_string(),
+                    (None, None) => unreachable!(),
+                };
+                Param {
+                    name: Ident(name, param.span),
+                    pattern,
+                    ty: param.ty,
+                    default: param.default.map(|expr| self.lower_expr(expr)),
+                    variadic: param.variadic,
+                }
+            })
+            .collect();
+        let decorators = self
+            .decorator_list(node.decorators)
+            .iter()
+            .map(|decorator| Decorator {
+                name: self.lower_name(decorator.name),
+                args: self.lower_args(decorator.args),
+            })
+            .collect();
+        let contracts = self
+            .contract_list(node.contracts)
+            .iter()
+            .map(|contract| match *contract {
+                ContractNode::Requires(expr) => Contract::Requires(self.lower_expr(expr)),
+                ContractNode::Ensures(expr) => Contract::Ensures(self.lower_expr(expr)),
+            })
+            .collect();
+        Stmt::FnStmt(
+            self.lower_name(node.name),
+            params,
+            node.ret,
+            Box::from(self.lower_stmt(node.body)),
+            decorators,
+            contracts,
+        )
+    }
+
+    fn lower_pattern(&self, id: PatternId) -> Pattern {
+        match *self.pattern(id) {
+            PatternNode::Ident(name) => Pattern::Ident(self.lower_name(name)),
+            PatternNode::Tuple(patterns) => Pattern::Tuple(
+                self.pattern_list(patterns)
+                    .iter()
+                    .map(|pattern| self.lower_pattern(*pattern))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn lower_args(&self, args: Items<ArgNode>) -> Vec<Arg> {
+        self.arg_list(args)
+            .iter()
+            .map(|arg| match arg.name {
+                Some(name) => Arg::Named(self.lower_name(name), self.lower_expr(arg.value)),
+                None => Arg::Positional(self.lower_expr(arg.value)),
+            })
+            .collect()
+    }
+
+    fn lower_box(&self, id: ExprId) -> Box<Expr> {
+        Box::from(self.lower_expr(id))
+    }
+
+    fn lower_expr(&self, id: ExprId) -> Expr {
+        match *self.expr(id) {
+            ExprNode::Ident(name) => Expr::IdentExpr(self.lower_name(name)),
+            ExprNode::Int(value) => Expr::LiteralExpr(Literal::IntLiteral(value)),
+            ExprNode::Bool(value) => Expr::LiteralExpr(Literal::BoolLiteral(value)),
+            ExprNode::Str(value) => Expr::LiteralExpr(Literal::StringLiteral(value.to_string())),
+            ExprNode::Nil => Expr::LiteralExpr(Literal::NilLiteral),
+            ExprNode::Assign(pattern, expr) => {
+                Expr::AssignmentExpr(self.lower_pattern(pattern), self.lower_box(expr))
+            }
+            ExprNode::Unary(op, expr) => Expr::UnaryExpr(op, self.lower_box(expr)),
+            ExprNode::Binary(left, op, right) => {
+                Expr::BinExpr(self.lower_box(left), op, self.lower_box(right))
+            }
+            ExprNode::Call(func, args) => {
+                Expr::CallExpr(self.lower_box(func), self.lower_args(args))
+            }
+            ExprNode::Propagate(expr) => Expr::PropagateExpr(self.lower_box(expr)),
+            ExprNode::Spawn(func, args) => {
+                Expr::SpawnExpr(self.lower_box(func), self.lower_args(args))
+            }
+            ExprNode::Coalesce(left, right) => {
+                Expr::CoalesceExpr(self.lower_box(left), self.lower_box(right))
+            }
+            ExprNode::OptionalCall(func, args) => {
+                Expr::OptionalCallExpr(self.lower_box(func), self.lower_args(args))
+            }
+            ExprNode::MethodCall(receiver, name, args, optional) => Expr::MethodCallExpr(
+                self.lower_box(receiver),
+                self.lower_name(name),
+                self.lower_args(args),
+                optional,
+            ),
+            ExprNode::Tuple(exprs) => Expr::TupleExpr(
+                self.expr_list(exprs)
+                    .iter()
+                    .map(|expr| self.lower_expr(*expr))
+                    .collect(),
+            ),
+            ExprNode::Range(start, end, inclusive, step) => Expr::RangeExpr(
+                self.lower_box(start),
+                self.lower_box(end),
+                inclusive,
+                step.map(|step| self.lower_box(step)),
+            ),
+            ExprNode::Index(target, index) => {
+                Expr::IndexExpr(self.lower_box(target), self.lower_box(index))
+            }
+        }
+    }
+}
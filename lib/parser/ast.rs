@@ -1,22 +1,371 @@
+use crate::lexer::token::Span;
+
+pub mod fold;
+pub mod visit;
+pub mod visit_mut;
+
+pub use fold::Fold;
+pub use visit::Visitor;
+pub use visit_mut::MutVisitor;
+
 pub type Program = Vec<Stmt>;
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Stmt {
-    LetStmt(Ident, Option<Expr>),
-    ReturnStmt(Option<Expr>),
+    LetStmt(Pattern, Option<Type>, Option<Expr>),
+    ReturnStmt(Option<Expr>, Span),
     ExprStmt(Expr),
     BlockStmt(Vec<Stmt>),
     IfStmt(Expr, Box<Stmt>, Option<Box<Stmt>>),
     WhileStmt(Expr, Box<Stmt>),
-    FnStmt(Ident, Vec<Expr>, Box<Stmt>),
+    ForStmt(Pattern, Expr, Box<Stmt>),
+    FnStmt(
+        Ident,
+        Vec<Param>,
+        Option<Type>,
+        Box<Stmt>,
+        Vec<Decorator>,
+        Vec<Contract>,
+    ),
+    TryStmt(Box<Stmt>, Option<(Ident, Box<Stmt>)>, Option<Box<Stmt>>),
+    ThrowStmt(Expr),
+    YieldStmt(Option<Expr>),
+    DeferStmt(Expr),
+    MacroStmt(Ident, Vec<Ident>, Box<Stmt>),
+    ErrorStmt(Span),
 }
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Expr {
     IdentExpr(Ident),
     LiteralExpr(Literal),
-    AssignmentExpr(Ident, Box<Expr>),
+    AssignmentExpr(Pattern, Box<Expr>),
     UnaryExpr(UnaryOp, Box<Expr>),
     BinExpr(Box<Expr>, BinOp, Box<Expr>),
-    CallExpr(Box<Expr>, Vec<Expr>),
+    CallExpr(Box<Expr>, Vec<Arg>),
+    PropagateExpr(Box<Expr>),
+    SpawnExpr(Box<Expr>, Vec<Arg>),
+    CoalesceExpr(Box<Expr>, Box<Expr>),
+    OptionalCallExpr(Box<Expr>, Vec<Arg>),
+    MethodCallExpr(Box<Expr>, Ident, Vec<Arg>, bool),
+    TupleExpr(Vec<Expr>),
+    RangeExpr(Box<Expr>, Box<Expr>, bool, Option<Box<Expr>>),
+    IndexExpr(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::AssignmentExpr(..) => 1,
+            Expr::CoalesceExpr(..) => 2,
+            Expr::BinExpr(_, BinOp::Equal | BinOp::NotEqual, _) => 3,
+            Expr::BinExpr(_, BinOp::Less | BinOp::Greater, _) => 4,
+            Expr::BinExpr(_, BinOp::LessEqual | BinOp::GreaterEqual, _) => 4,
+            Expr::RangeExpr(..) => 5,
+            Expr::BinExpr(_, BinOp::Plus | BinOp::Minus, _) => 6,
+            Expr::BinExpr(_, BinOp::Multiply | BinOp::Divide, _) => 7,
+            Expr::UnaryExpr(..) | Expr::SpawnExpr(..) => 8,
+            _ => 9,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut std::fmt::Formatter<'_>, min: u8) -> std::fmt::Result {
+        match self.precedence() < min {
+            true => write!(f, "({})", self),
+            false => write!(f, "{}", self),
+        }
+    }
+}
+
+fn fmt_args(f: &mut std::fmt::Formatter<'_>, args: &[Arg]) -> std::fmt::Result {
+    write!(f, "(")?;
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        match arg {
+            Arg::Positional(expr) => write!(f, "{}", expr)?,
+            Arg::Named(name, expr) => write!(f, "{}: {}", name.0, expr)?,
+        }
+    }
+    write!(f, ")")
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let precedence = self.precedence();
+        match self {
+            Expr::IdentExpr(ident) => write!(f, "{}", ident.0),
+            Expr::LiteralExpr(literal) => write!(f, "{}", literal),
+            Expr::AssignmentExpr(pattern, expr) => {
+                write!(f, "{} = ", pattern)?;
+                expr.fmt_operand(f, precedence + 1)
+            }
+            Expr::UnaryExpr(op, expr) => {
+                let op = match op {
+                    UnaryOp::UnaryPlus => "+",
+                    UnaryOp::UnaryMinus => "-",
+                    UnaryOp::Not => "!",
+                };
+                write!(f, "{}", op)?;
+                expr.fmt_operand(f, precedence)
+            }
+            Expr::BinExpr(l_expr, op, r_expr) => {
+                l_expr.fmt_operand(f, precedence)?;
+                write!(f, " {} ", op)?;
+                r_expr.fmt_operand(f, precedence + 1)
+            }
+            Expr::CallExpr(func, args) => {
+                func.fmt_operand(f, precedence)?;
+                fmt_args(f, args)
+            }
+            Expr::PropagateExpr(expr) => {
+                expr.fmt_operand(f, precedence)?;
+                write!(f, "?")
+            }
+            Expr::SpawnExpr(func, args) => {
+                write!(f, "spawn ")?;
+                func.fmt_operand(f, 9)?;
+                fmt_args(f, args)
+            }
+            Expr::CoalesceExpr(l_expr, r_expr) => {
+                l_expr.fmt_operand(f, precedence)?;
+                write!(f, " ?? ")?;
+                r_expr.fmt_operand(f, precedence + 1)
+            }
+            Expr::OptionalCallExpr(func, args) => {
+                func.fmt_operand(f, precedence)?;
+                write!(f, "?.")?;
+                fmt_args(f, args)
+            }
+            Expr::MethodCallExpr(receiver, name, args, optional) => {
+                receiver.fmt_operand(f, precedence)?;
+                match optional {
+                    true => write!(f, "?.{}", name.0)?,
+                    false => write!(f, ".{}", name.0)?,
+                }
+                fmt_args(f, args)
+            }
+            Expr::TupleExpr(exprs) => {
+                write!(f, "(")?;
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", expr)?;
+                }
+                if exprs.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Expr::RangeExpr(start, end, inclusive, step) => {
+                start.fmt_operand(f, precedence + 1)?;
+                match inclusive {
+                    true => write!(f, "..=")?,
+                    false => write!(f, "..")?,
+                }
+                end.fmt_operand(f, precedence + 1)?;
+                if let Some(step) = step {
+                    write!(f, " step ")?;
+                    step.fmt_operand(f, precedence + 1)?;
+                }
+                Ok(())
+            }
+            Expr::IndexExpr(target, index) => {
+                target.fmt_operand(f, precedence)?;
+                write!(f, "[{}]", index)
+            }
+        }
+    }
+}
+
+fn fmt_body(f: &mut std::fmt::Formatter<'_>, body: &Stmt) -> std::fmt::Result {
+    match body {
+        Stmt::BlockStmt(stmts) => {
+            write!(f, "{{")?;
+            for stmt in stmts {
+                write!(f, " {}", stmt)?;
+            }
+            write!(f, " }}")
+        }
+        stmt => write!(f, "{}", stmt),
+    }
+}
+
+impl std::fmt::Display for Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.variadic {
+            write!(f, "...")?;
+        }
+        match &self.pattern {
+            Some(pattern) => write!(f, "{}", pattern)?,
+            None => write!(f, "{}", self.name.0)?,
+        }
+        if let Some(ty) = self.ty {
+            write!(f, ": {}", ty)?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " = {}", default)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stmt::LetStmt(pattern, ty, init) => {
+                write!(f, "let {}", pattern)?;
+                if let Some(ty) = ty {
+                    write!(f, ": {}", ty)?;
+                }
+                if let Some(init) = init {
+                    write!(f, " = {}", init)?;
+                }
+                write!(f, ";")
+            }
+            Stmt::ReturnStmt(None, _) => write!(f, "return;"),
+            Stmt::ReturnStmt(Some(expr), _) => write!(f, "return {};", expr),
+            Stmt::ExprStmt(expr) => write!(f, "{};", expr),
+            Stmt::BlockStmt(_) => fmt_body(f, self),
+            Stmt::IfStmt(condition, body, alt) => {
+                write!(f, "if {} ", condition)?;
+                fmt_body(f, body)?;
+                match alt {
+                    Some(alt) => {
+                        write!(f, " else ")?;
+                        fmt_body(f, alt)
+                    }
+                    None => Ok(()),
+                }
+            }
+            Stmt::WhileStmt(condition, body) => {
+                write!(f, "while {} ", condition)?;
+                fmt_body(f, body)
+            }
+            Stmt::ForStmt(pattern, iterable, body) => {
+                write!(f, "for {} in {} ", pattern, iterable)?;
+                fmt_body(f, body)
+            }
+            Stmt::FnStmt(ident, params, ret, body, decorators, contracts) => {
+                for decorator in decorators {
+                    write!(f, "@{}", decorator.name.0)?;
+                    if !decorator.args.is_empty() {
+                        fmt_args(f, &decorator.args)?;
+                    }
+                    write!(f, " ")?;
+                }
+                let params: Vec<String> = params.iter().map(|param| param.to_string()).collect();
+                write!(f, "fn {}({})", ident.0, params.join(", "))?;
+                if let Some(ret) = ret {
+                    write!(f, " -> {}", ret)?;
+                }
+                for contract in contracts {
+                    write!(f, " {}", contract)?;
+                }
+                write!(f, " ")?;
+                match body.as_ref() {
+                    Stmt::BlockStmt(stmts)
+                        if matches!(stmts.last(), Some(Stmt::ReturnStmt(None, _))) =>
+                    {
+                        fmt_body(f, &Stmt::BlockStmt(stmts[..stmts.len() - 1].to_vec()))
+                    }
+                    body => fmt_body(f, body),
+                }
+            }
+            Stmt::TryStmt(body, handler, finally) => {
+                write!(f, "try ")?;
+                fmt_body(f, body)?;
+                if let Some((ident, body)) = handler {
+                    write!(f, " catch ({}) ", ident.0)?;
+                    fmt_body(f, body)?;
+                }
+                if let Some(finally) = finally {
+                    write!(f, " finally ")?;
+                    fmt_body(f, finally)?;
+                }
+                Ok(())
+            }
+            Stmt::ThrowStmt(expr) => write!(f, "throw {};", expr),
+            Stmt::YieldStmt(None) => write!(f, "yield;"),
+            Stmt::YieldStmt(Some(expr)) => write!(f, "yield {};", expr),
+            Stmt::DeferStmt(expr) => write!(f, "defer {};", expr),
+            Stmt::MacroStmt(ident, params, body) => {
+                let params: Vec<&str> = params.iter().map(|param| param.0.as_str()).collect();
+                write!(f, "macro {}({}) ", ident.0, params.join(", "))?;
+                fmt_body(f, body)
+            }
+            Stmt::ErrorStmt(span) => write!(f, "<error at {}>", span),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum Pattern {
+    Ident(Ident),
+    Tuple(Vec<Pattern>),
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Ident(ident) => write!(f, "{}", ident.0),
+            Pattern::Tuple(patterns) => {
+                write!(f, "(")?;
+                for (i, pattern) in patterns.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", pattern)?;
+                }
+                if patterns.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct Param {
+    pub name: Ident,
+    pub pattern: Option<Pattern>,
+    pub ty: Option<Type>,
+    pub default: Option<Expr>,
+    pub variadic: bool,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct Decorator {
+    pub name: Ident,
+    pub args: Vec<Arg>,
+}
+
+impl Decorator {
+    pub fn is_builtin(&self) -> bool {
+        matches!(self.name.0.as_str(), "memo" | "trace" | "deprecated")
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum Contract {
+    Requires(Expr),
+    Ensures(Expr),
+}
+
+impl std::fmt::Display for Contract {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Contract::Requires(expr) => write!(f, "requires {}", expr),
+            Contract::Ensures(expr) => write!(f, "ensures {}", expr),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum Arg {
+    Positional(Expr),
+    Named(Ident, Expr),
 }
 
 #[derive(Eq, PartialEq, Clone, Debug)]
@@ -24,16 +373,87 @@ pub enum Literal {
     IntLiteral(i64),
     BoolLiteral(bool),
     StringLiteral(String),
+    NilLiteral,
 }
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::IntLiteral(value) => write!(f, "{}", value),
+            Literal::BoolLiteral(value) => write!(f, "{}", value),
+            Literal::StringLiteral(value) => write!(f, "\"{}\"", value),
+            Literal::NilLiteral => write!(f, "nil"),
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Clone, Debug)]
-pub struct Ident(pub String);
-#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct Ident(pub String, pub Span);
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum Type {
+    Int,
+    Bool,
+    String,
+    Nil,
+    List,
+    Function,
+    Error,
+    Result,
+    Generator,
+    Channel,
+    Tuple,
+    Range,
+    Any,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "int" => Some(Type::Int),
+            "bool" => Some(Type::Bool),
+            "string" => Some(Type::String),
+            "nil" => Some(Type::Nil),
+            "list" => Some(Type::List),
+            "function" => Some(Type::Function),
+            "error" => Some(Type::Error),
+            "result" => Some(Type::Result),
+            "generator" => Some(Type::Generator),
+            "channel" => Some(Type::Channel),
+            "tuple" => Some(Type::Tuple),
+            "range" => Some(Type::Range),
+            "any" => Some(Type::Any),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Type::Int => "int",
+            Type::Bool => "bool",
+            Type::String => "string",
+            Type::Nil => "nil",
+            Type::List => "list",
+            Type::Function => "function",
+            Type::Error => "error",
+            Type::Result => "result",
+            Type::Generator => "generator",
+            Type::Channel => "channel",
+            Type::Tuple => "tuple",
+            Type::Range => "range",
+            Type::Any => "any",
+        };
+        write!(f, "{}", name)
+    }
+}
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
 pub enum UnaryOp {
     UnaryPlus,
     UnaryMinus,
     Not,
 }
-#[derive(Eq, PartialEq, Clone, Debug)]
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
 pub enum BinOp {
     Plus,
     Minus,
@@ -47,3 +467,21 @@ pub enum BinOp {
     Equal,
     NotEqual,
 }
+
+impl std::fmt::Display for BinOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            BinOp::Plus => "+",
+            BinOp::Minus => "-",
+            BinOp::Divide => "/",
+            BinOp::Multiply => "*",
+            BinOp::Less => "<",
+            BinOp::Greater => ">",
+            BinOp::LessEqual => "<=",
+            BinOp::GreaterEqual => ">=",
+            BinOp::Equal => "==",
+            BinOp::NotEqual => "!=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
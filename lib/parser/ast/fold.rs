@@ -0,0 +1,227 @@
+use crate::parser::ast::{
+    Arg, BinOp, Contract, Decorator, Expr, Ident, Literal, Param, Pattern, Program, Stmt, Type,
+    UnaryOp,
+};
+
+pub trait Fold: Sized {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_program(self, program)
+    }
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+        fold_stmt(self, stmt)
+    }
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_expr(self, expr)
+    }
+    fn fold_pattern(&mut self, pattern: Pattern) -> Pattern {
+        fold_pattern(self, pattern)
+    }
+    fn fold_param(&mut self, param: Param) -> Param {
+        fold_param(self, param)
+    }
+    fn fold_decorator(&mut self, decorator: Decorator) -> Decorator {
+        fold_decorator(self, decorator)
+    }
+    fn fold_contract(&mut self, contract: Contract) -> Contract {
+        fold_contract(self, contract)
+    }
+    fn fold_arg(&mut self, arg: Arg) -> Arg {
+        fold_arg(self, arg)
+    }
+    fn fold_ident(&mut self, ident: Ident) -> Ident {
+        ident
+    }
+    fn fold_literal(&mut self, literal: Literal) -> Literal {
+        literal
+    }
+    fn fold_type(&mut self, ty: Type) -> Type {
+        ty
+    }
+    fn fold_unary_op(&mut self, op: UnaryOp) -> UnaryOp {
+        op
+    }
+    fn fold_bin_op(&mut self, op: BinOp) -> BinOp {
+        op
+    }
+}
+
+pub fn fold_program<F: Fold>(folder: &mut F, program: Program) -> Program {
+    program
+        .into_iter()
+        .map(|stmt| folder.fold_stmt(stmt))
+        .collect()
+}
+
+fn fold_box<F: Fold>(folder: &mut F, expr: Expr) -> Box<Expr> {
+    Box::from(folder.fold_expr(expr))
+}
+
+fn fold_body<F: Fold>(folder: &mut F, stmt: Stmt) -> Box<Stmt> {
+    Box::from(folder.fold_stmt(stmt))
+}
+
+fn fold_args<F: Fold>(folder: &mut F, args: Vec<Arg>) -> Vec<Arg> {
+    args.into_iter().map(|arg| folder.fold_arg(arg)).collect()
+}
+
+pub fn fold_stmt<F: Fold>(folder: &mut F, stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::LetStmt(pattern, ty, init) => Stmt::LetStmt(
+            folder.fold_pattern(pattern),
+            ty.map(|ty| folder.fold_type(ty)),
+            init.map(|init| folder.fold_expr(init)),
+        ),
+        Stmt::ReturnStmt(expr, span) => {
+            Stmt::ReturnStmt(expr.map(|expr| folder.fold_expr(expr)), span)
+        }
+        Stmt::ExprStmt(expr) => Stmt::ExprStmt(folder.fold_expr(expr)),
+        Stmt::BlockStmt(stmts) => Stmt::BlockStmt(folder.fold_program(stmts)),
+        Stmt::IfStmt(condition, body, alt) => Stmt::IfStmt(
+            folder.fold_expr(condition),
+            fold_body(folder, *body),
+            alt.map(|alt| fold_body(folder, *alt)),
+        ),
+        Stmt::WhileStmt(condition, body) => {
+            Stmt::WhileStmt(folder.fold_expr(condition), fold_body(folder, *body))
+        }
+        Stmt::ForStmt(pattern, iterable, body) => Stmt::ForStmt(
+            folder.fold_pattern(pattern),
+            folder.fold_expr(iterable),
+            fold_body(folder, *body),
+        ),
+        Stmt::FnStmt(ident, params, ret, body, decorators, contracts) => {
+            let decorators = decorators
+                .into_iter()
+                .map(|decorator| folder.fold_decorator(decorator))
+                .collect();
+            let ident = folder.fold_ident(ident);
+            let params = params
+                .into_iter()
+                .map(|param| folder.fold_param(param))
+                .collect();
+            let ret = ret.map(|ret| folder.fold_type(ret));
+            let contracts = contracts
+                .into_iter()
+                .map(|contract| folder.fold_contract(contract))
+                .collect();
+            let body = fold_body(folder, *body);
+            Stmt::FnStmt(ident, params, ret, body, decorators, contracts)
+        }
+        Stmt::TryStmt(body, handler, finally) => Stmt::TryStmt(
+            fold_body(folder, *body),
+            handler.map(|(ident, body)| (folder.fold_ident(ident), fold_body(folder, *body))),
+            finally.map(|finally| fold_body(folder, *finally)),
+        ),
+        Stmt::ThrowStmt(expr) => Stmt::ThrowStmt(folder.fold_expr(expr)),
+        Stmt::YieldStmt(expr) => Stmt::YieldStmt(expr.map(|expr| folder.fold_expr(expr))),
+        Stmt::DeferStmt(expr) => Stmt::DeferStmt(folder.fold_expr(expr)),
+        Stmt::MacroStmt(ident, params, body) => Stmt::MacroStmt(
+            folder.fold_ident(ident),
+            params
+                .into_iter()
+                .map(|param| folder.fold_ident(param))
+                .collect(),
+            fold_body(folder, *body),
+        ),
+        Stmt::ErrorStmt(span) => Stmt::ErrorStmt(span),
+    }
+}
+
+pub fn fold_expr<F: Fold>(folder: &mut F, expr: Expr) -> Expr {
+    match expr {
+        Expr::IdentExpr(ident) => Expr::IdentExpr(folder.fold_ident(ident)),
+        Expr::LiteralExpr(literal) => Expr::LiteralExpr(folder.fold_literal(literal)),
+        Expr::AssignmentExpr(pattern, expr) => {
+            Expr::AssignmentExpr(folder.fold_pattern(pattern), fold_box(folder, *expr))
+        }
+        Expr::UnaryExpr(op, expr) => {
+            Expr::UnaryExpr(folder.fold_unary_op(op), fold_box(folder, *expr))
+        }
+        Expr::BinExpr(l_expr, op, r_expr) => Expr::BinExpr(
+            fold_box(folder, *l_expr),
+            folder.fold_bin_op(op),
+            fold_box(folder, *r_expr),
+        ),
+        Expr::CallExpr(func, args) => {
+            Expr::CallExpr(fold_box(folder, *func), fold_args(folder, args))
+        }
+        Expr::PropagateExpr(expr) => Expr::PropagateExpr(fold_box(folder, *expr)),
+        Expr::SpawnExpr(func, args) => {
+            Expr::SpawnExpr(fold_box(folder, *func), fold_args(folder, args))
+        }
+        Expr::CoalesceExpr(l_expr, r_expr) => {
+            Expr::CoalesceExpr(fold_box(folder, *l_expr), fold_box(folder, *r_expr))
+        }
+        Expr::OptionalCallExpr(func, args) => {
+            Expr::OptionalCallExpr(fold_box(folder, *func), fold_args(folder, args))
+        }
+        Expr::MethodCallExpr(receiver, name, args, optional) => Expr::MethodCallExpr(
+            fold_box(folder, *receiver),
+            folder.fold_ident(name),
+            fold_args(folder, args),
+            optional,
+        ),
+        Expr::TupleExpr(exprs) => Expr::TupleExpr(
+            exprs
+                .into_iter()
+                .map(|expr| folder.fold_expr(expr))
+                .collect(),
+        ),
+        Expr::RangeExpr(start, end, inclusive, step) => Expr::RangeExpr(
+            fold_box(folder, *start),
+            fold_box(folder, *end),
+            inclusive,
+            step.map(|step| fold_box(folder, *step)),
+        ),
+        Expr::IndexExpr(target, index) => {
+            Expr::IndexExpr(fold_box(folder, *target), fold_box(folder, *index))
+        }
+    }
+}
+
+pub fn fold_pattern<F: Fold>(folder: &mut F, pattern: Pattern) -> Pattern {
+    match pattern {
+        Pattern::Ident(ident) => Pattern::Ident(folder.fold_ident(ident)),
+        Pattern::Tuple(patterns) => Pattern::Tuple(
+            patterns
+                .into_iter()
+                .map(|pattern| folder.fold_pattern(pattern))
+                .collect(),
+        ),
+    }
+}
+
+pub fn fold_param<F: Fold>(folder: &mut F, param: Param) -> Param {
+    let (name, pattern) = match param.pattern {
+        Some(pattern) => (param.name, Some(folder.fold_pattern(pattern))),
+        None => (folder.fold_ident(param.name), None),
+    };
+    Param {
+        name,
+        pattern,
+        ty: param.ty.map(|ty| folder.fold_type(ty)),
+        default: param.default.map(|default| folder.fold_expr(default)),
+        variadic: param.variadic,
+    }
+}
+
+pub fn fold_decorator<F: Fold>(folder: &mut F, decorator: Decorator) -> Decorator {
+    Decorator {
+        name: folder.fold_ident(decorator.name),
+        args: fold_args(folder, decorator.args),
+    }
+}
+
+pub fn fold_contract<F: Fold>(folder: &mut F, contract: Contract) -> Contract {
+    match contract {
+        Contract::Requires(expr) => Contract::Requires(folder.fold_expr(expr)),
+        Contract::Ensures(expr) => Contract::Ensures(folder.fold_expr(expr)),
+    }
+}
+
+pub fn fold_arg<F: Fold>(folder: &mut F, arg: Arg) -> Arg {
+    match arg {
+        Arg::Positional(expr) => Arg::Positional(folder.fold_expr(expr)),
+        Arg::Named(ident, expr) => Arg::Named(folder.fold_ident(ident), folder.fold_expr(expr)),
+    }
+}
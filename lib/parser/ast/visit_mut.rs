@@ -0,0 +1,212 @@
+use crate::parser::ast::{
+    Arg, BinOp, Contract, Decorator, Expr, Ident, Literal, Param, Pattern, Stmt, Type, UnaryOp,
+};
+
+pub trait MutVisitor: Sized {
+    fn visit_program(&mut self, program: &mut Vec<Stmt>) {
+        walk_program(self, program)
+    }
+    fn visit_stmt(&mut self, stmt: &mut Stmt) {
+        walk_stmt(self, stmt)
+    }
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        walk_expr(self, expr)
+    }
+    fn visit_pattern(&mut self, pattern: &mut Pattern) {
+        walk_pattern(self, pattern)
+    }
+    fn visit_param(&mut self, param: &mut Param) {
+        walk_param(self, param)
+    }
+    fn visit_decorator(&mut self, decorator: &mut Decorator) {
+        walk_decorator(self, decorator)
+    }
+    fn visit_contract(&mut self, contract: &mut Contract) {
+        walk_contract(self, contract)
+    }
+    fn visit_arg(&mut self, arg: &mut Arg) {
+        walk_arg(self, arg)
+    }
+    fn visit_ident(&mut self, _ident: &mut Ident) {}
+    fn visit_literal(&mut self, _literal: &mut Literal) {}
+    fn visit_type(&mut self, _ty: &mut Type) {}
+    fn visit_unary_op(&mut self, _op: &mut UnaryOp) {}
+    fn visit_bin_op(&mut self, _op: &mut BinOp) {}
+}
+
+pub fn walk_program<V: MutVisitor>(visitor: &mut V, program: &mut [Stmt]) {
+    for stmt in program.iter_mut() {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: MutVisitor>(visitor: &mut V, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::LetStmt(pattern, ty, init) => {
+            visitor.visit_pattern(pattern);
+            if let Some(ty) = ty {
+                visitor.visit_type(ty);
+            }
+            if let Some(init) = init {
+                visitor.visit_expr(init);
+            }
+        }
+        Stmt::ReturnStmt(expr, _) | Stmt::YieldStmt(expr) => {
+            if let Some(expr) = expr {
+                visitor.visit_expr(expr);
+            }
+        }
+        Stmt::ExprStmt(expr) | Stmt::ThrowStmt(expr) | Stmt::DeferStmt(expr) => {
+            visitor.visit_expr(expr)
+        }
+        Stmt::BlockStmt(stmts) => visitor.visit_program(stmts),
+        Stmt::IfStmt(condition, body, alt) => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(body);
+            if let Some(alt) = alt {
+                visitor.visit_stmt(alt);
+            }
+        }
+        Stmt::WhileStmt(condition, body) => {
+            visitor.visit_expr(condition);
+            visitor.visit_stmt(body);
+        }
+        Stmt::ForStmt(pattern, iterable, body) => {
+            visitor.visit_pattern(pattern);
+            visitor.visit_expr(iterable);
+            visitor.visit_stmt(body);
+        }
+        Stmt::FnStmt(ident, params, ret, body, decorators, contracts) => {
+            for decorator in decorators {
+                visitor.visit_decorator(decorator);
+            }
+            visitor.visit_ident(ident);
+            for param in params {
+                visitor.visit_param(param);
+            }
+            if let Some(ret) = ret {
+                visitor.visit_type(ret);
+            }
+            for contract in contracts {
+                visitor.visit_contract(contract);
+            }
+            visitor.visit_stmt(body);
+        }
+        Stmt::TryStmt(body, handler, finally) => {
+            visitor.visit_stmt(body);
+            if let Some((ident, body)) = handler {
+                visitor.visit_ident(ident);
+                visitor.visit_stmt(body);
+            }
+            if let Some(finally) = finally {
+                visitor.visit_stmt(finally);
+            }
+        }
+        Stmt::MacroStmt(ident, params, body) => {
+            visitor.visit_ident(ident);
+            for param in params {
+                visitor.visit_ident(param);
+            }
+            visitor.visit_stmt(body);
+        }
+        Stmt::ErrorStmt(_) => (),
+    }
+}
+
+pub fn walk_expr<V: MutVisitor>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::IdentExpr(ident) => visitor.visit_ident(ident),
+        Expr::LiteralExpr(literal) => visitor.visit_literal(literal),
+        Expr::AssignmentExpr(pattern, expr) => {
+            visitor.visit_pattern(pattern);
+            visitor.visit_expr(expr);
+        }
+        Expr::UnaryExpr(op, expr) => {
+            visitor.visit_unary_op(op);
+            visitor.visit_expr(expr);
+        }
+        Expr::BinExpr(l_expr, op, r_expr) => {
+            visitor.visit_expr(l_expr);
+            visitor.visit_bin_op(op);
+            visitor.visit_expr(r_expr);
+        }
+        Expr::CallExpr(func, args)
+        | Expr::SpawnExpr(func, args)
+        | Expr::OptionalCallExpr(func, args) => {
+            visitor.visit_expr(func);
+            for arg in args {
+                visitor.visit_arg(arg);
+            }
+        }
+        Expr::PropagateExpr(expr) => visitor.visit_expr(expr),
+        Expr::CoalesceExpr(l_expr, r_expr) | Expr::IndexExpr(l_expr, r_expr) => {
+            visitor.visit_expr(l_expr);
+            visitor.visit_expr(r_expr);
+        }
+        Expr::MethodCallExpr(receiver, name, args, _) => {
+            visitor.visit_expr(receiver);
+            visitor.visit_ident(name);
+            for arg in args {
+                visitor.visit_arg(arg);
+            }
+        }
+        Expr::TupleExpr(exprs) => {
+            for expr in exprs {
+                visitor.visit_expr(expr);
+            }
+        }
+        Expr::RangeExpr(start, end, _, step) => {
+            visitor.visit_expr(start);
+            visitor.visit_expr(end);
+            if let Some(step) = step {
+                visitor.visit_expr(step);
+            }
+        }
+    }
+}
+
+pub fn walk_pattern<V: MutVisitor>(visitor: &mut V, pattern: &mut Pattern) {
+    match pattern {
+        Pattern::Ident(ident) => visitor.visit_ident(ident),
+        Pattern::Tuple(patterns) => {
+            for pattern in patterns {
+                visitor.visit_pattern(pattern);
+            }
+        }
+    }
+}
+
+pub fn walk_param<V: MutVisitor>(visitor: &mut V, param: &mut Param) {
+    match &mut param.pattern {
+        Some(pattern) => visitor.visit_pattern(pattern),
+        None => visitor.visit_ident(&mut param.name),
+    }
+    if let Some(ty) = &mut param.ty {
+        visitor.visit_type(ty);
+    }
+    if let Some(default) = &mut param.default {
+        visitor.visit_expr(default);
+    }
+}
+
+pub fn walk_decorator<V: MutVisitor>(visitor: &mut V, decorator: &mut Decorator) {
+    visitor.visit_ident(&mut decorator.name);
+    for arg in decorator.args.iter_mut() {
+        visitor.visit_arg(arg);
+    }
+}
+
+pub fn walk_contract<V: MutVisitor>(visitor: &mut V, contract: &mut Contract) {
+    let (Contract::Requires(expr) | Contract::Ensures(expr)) = contract;
+    visitor.visit_expr(expr);
+}
+
+pub fn walk_arg<V: MutVisitor>(visitor: &mut V, arg: &mut Arg) {
+    match arg {
+        Arg::Positional(expr) => visitor.visit_expr(expr),
+        Arg::Named(ident, expr) => {
+            visitor.visit_ident(ident);
+            visitor.visit_expr(expr);
+        }
+    }
+}
@@ -0,0 +1,792 @@
+use crate::lexer::token::{Lexeme, Span, Token, TokenKind};
+use crate::parser::ast::Type;
+use crate::parser::cst::{CstToken, Element, Node, NodeKind};
+use crate::parser::{ParseError, MAX_ERRORS};
+
+pub struct CstParser<'a, 'src> {
+    lexemes: &'a [Lexeme<'src>],
+    position: usize,
+    stack: Vec<(NodeKind, Vec<Element>)>,
+    previous: Option<Lexeme<'src>>,
+    panicking: bool,
+    pub errors: Vec<ParseError>,
+}
+
+impl<'a, 'src> CstParser<'a, 'src> {
+    pub fn new(lexemes: &'a [Lexeme<'src>]) -> Self {
+        Self {
+            lexemes,
+            position: 0,
+            stack: vec![],
+            previous: None,
+            panicking: false,
+            errors: vec![],
+        }
+    }
+    fn significant(&self, n: usize) -> Option<&Lexeme<'src>> {
+        self.lexemes[self.position.min(self.lexemes.len())..]
+            .iter()
+            .filter(|lexeme| !lexeme.kind.is_trivia())
+            .nth(n)
+    }
+    fn current(&self) -> usize {
+        self.position
+            + self.lexemes[self.position.min(self.lexemes.len())..]
+                .iter()
+                .take_while(|lexeme| lexeme.kind.is_trivia())
+                .count()
+    }
+    fn peek(&self) -> TokenKind {
+        self.peek_nth(0)
+    }
+    fn peek_nth(&self, n: usize) -> TokenKind {
+        self.significant(n)
+            .map(|lexeme| lexeme.kind)
+            .unwrap_or(TokenKind::EOF)
+    }
+    fn peek_text(&self) -> &'src str {
+        self.significant(0)
+            .map(|lexeme| lexeme.text)
+            .unwrap_or_default()
+    }
+    fn peek_ident(&self, name: &str) -> bool {
+        self.peek() == TokenKind::Ident && self.peek_text() == name
+    }
+    fn push(&mut self, lexeme: Lexeme<'src>) {
+        let token = CstToken {
+            kind: lexeme.kind,
+            text: lexeme.text.to_string(),
+            span: lexeme.span,
+        };
+        self.stack.last_mut().unwrap().1.push(Element::Token(token));
+    }
+    fn flush_trivia(&mut self) {
+        while let Some(lexeme) = self.lexemes.get(self.position) {
+            if !lexeme.kind.is_trivia() {
+                break;
+            }
+            self.push(*lexeme);
+            self.position += 1;
+        }
+    }
+    fn bump(&mut self) {
+        self.flush_trivia();
+        if let Some(lexeme) = self.lexemes.get(self.position) {
+            self.push(*lexeme);
+            self.previous = Some(*lexeme);
+            self.position += 1;
+        }
+    }
+    fn start(&mut self, kind: NodeKind) {
+        self.flush_trivia();
+        self.stack.push((kind, vec![]));
+    }
+    fn checkpoint(&mut self) -> usize {
+        self.flush_trivia();
+        self.stack.last().unwrap().1.len()
+    }
+    fn start_at(&mut self, checkpoint: usize, kind: NodeKind) {
+        let children = self.stack.last_mut().unwrap().1.split_off(checkpoint);
+        self.stack.push((kind, children));
+    }
+    fn finish(&mut self) {
+        let (kind, children) = self.stack.pop().unwrap();
+        self.stack
+            .last_mut()
+            .unwrap()
+            .1
+            .push(Element::Node(Node { kind, children }));
+    }
+    fn last_node(&self) -> Option<&Node> {
+        match self.stack.last().unwrap().1.last() {
+            Some(Element::Node(node)) => Some(node),
+            _ => None,
+        }
+    }
+    fn error(&mut self, message: &str) {
+        if self.panicking || self.gave_up() {
+            return;
+        }
+        self.panicking = true;
+        let message = match self.errors.len() == MAX_ERRORS {
+            true => "Too many errors, giving up",
+            false => message,
+        };
+        self.errors.push(ParseError {
+            token: match self.previous {
+                Some(lexeme) => lexeme.to_token(),
+                None => Token::EOF,
+            },
+            message: message.to_string(),
+            span: self.previous.map(|lexeme| lexeme.span).unwrap_or_default(),
+        })
+    }
+    fn gave_up(&self) -> bool {
+        self.errors.len() > MAX_ERRORS
+    }
+    fn expect(&mut self, kind: TokenKind, message: &str) -> Result<(), ()> {
+        if self.peek() != kind {
+            self.error(message);
+            return Err(());
+        }
+        self.bump();
+        Ok(())
+    }
+    fn synchronize(&mut self) {
+        loop {
+            match self.peek() {
+                TokenKind::RBrace => return,
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => self.skip_balanced(),
+                TokenKind::Let
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::Return
+                | TokenKind::Function
+                | TokenKind::At
+                | TokenKind::Try
+                | TokenKind::Throw
+                | TokenKind::Yield
+                | TokenKind::Defer
+                | TokenKind::EOF => return,
+                TokenKind::SemiColon => {
+                    self.bump();
+                    return;
+                }
+                _ => self.bump(),
+            }
+        }
+    }
+    fn skip_balanced(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => depth -= 1,
+                TokenKind::EOF => return,
+                _ => (),
+            }
+            self.bump();
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
+    pub fn parse(&mut self) -> Node {
+        self.stack.push((NodeKind::Root, vec![]));
+        while self.peek() != TokenKind::EOF {
+            if self.peek() == TokenKind::RBrace {
+                self.panicking = false;
+                self.start(NodeKind::Error);
+                self.bump();
+                self.error("Unexpected '}'");
+                self.finish();
+                continue;
+            }
+            self.parse_stmt_recover();
+        }
+        self.bump();
+        let (kind, children) = self.stack.pop().unwrap();
+        Node { kind, children }
+    }
+    fn parse_stmt_recover(&mut self) {
+        self.panicking = false;
+        let depth = self.stack.len();
+        let checkpoint = self.checkpoint();
+        let position = self.current();
+        if self.parse_stmt().is_err() {
+            while self.stack.len() > depth {
+                self.finish();
+            }
+            self.start_at(checkpoint, NodeKind::Error);
+            self.synchronize();
+            if self.current() == position {
+                self.bump();
+            }
+            self.finish();
+        }
+    }
+    fn parse_stmt(&mut self) -> Result<(), ()> {
+        match self.peek() {
+            TokenKind::Let => self.parse_let(),
+            TokenKind::LBrace => self.parse_block("Expect '{'"),
+            TokenKind::If => self.parse_if(),
+            TokenKind::While => self.parse_while(),
+            TokenKind::For => self.parse_for(),
+            TokenKind::Function | TokenKind::At => self.parse_fn(),
+            TokenKind::Return => self.parse_optional_expr(NodeKind::ReturnStmt),
+            TokenKind::Yield => self.parse_optional_expr(NodeKind::YieldStmt),
+            TokenKind::Try => self.parse_try(),
+            TokenKind::Throw => self.parse_keyword_expr(NodeKind::ThrowStmt, "statement"),
+            TokenKind::Defer => self.parse_keyword_expr(NodeKind::DeferStmt, "defer expression"),
+            TokenKind::Ident
+                if self.peek_ident("macro") && self.peek_nth(1) == TokenKind::Ident =>
+            {
+                self.parse_macro()
+            }
+            _ => {
+                self.start(NodeKind::ExprStmt);
+                self.parse_expr()?;
+                self.expect(TokenKind::SemiColon, "Expected ';' after expression")?;
+                self.finish();
+                Ok(())
+            }
+        }
+    }
+    fn parse_macro(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::MacroStmt);
+        self.bump();
+        self.bump();
+        self.expect(TokenKind::LParen, "Expected '(' after macro name")?;
+        let mut params = vec![];
+        if self.peek() != TokenKind::RParen {
+            loop {
+                if self.peek() != TokenKind::Ident {
+                    self.error("Expected macro parameter name");
+                    return Err(());
+                }
+                if params.contains(&self.peek_text()) {
+                    self.error("Duplicate macro parameter");
+                    return Err(());
+                }
+                params.push(self.peek_text());
+                self.bump();
+                if self.peek() != TokenKind::Comma {
+                    break;
+                }
+                self.bump();
+            }
+        }
+        self.expect(TokenKind::RParen, "Expected ')' after macro parameters")?;
+        self.parse_block("Expected '{' after macro parameters")?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_optional_expr(&mut self, kind: NodeKind) -> Result<(), ()> {
+        self.start(kind);
+        self.bump();
+        if self.peek() != TokenKind::SemiColon {
+            self.parse_expr()?;
+        }
+        self.expect(TokenKind::SemiColon, "Expected ';' after statement")?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_keyword_expr(&mut self, kind: NodeKind, what: &str) -> Result<(), ()> {
+        self.start(kind);
+        self.bump();
+        self.parse_expr()?;
+        self.expect(
+            TokenKind::SemiColon,
+            &format!("Expected ';' after {}", what),
+        )?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_let(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::LetStmt);
+        self.bump();
+        if !matches!(self.peek(), TokenKind::Ident | TokenKind::LParen) {
+            self.error("Expect identifier after 'let'");
+            return Err(());
+        }
+        let tuple = self.parse_pattern()?;
+        if self.peek() == TokenKind::Colon {
+            self.bump();
+            self.parse_type()?;
+        }
+        if self.peek() == TokenKind::Assign {
+            self.bump();
+            self.parse_expr()?;
+        } else if tuple && self.peek() == TokenKind::SemiColon {
+            self.error("Destructuring declaration needs an initializer");
+            return Err(());
+        }
+        self.expect(TokenKind::SemiColon, "Expected ';' after statement")?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_pattern(&mut self) -> Result<bool, ()> {
+        match self.peek() {
+            TokenKind::Ident => {
+                self.start(NodeKind::NamePattern);
+                self.bump();
+                self.finish();
+                Ok(false)
+            }
+            TokenKind::LParen => {
+                let checkpoint = self.checkpoint();
+                self.bump();
+                let mut count = 0;
+                let mut trailing_comma = false;
+                let mut tuple = false;
+                while self.peek() != TokenKind::RParen {
+                    tuple = self.parse_pattern()?;
+                    count += 1;
+                    trailing_comma = self.peek() == TokenKind::Comma;
+                    if !trailing_comma {
+                        break;
+                    }
+                    self.bump();
+                }
+                self.expect(TokenKind::RParen, "Expected ')' after tuple pattern")?;
+                if count == 0 {
+                    self.error("Tuple pattern cannot be empty");
+                    return Err(());
+                }
+                let paren = count == 1 && !trailing_comma;
+                let kind = match paren {
+                    true => NodeKind::ParenPattern,
+                    false => NodeKind::TuplePattern,
+                };
+                self.start_at(checkpoint, kind);
+                self.finish();
+                Ok(!paren || tuple)
+            }
+            _ => {
+                self.error("Expected identifier or tuple pattern");
+                Err(())
+            }
+        }
+    }
+    fn parse_type(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::TypeRef);
+        if self.peek() != TokenKind::Ident {
+            self.error("Expected type name");
+            return Err(());
+        }
+        if Type::from_name(self.peek_text()).is_none() {
+            self.error("Unknown type name");
+            return Err(());
+        }
+        self.bump();
+        self.finish();
+        Ok(())
+    }
+    fn parse_block(&mut self, message: &str) -> Result<(), ()> {
+        self.start(NodeKind::Block);
+        self.expect(TokenKind::LBrace, message)?;
+        while !matches!(self.peek(), TokenKind::RBrace | TokenKind::EOF) {
+            self.parse_stmt_recover();
+        }
+        self.expect(TokenKind::RBrace, "Expect '}' after block")?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_if(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::IfStmt);
+        self.bump();
+        self.parse_expr()?;
+        self.parse_block("Expect '{' after if condition")?;
+        if self.peek() == TokenKind::Else {
+            self.bump();
+            match self.peek() {
+                TokenKind::If => self.parse_if()?,
+                _ => self.parse_block("Expect '{' after else")?,
+            }
+        }
+        self.finish();
+        Ok(())
+    }
+    fn parse_while(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::WhileStmt);
+        self.bump();
+        self.parse_expr()?;
+        self.parse_block("Expect '{' after while condition")?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_for(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::ForStmt);
+        self.bump();
+        self.parse_pattern()?;
+        self.expect(TokenKind::In, "Expect 'in' after for loop variable")?;
+        self.parse_expr()?;
+        self.parse_block("Expect '{' after for loop iterable")?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_try(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::TryStmt);
+        self.bump();
+        self.parse_block("Expect '{' after try")?;
+        let mut handled = false;
+        if self.peek() == TokenKind::Catch {
+            self.start(NodeKind::CatchClause);
+            self.bump();
+            self.expect(TokenKind::LParen, "Expect '(' after catch")?;
+            self.expect(TokenKind::Ident, "Expect identifier in catch clause")?;
+            self.expect(TokenKind::RParen, "Expect ')' after catch identifier")?;
+            self.parse_block("Expect '{' after catch clause")?;
+            self.finish();
+            handled = true;
+        }
+        if self.peek() == TokenKind::Finally {
+            self.start(NodeKind::FinallyClause);
+            self.bump();
+            self.parse_block("Expect '{' after finally")?;
+            self.finish();
+            handled = true;
+        }
+        if !handled {
+            self.error("Expect 'catch' or 'finally' after try block");
+            return Err(());
+        }
+        self.finish();
+        Ok(())
+    }
+    fn parse_fn(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::FnStmt);
+        while self.peek() == TokenKind::At {
+            self.start(NodeKind::Decorator);
+            self.bump();
+            self.expect(TokenKind::Ident, "Expected decorator name after '@'")?;
+            if self.peek() == TokenKind::LParen {
+                self.parse_args()?;
+            }
+            self.finish();
+        }
+        self.expect(TokenKind::Function, "Expected 'fn' after decorators")?;
+        self.expect(
+            TokenKind::Ident,
+            "Expected identifier after function definition",
+        )?;
+        self.parse_params()?;
+        if self.peek() == TokenKind::Arrow {
+            self.bump();
+            self.parse_type()?;
+        }
+        while self.peek_ident("requires") || self.peek_ident("ensures") {
+            self.start(NodeKind::Contract);
+            self.bump();
+            self.parse_expr()?;
+            self.finish();
+        }
+        self.parse_block("Expected '{' after parameters")?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_params(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::ParamList);
+        self.expect(TokenKind::LParen, "Expected '(' after identifier")?;
+        let mut params = Vec::new();
+        if self.peek() != TokenKind::RParen {
+            loop {
+                params.push(self.parse_param()?);
+                if params.len() > 255 {
+                    self.error("Cannot have more than 255 parameters");
+                    return Err(());
+                }
+                if self.peek() != TokenKind::Comma {
+                    break;
+                }
+                self.bump();
+            }
+        }
+        let mut seen_default = false;
+        for (i, &(variadic, default)) in params.iter().enumerate() {
+            if variadic {
+                if i != params.len() - 1 {
+                    self.error("Variadic parameter must be the last parameter");
+                    return Err(());
+                }
+            } else if default {
+                seen_default = true;
+            } else if seen_default {
+                self.error("Parameter without default cannot follow parameter with default");
+                return Err(());
+            }
+        }
+        self.expect(TokenKind::RParen, "Expected ')' after parameters")?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_param(&mut self) -> Result<(bool, bool), ()> {
+        self.start(NodeKind::Param);
+        let variadic = self.peek() == TokenKind::Ellipsis;
+        if variadic {
+            self.bump();
+        }
+        match self.peek() {
+            TokenKind::Ident => self.bump(),
+            TokenKind::LParen if !variadic => {
+                self.parse_pattern()?;
+            }
+            _ => {
+                self.error("Function parameter must be identifiers or tuple patterns");
+                return Err(());
+            }
+        }
+        if self.peek() == TokenKind::Colon {
+            if variadic {
+                self.error("Variadic parameter cannot have a type annotation");
+                return Err(());
+            }
+            self.bump();
+            self.parse_type()?;
+        }
+        let default = self.peek() == TokenKind::Assign;
+        if default {
+            if variadic {
+                self.error("Variadic parameter cannot have a default value");
+                return Err(());
+            }
+            self.bump();
+            self.parse_equality()?;
+        }
+        self.finish();
+        Ok((variadic, default))
+    }
+    fn parse_expr(&mut self) -> Result<(), ()> {
+        let checkpoint = self.checkpoint();
+        self.parse_coalesce()?;
+        if self.peek() == TokenKind::Assign {
+            let assignable = self.last_node().is_some_and(Self::assignable);
+            self.start_at(checkpoint, NodeKind::AssignExpr);
+            self.bump();
+            self.parse_coalesce()?;
+            self.finish();
+            if !assignable {
+                self.error("Illegal assignment");
+                return Err(());
+            }
+        }
+        Ok(())
+    }
+    fn assignable(node: &Node) -> bool {
+        match node.kind {
+            NodeKind::NameExpr => true,
+            NodeKind::TupleExpr | NodeKind::ParenExpr => node.nodes().all(Self::assignable),
+            _ => false,
+        }
+    }
+    fn callable(node: &Node) -> bool {
+        match node.kind {
+            NodeKind::CallExpr => true,
+            NodeKind::ParenExpr => node.nodes().all(Self::callable),
+            _ => false,
+        }
+    }
+    fn parse_coalesce(&mut self) -> Result<(), ()> {
+        let checkpoint = self.checkpoint();
+        self.parse_equality()?;
+        while self.peek() == TokenKind::DoubleQuestion {
+            self.start_at(checkpoint, NodeKind::CoalesceExpr);
+            self.bump();
+            self.parse_equality()?;
+            self.finish();
+        }
+        Ok(())
+    }
+    fn binary(
+        &mut self,
+        operand: fn(&mut Self) -> Result<(), ()>,
+        operators: &[TokenKind],
+    ) -> Result<(), ()> {
+        let checkpoint = self.checkpoint();
+        operand(self)?;
+        while operators.contains(&self.peek()) {
+            self.start_at(checkpoint, NodeKind::BinExpr);
+            self.bump();
+            operand(self)?;
+            self.finish();
+        }
+        Ok(())
+    }
+    fn parse_equality(&mut self) -> Result<(), ()> {
+        self.binary(
+            Self::parse_comparison,
+            &[TokenKind::Equal, TokenKind::NotEqual],
+        )
+    }
+    fn parse_comparison(&mut self) -> Result<(), ()> {
+        self.binary(
+            Self::parse_range,
+            &[
+                TokenKind::Less,
+                TokenKind::LessEqual,
+                TokenKind::Greater,
+                TokenKind::GreaterEqual,
+            ],
+        )
+    }
+    fn parse_range(&mut self) -> Result<(), ()> {
+        let checkpoint = self.checkpoint();
+        self.parse_term()?;
+        if matches!(self.peek(), TokenKind::DotDot | TokenKind::DotDotEqual) {
+            self.start_at(checkpoint, NodeKind::RangeExpr);
+            self.bump();
+            self.parse_term()?;
+            if self.peek_ident("step") {
+                self.bump();
+                self.parse_term()?;
+            }
+            self.finish();
+        }
+        Ok(())
+    }
+    fn parse_term(&mut self) -> Result<(), ()> {
+        self.binary(Self::parse_factor, &[TokenKind::Plus, TokenKind::Minus])
+    }
+    fn parse_factor(&mut self) -> Result<(), ()> {
+        self.binary(Self::parse_unary, &[TokenKind::Multiply, TokenKind::Divide])
+    }
+    fn parse_unary(&mut self) -> Result<(), ()> {
+        match self.peek() {
+            TokenKind::Minus | TokenKind::Plus | TokenKind::Not => {
+                self.start(NodeKind::UnaryExpr);
+                self.bump();
+                self.parse_unary()?;
+                self.finish();
+                Ok(())
+            }
+            TokenKind::Spawn => {
+                self.start(NodeKind::SpawnExpr);
+                self.bump();
+                self.parse_call()?;
+                if !self.last_node().is_some_and(Self::callable) {
+                    self.error("Expected function call after 'spawn'");
+                    return Err(());
+                }
+                self.finish();
+                Ok(())
+            }
+            _ => self.parse_call(),
+        }
+    }
+    fn parse_call(&mut self) -> Result<(), ()> {
+        let checkpoint = self.checkpoint();
+        self.parse_primary()?;
+        loop {
+            match self.peek() {
+                TokenKind::LParen => {
+                    self.start_at(checkpoint, NodeKind::CallExpr);
+                    self.parse_args()?;
+                }
+                TokenKind::LBracket => {
+                    self.start_at(checkpoint, NodeKind::IndexExpr);
+                    self.bump();
+                    self.parse_expr()?;
+                    self.expect(TokenKind::RBracket, "Expected ']' after index")?;
+                }
+                TokenKind::QuestionDot if self.peek_nth(1) == TokenKind::LParen => {
+                    self.start_at(checkpoint, NodeKind::OptionalCallExpr);
+                    self.bump();
+                    self.parse_args()?;
+                }
+                TokenKind::Dot | TokenKind::QuestionDot => {
+                    self.start_at(checkpoint, NodeKind::MethodCallExpr);
+                    self.bump();
+                    self.expect(TokenKind::Ident, "Expected method name after '.'")?;
+                    if self.peek() != TokenKind::LParen {
+                        self.error("Expected '(' after method name");
+                        return Err(());
+                    }
+                    self.parse_args()?;
+                }
+                TokenKind::Question => {
+                    self.start_at(checkpoint, NodeKind::PropagateExpr);
+                    self.bump();
+                }
+                _ => return Ok(()),
+            }
+            self.finish();
+        }
+    }
+    fn parse_args(&mut self) -> Result<(), ()> {
+        self.start(NodeKind::ArgList);
+        self.expect(TokenKind::LParen, "Expected '(' before arguments")?;
+        let mut names = vec![];
+        let mut count = 0;
+        if self.peek() != TokenKind::RParen {
+            loop {
+                self.parse_arg(&mut names)?;
+                count += 1;
+                if count > 255 {
+                    self.error("Cannot have more than 255 arguments");
+                    return Err(());
+                }
+                if self.peek() != TokenKind::Comma {
+                    break;
+                }
+                self.bump();
+            }
+        }
+        self.expect(TokenKind::RParen, "Expected ')' after arguments")?;
+        self.finish();
+        Ok(())
+    }
+    fn parse_arg(&mut self, names: &mut Vec<String>) -> Result<(), ()> {
+        let checkpoint = self.checkpoint();
+        self.parse_expr()?;
+        let name = self
+            .last_node()
+            .filter(|node| node.kind == NodeKind::NameExpr)
+            .and_then(|node| node.token(TokenKind::Ident))
+            .map(|token| token.text.clone());
+        match name {
+            Some(name) if self.peek() == TokenKind::Colon => {
+                self.start_at(checkpoint, NodeKind::NamedArg);
+                self.bump();
+                self.parse_expr()?;
+                if names.contains(&name) {
+                    self.error("Named argument is given more than once");
+                    return Err(());
+                }
+                names.push(name);
+            }
+            _ => {
+                if !names.is_empty() {
+                    self.error("Positional argument cannot follow named arguments");
+                    return Err(());
+                }
+                self.start_at(checkpoint, NodeKind::Arg);
+            }
+        }
+        self.finish();
+        Ok(())
+    }
+    fn parse_primary(&mut self) -> Result<(), ()> {
+        match self.peek() {
+            TokenKind::Ident => self.start(NodeKind::NameExpr),
+            TokenKind::BooleanLiteral
+            | TokenKind::IntLiteral
+            | TokenKind::StringLiteral
+            | TokenKind::Nil => self.start(NodeKind::Literal),
+            TokenKind::LParen => {
+                let checkpoint = self.checkpoint();
+                self.bump();
+                self.parse_expr()?;
+                let kind = match self.peek() {
+                    TokenKind::Comma => {
+                        while self.peek() == TokenKind::Comma {
+                            self.bump();
+                            if self.peek() == TokenKind::RParen {
+                                break;
+                            }
+                            self.parse_expr()?;
+                        }
+                        self.expect(TokenKind::RParen, "Expected ')' after tuple elements")?;
+                        NodeKind::TupleExpr
+                    }
+                    _ => {
+                        self.expect(TokenKind::RParen, "Expected ')' after expression")?;
+                        NodeKind::ParenExpr
+                    }
+                };
+                self.start_at(checkpoint, kind);
+                self.finish();
+                return Ok(());
+            }
+            _ => {
+                self.bump();
+                self.error("Unexpected Token");
+                return Err(());
+            }
+        }
+        self.bump();
+        self.finish();
+        Ok(())
+    }
+}
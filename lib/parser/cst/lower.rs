@@ -0,0 +1,289 @@
+use crate::lexer::token::{Span, TokenKind};
+use crate::parser::ast::{self, Expr, Ident, Pattern, Program, Stmt};
+use crate::parser::cst::typed::*;
+use crate::parser::cst::CstToken;
+
+fn ident(token: &CstToken) -> Ident {
+    Ident(token.text.clone(), token.span)
+}
+
+impl Root<'_> {
+    pub fn lower(&self) -> Program {
+        self.stmts().map(lower_stmt).collect()
+    }
+}
+
+fn lower_stmt(stmt: AnyStmt) -> Stmt {
+    try_lower_stmt(stmt).unwrap_or_else(|| Stmt::ErrorStmt(stmt_span(stmt)))
+}
+
+fn try_lower_stmt(stmt: AnyStmt) -> Option<Stmt> {
+    match stmt {
+        AnyStmt::Let(stmt) => lower_let(stmt),
+        AnyStmt::Return(stmt) => Some(Stmt::ReturnStmt(
+            lower_optional(stmt.expr())?,
+            stmt.keyword().span,
+        )),
+        AnyStmt::Expr(stmt) => stmt.expr().and_then(lower_expr).map(Stmt::ExprStmt),
+        AnyStmt::Block(block) => Some(lower_block(block)),
+        AnyStmt::If(stmt) => lower_if(stmt),
+        AnyStmt::While(stmt) => Some(Stmt::WhileStmt(
+            lower_expr(stmt.condition()?)?,
+            Box::from(lower_block(stmt.body()?)),
+        )),
+        AnyStmt::For(stmt) => Some(Stmt::ForStmt(
+            lower_pattern(stmt.pattern()?)?,
+            lower_expr(stmt.iterable()?)?,
+            Box::from(lower_block(stmt.body()?)),
+        )),
+        AnyStmt::Fn(stmt) => lower_fn(stmt),
+        AnyStmt::Try(stmt) => lower_try(stmt),
+        AnyStmt::Throw(stmt) => stmt.expr().and_then(lower_expr).map(Stmt::ThrowStmt),
+        AnyStmt::Yield(stmt) => Some(Stmt::YieldStmt(lower_optional(stmt.expr())?)),
+        AnyStmt::Defer(stmt) => stmt.expr().and_then(lower_expr).map(Stmt::DeferStmt),
+        AnyStmt::Macro(stmt) => Some(Stmt::MacroStmt(
+            ident(stmt.name()?),
+            stmt.params().map(ident).collect(),
+            Box::from(lower_block(stmt.body()?)),
+        )),
+        AnyStmt::Error(_) => None,
+    }
+}
+
+fn stmt_span(stmt: AnyStmt) -> Span {
+    let node = match stmt {
+        AnyStmt::Let(stmt) => stmt.syntax(),
+        AnyStmt::Return(stmt) => stmt.syntax(),
+        AnyStmt::Expr(stmt) => stmt.syntax(),
+        AnyStmt::Block(stmt) => stmt.syntax(),
+        AnyStmt::If(stmt) => stmt.syntax(),
+        AnyStmt::While(stmt) => stmt.syntax(),
+        AnyStmt::For(stmt) => stmt.syntax(),
+        AnyStmt::Fn(stmt) => stmt.syntax(),
+        AnyStmt::Try(stmt) => stmt.syntax(),
+        AnyStmt::Throw(stmt) => stmt.syntax(),
+        AnyStmt::Yield(stmt) => stmt.syntax(),
+        AnyStmt::Defer(stmt) => stmt.syntax(),
+        AnyStmt::Macro(stmt) => stmt.syntax(),
+        AnyStmt::Error(stmt) => stmt.syntax(),
+    };
+    node.span()
+}
+
+fn lower_let(stmt: LetStmt) -> Option<Stmt> {
+    let ty = match stmt.ty() {
+        Some(ty) => Some(ty.ty()?),
+        None => None,
+    };
+    Some(Stmt::LetStmt(
+        lower_pattern(stmt.pattern()?)?,
+        ty,
+        lower_optional(stmt.init())?,
+    ))
+}
+
+fn lower_block(block: Block) -> Stmt {
+    Stmt::BlockStmt(block.stmts().map(lower_stmt).collect())
+}
+
+fn lower_if(stmt: IfStmt) -> Option<Stmt> {
+    let else_branch = stmt
+        .else_branch()
+        .map(|branch| Box::from(lower_stmt(branch)));
+    Some(Stmt::IfStmt(
+        lower_expr(stmt.condition()?)?,
+        Box::from(lower_block(stmt.then_branch()?)),
+        else_branch,
+    ))
+}
+
+fn lower_fn(stmt: FnStmt) -> Option<Stmt> {
+    let mut params = vec![];
+    for param in stmt.params()?.params() {
+        params.push(lower_param(param)?);
+    }
+    let ret = match stmt.ret() {
+        Some(ty) => Some(ty.ty()?),
+        None => None,
+    };
+    let mut decorators = vec![];
+    for decorator in stmt.decorators() {
+        let args = match decorator.args() {
+            Some(args) => lower_args(args)?,
+            None => vec![],
+        };
+        decorators.push(ast::Decorator {
+            name: ident(decorator.name()),
+            args,
+        });
+    }
+    let mut contracts = vec![];
+    for contract in stmt.contracts() {
+        let expr = lower_expr(contract.expr()?)?;
+        contracts.push(match contract.is_requires() {
+            true => ast::Contract::Requires(expr),
+            false => ast::Contract::Ensures(expr),
+        });
+    }
+    let body = stmt.body()?;
+    let mut stmts: Vec<Stmt> = body.stmts().map(lower_stmt).collect();
+    if !matches!(stmts.last(), Some(Stmt::ReturnStmt(..))) {
+        stmts.push(Stmt::ReturnStmt(None, body.r_brace()?.span));
+    }
+    Some(Stmt::FnStmt(
+        ident(stmt.name()),
+        params,
+        ret,
+        Box::from(Stmt::BlockStmt(stmts)),
+        decorators,
+        contracts,
+    ))
+}
+
+fn lower_param(param: Param) -> Option<ast::Param> {
+    let (name, pattern) = match param.name() {
+        Some(name) => (ident(name), None),
+        None => {
+            let node = param.pattern()?;
+            let pattern = lower_pattern(node)?;
+            let span = match node {
+                AnyPattern::Name(node) => node.syntax().span(),
+                AnyPattern::Tuple(node) => node.syntax().span(),
+                AnyPattern::Paren(node) => node.syntax().span(),
+            };
+            (Ident(pattern.to_string(), span), Some(pattern))
+        }
+    };
+    let ty = match param.ty() {
+        Some(ty) => Some(ty.ty()?),
+        None => None,
+    };
+    Some(ast::Param {
+        name,
+        pattern,
+        ty,
+        default: lower_optional(param.default())?,
+        variadic: param.variadic(),
+    })
+}
+
+fn lower_try(stmt: TryStmt) -> Option<Stmt> {
+    let handler = match stmt.catch_clause() {
+        Some(clause) => Some((ident(clause.name()), Box::from(lower_block(clause.body()?)))),
+        None => None,
+    };
+    let finally = match stmt.finally_clause() {
+        Some(clause) => Some(Box::from(lower_block(clause.body()?))),
+        None => None,
+    };
+    Some(Stmt::TryStmt(
+        Box::from(lower_block(stmt.body()?)),
+        handler,
+        finally,
+    ))
+}
+
+fn lower_pattern(pattern: AnyPattern) -> Option<Pattern> {
+    match pattern {
+        AnyPattern::Name(pattern) => Some(Pattern::Ident(ident(pattern.name()))),
+        AnyPattern::Tuple(pattern) => pattern
+            .patterns()
+            .map(lower_pattern)
+            .collect::<Option<Vec<_>>>()
+            .map(Pattern::Tuple),
+        AnyPattern::Paren(pattern) => lower_pattern(pattern.pattern()?),
+    }
+}
+
+fn expr_pattern(expr: AnyExpr) -> Option<Pattern> {
+    match expr {
+        AnyExpr::Name(expr) => Some(Pattern::Ident(ident(expr.name()))),
+        AnyExpr::Tuple(expr) => expr
+            .exprs()
+            .map(expr_pattern)
+            .collect::<Option<Vec<_>>>()
+            .map(Pattern::Tuple),
+        AnyExpr::Paren(expr) => expr_pattern(expr.expr()?),
+        _ => None,
+    }
+}
+
+fn lower_args(args: ArgList) -> Option<Vec<ast::Arg>> {
+    args.args()
+        .map(|arg| match arg {
+            AnyArg::Positional(arg) => Some(ast::Arg::Positional(lower_expr(arg.expr()?)?)),
+            AnyArg::Named(arg) => Some(ast::Arg::Named(
+                ident(arg.name()?),
+                lower_expr(arg.value()?)?,
+            )),
+        })
+        .collect()
+}
+
+fn lower_optional(expr: Option<AnyExpr>) -> Option<Option<Expr>> {
+    match expr {
+        Some(expr) => Some(Some(lower_expr(expr)?)),
+        None => Some(None),
+    }
+}
+
+fn lower_box(expr: Option<AnyExpr>) -> Option<Box<Expr>> {
+    Some(Box::from(lower_expr(expr?)?))
+}
+
+fn lower_literal(token: &CstToken) -> Option<ast::Literal> {
+    let literal = match token.kind {
+        TokenKind::IntLiteral => ast::Literal::IntLiteral(token.text.parse().ok()?),
+        TokenKind::BooleanLiteral => ast::Literal::BoolLiteral(token.text == "true"),
+        TokenKind::StringLiteral => ast::Literal::StringLiteral(token.string_value().to_string()),
+        TokenKind::Nil => ast::Literal::NilLiteral,
+        _ => return None,
+    };
+    Some(literal)
+}
+
+fn lower_expr(expr: AnyExpr) -> Option<Expr> {
+    let lowered = match expr {
+        AnyExpr::Name(expr) => Expr::IdentExpr(ident(expr.name())),
+        AnyExpr::Literal(expr) => Expr::LiteralExpr(lower_literal(expr.token())?),
+        AnyExpr::Paren(expr) => lower_expr(expr.expr()?)?,
+        AnyExpr::Tuple(expr) => {
+            Expr::TupleExpr(expr.exprs().map(lower_expr).collect::<Option<Vec<_>>>()?)
+        }
+        AnyExpr::Assign(expr) => {
+            Expr::AssignmentExpr(expr_pattern(expr.target()?)?, lower_box(expr.value())?)
+        }
+        AnyExpr::Binary(expr) => {
+            Expr::BinExpr(lower_box(expr.lhs())?, expr.op()?, lower_box(expr.rhs())?)
+        }
+        AnyExpr::Unary(expr) => Expr::UnaryExpr(expr.op()?, lower_box(expr.operand())?),
+        AnyExpr::Coalesce(expr) => {
+            Expr::CoalesceExpr(lower_box(expr.lhs())?, lower_box(expr.rhs())?)
+        }
+        AnyExpr::Range(expr) => Expr::RangeExpr(
+            lower_box(expr.start())?,
+            lower_box(expr.end())?,
+            expr.inclusive(),
+            lower_optional(expr.step())?.map(Box::from),
+        ),
+        AnyExpr::Call(expr) => Expr::CallExpr(lower_box(expr.callee())?, lower_args(expr.args()?)?),
+        AnyExpr::Spawn(expr) => {
+            let call = expr.call()?;
+            Expr::SpawnExpr(lower_box(call.callee())?, lower_args(call.args()?)?)
+        }
+        AnyExpr::OptionalCall(expr) => {
+            Expr::OptionalCallExpr(lower_box(expr.callee())?, lower_args(expr.args()?)?)
+        }
+        AnyExpr::MethodCall(expr) => Expr::MethodCallExpr(
+            lower_box(expr.receiver())?,
+            ident(expr.name()),
+            lower_args(expr.args()?)?,
+            expr.optional(),
+        ),
+        AnyExpr::Index(expr) => {
+            Expr::IndexExpr(lower_box(expr.target())?, lower_box(expr.index())?)
+        }
+        AnyExpr::Propagate(expr) => Expr::PropagateExpr(lower_box(expr.expr())?),
+    };
+    Some(lowered)
+}
@@ -0,0 +1,136 @@
+use crate::lexer::token::{Span, TokenKind};
+
+mod grammar;
+mod lower;
+pub mod typed;
+
+pub use grammar::CstParser;
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum NodeKind {
+    Root,
+    Error,
+
+    LetStmt,
+    ReturnStmt,
+    ExprStmt,
+    Block,
+    IfStmt,
+    WhileStmt,
+    ForStmt,
+    FnStmt,
+    Decorator,
+    ParamList,
+    Param,
+    Contract,
+    TypeRef,
+    TryStmt,
+    CatchClause,
+    FinallyClause,
+    ThrowStmt,
+    YieldStmt,
+    DeferStmt,
+    MacroStmt,
+
+    NameExpr,
+    Literal,
+    ParenExpr,
+    TupleExpr,
+    AssignExpr,
+    BinExpr,
+    UnaryExpr,
+    CoalesceExpr,
+    RangeExpr,
+    CallExpr,
+    SpawnExpr,
+    OptionalCallExpr,
+    MethodCallExpr,
+    IndexExpr,
+    PropagateExpr,
+    ArgList,
+    Arg,
+    NamedArg,
+
+    NamePattern,
+    TuplePattern,
+    ParenPattern,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct CstToken {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: Span,
+}
+
+impl CstToken {
+    pub fn string_value(&self) -> &str {
+        &self.text[1..self.text.len() - 1]
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum Element {
+    Node(Node),
+    Token(CstToken),
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &CstToken> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Token(token) if !token.kind.is_trivia() => Some(token),
+            _ => None,
+        })
+    }
+
+    pub fn token(&self, kind: TokenKind) -> Option<&CstToken> {
+        self.tokens().find(|token| token.kind == kind)
+    }
+
+    pub fn has_token(&self, kind: TokenKind) -> bool {
+        self.token(kind).is_some()
+    }
+
+    pub fn first_token(&self) -> Option<&CstToken> {
+        self.children.iter().find_map(|child| match child {
+            Element::Node(node) => node.first_token(),
+            Element::Token(token) if !token.kind.is_trivia() => Some(token),
+            Element::Token(_) => None,
+        })
+    }
+
+    pub fn span(&self) -> Span {
+        self.first_token()
+            .map(|token| token.span)
+            .unwrap_or_default()
+    }
+
+    pub fn text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl std::fmt::Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for child in self.children.iter() {
+            match child {
+                Element::Node(node) => write!(f, "{}", node)?,
+                Element::Token(token) => write!(f, "{}", token.text)?,
+            }
+        }
+        Ok(())
+    }
+}
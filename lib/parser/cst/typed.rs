@@ -0,0 +1,656 @@
+use crate::lexer::token::TokenKind;
+use crate::parser::ast::{BinOp, Type, UnaryOp};
+use crate::parser::cst::{CstToken, Node, NodeKind};
+
+macro_rules! typed_nodes {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[derive(Clone, Copy, Debug)]
+            pub struct $name<'a>(&'a Node);
+
+            impl<'a> $name<'a> {
+                pub fn cast(node: &'a Node) -> Option<Self> {
+                    (node.kind == NodeKind::$name).then_some(Self(node))
+                }
+
+                pub fn syntax(&self) -> &'a Node {
+                    self.0
+                }
+            }
+        )*
+    };
+}
+
+typed_nodes!(
+    Root,
+    Error,
+    LetStmt,
+    ReturnStmt,
+    ExprStmt,
+    Block,
+    IfStmt,
+    WhileStmt,
+    ForStmt,
+    FnStmt,
+    Decorator,
+    ParamList,
+    Param,
+    Contract,
+    TypeRef,
+    TryStmt,
+    CatchClause,
+    FinallyClause,
+    ThrowStmt,
+    YieldStmt,
+    DeferStmt,
+    MacroStmt,
+    NameExpr,
+    Literal,
+    ParenExpr,
+    TupleExpr,
+    AssignExpr,
+    BinExpr,
+    UnaryExpr,
+    CoalesceExpr,
+    RangeExpr,
+    CallExpr,
+    SpawnExpr,
+    OptionalCallExpr,
+    MethodCallExpr,
+    IndexExpr,
+    PropagateExpr,
+    ArgList,
+    Arg,
+    NamedArg,
+    NamePattern,
+    TuplePattern,
+    ParenPattern,
+);
+
+fn child<'a, T>(node: &'a Node, cast: fn(&'a Node) -> Option<T>) -> Option<T> {
+    node.nodes().find_map(cast)
+}
+
+fn children<'a, T: 'a>(
+    node: &'a Node,
+    cast: fn(&'a Node) -> Option<T>,
+) -> impl Iterator<Item = T> + 'a {
+    node.nodes().filter_map(cast)
+}
+
+fn token(node: &Node, kind: TokenKind) -> &CstToken {
+    node.token(kind)
+        .expect("token is missing from the syntax tree")
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AnyStmt<'a> {
+    Let(LetStmt<'a>),
+    Return(ReturnStmt<'a>),
+    Expr(ExprStmt<'a>),
+    Block(Block<'a>),
+    If(IfStmt<'a>),
+    While(WhileStmt<'a>),
+    For(ForStmt<'a>),
+    Fn(FnStmt<'a>),
+    Try(TryStmt<'a>),
+    Throw(ThrowStmt<'a>),
+    Yield(YieldStmt<'a>),
+    Defer(DeferStmt<'a>),
+    Macro(MacroStmt<'a>),
+    Error(Error<'a>),
+}
+
+impl<'a> AnyStmt<'a> {
+    pub fn cast(node: &'a Node) -> Option<Self> {
+        let stmt = match node.kind {
+            NodeKind::LetStmt => AnyStmt::Let(LetStmt(node)),
+            NodeKind::ReturnStmt => AnyStmt::Return(ReturnStmt(node)),
+            NodeKind::ExprStmt => AnyStmt::Expr(ExprStmt(node)),
+            NodeKind::Block => AnyStmt::Block(Block(node)),
+            NodeKind::IfStmt => AnyStmt::If(IfStmt(node)),
+            NodeKind::WhileStmt => AnyStmt::While(WhileStmt(node)),
+            NodeKind::ForStmt => AnyStmt::For(ForStmt(node)),
+            NodeKind::FnStmt => AnyStmt::Fn(FnStmt(node)),
+            NodeKind::TryStmt => AnyStmt::Try(TryStmt(node)),
+            NodeKind::ThrowStmt => AnyStmt::Throw(ThrowStmt(node)),
+            NodeKind::YieldStmt => AnyStmt::Yield(YieldStmt(node)),
+            NodeKind::DeferStmt => AnyStmt::Defer(DeferStmt(node)),
+            NodeKind::MacroStmt => AnyStmt::Macro(MacroStmt(node)),
+            NodeKind::Error => AnyStmt::Error(Error(node)),
+            _ => return None,
+        };
+        Some(stmt)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AnyExpr<'a> {
+    Name(NameExpr<'a>),
+    Literal(Literal<'a>),
+    Paren(ParenExpr<'a>),
+    Tuple(TupleExpr<'a>),
+    Assign(AssignExpr<'a>),
+    Binary(BinExpr<'a>),
+    Unary(UnaryExpr<'a>),
+    Coalesce(CoalesceExpr<'a>),
+    Range(RangeExpr<'a>),
+    Call(CallExpr<'a>),
+    Spawn(SpawnExpr<'a>),
+    OptionalCall(OptionalCallExpr<'a>),
+    MethodCall(MethodCallExpr<'a>),
+    Index(IndexExpr<'a>),
+    Propagate(PropagateExpr<'a>),
+}
+
+impl<'a> AnyExpr<'a> {
+    pub fn cast(node: &'a Node) -> Option<Self> {
+        let expr = match node.kind {
+            NodeKind::NameExpr => AnyExpr::Name(NameExpr(node)),
+            NodeKind::Literal => AnyExpr::Literal(Literal(node)),
+            NodeKind::ParenExpr => AnyExpr::Paren(ParenExpr(node)),
+            NodeKind::TupleExpr => AnyExpr::Tuple(TupleExpr(node)),
+            NodeKind::AssignExpr => AnyExpr::Assign(AssignExpr(node)),
+            NodeKind::BinExpr => AnyExpr::Binary(BinExpr(node)),
+            NodeKind::UnaryExpr => AnyExpr::Unary(UnaryExpr(node)),
+            NodeKind::CoalesceExpr => AnyExpr::Coalesce(CoalesceExpr(node)),
+            NodeKind::RangeExpr => AnyExpr::Range(RangeExpr(node)),
+            NodeKind::CallExpr => AnyExpr::Call(CallExpr(node)),
+            NodeKind::SpawnExpr => AnyExpr::Spawn(SpawnExpr(node)),
+            NodeKind::OptionalCallExpr => AnyExpr::OptionalCall(OptionalCallExpr(node)),
+            NodeKind::MethodCallExpr => AnyExpr::MethodCall(MethodCallExpr(node)),
+            NodeKind::IndexExpr => AnyExpr::Index(IndexExpr(node)),
+            NodeKind::PropagateExpr => AnyExpr::Propagate(PropagateExpr(node)),
+            _ => return None,
+        };
+        Some(expr)
+    }
+
+    pub fn syntax(&self) -> &'a Node {
+        match self {
+            AnyExpr::Name(expr) => expr.0,
+            AnyExpr::Literal(expr) => expr.0,
+            AnyExpr::Paren(expr) => expr.0,
+            AnyExpr::Tuple(expr) => expr.0,
+            AnyExpr::Assign(expr) => expr.0,
+            AnyExpr::Binary(expr) => expr.0,
+            AnyExpr::Unary(expr) => expr.0,
+            AnyExpr::Coalesce(expr) => expr.0,
+            AnyExpr::Range(expr) => expr.0,
+            AnyExpr::Call(expr) => expr.0,
+            AnyExpr::Spawn(expr) => expr.0,
+            AnyExpr::OptionalCall(expr) => expr.0,
+            AnyExpr::MethodCall(expr) => expr.0,
+            AnyExpr::Index(expr) => expr.0,
+            AnyExpr::Propagate(expr) => expr.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AnyPattern<'a> {
+    Name(NamePattern<'a>),
+    Tuple(TuplePattern<'a>),
+    Paren(ParenPattern<'a>),
+}
+
+impl<'a> AnyPattern<'a> {
+    pub fn cast(node: &'a Node) -> Option<Self> {
+        let pattern = match node.kind {
+            NodeKind::NamePattern => AnyPattern::Name(NamePattern(node)),
+            NodeKind::TuplePattern => AnyPattern::Tuple(TuplePattern(node)),
+            NodeKind::ParenPattern => AnyPattern::Paren(ParenPattern(node)),
+            _ => return None,
+        };
+        Some(pattern)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AnyArg<'a> {
+    Positional(Arg<'a>),
+    Named(NamedArg<'a>),
+}
+
+impl<'a> Root<'a> {
+    pub fn stmts(&self) -> impl Iterator<Item = AnyStmt<'a>> {
+        children(self.0, AnyStmt::cast)
+    }
+}
+
+impl<'a> LetStmt<'a> {
+    pub fn pattern(&self) -> Option<AnyPattern<'a>> {
+        child(self.0, AnyPattern::cast)
+    }
+
+    pub fn ty(&self) -> Option<TypeRef<'a>> {
+        child(self.0, TypeRef::cast)
+    }
+
+    pub fn init(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> ReturnStmt<'a> {
+    pub fn keyword(&self) -> &'a CstToken {
+        token(self.0, TokenKind::Return)
+    }
+
+    pub fn expr(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> ExprStmt<'a> {
+    pub fn expr(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> Block<'a> {
+    pub fn stmts(&self) -> impl Iterator<Item = AnyStmt<'a>> {
+        children(self.0, AnyStmt::cast)
+    }
+
+    pub fn r_brace(&self) -> Option<&'a CstToken> {
+        self.0.token(TokenKind::RBrace)
+    }
+}
+
+impl<'a> IfStmt<'a> {
+    pub fn condition(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn then_branch(&self) -> Option<Block<'a>> {
+        child(self.0, Block::cast)
+    }
+
+    pub fn else_branch(&self) -> Option<AnyStmt<'a>> {
+        self.0.nodes().nth(2).and_then(AnyStmt::cast)
+    }
+}
+
+impl<'a> WhileStmt<'a> {
+    pub fn condition(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn body(&self) -> Option<Block<'a>> {
+        child(self.0, Block::cast)
+    }
+}
+
+impl<'a> ForStmt<'a> {
+    pub fn pattern(&self) -> Option<AnyPattern<'a>> {
+        child(self.0, AnyPattern::cast)
+    }
+
+    pub fn iterable(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn body(&self) -> Option<Block<'a>> {
+        child(self.0, Block::cast)
+    }
+}
+
+impl<'a> FnStmt<'a> {
+    pub fn decorators(&self) -> impl Iterator<Item = Decorator<'a>> {
+        children(self.0, Decorator::cast)
+    }
+
+    pub fn name(&self) -> &'a CstToken {
+        token(self.0, TokenKind::Ident)
+    }
+
+    pub fn params(&self) -> Option<ParamList<'a>> {
+        child(self.0, ParamList::cast)
+    }
+
+    pub fn ret(&self) -> Option<TypeRef<'a>> {
+        child(self.0, TypeRef::cast)
+    }
+
+    pub fn contracts(&self) -> impl Iterator<Item = Contract<'a>> {
+        children(self.0, Contract::cast)
+    }
+
+    pub fn body(&self) -> Option<Block<'a>> {
+        child(self.0, Block::cast)
+    }
+}
+
+impl<'a> Decorator<'a> {
+    pub fn name(&self) -> &'a CstToken {
+        token(self.0, TokenKind::Ident)
+    }
+
+    pub fn args(&self) -> Option<ArgList<'a>> {
+        child(self.0, ArgList::cast)
+    }
+}
+
+impl<'a> ParamList<'a> {
+    pub fn params(&self) -> impl Iterator<Item = Param<'a>> {
+        children(self.0, Param::cast)
+    }
+}
+
+impl<'a> Param<'a> {
+    pub fn variadic(&self) -> bool {
+        self.0.has_token(TokenKind::Ellipsis)
+    }
+
+    pub fn name(&self) -> Option<&'a CstToken> {
+        self.0.token(TokenKind::Ident)
+    }
+
+    pub fn pattern(&self) -> Option<AnyPattern<'a>> {
+        child(self.0, AnyPattern::cast)
+    }
+
+    pub fn ty(&self) -> Option<TypeRef<'a>> {
+        child(self.0, TypeRef::cast)
+    }
+
+    pub fn default(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> Contract<'a> {
+    pub fn is_requires(&self) -> bool {
+        token(self.0, TokenKind::Ident).text == "requires"
+    }
+
+    pub fn expr(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> TypeRef<'a> {
+    pub fn ty(&self) -> Option<Type> {
+        Type::from_name(&self.0.token(TokenKind::Ident)?.text)
+    }
+}
+
+impl<'a> TryStmt<'a> {
+    pub fn body(&self) -> Option<Block<'a>> {
+        child(self.0, Block::cast)
+    }
+
+    pub fn catch_clause(&self) -> Option<CatchClause<'a>> {
+        child(self.0, CatchClause::cast)
+    }
+
+    pub fn finally_clause(&self) -> Option<FinallyClause<'a>> {
+        child(self.0, FinallyClause::cast)
+    }
+}
+
+impl<'a> CatchClause<'a> {
+    pub fn name(&self) -> &'a CstToken {
+        token(self.0, TokenKind::Ident)
+    }
+
+    pub fn body(&self) -> Option<Block<'a>> {
+        child(self.0, Block::cast)
+    }
+}
+
+impl<'a> FinallyClause<'a> {
+    pub fn body(&self) -> Option<Block<'a>> {
+        child(self.0, Block::cast)
+    }
+}
+
+impl<'a> ThrowStmt<'a> {
+    pub fn expr(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> YieldStmt<'a> {
+    pub fn expr(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> DeferStmt<'a> {
+    pub fn expr(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> MacroStmt<'a> {
+    pub fn name(&self) -> Option<&'a CstToken> {
+        self.0
+            .tokens()
+            .filter(|token| token.kind == TokenKind::Ident)
+            .nth(1)
+    }
+
+    pub fn params(&self) -> impl Iterator<Item = &'a CstToken> {
+        self.0
+            .tokens()
+            .filter(|token| token.kind == TokenKind::Ident)
+            .skip(2)
+    }
+
+    pub fn body(&self) -> Option<Block<'a>> {
+        child(self.0, Block::cast)
+    }
+}
+
+impl<'a> NameExpr<'a> {
+    pub fn name(&self) -> &'a CstToken {
+        token(self.0, TokenKind::Ident)
+    }
+}
+
+impl<'a> Literal<'a> {
+    pub fn token(&self) -> &'a CstToken {
+        self.0.tokens().next().expect("literal without a token")
+    }
+}
+
+impl<'a> ParenExpr<'a> {
+    pub fn expr(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> TupleExpr<'a> {
+    pub fn exprs(&self) -> impl Iterator<Item = AnyExpr<'a>> {
+        children(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> AssignExpr<'a> {
+    pub fn target(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn value(&self) -> Option<AnyExpr<'a>> {
+        children(self.0, AnyExpr::cast).nth(1)
+    }
+}
+
+impl<'a> BinExpr<'a> {
+    pub fn lhs(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn op(&self) -> Option<BinOp> {
+        self.0.tokens().find_map(|token| match token.kind {
+            TokenKind::Plus => Some(BinOp::Plus),
+            TokenKind::Minus => Some(BinOp::Minus),
+            TokenKind::Multiply => Some(BinOp::Multiply),
+            TokenKind::Divide => Some(BinOp::Divide),
+            TokenKind::Less => Some(BinOp::Less),
+            TokenKind::Greater => Some(BinOp::Greater),
+            TokenKind::LessEqual => Some(BinOp::LessEqual),
+            TokenKind::GreaterEqual => Some(BinOp::GreaterEqual),
+            TokenKind::Equal => Some(BinOp::Equal),
+            TokenKind::NotEqual => Some(BinOp::NotEqual),
+            _ => None,
+        })
+    }
+
+    pub fn rhs(&self) -> Option<AnyExpr<'a>> {
+        children(self.0, AnyExpr::cast).nth(1)
+    }
+}
+
+impl<'a> UnaryExpr<'a> {
+    pub fn op(&self) -> Option<UnaryOp> {
+        self.0.tokens().find_map(|token| match token.kind {
+            TokenKind::Plus => Some(UnaryOp::UnaryPlus),
+            TokenKind::Minus => Some(UnaryOp::UnaryMinus),
+            TokenKind::Not => Some(UnaryOp::Not),
+            _ => None,
+        })
+    }
+
+    pub fn operand(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> CoalesceExpr<'a> {
+    pub fn lhs(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn rhs(&self) -> Option<AnyExpr<'a>> {
+        children(self.0, AnyExpr::cast).nth(1)
+    }
+}
+
+impl<'a> RangeExpr<'a> {
+    pub fn start(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn end(&self) -> Option<AnyExpr<'a>> {
+        children(self.0, AnyExpr::cast).nth(1)
+    }
+
+    pub fn inclusive(&self) -> bool {
+        self.0.has_token(TokenKind::DotDotEqual)
+    }
+
+    pub fn step(&self) -> Option<AnyExpr<'a>> {
+        children(self.0, AnyExpr::cast).nth(2)
+    }
+}
+
+impl<'a> CallExpr<'a> {
+    pub fn callee(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn args(&self) -> Option<ArgList<'a>> {
+        child(self.0, ArgList::cast)
+    }
+}
+
+impl<'a> SpawnExpr<'a> {
+    pub fn call(&self) -> Option<CallExpr<'a>> {
+        let mut node = self.0.nodes().next()?;
+        while node.kind == NodeKind::ParenExpr {
+            node = node.nodes().next()?;
+        }
+        CallExpr::cast(node)
+    }
+}
+
+impl<'a> OptionalCallExpr<'a> {
+    pub fn callee(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn args(&self) -> Option<ArgList<'a>> {
+        child(self.0, ArgList::cast)
+    }
+}
+
+impl<'a> MethodCallExpr<'a> {
+    pub fn receiver(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn name(&self) -> &'a CstToken {
+        token(self.0, TokenKind::Ident)
+    }
+
+    pub fn optional(&self) -> bool {
+        self.0.has_token(TokenKind::QuestionDot)
+    }
+
+    pub fn args(&self) -> Option<ArgList<'a>> {
+        child(self.0, ArgList::cast)
+    }
+}
+
+impl<'a> IndexExpr<'a> {
+    pub fn target(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+
+    pub fn index(&self) -> Option<AnyExpr<'a>> {
+        children(self.0, AnyExpr::cast).nth(1)
+    }
+}
+
+impl<'a> PropagateExpr<'a> {
+    pub fn expr(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> ArgList<'a> {
+    pub fn args(&self) -> impl Iterator<Item = AnyArg<'a>> {
+        self.0.nodes().filter_map(|node| match node.kind {
+            NodeKind::Arg => Some(AnyArg::Positional(Arg(node))),
+            NodeKind::NamedArg => Some(AnyArg::Named(NamedArg(node))),
+            _ => None,
+        })
+    }
+}
+
+impl<'a> Arg<'a> {
+    pub fn expr(&self) -> Option<AnyExpr<'a>> {
+        child(self.0, AnyExpr::cast)
+    }
+}
+
+impl<'a> NamedArg<'a> {
+    pub fn name(&self) -> Option<&'a CstToken> {
+        self.0.nodes().next()?.token(TokenKind::Ident)
+    }
+
+    pub fn value(&self) -> Option<AnyExpr<'a>> {
+        children(self.0, AnyExpr::cast).nth(1)
+    }
+}
+
+impl<'a> NamePattern<'a> {
+    pub fn name(&self) -> &'a CstToken {
+        token(self.0, TokenKind::Ident)
+    }
+}
+
+impl<'a> TuplePattern<'a> {
+    pub fn patterns(&self) -> impl Iterator<Item = AnyPattern<'a>> {
+        children(self.0, AnyPattern::cast)
+    }
+}
+
+impl<'a> ParenPattern<'a> {
+    pub fn pattern(&self) -> Option<AnyPattern<'a>> {
+        child(self.0, AnyPattern::cast)
+    }
+}
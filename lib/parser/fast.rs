@@ -0,0 +1,981 @@
+use crate::lexer::token::{Lexeme, Span, Token, TokenKind};
+use crate::parser::arena::{
+    ArgNode, Ast, ContractNode, DecoratorNode, ExprId, ExprNode, FnId, FnNode, Items, Name,
+    ParamNode, PatternId, PatternNode, StmtId, StmtNode,
+};
+use crate::parser::ast::{BinOp, Type, UnaryOp};
+use crate::parser::{ParseError, MAX_ERRORS};
+
+pub struct ArenaParser<'a, 'src> {
+    lexemes: &'a [Lexeme<'src>],
+    previous: Option<Lexeme<'src>>,
+    previous_span: Span,
+    position: usize,
+    panicking: bool,
+    ast: Ast<'src>,
+    stmts: Vec<StmtId>,
+    exprs: Vec<ExprId>,
+    patterns: Vec<PatternId>,
+    args: Vec<ArgNode>,
+    params: Vec<ParamNode>,
+    decorators: Vec<DecoratorNode>,
+    contracts: Vec<ContractNode>,
+    pub errors: Vec<ParseError>,
+}
+
+impl<'a, 'src> ArenaParser<'a, 'src> {
+    pub fn new(lexemes: &'a [Lexeme<'src>]) -> Self {
+        Self {
+            lexemes,
+            previous: None,
+            previous_span: Span::default(),
+            position: 0,
+            panicking: false,
+            ast: Ast::default(),
+            stmts: vec![],
+            exprs: vec![],
+            patterns: vec![],
+            args: vec![],
+            params: vec![],
+            decorators: vec![],
+            contracts: vec![],
+            errors: vec![],
+        }
+    }
+    fn advance(&mut self) {
+        self.previous = self.lexemes.get(self.position).copied();
+        self.previous_span = self.previous.map(|lexeme| lexeme.span).unwrap_or_default();
+        self.position += 1;
+    }
+    fn span(&self) -> Span {
+        self.lexemes
+            .get(self.position)
+            .map(|lexeme| lexeme.span)
+            .unwrap_or_default()
+    }
+    fn peek(&self) -> TokenKind {
+        self.lexemes
+            .get(self.position)
+            .map(|lexeme| lexeme.kind)
+            .unwrap_or(TokenKind::EOF)
+    }
+    fn peek_second(&self) -> TokenKind {
+        self.lexemes
+            .get(self.position + 1)
+            .map(|lexeme| lexeme.kind)
+            .unwrap_or(TokenKind::EOF)
+    }
+    fn peek_text(&self) -> &'src str {
+        self.lexemes
+            .get(self.position)
+            .map(|lexeme| lexeme.text)
+            .unwrap_or_default()
+    }
+    fn peek_ident(&self, name: &str) -> bool {
+        self.peek() == TokenKind::Ident && self.peek_text() == name
+    }
+    fn name(&mut self) -> Name {
+        let text = self.previous.map(|lexeme| lexeme.text).unwrap_or_default();
+        Name {
+            symbol: self.ast.interner.intern(text),
+            span: self.previous_span,
+        }
+    }
+    fn error(&mut self, message: &str) {
+        if self.panicking || self.gave_up() {
+            return;
+        }
+        self.panicking = true;
+        let message = match self.errors.len() == MAX_ERRORS {
+            true => "Too many errors, giving up",
+            false => message,
+        };
+        self.errors.push(ParseError {
+            token: match self.previous {
+                Some(lexeme) => lexeme.to_token(),
+                None => Token::EOF,
+            },
+            message: message.to_string(),
+            span: self.previous_span,
+        })
+    }
+    fn gave_up(&self) -> bool {
+        self.errors.len() > MAX_ERRORS
+    }
+    fn synchronize(&mut self) {
+        loop {
+            match self.peek() {
+                TokenKind::RBrace => return,
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => self.skip_balanced(),
+                TokenKind::Let
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::Return
+                | TokenKind::Function
+                | TokenKind::At
+                | TokenKind::Try
+                | TokenKind::Throw
+                | TokenKind::Yield
+                | TokenKind::Defer
+                | TokenKind::EOF => {
+                    return;
+                }
+                TokenKind::SemiColon => {
+                    self.advance();
+                    return;
+                }
+                _ => self.advance(),
+            }
+        }
+    }
+    fn skip_balanced(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => depth -= 1,
+                TokenKind::EOF => return,
+                _ => (),
+            }
+            self.advance();
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
+    pub fn parse(&mut self) -> Ast<'src> {
+        while self.peek() != TokenKind::EOF && !self.gave_up() {
+            if self.peek() == TokenKind::RBrace {
+                self.panicking = false;
+                self.advance();
+                self.error("Unexpected '}'");
+                let stmt = self.ast.alloc_stmt(StmtNode::Error(self.previous_span));
+                self.stmts.push(stmt);
+                continue;
+            }
+            self.parse_into();
+        }
+        let mut ast = std::mem::take(&mut self.ast);
+        ast.root = ast.commit_stmts(&mut self.stmts, 0);
+        ast
+    }
+    fn parse_into(&mut self) {
+        self.panicking = false;
+        let position = self.position;
+        let span = self.span();
+        let marks = (
+            self.stmts.len(),
+            self.exprs.len(),
+            self.patterns.len(),
+            self.args.len(),
+            self.params.len(),
+            self.decorators.len(),
+            self.contracts.len(),
+        );
+        match self.parse_stmt() {
+            Ok(stmt) => self.stmts.push(stmt),
+            Err(_) => {
+                self.stmts.truncate(marks.0);
+                self.exprs.truncate(marks.1);
+                self.patterns.truncate(marks.2);
+                self.args.truncate(marks.3);
+                self.params.truncate(marks.4);
+                self.decorators.truncate(marks.5);
+                self.contracts.truncate(marks.6);
+                let stmt = self.ast.alloc_stmt(StmtNode::Error(span));
+                self.stmts.push(stmt);
+                self.synchronize();
+                if self.position == position {
+                    self.advance();
+                }
+            }
+        }
+    }
+    fn parse_stmt(&mut self) -> Result<StmtId, ()> {
+        match self.peek() {
+            TokenKind::Let => {
+                self.advance();
+                self.parse_let()
+            }
+            TokenKind::LBrace => {
+                self.advance();
+                self.parse_block()
+            }
+            TokenKind::If => {
+                self.advance();
+                self.parse_if()
+            }
+            TokenKind::While => {
+                self.advance();
+                self.parse_while()
+            }
+            TokenKind::For => {
+                self.advance();
+                self.parse_for()
+            }
+            TokenKind::Function => {
+                self.advance();
+                let function = self.parse_fn()?;
+                Ok(self.ast.alloc_stmt(StmtNode::Fn(function)))
+            }
+            TokenKind::At => self.parse_decorated(),
+            TokenKind::Return => {
+                self.advance();
+                self.parse_return()
+            }
+            TokenKind::Try => {
+                self.advance();
+                self.parse_try()
+            }
+            TokenKind::Throw => {
+                self.advance();
+                self.parse_throw()
+            }
+            TokenKind::Yield => {
+                self.advance();
+                self.parse_yield()
+            }
+            TokenKind::Defer => {
+                self.advance();
+                self.parse_defer()
+            }
+            TokenKind::Ident
+                if self.peek_ident("macro") && self.peek_second() == TokenKind::Ident =>
+            {
+                self.advance();
+                self.parse_macro()
+            }
+            _ => self.parse_expr_stmt(),
+        }
+    }
+    fn expect_semicolon(&mut self, message: &str) -> Result<(), ()> {
+        if self.peek() != TokenKind::SemiColon {
+            self.error(message);
+            return Err(());
+        }
+        self.advance();
+        Ok(())
+    }
+    fn parse_return(&mut self) -> Result<StmtId, ()> {
+        let span = self.previous_span;
+        if self.peek() == TokenKind::SemiColon {
+            self.advance();
+            return Ok(self.ast.alloc_stmt(StmtNode::Return(None, span)));
+        }
+        let expr = self.parse_expr()?;
+        self.expect_semicolon("Expected ';' after statement")?;
+        Ok(self.ast.alloc_stmt(StmtNode::Return(Some(expr), span)))
+    }
+    fn parse_yield(&mut self) -> Result<StmtId, ()> {
+        if self.peek() == TokenKind::SemiColon {
+            self.advance();
+            return Ok(self.ast.alloc_stmt(StmtNode::Yield(None)));
+        }
+        let expr = self.parse_expr()?;
+        self.expect_semicolon("Expected ';' after statement")?;
+        Ok(self.ast.alloc_stmt(StmtNode::Yield(Some(expr))))
+    }
+    fn parse_defer(&mut self) -> Result<StmtId, ()> {
+        let expr = self.parse_expr()?;
+        self.expect_semicolon("Expected ';' after defer expression")?;
+        Ok(self.ast.alloc_stmt(StmtNode::Defer(expr)))
+    }
+    fn parse_throw(&mut self) -> Result<StmtId, ()> {
+        let expr = self.parse_expr()?;
+        self.expect_semicolon("Expected ';' after statement")?;
+        Ok(self.ast.alloc_stmt(StmtNode::Throw(expr)))
+    }
+    fn expect(&mut self, kind: TokenKind, message: &str) -> Result<(), ()> {
+        if self.peek() != kind {
+            self.error(message);
+            return Err(());
+        }
+        self.advance();
+        Ok(())
+    }
+    fn parse_try(&mut self) -> Result<StmtId, ()> {
+        self.expect(TokenKind::LBrace, "Expect '{' after try")?;
+        let body = self.parse_block()?;
+        let mut handler = None;
+        if self.peek() == TokenKind::Catch {
+            self.advance();
+            self.expect(TokenKind::LParen, "Expect '(' after catch")?;
+            if self.peek() != TokenKind::Ident {
+                self.error("Expect identifier in catch clause");
+                return Err(());
+            }
+            self.advance();
+            let name = self.name();
+            self.expect(TokenKind::RParen, "Expect ')' after catch identifier")?;
+            self.expect(TokenKind::LBrace, "Expect '{' after catch clause")?;
+            handler = Some((name, self.parse_block()?));
+        }
+        let mut finally = None;
+        if self.peek() == TokenKind::Finally {
+            self.advance();
+            self.expect(TokenKind::LBrace, "Expect '{' after finally")?;
+            finally = Some(self.parse_block()?);
+        }
+        if handler.is_none() && finally.is_none() {
+            self.error("Expect 'catch' or 'finally' after try block");
+            return Err(());
+        }
+        Ok(self.ast.alloc_stmt(StmtNode::Try(body, handler, finally)))
+    }
+    fn parse_macro(&mut self) -> Result<StmtId, ()> {
+        self.advance();
+        let name = self.name();
+        self.expect(TokenKind::LParen, "Expected '(' after macro name")?;
+        let start = self.patterns.len();
+        if self.peek() != TokenKind::RParen {
+            loop {
+                if self.peek() != TokenKind::Ident {
+                    self.error("Expected macro parameter name");
+                    return Err(());
+                }
+                self.advance();
+                let param = self.name();
+                let duplicate =
+                    self.patterns[start..]
+                        .iter()
+                        .any(|seen| match self.ast.pattern(*seen) {
+                            PatternNode::Ident(seen) => seen.symbol == param.symbol,
+                            PatternNode::Tuple(_) => false,
+                        });
+                if duplicate {
+                    self.error("Duplicate macro parameter");
+                    return Err(());
+                }
+                let param = self.ast.alloc_pattern(PatternNode::Ident(param));
+                self.patterns.push(param);
+                if self.peek() != TokenKind::Comma {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        self.expect(TokenKind::RParen, "Expected ')' after macro parameters")?;
+        self.expect(TokenKind::LBrace, "Expected '{' after macro parameters")?;
+        let params = self.ast.commit_patterns(&mut self.patterns, start);
+        let body = self.parse_block()?;
+        Ok(self.ast.alloc_stmt(StmtNode::Macro(name, params, body)))
+    }
+    fn parse_decorated(&mut self) -> Result<StmtId, ()> {
+        let start = self.decorators.len();
+        while self.peek() == TokenKind::At {
+            self.advance();
+            if self.peek() != TokenKind::Ident {
+                self.error("Expected decorator name after '@'");
+                return Err(());
+            }
+            self.advance();
+            let name = self.name();
+            let mut args = Items::default();
+            if self.peek() == TokenKind::LParen {
+                self.advance();
+                args = self.parse_args()?;
+            }
+            self.decorators.push(DecoratorNode { name, args });
+        }
+        self.expect(TokenKind::Function, "Expected 'fn' after decorators")?;
+        let function = self.parse_fn()?;
+        let decorators = self.ast.commit_decorators(&mut self.decorators, start);
+        self.ast.function_mut(function).decorators = decorators;
+        Ok(self.ast.alloc_stmt(StmtNode::Fn(function)))
+    }
+    fn parse_fn(&mut self) -> Result<FnId, ()> {
+        if self.peek() != TokenKind::Ident {
+            self.error("Expected identifier after function definition");
+            return Err(());
+        }
+        self.advance();
+        let name = self.name();
+        self.expect(TokenKind::LParen, "Expected '(' after identifier")?;
+        let start = self.params.len();
+        if self.peek() != TokenKind::RParen {
+            let param = self.parse_param()?;
+            self.params.push(param);
+            while self.peek() == TokenKind::Comma {
+                self.advance();
+                let param = self.parse_param()?;
+                self.params.push(param);
+                if self.params.len() - start > 255 {
+                    self.error("Cannot have more than 255 parameters");
+                    return Err(());
+                }
+            }
+        }
+        if !self.check_params(start) {
+            return Err(());
+        }
+        self.expect(TokenKind::RParen, "Expected ')' after parameters")?;
+        let params = self.ast.commit_params(&mut self.params, start);
+        let mut ret = None;
+        if self.peek() == TokenKind::Arrow {
+            self.advance();
+            ret = Some(self.parse_type()?);
+        }
+        let start = self.contracts.len();
+        loop {
+            if self.peek_ident("requires") {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.contracts.push(ContractNode::Requires(expr));
+            } else if self.peek_ident("ensures") {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.contracts.push(ContractNode::Ensures(expr));
+            } else {
+                break;
+            }
+        }
+        let contracts = self.ast.commit_contracts(&mut self.contracts, start);
+        if self.peek() != TokenKind::LBrace {
+            self.error("Expected '{' after parameters");
+        }
+        self.advance();
+        let body = self.parse_fn_block()?;
+        Ok(self.ast.alloc_fn(FnNode {
+            name,
+            params,
+            ret,
+            body,
+            decorators: Items::default(),
+            contracts,
+        }))
+    }
+    fn parse_param(&mut self) -> Result<ParamNode, ()> {
+        let variadic = self.peek() == TokenKind::Ellipsis;
+        if variadic {
+            self.advance();
+        }
+        let (name, span, pattern) = match self.peek() {
+            TokenKind::Ident => {
+                self.advance();
+                let name = self.name();
+                (Some(name.symbol), name.span, None)
+            }
+            TokenKind::LParen if !variadic => {
+                let span = self.span();
+                (None, span, Some(self.parse_pattern()?))
+            }
+            _ => {
+                self.advance();
+                self.error("Function parameter must be identifiers or tuple patterns");
+                return Err(());
+            }
+        };
+        let mut ty = None;
+        if self.peek() == TokenKind::Colon {
+            self.advance();
+            if variadic {
+                self.error("Variadic parameter cannot have a type annotation");
+                return Err(());
+            }
+            ty = Some(self.parse_type()?);
+        }
+        let default = if self.peek() == TokenKind::Assign {
+            self.advance();
+            if variadic {
+                self.error("Variadic parameter cannot have a default value");
+                return Err(());
+            }
+            Some(self.parse_equality()?)
+        } else {
+            None
+        };
+        Ok(ParamNode {
+            name,
+            span,
+            pattern,
+            ty,
+            default,
+            variadic,
+        })
+    }
+    fn parse_type(&mut self) -> Result<Type, ()> {
+        if self.peek() == TokenKind::Ident {
+            let text = self.peek_text();
+            self.advance();
+            match Type::from_name(text) {
+                Some(ty) => Ok(ty),
+                None => {
+                    self.error("Unknown type name");
+                    Err(())
+                }
+            }
+        } else {
+            self.advance();
+            self.error("Expected type name");
+            Err(())
+        }
+    }
+    fn check_params(&mut self, start: usize) -> bool {
+        let params = &self.params[start..];
+        let mut seen_default = false;
+        let mut message = None;
+        for (i, param) in params.iter().enumerate() {
+            if param.variadic {
+                if i != params.len() - 1 {
+                    message = Some("Variadic parameter must be the last parameter");
+                    break;
+                }
+            } else if param.default.is_some() {
+                seen_default = true;
+            } else if seen_default {
+                message = Some("Parameter without default cannot follow parameter with default");
+                break;
+            }
+        }
+        match message {
+            Some(message) => {
+                self.error(message);
+                false
+            }
+            None => true,
+        }
+    }
+    fn parse_while(&mut self) -> Result<StmtId, ()> {
+        let condition = self.parse_expr()?;
+        self.expect(TokenKind::LBrace, "Expect '{' after while condition")?;
+        let body = self.parse_block()?;
+        Ok(self.ast.alloc_stmt(StmtNode::While(condition, body)))
+    }
+    fn parse_for(&mut self) -> Result<StmtId, ()> {
+        let pattern = self.parse_pattern()?;
+        self.expect(TokenKind::In, "Expect 'in' after for loop variable")?;
+        let iterable = self.parse_expr()?;
+        self.expect(TokenKind::LBrace, "Expect '{' after for loop iterable")?;
+        let body = self.parse_block()?;
+        Ok(self.ast.alloc_stmt(StmtNode::For(pattern, iterable, body)))
+    }
+    fn parse_if(&mut self) -> Result<StmtId, ()> {
+        let condition = self.parse_expr()?;
+        self.expect(TokenKind::LBrace, "Expect '{' after if condition")?;
+        let then_branch = self.parse_block()?;
+        let mut else_branch = None;
+        if self.peek() == TokenKind::Else {
+            self.advance();
+            if self.peek() == TokenKind::If {
+                self.advance();
+                else_branch = Some(self.parse_if()?);
+            } else {
+                self.advance();
+                else_branch = Some(self.parse_block()?);
+            }
+        }
+        Ok(self
+            .ast
+            .alloc_stmt(StmtNode::If(condition, then_branch, else_branch)))
+    }
+    fn parse_statements(&mut self) -> Result<(), ()> {
+        loop {
+            match self.peek() {
+                TokenKind::RBrace | TokenKind::EOF => return Ok(()),
+                _ if self.gave_up() => return Err(()),
+                _ => self.parse_into(),
+            }
+        }
+    }
+    fn parse_block(&mut self) -> Result<StmtId, ()> {
+        let start = self.stmts.len();
+        self.parse_statements()?;
+        if self.peek() != TokenKind::RBrace {
+            self.error("Expect '}' after block");
+            return Err(());
+        }
+        self.advance();
+        let stmts = self.ast.commit_stmts(&mut self.stmts, start);
+        Ok(self.ast.alloc_stmt(StmtNode::Block(stmts)))
+    }
+    fn parse_fn_block(&mut self) -> Result<StmtId, ()> {
+        let start = self.stmts.len();
+        self.parse_statements()?;
+        if self.peek() != TokenKind::RBrace {
+            self.error("Expect '}' after function body (block)");
+            return Err(());
+        }
+        self.advance();
+        let returns = match self.stmts[start..].last() {
+            Some(stmt) => matches!(self.ast.stmt(*stmt), StmtNode::Return(..)),
+            None => false,
+        };
+        if !returns {
+            let stmt = self
+                .ast
+                .alloc_stmt(StmtNode::Return(None, self.previous_span));
+            self.stmts.push(stmt);
+        }
+        let stmts = self.ast.commit_stmts(&mut self.stmts, start);
+        Ok(self.ast.alloc_stmt(StmtNode::Block(stmts)))
+    }
+    fn parse_pattern(&mut self) -> Result<PatternId, ()> {
+        match self.peek() {
+            TokenKind::Ident => {
+                self.advance();
+                let name = self.name();
+                Ok(self.ast.alloc_pattern(PatternNode::Ident(name)))
+            }
+            TokenKind::LParen => {
+                self.advance();
+                let start = self.patterns.len();
+                let mut trailing_comma = false;
+                while self.peek() != TokenKind::RParen {
+                    let pattern = self.parse_pattern()?;
+                    self.patterns.push(pattern);
+                    trailing_comma = self.peek() == TokenKind::Comma;
+                    if !trailing_comma {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(TokenKind::RParen, "Expected ')' after tuple pattern")?;
+                match self.patterns.len() - start {
+                    0 => {
+                        self.error("Tuple pattern cannot be empty");
+                        Err(())
+                    }
+                    1 if !trailing_comma => Ok(self.patterns.pop().unwrap()),
+                    _ => {
+                        let patterns = self.ast.commit_patterns(&mut self.patterns, start);
+                        Ok(self.ast.alloc_pattern(PatternNode::Tuple(patterns)))
+                    }
+                }
+            }
+            _ => {
+                self.advance();
+                self.error("Expected identifier or tuple pattern");
+                Err(())
+            }
+        }
+    }
+    fn parse_let(&mut self) -> Result<StmtId, ()> {
+        if !matches!(self.peek(), TokenKind::Ident | TokenKind::LParen) {
+            self.error("Expect identifier after 'let'");
+            return Err(());
+        }
+        let pattern = self.parse_pattern()?;
+        let mut ty = None;
+        if self.peek() == TokenKind::Colon {
+            self.advance();
+            ty = Some(self.parse_type()?);
+        }
+        match self.peek() {
+            TokenKind::Assign => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                self.expect_semicolon("Expected ';' after statement")?;
+                Ok(self.ast.alloc_stmt(StmtNode::Let(pattern, ty, Some(expr))))
+            }
+            TokenKind::SemiColon if matches!(self.ast.pattern(pattern), PatternNode::Tuple(_)) => {
+                self.error("Destructuring declaration needs an initializer");
+                Err(())
+            }
+            TokenKind::SemiColon => {
+                self.advance();
+                Ok(self.ast.alloc_stmt(StmtNode::Let(pattern, ty, None)))
+            }
+            _ => {
+                self.error("Expected ';' after statement");
+                Err(())
+            }
+        }
+    }
+    fn parse_expr_stmt(&mut self) -> Result<StmtId, ()> {
+        let expr = self.parse_expr()?;
+        self.expect_semicolon("Expected ';' after expression")?;
+        Ok(self.ast.alloc_stmt(StmtNode::Expr(expr)))
+    }
+    fn parse_expr(&mut self) -> Result<ExprId, ()> {
+        self.parse_assignment()
+    }
+    fn parse_assignment(&mut self) -> Result<ExprId, ()> {
+        let left = self.parse_coalesce()?;
+        if self.peek() != TokenKind::Assign {
+            return Ok(left);
+        }
+        self.advance();
+        let expr = self.parse_coalesce()?;
+        match self.expr_pattern(left) {
+            Some(pattern) => Ok(self.ast.alloc_expr(ExprNode::Assign(pattern, expr))),
+            None => {
+                self.error("Illegal assignment");
+                Err(())
+            }
+        }
+    }
+    fn expr_pattern(&mut self, expr: ExprId) -> Option<PatternId> {
+        match *self.ast.expr(expr) {
+            ExprNode::Ident(name) => Some(self.ast.alloc_pattern(PatternNode::Ident(name))),
+            ExprNode::Tuple(exprs) => {
+                let start = self.patterns.len();
+                for i in 0..exprs.len() {
+                    let expr = self.ast.expr_list(exprs)[i];
+                    match self.expr_pattern(expr) {
+                        Some(pattern) => self.patterns.push(pattern),
+                        None => {
+                            self.patterns.truncate(start);
+                            return None;
+                        }
+                    }
+                }
+                let patterns = self.ast.commit_patterns(&mut self.patterns, start);
+                Some(self.ast.alloc_pattern(PatternNode::Tuple(patterns)))
+            }
+            _ => None,
+        }
+    }
+    fn parse_coalesce(&mut self) -> Result<ExprId, ()> {
+        let mut expr = self.parse_equality()?;
+        while self.peek() == TokenKind::DoubleQuestion {
+            self.advance();
+            let right = self.parse_equality()?;
+            expr = self.ast.alloc_expr(ExprNode::Coalesce(expr, right));
+        }
+        Ok(expr)
+    }
+    fn binary(
+        &mut self,
+        operand: fn(&mut Self) -> Result<ExprId, ()>,
+        op: fn(TokenKind) -> Option<BinOp>,
+    ) -> Result<ExprId, ()> {
+        let mut expr = operand(self)?;
+        while let Some(op) = op(self.peek()) {
+            self.advance();
+            let right = operand(self)?;
+            expr = self.ast.alloc_expr(ExprNode::Binary(expr, op, right));
+        }
+        Ok(expr)
+    }
+    fn parse_equality(&mut self) -> Result<ExprId, ()> {
+        self.binary(Self::parse_comparison, |kind| match kind {
+            TokenKind::Equal => Some(BinOp::Equal),
+            TokenKind::NotEqual => Some(BinOp::NotEqual),
+            _ => None,
+        })
+    }
+    fn parse_comparison(&mut self) -> Result<ExprId, ()> {
+        self.binary(Self::parse_range, |kind| match kind {
+            TokenKind::Less => Some(BinOp::Less),
+            TokenKind::LessEqual => Some(BinOp::LessEqual),
+            TokenKind::Greater => Some(BinOp::Greater),
+            TokenKind::GreaterEqual => Some(BinOp::GreaterEqual),
+            _ => None,
+        })
+    }
+    fn parse_range(&mut self) -> Result<ExprId, ()> {
+        let expr = self.parse_term()?;
+        let inclusive = match self.peek() {
+            TokenKind::DotDot => false,
+            TokenKind::DotDotEqual => true,
+            _ => return Ok(expr),
+        };
+        self.advance();
+        let end = self.parse_term()?;
+        let step = match self.peek_ident("step") {
+            true => {
+                self.advance();
+                Some(self.parse_term()?)
+            }
+            false => None,
+        };
+        Ok(self
+            .ast
+            .alloc_expr(ExprNode::Range(expr, end, inclusive, step)))
+    }
+    fn parse_term(&mut self) -> Result<ExprId, ()> {
+        self.binary(Self::parse_factor, |kind| match kind {
+            TokenKind::Plus => Some(BinOp::Plus),
+            TokenKind::Minus => Some(BinOp::Minus),
+            _ => None,
+        })
+    }
+    fn parse_factor(&mut self) -> Result<ExprId, ()> {
+        self.binary(Self::parse_unary, |kind| match kind {
+            TokenKind::Multiply => Some(BinOp::Multiply),
+            TokenKind::Divide => Some(BinOp::Divide),
+            _ => None,
+        })
+    }
+    fn parse_unary(&mut self) -> Result<ExprId, ()> {
+        let op = match self.peek() {
+            TokenKind::Minus => UnaryOp::UnaryMinus,
+            TokenKind::Plus => UnaryOp::UnaryPlus,
+            TokenKind::Not => UnaryOp::Not,
+            TokenKind::Spawn => {
+                self.advance();
+                let call = self.parse_call()?;
+                return match *self.ast.expr(call) {
+                    ExprNode::Call(func, args) => {
+                        self.ast.replace_expr(call, ExprNode::Spawn(func, args));
+                        Ok(call)
+                    }
+                    _ => {
+                        self.error("Expected function call after 'spawn'");
+                        Err(())
+                    }
+                };
+            }
+            _ => return self.parse_call(),
+        };
+        self.advance();
+        let expr = self.parse_unary()?;
+        Ok(self.ast.alloc_expr(ExprNode::Unary(op, expr)))
+    }
+    fn parse_call(&mut self) -> Result<ExprId, ()> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            match self.peek() {
+                TokenKind::LParen => {
+                    self.advance();
+                    let args = self.parse_args()?;
+                    expr = self.ast.alloc_expr(ExprNode::Call(expr, args));
+                }
+                TokenKind::LBracket => {
+                    self.advance();
+                    let index = self.parse_expr()?;
+                    self.expect(TokenKind::RBracket, "Expected ']' after index")?;
+                    expr = self.ast.alloc_expr(ExprNode::Index(expr, index));
+                }
+                TokenKind::Dot => {
+                    self.advance();
+                    expr = self.parse_method_call(expr, false)?;
+                }
+                TokenKind::QuestionDot => {
+                    self.advance();
+                    if self.peek() != TokenKind::LParen {
+                        expr = self.parse_method_call(expr, true)?;
+                        continue;
+                    }
+                    self.advance();
+                    let args = self.parse_args()?;
+                    expr = self.ast.alloc_expr(ExprNode::OptionalCall(expr, args));
+                }
+                TokenKind::Question => {
+                    self.advance();
+                    expr = self.ast.alloc_expr(ExprNode::Propagate(expr));
+                }
+                _ => break,
+            }
+        }
+        Ok(expr)
+    }
+    fn parse_method_call(&mut self, receiver: ExprId, optional: bool) -> Result<ExprId, ()> {
+        if self.peek() != TokenKind::Ident {
+            self.error("Expected method name after '.'");
+            return Err(());
+        }
+        self.advance();
+        let name = self.name();
+        self.expect(TokenKind::LParen, "Expected '(' after method name")?;
+        let args = self.parse_args()?;
+        Ok(self
+            .ast
+            .alloc_expr(ExprNode::MethodCall(receiver, name, args, optional)))
+    }
+    fn parse_args(&mut self) -> Result<Items<ArgNode>, ()> {
+        let start = self.args.len();
+        if self.peek() != TokenKind::RParen {
+            let arg = self.parse_arg(start)?;
+            self.args.push(arg);
+            while self.peek() == TokenKind::Comma {
+                self.advance();
+                let arg = self.parse_arg(start)?;
+                self.args.push(arg);
+                if self.args.len() - start > 255 {
+                    self.error("Cannot have more than 255 arguments");
+                    return Err(());
+                }
+            }
+        }
+        self.expect(TokenKind::RParen, "Expected ')' after arguments")?;
+        Ok(self.ast.commit_args(&mut self.args, start))
+    }
+    fn parse_arg(&mut self, start: usize) -> Result<ArgNode, ()> {
+        let expr = self.parse_expr()?;
+        match *self.ast.expr(expr) {
+            ExprNode::Ident(name) if self.peek() == TokenKind::Colon => {
+                self.advance();
+                let value = self.parse_expr()?;
+                if self.args[start..]
+                    .iter()
+                    .any(|arg| matches!(arg.name, Some(other) if other.symbol == name.symbol))
+                {
+                    self.error("Named argument is given more than once");
+                    return Err(());
+                }
+                Ok(ArgNode {
+                    name: Some(name),
+                    value,
+                })
+            }
+            _ => {
+                if matches!(self.args[start..].last(), Some(arg) if arg.name.is_some()) {
+                    self.error("Positional argument cannot follow named arguments");
+                    return Err(());
+                }
+                Ok(ArgNode {
+                    name: None,
+                    value: expr,
+                })
+            }
+        }
+    }
+    fn parse_primary(&mut self) -> Result<ExprId, ()> {
+        let text = self.peek_text();
+        let node = match self.peek() {
+            TokenKind::Ident => {
+                self.advance();
+                ExprNode::Ident(self.name())
+            }
+            TokenKind::BooleanLiteral => {
+                self.advance();
+                ExprNode::Bool(text == "true")
+            }
+            TokenKind::IntLiteral => {
+                self.advance();
+                ExprNode::Int(text.parse().unwrap_or_default())
+            }
+            TokenKind::StringLiteral => {
+                self.advance();
+                ExprNode::Str(&text[1..text.len() - 1])
+            }
+            TokenKind::Nil => {
+                self.advance();
+                ExprNode::Nil
+            }
+            TokenKind::LParen => {
+                self.advance();
+                let expr = self.parse_expr()?;
+                if self.peek() == TokenKind::Comma {
+                    return self.parse_tuple(expr);
+                }
+                self.expect(TokenKind::RParen, "Expected ')' after expression")?;
+                return Ok(expr);
+            }
+            _ => {
+                self.advance();
+                self.error("Unexpected Token");
+                return Err(());
+            }
+        };
+        Ok(self.ast.alloc_expr(node))
+    }
+    fn parse_tuple(&mut self, first: ExprId) -> Result<ExprId, ()> {
+        let start = self.exprs.len();
+        self.exprs.push(first);
+        while self.peek() == TokenKind::Comma {
+            self.advance();
+            if self.peek() == TokenKind::RParen {
+                break;
+            }
+            let expr = self.parse_expr()?;
+            self.exprs.push(expr);
+        }
+        self.expect(TokenKind::RParen, "Expected ')' after tuple elements")?;
+        let exprs = self.ast.commit_exprs(&mut self.exprs, start);
+        Ok(self.ast.alloc_expr(ExprNode::Tuple(exprs)))
+    }
+}
@@ -1,52 +1,86 @@
 #![allow(unused)]
-use crate::lexer::token::{Token, Tokens};
-use crate::parser::ast::{BinOp, Expr, Ident, Literal, Program, Stmt, UnaryOp};
+use crate::lexer::token::{Span, Token, Tokens};
+use crate::parser::ast::{
+    Arg, BinOp, Contract, Decorator, Expr, Ident, Literal, Param, Pattern, Program, Stmt, Type,
+    UnaryOp,
+};
 use std::slice::Iter;
 
+pub mod arena;
 pub mod ast;
+pub mod cst;
+pub mod fast;
 
 pub struct TokenCursor<'a> {
     tokens: Iter<'a, Token>,
 }
+const MAX_ERRORS: usize = 20;
+
 #[derive(Debug)]
 pub struct ParseError {
     token: Token,
     message: String,
+    span: Span,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} (at {:?})", self.span, self.message, self.token)
+    }
 }
 impl<'a> TokenCursor<'a> {
     pub fn new(input: &'a Tokens) -> Self {
         Self {
-            tokens: input.into_iter(),
+            tokens: input.iter(),
         }
     }
     pub fn peek_first(&self) -> Option<&Token> {
         self.tokens.clone().next()
     }
-    pub fn next(&mut self) -> Option<&Token> {
+    pub fn peek_second(&self) -> Option<&Token> {
+        self.tokens.clone().nth(1)
+    }
+    pub fn next_token(&mut self) -> Option<&Token> {
         self.tokens.next()
     }
 }
 pub struct Parser<'a> {
     previous: Token,
+    previous_span: Span,
     token_cursor: TokenCursor<'a>,
+    spans: &'a [Span],
+    position: usize,
+    panicking: bool,
     pub errors: Vec<ParseError>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a Tokens) -> Self {
+        Self::with_spans(tokens, &[])
+    }
+    pub fn with_spans(tokens: &'a Tokens, spans: &'a [Span]) -> Self {
         Self {
             previous: Token::EOF,
+            previous_span: Span::default(),
             token_cursor: TokenCursor::new(tokens),
+            spans,
+            position: 0,
+            panicking: false,
             errors: vec![],
         }
     }
     fn advance(&mut self) {
-        match self.token_cursor.next() {
+        match self.token_cursor.next_token() {
             Some(tok) => self.previous = tok.clone(),
             None => self.previous = Token::EOF,
         }
+        self.previous_span = self.spans.get(self.position).copied().unwrap_or_default();
+        self.position += 1;
     }
 
+    fn span(&self) -> Span {
+        self.spans.get(self.position).copied().unwrap_or_default()
+    }
     fn peek(&self) -> Token {
         match self.token_cursor.peek_first() {
             Some(tok) => tok.clone(),
@@ -54,15 +88,40 @@ impl<'a> Parser<'a> {
         }
     }
     fn error(&mut self, message: &str) {
+        if self.panicking || self.gave_up() {
+            return;
+        }
+        self.panicking = true;
+        let message = match self.errors.len() == MAX_ERRORS {
+            true => "Too many errors, giving up",
+            false => message,
+        };
         self.errors.push(ParseError {
             token: self.previous.clone(),
             message: message.to_string(),
+            span: self.previous_span,
         })
     }
+    fn gave_up(&self) -> bool {
+        self.errors.len() > MAX_ERRORS
+    }
     fn synchronize(&mut self) {
         loop {
             match self.peek() {
-                Token::Let | Token::If | Token::Return | Token::Function | Token::EOF => {
+                Token::RBrace => return,
+                Token::LParen | Token::LBracket | Token::LBrace => self.skip_balanced(),
+                Token::Let
+                | Token::If
+                | Token::While
+                | Token::For
+                | Token::Return
+                | Token::Function
+                | Token::At
+                | Token::Try
+                | Token::Throw
+                | Token::Yield
+                | Token::Defer
+                | Token::EOF => {
                     return;
                 }
                 Token::SemiColon => {
@@ -74,18 +133,51 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn skip_balanced(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth -= 1,
+                Token::EOF => return,
+                _ => (),
+            }
+            self.advance();
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
     pub fn parse(&mut self) -> Program {
         let mut statements = vec![];
-        while self.peek() != Token::EOF {
-            match self.parse_stmt() {
-                Ok(stmt) => {
-                    statements.push(stmt);
-                }
-                Err(_) => self.synchronize(),
+        while self.peek() != Token::EOF && !self.gave_up() {
+            if self.peek() == Token::RBrace {
+                self.panicking = false;
+                self.advance();
+                self.error("Unexpected '}'");
+                statements.push(Stmt::ErrorStmt(self.previous_span));
+                continue;
             }
+            self.parse_into(&mut statements);
         }
         statements
     }
+    fn parse_into(&mut self, statements: &mut Vec<Stmt>) {
+        self.panicking = false;
+        let position = self.position;
+        let span = self.span();
+        match self.parse_stmt() {
+            Ok(stmt) => statements.push(stmt),
+            Err(_) => {
+                statements.push(Stmt::ErrorStmt(span));
+                self.synchronize();
+                if self.position == position {
+                    self.advance();
+                }
+            }
+        }
+    }
     fn parse_stmt(&mut self) -> Result<Stmt, ()> {
         match self.peek() {
             Token::Let => {
@@ -104,21 +196,50 @@ impl<'a> Parser<'a> {
                 self.advance();
                 self.parse_while()
             }
+            Token::For => {
+                self.advance();
+                self.parse_for()
+            }
             Token::Function => {
                 self.advance();
                 self.parse_fn()
             }
+            Token::At => self.parse_decorated(),
             Token::Return => {
                 self.advance();
                 self.parse_return()
             }
+            Token::Try => {
+                self.advance();
+                self.parse_try()
+            }
+            Token::Throw => {
+                self.advance();
+                self.parse_throw()
+            }
+            Token::Yield => {
+                self.advance();
+                self.parse_yield()
+            }
+            Token::Defer => {
+                self.advance();
+                self.parse_defer()
+            }
+            Token::Ident(ident)
+                if ident == "macro"
+                    && matches!(self.token_cursor.peek_second(), Some(Token::Ident(_))) =>
+            {
+                self.advance();
+                self.parse_macro()
+            }
             _ => self.parse_expr_stmt(),
         }
     }
     fn parse_return(&mut self) -> Result<Stmt, ()> {
+        let span = self.previous_span;
         if self.peek() == Token::SemiColon {
             self.advance();
-            Ok(Stmt::ReturnStmt(None))
+            Ok(Stmt::ReturnStmt(None, span))
         } else {
             let expr = self.parse_expr()?;
             if self.peek() != Token::SemiColon {
@@ -126,13 +247,131 @@ impl<'a> Parser<'a> {
                 Err(())
             } else {
                 self.advance();
-                Ok(Stmt::ReturnStmt(Some(expr)))
+                Ok(Stmt::ReturnStmt(Some(expr), span))
+            }
+        }
+    }
+    fn parse_yield(&mut self) -> Result<Stmt, ()> {
+        if self.peek() == Token::SemiColon {
+            self.advance();
+            return Ok(Stmt::YieldStmt(None));
+        }
+        let expr = self.parse_expr()?;
+        if self.peek() != Token::SemiColon {
+            self.error("Expected ';' after statement");
+            Err(())
+        } else {
+            self.advance();
+            Ok(Stmt::YieldStmt(Some(expr)))
+        }
+    }
+    fn parse_defer(&mut self) -> Result<Stmt, ()> {
+        let expr = self.parse_expr()?;
+        if self.peek() != Token::SemiColon {
+            self.error("Expected ';' after defer expression");
+            return Err(());
+        }
+        self.advance();
+        Ok(Stmt::DeferStmt(expr))
+    }
+    fn parse_throw(&mut self) -> Result<Stmt, ()> {
+        let expr = self.parse_expr()?;
+        if self.peek() != Token::SemiColon {
+            self.error("Expected ';' after statement");
+            Err(())
+        } else {
+            self.advance();
+            Ok(Stmt::ThrowStmt(expr))
+        }
+    }
+    fn parse_try(&mut self) -> Result<Stmt, ()> {
+        if self.peek() != Token::LBrace {
+            self.error("Expect '{' after try");
+            return Err(());
+        }
+        self.advance();
+        let body = self.parse_block()?;
+        let mut handler = None;
+        if self.peek() == Token::Catch {
+            self.advance();
+            if self.peek() != Token::LParen {
+                self.error("Expect '(' after catch");
+                return Err(());
+            }
+            self.advance();
+            let ident = match self.peek() {
+                Token::Ident(ident) => Ident(ident, self.span()),
+                _ => {
+                    self.error("Expect identifier in catch clause");
+                    return Err(());
+                }
+            };
+            self.advance();
+            if self.peek() != Token::RParen {
+                self.error("Expect ')' after catch identifier");
+                return Err(());
+            }
+            self.advance();
+            if self.peek() != Token::LBrace {
+                self.error("Expect '{' after catch clause");
+                return Err(());
+            }
+            self.advance();
+            handler = Some((ident, Box::from(self.parse_block()?)));
+        }
+        let mut finally = None;
+        if self.peek() == Token::Finally {
+            self.advance();
+            if self.peek() != Token::LBrace {
+                self.error("Expect '{' after finally");
+                return Err(());
+            }
+            self.advance();
+            finally = Some(Box::from(self.parse_block()?));
+        }
+        if handler.is_none() && finally.is_none() {
+            self.error("Expect 'catch' or 'finally' after try block");
+            return Err(());
+        }
+        Ok(Stmt::TryStmt(Box::from(body), handler, finally))
+    }
+    fn parse_decorated(&mut self) -> Result<Stmt, ()> {
+        let mut decorators = vec![];
+        while self.peek() == Token::At {
+            self.advance();
+            let name = match self.peek() {
+                Token::Ident(name) => {
+                    self.advance();
+                    Ident(name, self.previous_span)
+                }
+                _ => {
+                    self.error("Expected decorator name after '@'");
+                    return Err(());
+                }
+            };
+            let mut args = vec![];
+            if self.peek() == Token::LParen {
+                self.advance();
+                args = self.parse_args()?;
             }
+            decorators.push(Decorator { name, args });
+        }
+        if self.peek() != Token::Function {
+            self.error("Expected 'fn' after decorators");
+            return Err(());
+        }
+        self.advance();
+        match self.parse_fn()? {
+            Stmt::FnStmt(ident, params, ret, body, _, contracts) => Ok(Stmt::FnStmt(
+                ident, params, ret, body, decorators, contracts,
+            )),
+            _ => unreachable!(),
         }
     }
     fn parse_fn(&mut self) -> Result<Stmt, ()> {
         if let Token::Ident(ident) = self.peek() {
             self.advance();
+            let span = self.previous_span;
             if self.peek() != Token::LParen {
                 self.error("Expected '(' after identifier");
                 return Err(());
@@ -140,42 +379,188 @@ impl<'a> Parser<'a> {
             self.advance();
             let mut parameters = vec![];
             if self.peek() != Token::RParen {
-                let para = self.parse_expr()?;
-                if !matches!(para, Expr::IdentExpr(_)) {
-                    self.error("Function parameter must be identifiers");
-                    return Err(());
-                }
-                parameters.push(para);
+                parameters.push(self.parse_param()?);
                 while self.peek() == Token::Comma {
                     self.advance();
-                    let para = self.parse_expr()?;
-                    if !matches!(para, Expr::IdentExpr(_)) {
-                        self.error("Function parameter must be identifiers");
-                        return Err(());
-                    }
-                    parameters.push(para);
+                    parameters.push(self.parse_param()?);
                     if parameters.len() > 255 {
                         self.error("Cannot have more than 255 parameters");
                         return Err(());
                     }
                 }
             }
+            if !self.check_params(&parameters) {
+                return Err(());
+            }
             if self.peek() != Token::RParen {
                 self.error("Expected ')' after parameters");
                 return Err(());
             }
             self.advance();
+            let mut return_type = None;
+            if self.peek() == Token::Arrow {
+                self.advance();
+                return_type = Some(self.parse_type()?);
+            }
+            let mut contracts = vec![];
+            loop {
+                match self.peek() {
+                    Token::Ident(ident) if ident == "requires" => {
+                        self.advance();
+                        contracts.push(Contract::Requires(self.parse_expr()?));
+                    }
+                    Token::Ident(ident) if ident == "ensures" => {
+                        self.advance();
+                        contracts.push(Contract::Ensures(self.parse_expr()?));
+                    }
+                    _ => break,
+                }
+            }
             if self.peek() != Token::LBrace {
                 self.error("Expected '{' after parameters");
             }
             self.advance();
             let body = self.parse_fn_block()?;
-            Ok(Stmt::FnStmt(Ident(ident), parameters, Box::from(body)))
+            Ok(Stmt::FnStmt(
+                Ident(ident, span),
+                parameters,
+                return_type,
+                Box::from(body),
+                vec![],
+                contracts,
+            ))
         } else {
             self.error("Expected identifier after function definition");
             Err(())
         }
     }
+    fn parse_macro(&mut self) -> Result<Stmt, ()> {
+        self.advance();
+        let name = match &self.previous {
+            Token::Ident(name) => Ident(name.clone(), self.previous_span),
+            _ => unreachable!("macro name is checked before parsing"),
+        };
+        if self.peek() != Token::LParen {
+            self.error("Expected '(' after macro name");
+            return Err(());
+        }
+        self.advance();
+        let mut params: Vec<Ident> = vec![];
+        if self.peek() != Token::RParen {
+            loop {
+                let param = match self.peek() {
+                    Token::Ident(param) => param,
+                    _ => {
+                        self.error("Expected macro parameter name");
+                        return Err(());
+                    }
+                };
+                self.advance();
+                if params.iter().any(|seen| seen.0 == param) {
+                    self.error("Duplicate macro parameter");
+                    return Err(());
+                }
+                params.push(Ident(param, self.previous_span));
+                if self.peek() != Token::Comma {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        if self.peek() != Token::RParen {
+            self.error("Expected ')' after macro parameters");
+            return Err(());
+        }
+        self.advance();
+        if self.peek() != Token::LBrace {
+            self.error("Expected '{' after macro parameters");
+            return Err(());
+        }
+        self.advance();
+        let body = self.parse_block()?;
+        Ok(Stmt::MacroStmt(name, params, Box::from(body)))
+    }
+    fn parse_param(&mut self) -> Result<Param, ()> {
+        let variadic = self.peek() == Token::Ellipsis;
+        if variadic {
+            self.advance();
+        }
+        let (name, pattern) = match self.peek() {
+            Token::Ident(ident) => {
+                self.advance();
+                (Ident(ident, self.previous_span), None)
+            }
+            Token::LParen if !variadic => {
+                let span = self.span();
+                let pattern = self.parse_pattern()?;
+                (Ident(pattern.to_string(), span), Some(pattern))
+            }
+            _ => {
+                self.advance();
+                self.error("Function parameter must be identifiers or tuple patterns");
+                return Err(());
+            }
+        };
+        let mut ty = None;
+        if self.peek() == Token::Colon {
+            self.advance();
+            if variadic {
+                self.error("Variadic parameter cannot have a type annotation");
+                return Err(());
+            }
+            ty = Some(self.parse_type()?);
+        }
+        let default = if self.peek() == Token::Assign {
+            self.advance();
+            if variadic {
+                self.error("Variadic parameter cannot have a default value");
+                return Err(());
+            }
+            Some(self.parse_equality()?)
+        } else {
+            None
+        };
+        Ok(Param {
+            name,
+            pattern,
+            ty,
+            default,
+            variadic,
+        })
+    }
+    fn parse_type(&mut self) -> Result<Type, ()> {
+        if let Token::Ident(name) = self.peek() {
+            self.advance();
+            match Type::from_name(&name) {
+                Some(ty) => Ok(ty),
+                None => {
+                    self.error("Unknown type name");
+                    Err(())
+                }
+            }
+        } else {
+            self.advance();
+            self.error("Expected type name");
+            Err(())
+        }
+    }
+    fn check_params(&mut self, params: &[Param]) -> bool {
+        let mut seen_default = false;
+        for (i, param) in params.iter().enumerate() {
+            if param.variadic {
+                if i != params.len() - 1 {
+                    self.error("Variadic parameter must be the last parameter");
+                    return false;
+                }
+            } else if param.default.is_some() {
+                seen_default = true;
+            } else if seen_default {
+                self.error("Parameter without default cannot follow parameter with default");
+                return false;
+            }
+        }
+        true
+    }
     fn parse_while(&mut self) -> Result<Stmt, ()> {
         let condition = self.parse_expr()?;
         if self.peek() != Token::LBrace {
@@ -186,6 +571,22 @@ impl<'a> Parser<'a> {
         let loop_block = self.parse_block()?;
         Ok(Stmt::WhileStmt(condition, Box::from(loop_block)))
     }
+    fn parse_for(&mut self) -> Result<Stmt, ()> {
+        let pattern = self.parse_pattern()?;
+        if self.peek() != Token::In {
+            self.error("Expect 'in' after for loop variable");
+            return Err(());
+        }
+        self.advance();
+        let iterable = self.parse_expr()?;
+        if self.peek() != Token::LBrace {
+            self.error("Expect '{' after for loop iterable");
+            return Err(());
+        }
+        self.advance();
+        let loop_block = self.parse_block()?;
+        Ok(Stmt::ForStmt(pattern, iterable, Box::from(loop_block)))
+    }
     fn parse_if(&mut self) -> Result<Stmt, ()> {
         let condition = self.parse_expr()?;
         if self.peek() != Token::LBrace {
@@ -224,12 +625,8 @@ impl<'a> Parser<'a> {
                 Token::RBrace | Token::EOF => {
                     break;
                 }
-                _ => {
-                    let stmt_res = self.parse_stmt();
-                    if let Ok(stmt) = stmt_res {
-                        statements.push(stmt);
-                    }
-                }
+                _ if self.gave_up() => return Err(()),
+                _ => self.parse_into(&mut statements),
             }
         }
         if self.peek() == Token::RBrace {
@@ -247,18 +644,14 @@ impl<'a> Parser<'a> {
                 Token::RBrace | Token::EOF => {
                     break;
                 }
-                _ => {
-                    let stmt_res = self.parse_stmt();
-                    if let Ok(stmt) = stmt_res {
-                        statements.push(stmt);
-                    }
-                }
+                _ if self.gave_up() => return Err(()),
+                _ => self.parse_into(&mut statements),
             }
         }
         if self.peek() == Token::RBrace {
             self.advance();
-            if !matches!(statements.last(), Some(Stmt::ReturnStmt(_))) {
-                statements.push(Stmt::ReturnStmt(None));
+            if !matches!(statements.last(), Some(Stmt::ReturnStmt(..))) {
+                statements.push(Stmt::ReturnStmt(None, self.previous_span));
             }
             Ok(Stmt::BlockStmt(statements))
         } else {
@@ -266,9 +659,53 @@ impl<'a> Parser<'a> {
             Err(())
         }
     }
+    fn parse_pattern(&mut self) -> Result<Pattern, ()> {
+        match self.peek() {
+            Token::Ident(ident) => {
+                self.advance();
+                Ok(Pattern::Ident(Ident(ident, self.previous_span)))
+            }
+            Token::LParen => {
+                self.advance();
+                let mut patterns = vec![];
+                let mut trailing_comma = false;
+                while self.peek() != Token::RParen {
+                    patterns.push(self.parse_pattern()?);
+                    trailing_comma = self.peek() == Token::Comma;
+                    if !trailing_comma {
+                        break;
+                    }
+                    self.advance();
+                }
+                if self.peek() != Token::RParen {
+                    self.error("Expected ')' after tuple pattern");
+                    return Err(());
+                }
+                self.advance();
+                match patterns.len() {
+                    0 => {
+                        self.error("Tuple pattern cannot be empty");
+                        Err(())
+                    }
+                    1 if !trailing_comma => Ok(patterns.pop().unwrap()),
+                    _ => Ok(Pattern::Tuple(patterns)),
+                }
+            }
+            _ => {
+                self.advance();
+                self.error("Expected identifier or tuple pattern");
+                Err(())
+            }
+        }
+    }
     fn parse_let(&mut self) -> Result<Stmt, ()> {
-        if let Token::Ident(ident) = self.peek() {
-            self.advance();
+        if matches!(self.peek(), Token::Ident(_) | Token::LParen) {
+            let pattern = self.parse_pattern()?;
+            let mut ty = None;
+            if self.peek() == Token::Colon {
+                self.advance();
+                ty = Some(self.parse_type()?);
+            }
             match self.peek() {
                 Token::Assign => {
                     self.advance();
@@ -276,7 +713,7 @@ impl<'a> Parser<'a> {
                     match self.peek() {
                         Token::SemiColon => {
                             self.advance();
-                            Ok(Stmt::LetStmt(Ident(ident), Some(expr)))
+                            Ok(Stmt::LetStmt(pattern, ty, Some(expr)))
                         }
                         _ => {
                             self.error("Expected ';' after statement");
@@ -284,9 +721,13 @@ impl<'a> Parser<'a> {
                         }
                     }
                 }
+                Token::SemiColon if matches!(pattern, Pattern::Tuple(_)) => {
+                    self.error("Destructuring declaration needs an initializer");
+                    Err(())
+                }
                 Token::SemiColon => {
                     self.advance();
-                    Ok(Stmt::LetStmt(Ident(ident.clone()), None))
+                    Ok(Stmt::LetStmt(pattern, ty, None))
                 }
                 _ => {
                     self.error("Expected ';' after statement");
@@ -315,21 +756,42 @@ impl<'a> Parser<'a> {
         self.parse_assignment()
     }
     fn parse_assignment(&mut self) -> Result<Expr, ()> {
-        let left = self.parse_equality()?;
+        let left = self.parse_coalesce()?;
         match self.peek() {
             Token::Assign => {
                 self.advance();
-                let expr = self.parse_equality()?;
-                if let Expr::IdentExpr(ident) = left {
-                    Ok(Expr::AssignmentExpr(ident, Box::from(expr)))
-                } else {
-                    self.error("Illegal assignment");
-                    Err(())
+                let expr = self.parse_coalesce()?;
+                match Self::to_pattern(left) {
+                    Some(pattern) => Ok(Expr::AssignmentExpr(pattern, Box::from(expr))),
+                    None => {
+                        self.error("Illegal assignment");
+                        Err(())
+                    }
                 }
             }
             _ => Ok(left),
         }
     }
+    fn to_pattern(expr: Expr) -> Option<Pattern> {
+        match expr {
+            Expr::IdentExpr(ident) => Some(Pattern::Ident(ident)),
+            Expr::TupleExpr(exprs) => exprs
+                .into_iter()
+                .map(Self::to_pattern)
+                .collect::<Option<Vec<_>>>()
+                .map(Pattern::Tuple),
+            _ => None,
+        }
+    }
+    fn parse_coalesce(&mut self) -> Result<Expr, ()> {
+        let mut expr = self.parse_equality()?;
+        while self.peek() == Token::DoubleQuestion {
+            self.advance();
+            let right = self.parse_equality()?;
+            expr = Expr::CoalesceExpr(Box::from(expr), Box::from(right));
+        }
+        Ok(expr)
+    }
     fn parse_equality(&mut self) -> Result<Expr, ()> {
         let mut expr = self.parse_comparison()?;
         loop {
@@ -350,27 +812,27 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
     fn parse_comparison(&mut self) -> Result<Expr, ()> {
-        let mut expr = self.parse_term()?;
+        let mut expr = self.parse_range()?;
         loop {
             match self.peek() {
                 Token::Less => {
                     self.advance();
-                    let right = self.parse_term()?;
+                    let right = self.parse_range()?;
                     expr = Expr::BinExpr(Box::from(expr), BinOp::Less, Box::from(right));
                 }
                 Token::LessEqual => {
                     self.advance();
-                    let right = self.parse_term()?;
+                    let right = self.parse_range()?;
                     expr = Expr::BinExpr(Box::from(expr), BinOp::LessEqual, Box::from(right));
                 }
                 Token::Greater => {
                     self.advance();
-                    let right = self.parse_term()?;
+                    let right = self.parse_range()?;
                     expr = Expr::BinExpr(Box::from(expr), BinOp::Greater, Box::from(right));
                 }
                 Token::GreaterEqual => {
                     self.advance();
-                    let right = self.parse_term()?;
+                    let right = self.parse_range()?;
                     expr = Expr::BinExpr(Box::from(expr), BinOp::GreaterEqual, Box::from(right));
                 }
                 _ => break,
@@ -378,6 +840,29 @@ impl<'a> Parser<'a> {
         }
         Ok(expr)
     }
+    fn parse_range(&mut self) -> Result<Expr, ()> {
+        let expr = self.parse_term()?;
+        let inclusive = match self.peek() {
+            Token::DotDot => false,
+            Token::DotDotEqual => true,
+            _ => return Ok(expr),
+        };
+        self.advance();
+        let end = self.parse_term()?;
+        let step = match self.peek() {
+            Token::Ident(ident) if ident == "step" => {
+                self.advance();
+                Some(Box::from(self.parse_term()?))
+            }
+            _ => None,
+        };
+        Ok(Expr::RangeExpr(
+            Box::from(expr),
+            Box::from(end),
+            inclusive,
+            step,
+        ))
+    }
     fn parse_term(&mut self) -> Result<Expr, ()> {
         let mut expr = self.parse_factor()?;
         loop {
@@ -433,6 +918,16 @@ impl<'a> Parser<'a> {
                 let expr = self.parse_unary()?;
                 Ok(Expr::UnaryExpr(UnaryOp::Not, Box::from(expr)))
             }
+            Token::Spawn => {
+                self.advance();
+                match self.parse_call()? {
+                    Expr::CallExpr(func, args) => Ok(Expr::SpawnExpr(func, args)),
+                    _ => {
+                        self.error("Expected function call after 'spawn'");
+                        Err(())
+                    }
+                }
+            }
             _ => self.parse_call(),
         }
     }
@@ -441,35 +936,111 @@ impl<'a> Parser<'a> {
         loop {
             if self.peek() == Token::LParen {
                 self.advance();
-                let mut arguments = vec![];
-                if self.peek() != Token::RParen {
-                    arguments.push(self.parse_expr()?);
-                    while self.peek() == Token::Comma {
-                        self.advance();
-                        arguments.push(self.parse_expr()?);
-                        if arguments.len() > 255 {
-                            self.error("Cannot have more than 255 arguments");
-                            return Err(());
-                        }
-                    }
-                }
-                if self.peek() != Token::RParen {
-                    self.error("Expected ')' after arguments");
+                let arguments = self.parse_args()?;
+                expr = Expr::CallExpr(Box::from(expr), arguments);
+            } else if self.peek() == Token::LBracket {
+                self.advance();
+                let index = self.parse_expr()?;
+                if self.peek() != Token::RBracket {
+                    self.error("Expected ']' after index");
                     return Err(());
                 }
                 self.advance();
-                expr = Expr::CallExpr(Box::from(expr), arguments);
+                expr = Expr::IndexExpr(Box::from(expr), Box::from(index));
+            } else if self.peek() == Token::Dot {
+                self.advance();
+                expr = self.parse_method_call(expr, false)?;
+            } else if self.peek() == Token::QuestionDot {
+                self.advance();
+                if self.peek() != Token::LParen {
+                    expr = self.parse_method_call(expr, true)?;
+                    continue;
+                }
+                self.advance();
+                let arguments = self.parse_args()?;
+                expr = Expr::OptionalCallExpr(Box::from(expr), arguments);
+            } else if self.peek() == Token::Question {
+                self.advance();
+                expr = Expr::PropagateExpr(Box::from(expr));
             } else {
                 break;
             }
         }
         Ok(expr)
     }
+    fn parse_method_call(&mut self, receiver: Expr, optional: bool) -> Result<Expr, ()> {
+        let name = match self.peek() {
+            Token::Ident(name) => {
+                self.advance();
+                Ident(name, self.previous_span)
+            }
+            _ => {
+                self.error("Expected method name after '.'");
+                return Err(());
+            }
+        };
+        if self.peek() != Token::LParen {
+            self.error("Expected '(' after method name");
+            return Err(());
+        }
+        self.advance();
+        let arguments = self.parse_args()?;
+        Ok(Expr::MethodCallExpr(
+            Box::from(receiver),
+            name,
+            arguments,
+            optional,
+        ))
+    }
+    fn parse_args(&mut self) -> Result<Vec<Arg>, ()> {
+        let mut arguments = vec![];
+        if self.peek() != Token::RParen {
+            arguments.push(self.parse_arg(&arguments)?);
+            while self.peek() == Token::Comma {
+                self.advance();
+                arguments.push(self.parse_arg(&arguments)?);
+                if arguments.len() > 255 {
+                    self.error("Cannot have more than 255 arguments");
+                    return Err(());
+                }
+            }
+        }
+        if self.peek() != Token::RParen {
+            self.error("Expected ')' after arguments");
+            return Err(());
+        }
+        self.advance();
+        Ok(arguments)
+    }
+    fn parse_arg(&mut self, previous: &[Arg]) -> Result<Arg, ()> {
+        let expr = self.parse_expr()?;
+        match expr {
+            Expr::IdentExpr(ident) if self.peek() == Token::Colon => {
+                self.advance();
+                let value = self.parse_expr()?;
+                if previous
+                    .iter()
+                    .any(|arg| matches!(arg, Arg::Named(name, _) if name.0 == ident.0))
+                {
+                    self.error("Named argument is given more than once");
+                    return Err(());
+                }
+                Ok(Arg::Named(ident, value))
+            }
+            _ => {
+                if matches!(previous.last(), Some(Arg::Named(_, _))) {
+                    self.error("Positional argument cannot follow named arguments");
+                    return Err(());
+                }
+                Ok(Arg::Positional(expr))
+            }
+        }
+    }
     fn parse_primary(&mut self) -> Result<Expr, ()> {
         match self.peek() {
             Token::Ident(ident) => {
                 self.advance();
-                Ok(Expr::IdentExpr(Ident(ident)))
+                Ok(Expr::IdentExpr(Ident(ident, self.previous_span)))
             }
             Token::BooleanLiteral(literal) => {
                 self.advance();
@@ -483,9 +1054,16 @@ impl<'a> Parser<'a> {
                 self.advance();
                 Ok(Expr::LiteralExpr(Literal::StringLiteral(literal)))
             }
+            Token::Nil => {
+                self.advance();
+                Ok(Expr::LiteralExpr(Literal::NilLiteral))
+            }
             Token::LParen => {
                 self.advance();
                 let expr = self.parse_expr()?;
+                if self.peek() == Token::Comma {
+                    return self.parse_tuple(expr);
+                }
                 match self.peek() {
                     Token::RParen => {
                         self.advance();
@@ -504,11 +1082,28 @@ impl<'a> Parser<'a> {
             }
         }
     }
+    fn parse_tuple(&mut self, first: Expr) -> Result<Expr, ()> {
+        let mut exprs = vec![first];
+        while self.peek() == Token::Comma {
+            self.advance();
+            if self.peek() == Token::RParen {
+                break;
+            }
+            exprs.push(self.parse_expr()?);
+        }
+        if self.peek() != Token::RParen {
+            self.error("Expected ')' after tuple elements");
+            return Err(());
+        }
+        self.advance();
+        Ok(Expr::TupleExpr(exprs))
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::lexer::Lexer;
 
     #[test]
     fn test_parser_1() {
@@ -591,4 +1186,272 @@ mod test {
             println!("{:?}", stmt);
         }
     }
+
+    #[test]
+    fn test_parser_2() {
+        let source = "
+            fn f(a) {
+                let = 1;
+                if a {
+                    print(a +);
+                    let b = (1, 2;
+                }
+                while a > 0 { a = a - 1; }
+                return a;
+            }
+            let y = 2;
+            }
+            let z = ;
+        ";
+        let (tokens, spans) = Lexer::lex_spanned(source);
+        let mut parser = Parser::with_spans(&tokens, &spans);
+        let result = parser.parse();
+        let messages: Vec<String> = parser.errors.iter().map(|err| err.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "3:17: Expect identifier after 'let' (at Let)",
+                "5:30: Unexpected Token (at RParen)",
+                "6:33: Expected ')' after tuple elements (at IntLiteral(2))",
+                "12:13: Unexpected '}' (at RBrace)",
+                "13:21: Unexpected Token (at SemiColon)",
+            ]
+        );
+        assert_eq!(result.len(), 4);
+        let body = match &result[0] {
+            Stmt::FnStmt(_, _, _, body, _, _) => body,
+            stmt => panic!("Expected a function, found {:?}", stmt),
+        };
+        match body.as_ref() {
+            Stmt::BlockStmt(stmts) => {
+                assert!(matches!(stmts[0], Stmt::ErrorStmt(_)));
+                assert!(matches!(stmts[1], Stmt::IfStmt(..)));
+                assert!(matches!(stmts[2], Stmt::WhileStmt(..)));
+                assert!(matches!(stmts[3], Stmt::ReturnStmt(..)));
+            }
+            stmt => panic!("Expected a block, found {:?}", stmt),
+        }
+        assert!(matches!(result[1], Stmt::LetStmt(..)));
+        assert!(matches!(result[2], Stmt::ErrorStmt(_)));
+        assert!(matches!(result[3], Stmt::ErrorStmt(_)));
+    }
+
+    #[test]
+    fn test_parser_3() {
+        let source = "let = 1;\n".repeat(100);
+        let tokens = Lexer::lex_tokens(&source);
+        let mut parser = Parser::new(&tokens);
+        parser.parse();
+        assert_eq!(parser.errors.len(), MAX_ERRORS + 1);
+        assert_eq!(
+            parser.errors.last().unwrap().message,
+            "Too many errors, giving up"
+        );
+    }
+
+    fn error_sources() -> Vec<String> {
+        [
+            "
+            @trace @deprecated(\"old\")
+            fn f(a: int, (b, c), d = 1, ...rest) -> int requires a > 0 ensures result > 0 {
+                let (x, y) = (a, b,);
+                for (i, j) in [1, 2] { x = x ?? y; }
+                try { throw err(\"e\"); } catch (e) { print(e); } finally { defer close(); }
+                let g = spawn worker(ch, n: 2);
+                yield 1..=10 step 2;
+                return -x * (y + !c)[0]?.len()?;
+            }
+            ",
+            "
+            fn f(a) {
+                let = 1;
+                if a {
+                    print(a +);
+                    let b = (1, 2;
+                }
+                while a > 0 { a = a - 1; }
+                return a;
+            }
+            let y = 2;
+            }
+            let z = ;
+            f(a: 1, 2); f(a: 1, a: 2); 1 = 2; @memo let x;
+            fn g(...a, b) {} fn h(a = 1, b) {} let (a, b);
+            ",
+            &"let = 1;\n".repeat(100),
+            "
+            macro swap(a, b) { let tmp = a; a = b; b = tmp; }
+            macro nothing() {}
+            let macro = 1; macro = macro(2);
+            macro m a, b) {}
+            macro m(a, 1) {}
+            macro m(a, a) {}
+            macro m(a, b {}
+            macro m(a) print(a);
+            ",
+        ]
+        .iter()
+        .map(|source| source.to_string())
+        .collect()
+    }
+
+    fn messages(errors: &[ParseError]) -> Vec<String> {
+        errors.iter().map(|err| err.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parser_4() {
+        for source in error_sources() {
+            let (tokens, spans) = Lexer::lex_spanned(&source);
+            let mut parser = Parser::with_spans(&tokens, &spans);
+            let expected = parser.parse();
+            let lexemes = Lexer::scan(&source);
+            let mut arena_parser = fast::ArenaParser::new(&lexemes);
+            let ast = arena_parser.parse();
+            assert_eq!(ast.lower(), expected);
+            assert_eq!(messages(&arena_parser.errors), messages(&parser.errors));
+        }
+    }
+
+    #[test]
+    fn test_parser_5() {
+        let source = "// leading comment\n\
+            fn add(a, b) { // trailing comment\n\
+            \t  return a +  b; }\r\n\
+            let x = add(1, 2);  // done\n";
+        let lexemes = Lexer::scan_lossless(source);
+        let mut parser = cst::CstParser::new(&lexemes);
+        let root = parser.parse();
+        assert!(parser.errors.is_empty());
+        assert_eq!(root.text(), source);
+        let typed = cst::typed::Root::cast(&root).unwrap();
+        let stmts: Vec<_> = typed.stmts().collect();
+        match stmts[0] {
+            cst::typed::AnyStmt::Fn(function) => {
+                assert_eq!(function.name().text, "add");
+                assert_eq!(function.params().unwrap().params().count(), 2);
+            }
+            stmt => panic!("Expected a function, found {:?}", stmt),
+        }
+        let (tokens, spans) = Lexer::lex_spanned(source);
+        let expected = Parser::with_spans(&tokens, &spans).parse();
+        assert_eq!(typed.lower(), expected);
+
+        let source = "fn f(a) {\n  let = 1; // broken\n  print(a +);\n}\n}\nlet z = ;\n";
+        let lexemes = Lexer::scan_lossless(source);
+        let mut parser = cst::CstParser::new(&lexemes);
+        let root = parser.parse();
+        assert_eq!(root.text(), source);
+        let (tokens, spans) = Lexer::lex_spanned(source);
+        let mut expected = Parser::with_spans(&tokens, &spans);
+        expected.parse();
+        assert_eq!(messages(&parser.errors), messages(&expected.errors));
+        assert_eq!(
+            messages(&parser.errors),
+            vec![
+                "2:3: Expect identifier after 'let' (at Let)",
+                "3:12: Unexpected Token (at RParen)",
+                "5:1: Unexpected '}' (at RBrace)",
+                "6:9: Unexpected Token (at SemiColon)",
+            ]
+        );
+        let program = cst::typed::Root::cast(&root).unwrap().lower();
+        assert_eq!(program.len(), 3);
+        match &program[0] {
+            Stmt::FnStmt(_, _, _, body, _, _) => match body.as_ref() {
+                Stmt::BlockStmt(stmts) => {
+                    assert!(matches!(stmts[0], Stmt::ErrorStmt(_)));
+                    assert!(matches!(stmts[1], Stmt::ErrorStmt(_)));
+                    assert!(matches!(stmts[2], Stmt::ReturnStmt(None, _)));
+                }
+                stmt => panic!("Expected a block, found {:?}", stmt),
+            },
+            stmt => panic!("Expected a function, found {:?}", stmt),
+        }
+        assert!(matches!(program[1], Stmt::ErrorStmt(_)));
+        assert!(matches!(program[2], Stmt::ErrorStmt(_)));
+
+        let mut sources = error_sources();
+        sources.push("let x = 1 let y = 2;".to_string());
+        sources.push("print(1)\n// c".to_string());
+        for source in sources {
+            let (tokens, spans) = Lexer::lex_spanned(&source);
+            let mut expected = Parser::with_spans(&tokens, &spans);
+            expected.parse();
+            let lexemes = Lexer::scan_lossless(&source);
+            let mut parser = cst::CstParser::new(&lexemes);
+            assert_eq!(parser.parse().text(), source);
+            assert_eq!(messages(&parser.errors), messages(&expected.errors));
+        }
+    }
+
+    #[test]
+    fn test_parser_6() {
+        use ast::{Fold, MutVisitor, Visitor};
+
+        struct Counter {
+            idents: Vec<String>,
+            literals: usize,
+        }
+        impl Visitor for Counter {
+            fn visit_ident(&mut self, ident: &Ident) {
+                self.idents.push(ident.0.clone());
+            }
+            fn visit_literal(&mut self, _literal: &Literal) {
+                self.literals += 1;
+            }
+        }
+
+        struct Renamer;
+        impl MutVisitor for Renamer {
+            fn visit_ident(&mut self, ident: &mut Ident) {
+                if ident.0 == "a" {
+                    ident.0 = "b".to_string();
+                }
+            }
+        }
+
+        struct Doubler;
+        impl Fold for Doubler {
+            fn fold_literal(&mut self, literal: Literal) -> Literal {
+                match literal {
+                    Literal::IntLiteral(value) => Literal::IntLiteral(value * 2),
+                    literal => literal,
+                }
+            }
+        }
+
+        let source = "fn f(a = 1) requires a > 0 { return a + 2; }\nlet (x, y) = (f(a: 3), \"s\");";
+        let (tokens, spans) = Lexer::lex_spanned(source);
+        let mut program = Parser::with_spans(&tokens, &spans).parse();
+        let mut counter = Counter {
+            idents: vec![],
+            literals: 0,
+        };
+        counter.visit_program(&program);
+        assert_eq!(counter.idents, ["f", "a", "a", "a", "x", "y", "f", "a"]);
+        assert_eq!(counter.literals, 5);
+
+        Renamer.visit_program(&mut program);
+        let program = Doubler.fold_program(program);
+        match &program[0] {
+            Stmt::FnStmt(_, params, _, body, _, contracts) => {
+                assert_eq!(params[0].name.0, "b");
+                assert_eq!(params[0].default.as_ref().unwrap().to_string(), "2");
+                assert_eq!(contracts[0].to_string(), "requires b > 0");
+                match body.as_ref() {
+                    Stmt::BlockStmt(stmts) => match &stmts[0] {
+                        Stmt::ReturnStmt(Some(expr), _) => assert_eq!(expr.to_string(), "b + 4"),
+                        stmt => panic!("Expected a return, found {:?}", stmt),
+                    },
+                    stmt => panic!("Expected a block, found {:?}", stmt),
+                }
+            }
+            stmt => panic!("Expected a function, found {:?}", stmt),
+        }
+        match &program[1] {
+            Stmt::LetStmt(_, _, Some(expr)) => assert_eq!(expr.to_string(), "(f(b: 6), \"s\")"),
+            stmt => panic!("Expected a let, found {:?}", stmt),
+        }
+    }
 }
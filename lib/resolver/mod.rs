@@ -0,0 +1,473 @@
+use crate::lexer::token::Span;
+use crate::parser::ast::{Arg, Contract, Expr, Ident, Param, Pattern, Program, Stmt};
+use crate::vm::VM;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub struct ResolveError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+struct Variable {
+    depth: usize,
+    params: Option<Vec<Param>>,
+    assigned_by_fn: bool,
+}
+
+#[derive(Clone)]
+struct Flow {
+    assigned: HashSet<usize>,
+    diverged: bool,
+}
+
+impl Flow {
+    fn new() -> Self {
+        Self {
+            assigned: HashSet::new(),
+            diverged: false,
+        }
+    }
+
+    fn merge(self, other: Flow) -> Flow {
+        if self.diverged {
+            return other;
+        }
+        if other.diverged {
+            return self;
+        }
+        Flow {
+            assigned: self
+                .assigned
+                .intersection(&other.assigned)
+                .copied()
+                .collect(),
+            diverged: false,
+        }
+    }
+}
+
+pub struct Resolver {
+    pub errors: Vec<ResolveError>,
+    builtins: HashSet<String>,
+    scopes: Vec<HashMap<String, usize>>,
+    variables: Vec<Variable>,
+    unassigned_reads: Vec<(usize, Ident)>,
+    flow: Flow,
+    depth: usize,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            errors: vec![],
+            builtins: VM::builtins().into_iter().collect(),
+            scopes: vec![HashMap::new()],
+            variables: vec![],
+            unassigned_reads: vec![],
+            flow: Flow::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn resolve(&mut self, program: &Program) {
+        self.resolve_stmts(program);
+        // A nested function may assign the variable before the read runs, so only
+        // variables that no function assigns are reported.
+        for (id, ident) in std::mem::take(&mut self.unassigned_reads) {
+            if !self.variables[id].assigned_by_fn {
+                self.error(
+                    format!("Can not use variable that is not given value: {}", ident.0),
+                    ident.1,
+                );
+            }
+        }
+        self.errors
+            .sort_by_key(|err| (err.span.line, err.span.column));
+    }
+
+    fn resolve_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts.iter() {
+            if let Stmt::FnStmt(ident, params, _, _, decorators, _) = stmt {
+                let id = self.declare(ident, true);
+                if decorators.iter().all(|decorator| decorator.is_builtin()) {
+                    self.variables[id].params = Some(params.clone());
+                }
+            }
+        }
+        for stmt in stmts.iter() {
+            match stmt {
+                Stmt::FnStmt(_, _, _, _, decorators, _) => {
+                    for decorator in decorators.iter() {
+                        if !decorator.is_builtin() {
+                            self.resolve_read(&decorator.name);
+                        }
+                        self.resolve_args(&decorator.args);
+                    }
+                }
+                stmt => self.resolve_stmt(stmt),
+            }
+        }
+        for stmt in stmts.iter() {
+            if let Stmt::FnStmt(ident, params, _, body, _, contracts) = stmt {
+                self.resolve_fn(ident, params, body, contracts);
+            }
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::ExprStmt(expr) | Stmt::DeferStmt(expr) => self.resolve_expr(expr),
+            Stmt::LetStmt(pattern, _, expr) => {
+                if let Some(expr) = expr {
+                    self.resolve_expr(expr);
+                }
+                self.declare_pattern(pattern, expr.is_some());
+            }
+            Stmt::ReturnStmt(expr, span) => {
+                if self.depth == 0 {
+                    self.error("Return should live in a function".to_string(), *span);
+                }
+                if let Some(expr) = expr {
+                    self.resolve_expr(expr);
+                }
+                self.flow.diverged = true;
+            }
+            Stmt::ThrowStmt(expr) => {
+                self.resolve_expr(expr);
+                self.flow.diverged = true;
+            }
+            Stmt::YieldStmt(expr) => {
+                if let Some(expr) = expr {
+                    self.resolve_expr(expr);
+                }
+            }
+            Stmt::BlockStmt(stmts) => self.resolve_block(stmts),
+            Stmt::IfStmt(condition, body, alt) => {
+                self.resolve_expr(condition);
+                let before = self.flow.clone();
+                self.resolve_stmt(body);
+                let then = std::mem::replace(&mut self.flow, before.clone());
+                if let Some(alt) = alt {
+                    self.resolve_stmt(alt);
+                }
+                let otherwise = std::mem::replace(&mut self.flow, Flow::new());
+                self.flow = then.merge(otherwise);
+            }
+            Stmt::WhileStmt(condition, body) => {
+                self.resolve_expr(condition);
+                let before = self.flow.clone();
+                self.resolve_stmt(body);
+                self.flow = before;
+            }
+            Stmt::ForStmt(pattern, iterable, body) => {
+                self.resolve_expr(iterable);
+                let before = self.flow.clone();
+                self.scopes.push(HashMap::new());
+                self.declare_pattern(pattern, true);
+                self.resolve_stmt(body);
+                self.scopes.pop();
+                self.flow = before;
+            }
+            Stmt::TryStmt(body, handler, finally) => {
+                let before = self.flow.clone();
+                self.resolve_stmt(body);
+                let mut after = std::mem::replace(&mut self.flow, before.clone());
+                if let Some((ident, body)) = handler {
+                    self.scopes.push(HashMap::new());
+                    self.declare(ident, true);
+                    self.resolve_stmt(body);
+                    self.scopes.pop();
+                    let caught = std::mem::replace(&mut self.flow, before.clone());
+                    after = after.merge(caught);
+                }
+                if let Some(finally) = finally {
+                    self.resolve_stmt(finally);
+                    after.assigned.extend(self.flow.assigned.iter());
+                    after.diverged |= self.flow.diverged;
+                }
+                self.flow = after;
+            }
+            Stmt::FnStmt(..) | Stmt::MacroStmt(..) | Stmt::ErrorStmt(_) => (),
+        }
+    }
+
+    fn resolve_block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        self.resolve_stmts(stmts);
+        self.scopes.pop();
+    }
+
+    fn resolve_fn(&mut self, ident: &Ident, params: &[Param], body: &Stmt, contracts: &[Contract]) {
+        let outer = std::mem::replace(&mut self.flow, Flow::new());
+        self.depth += 1;
+        self.scopes.push(HashMap::new());
+        let mut names = HashSet::new();
+        for param in params.iter() {
+            let mut bound = vec![];
+            match &param.pattern {
+                Some(pattern) => collect_pattern(pattern, &mut bound),
+                None => bound.push(&param.name),
+            }
+            for name in bound {
+                if !names.insert(name.0.to_string()) {
+                    self.error(
+                        format!("Duplicate parameter '{}' in function '{}'", name.0, ident.0),
+                        name.1,
+                    );
+                }
+                self.declare(name, true);
+            }
+        }
+        for param in params.iter() {
+            if let Some(default) = &param.default {
+                self.resolve_expr(default);
+            }
+        }
+        for contract in contracts.iter() {
+            match contract {
+                Contract::Requires(expr) => self.resolve_expr(expr),
+                Contract::Ensures(expr) => {
+                    self.scopes.push(HashMap::new());
+                    self.declare(&Ident("result".to_string(), ident.1), true);
+                    self.resolve_expr(expr);
+                    self.scopes.pop();
+                }
+            }
+        }
+        match body {
+            Stmt::BlockStmt(stmts) => self.resolve_stmts(stmts),
+            _ => self.resolve_stmt(body),
+        }
+        self.scopes.pop();
+        self.depth -= 1;
+        self.flow = outer;
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::IdentExpr(ident) => self.resolve_read(ident),
+            Expr::LiteralExpr(_) => (),
+            Expr::AssignmentExpr(pattern, expr) => {
+                self.resolve_expr(expr);
+                let mut bound = vec![];
+                collect_pattern(pattern, &mut bound);
+                for ident in bound {
+                    self.resolve_write(ident);
+                }
+            }
+            Expr::UnaryExpr(_, expr) | Expr::PropagateExpr(expr) => self.resolve_expr(expr),
+            Expr::BinExpr(l_expr, _, r_expr) | Expr::CoalesceExpr(l_expr, r_expr) => {
+                self.resolve_expr(l_expr);
+                self.resolve_expr(r_expr);
+            }
+            Expr::CallExpr(func, args) | Expr::SpawnExpr(func, args) => {
+                self.resolve_expr(func);
+                self.resolve_args(args);
+                if let Expr::IdentExpr(ident) = func.as_ref() {
+                    self.check_call(ident, args);
+                }
+            }
+            Expr::OptionalCallExpr(func, args) | Expr::MethodCallExpr(func, _, args, _) => {
+                self.resolve_expr(func);
+                self.resolve_args(args);
+            }
+            Expr::TupleExpr(exprs) => {
+                for expr in exprs.iter() {
+                    self.resolve_expr(expr);
+                }
+            }
+            Expr::RangeExpr(start, end, _, step) => {
+                self.resolve_expr(start);
+                self.resolve_expr(end);
+                if let Some(step) = step {
+                    self.resolve_expr(step);
+                }
+            }
+            Expr::IndexExpr(target, index) => {
+                self.resolve_expr(target);
+                self.resolve_expr(index);
+            }
+        }
+    }
+
+    fn resolve_args(&mut self, args: &[Arg]) {
+        for arg in args.iter() {
+            match arg {
+                Arg::Positional(expr) | Arg::Named(_, expr) => self.resolve_expr(expr),
+            }
+        }
+    }
+
+    fn resolve_read(&mut self, ident: &Ident) {
+        match self.lookup(&ident.0) {
+            Some(id) => {
+                if self.variables[id].depth == self.depth
+                    && !self.flow.diverged
+                    && !self.flow.assigned.contains(&id)
+                {
+                    self.unassigned_reads.push((id, ident.clone()));
+                }
+            }
+            None if self.builtins.contains(&ident.0) => (),
+            None => self.error(format!("No such variable: {}", ident.0), ident.1),
+        }
+    }
+
+    fn resolve_write(&mut self, ident: &Ident) {
+        match self.lookup(&ident.0) {
+            Some(id) => {
+                self.flow.assigned.insert(id);
+                self.variables[id].params = None;
+                if self.variables[id].depth < self.depth {
+                    self.variables[id].assigned_by_fn = true;
+                }
+            }
+            None if self.builtins.contains(&ident.0) => (),
+            None => self.error(format!("No such variable: {}", ident.0), ident.1),
+        }
+    }
+
+    fn check_call(&mut self, ident: &Ident, args: &[Arg]) {
+        let params = match self.lookup(&ident.0) {
+            Some(id) => match &self.variables[id].params {
+                Some(params) => params.clone(),
+                None => return,
+            },
+            None => return,
+        };
+        let signature = format!(
+            "{}({})",
+            ident.0,
+            params
+                .iter()
+                .map(|param| {
+                    if param.variadic {
+                        format!("...{}", param.name.0)
+                    } else if param.default.is_some() {
+                        format!("{}?", param.name.0)
+                    } else {
+                        param.name.0.to_string()
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        let fixed: Vec<&Param> = params.iter().filter(|param| !param.variadic).collect();
+        let variadic = params.iter().any(|param| param.variadic);
+        let positional = args
+            .iter()
+            .filter(|arg| matches!(arg, Arg::Positional(_)))
+            .count();
+        if !variadic && positional > fixed.len() {
+            self.error(
+                format!(
+                    "{} takes at most {} positional arguments but {} were given",
+                    signature,
+                    fixed.len(),
+                    positional
+                ),
+                ident.1,
+            );
+            return;
+        }
+        let mut given: Vec<bool> = (0..fixed.len()).map(|i| i < positional).collect();
+        for arg in args.iter() {
+            if let Arg::Named(name, _) = arg {
+                match fixed.iter().position(|param| param.name.0 == name.0) {
+                    Some(index) if given[index] => {
+                        self.error(
+                            format!(
+                                "{} got multiple values for argument '{}'",
+                                signature, name.0
+                            ),
+                            name.1,
+                        );
+                        return;
+                    }
+                    Some(index) => given[index] = true,
+                    None => {
+                        self.error(
+                            format!(
+                                "{} got an unexpected named argument '{}'",
+                                signature, name.0
+                            ),
+                            name.1,
+                        );
+                        return;
+                    }
+                }
+            }
+        }
+        if let Some((param, _)) = fixed
+            .iter()
+            .zip(given)
+            .find(|(param, given)| !given && param.default.is_none())
+        {
+            self.error(
+                format!("{} missing required argument '{}'", signature, param.name.0),
+                ident.1,
+            );
+        }
+    }
+
+    fn declare_pattern(&mut self, pattern: &Pattern, assigned: bool) {
+        let mut bound = vec![];
+        collect_pattern(pattern, &mut bound);
+        for ident in bound {
+            self.declare(ident, assigned);
+        }
+    }
+
+    fn declare(&mut self, ident: &Ident, assigned: bool) -> usize {
+        let id = self.variables.len();
+        self.variables.push(Variable {
+            depth: self.depth,
+            params: None,
+            assigned_by_fn: false,
+        });
+        if assigned {
+            self.flow.assigned.insert(id);
+        }
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(ident.0.to_string(), id);
+        id
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        self.errors.push(ResolveError { message, span })
+    }
+}
+
+fn collect_pattern<'a>(pattern: &'a Pattern, names: &mut Vec<&'a Ident>) {
+    match pattern {
+        Pattern::Ident(ident) => names.push(ident),
+        Pattern::Tuple(patterns) => {
+            for pattern in patterns.iter() {
+                collect_pattern(pattern, names);
+            }
+        }
+    }
+}
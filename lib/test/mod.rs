@@ -1,5 +1,24 @@
 #[cfg(test)]
-use crate::{compiler::Compiler, lexer::Lexer, parser::Parser, vm::VM};
+use crate::{
+    checker::Checker,
+    compiler::Compiler,
+    expander::Expander,
+    formatter::Formatter,
+    json::{FromJson, Json, ToJson},
+    lexer::Lexer,
+    optimizer::Optimizer,
+    parser::{
+        ast::Program,
+        cst::{typed::Root, CstParser},
+        fast::ArenaParser,
+        Parser,
+    },
+    resolver::Resolver,
+    vm::{
+        opcode::{Opcode, Value},
+        VM,
+    },
+};
 
 #[test]
 fn run_all_1() {
@@ -190,4 +209,1827 @@ fn run_all_6() {
     compiler.compile(&parse_result);
     let mut vm = VM::new(compiler.codes);
     assert_eq!(vm.run(), Ok(()));
-}
\ No newline at end of file
+}
+#[cfg(test)]
+fn arena_parse(s: &str) -> Program {
+    let lexemes = Lexer::scan(s);
+    let mut parser = ArenaParser::new(&lexemes);
+    let ast = parser.parse();
+    assert_eq!(parser.errors.len(), 0);
+    ast.lower()
+}
+
+#[cfg(test)]
+fn cst_parse(s: &str) -> Program {
+    let lexemes = Lexer::scan_lossless(s);
+    let mut parser = CstParser::new(&lexemes);
+    let root = parser.parse();
+    assert_eq!(parser.errors.len(), 0);
+    assert_eq!(root.text(), s);
+    let mut formatter = Formatter::new();
+    let formatted = formatter.format(s);
+    assert_eq!(formatter.format(&formatted), formatted);
+    assert_eq!(formatter.errors.len(), 0);
+    Root::cast(&root).unwrap().lower()
+}
+
+#[cfg(test)]
+fn json_roundtrip(program: &Program) -> Program {
+    let json = Json::parse(&program.to_json().to_string()).unwrap();
+    Program::from_json(&json).unwrap()
+}
+
+#[cfg(test)]
+fn expand(program: Program) -> Program {
+    let mut expander = Expander::new();
+    let program = expander.expand(program);
+    for err in expander.errors.iter() {
+        println!("{}", err);
+    }
+    assert_eq!(expander.errors.len(), 0);
+    program
+}
+
+#[cfg(test)]
+fn run(s: &str) -> VM {
+    let (tokens, spans) = Lexer::lex_spanned(s);
+    let mut parser = Parser::with_spans(&tokens, &spans);
+    let parse_result = parser.parse();
+    for err in parser.errors.iter() {
+        println!("{:?}", err);
+    }
+    assert_eq!(parser.errors.len(), 0);
+    assert_eq!(arena_parse(s), parse_result);
+    assert_eq!(cst_parse(s), parse_result);
+    assert_eq!(json_roundtrip(&parse_result), parse_result);
+    let parse_result = expand(parse_result);
+    let mut checker = Checker::new();
+    checker.check(&parse_result);
+    for err in checker.errors.iter() {
+        println!("{:?}", err);
+    }
+    assert_eq!(checker.errors.len(), 0);
+    let parse_result = Optimizer::new().optimize(parse_result);
+    let mut compiler = Compiler::new();
+    compiler.compile(&parse_result);
+    assert_eq!(compiler.errors.len(), 0);
+    let mut vm = VM::new(compiler.codes);
+    assert_eq!(vm.run(), Ok(()));
+    vm
+}
+
+#[cfg(test)]
+fn run_err(s: &str) -> String {
+    let (tokens, spans) = Lexer::lex_spanned(s);
+    let mut parser = Parser::with_spans(&tokens, &spans);
+    let parse_result = parser.parse();
+    assert_eq!(parser.errors.len(), 0);
+    assert_eq!(arena_parse(s), parse_result);
+    assert_eq!(cst_parse(s), parse_result);
+    assert_eq!(json_roundtrip(&parse_result), parse_result);
+    let parse_result = expand(parse_result);
+    let mut checker = Checker::new();
+    checker.check(&parse_result);
+    for err in checker.errors.iter() {
+        println!("{:?}", err);
+    }
+    assert_eq!(checker.errors.len(), 0);
+    let parse_result = Optimizer::new().optimize(parse_result);
+    let mut compiler = Compiler::new();
+    compiler.compile(&parse_result);
+    assert_eq!(compiler.errors.len(), 0);
+    let mut vm = VM::new(compiler.codes);
+    vm.run().unwrap_err()
+}
+
+#[cfg(test)]
+fn check_err(s: &str) -> Vec<String> {
+    let lex_result = Lexer::lex_tokens(s);
+    let mut parser = Parser::new(&lex_result);
+    let parse_result = parser.parse();
+    assert_eq!(parser.errors.len(), 0);
+    let mut checker = Checker::new();
+    checker.check(&parse_result);
+    checker.errors.into_iter().map(|err| err.message).collect()
+}
+
+#[cfg(test)]
+fn resolve_err(s: &str) -> Vec<String> {
+    let (lex_result, spans) = Lexer::lex_spanned(s);
+    let mut parser = Parser::with_spans(&lex_result, &spans);
+    let parse_result = parser.parse();
+    assert_eq!(parser.errors.len(), 0);
+    let mut resolver = Resolver::new();
+    resolver.resolve(&parse_result);
+    resolver
+        .errors
+        .into_iter()
+        .map(|err| err.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+fn expand_err(s: &str) -> Vec<String> {
+    let (lex_result, spans) = Lexer::lex_spanned(s);
+    let mut parser = Parser::with_spans(&lex_result, &spans);
+    let parse_result = parser.parse();
+    assert_eq!(parser.errors.len(), 0);
+    let mut expander = Expander::new();
+    expander.expand(parse_result);
+    expander
+        .errors
+        .into_iter()
+        .map(|err| err.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+fn global(vm: &VM, name: &str) -> String {
+    vm.get_global(name).unwrap().to_string()
+}
+
+#[test]
+fn run_all_7() {
+    let vm = run("\
+            fn greet(name, greeting = \"hello \" + name, ...rest) {\
+                return greeting;\
+            }\
+            fn count(...rest) {\
+                return rest;\
+            }\
+            let a = greet(\"drop\");\
+            let b = greet(\"drop\", \"hi\");\
+            let c = greet(greeting: \"hey\", name: \"drop\");\
+            let d = count(1, 2, 3);\
+            let e = count();\
+            ");
+    assert_eq!(global(&vm, "a"), "hello drop");
+    assert_eq!(global(&vm, "b"), "hi");
+    assert_eq!(global(&vm, "c"), "hey");
+    assert_eq!(global(&vm, "d"), "[1, 2, 3]");
+    assert_eq!(global(&vm, "e"), "[]");
+}
+
+#[test]
+fn run_all_8() {
+    let vm = run("\
+            let calls = 0;\
+            fn next_id() {\
+                calls = calls + 1;\
+                return calls;\
+            }\
+            fn make(id = next_id()) {\
+                return id;\
+            }\
+            let a = make();\
+            let b = make(10);\
+            let c = make();\
+            ");
+    assert_eq!(global(&vm, "a"), "1");
+    assert_eq!(global(&vm, "b"), "10");
+    assert_eq!(global(&vm, "c"), "2");
+}
+
+#[test]
+fn run_all_9() {
+    let err = run_err(
+        "\
+            fn add(a, b) {\
+                return a + b;\
+            }\
+            add(1);\
+            ",
+    );
+    assert_eq!(
+        err,
+        "Uncaught ArgumentError: add(a, b) missing required argument 'b'"
+    );
+}
+
+#[test]
+fn run_all_10() {
+    let err = run_err(
+        "\
+            fn log(message, verbose = false) {\
+                print(message);\
+            }\
+            log(\"x\", level: 1);\
+            ",
+    );
+    assert_eq!(
+        err,
+        "Uncaught ArgumentError: log(message, verbose?) got an unexpected named argument 'level'"
+    );
+}
+
+#[test]
+fn run_all_11() {
+    let vm = run("\
+            let log = \"\";\
+            fn check(n) {\
+                if n < 0 {\
+                    throw error(\"negative\", \"TypeError\");\
+                }\
+                return n;\
+            }\
+            fn wrapped(n) {\
+                try {\
+                    return check(n);\
+                } finally {\
+                    log = log + \"f\";\
+                }\
+            }\
+            let a = 0;\
+            let kind = \"\";\
+            try {\
+                a = wrapped(5);\
+                a = wrapped(-1);\
+                a = 100;\
+            } catch (e) {\
+                kind = error_kind(e);\
+                log = log + error_message(e);\
+            }\
+            let b = 0;\
+            try {\
+                b = 1 / 0;\
+            } catch (e) {\
+                b = error_kind(e);\
+            }\
+            let c = 0;\
+            try {\
+                c = undefined_name;\
+            } catch (e) {\
+                c = error_kind(e);\
+            }\
+            let d = 0;\
+            try {\
+                try {\
+                    throw 42;\
+                } finally {\
+                    d = d + 1;\
+                }\
+            } catch (e) {\
+                d = d + e;\
+            }\
+            ");
+    assert_eq!(global(&vm, "a"), "5");
+    assert_eq!(global(&vm, "kind"), "TypeError");
+    assert_eq!(global(&vm, "log"), "ffnegative");
+    assert_eq!(global(&vm, "b"), "ZeroDivisionError");
+    assert_eq!(global(&vm, "c"), "NameError");
+    assert_eq!(global(&vm, "d"), "43");
+}
+
+#[test]
+fn run_all_12() {
+    let s = String::from(
+        "\
+            fn add(a, b) {\
+                return a + b;\
+            }\
+            let caught = \"\";\
+            try {\
+                add(1, \"x\");\
+            } catch (e) {\
+                caught = error_kind(e);\
+            }\
+            try {\
+                add(1);\
+            } catch (e) {\
+                caught = caught + \" \" + error_kind(e);\
+            }\
+            add(true, \"x\");\
+            ",
+    );
+    let lex_result = Lexer::lex_tokens(s.as_str());
+    let mut parser = Parser::new(&lex_result);
+    let parse_result = parser.parse();
+    assert_eq!(parser.errors.len(), 0);
+    let mut compiler = Compiler::new();
+    compiler.compile(&parse_result);
+    let mut vm = VM::new(compiler.codes);
+    assert_eq!(
+        vm.run(),
+        Err("Uncaught TypeError: You cannot add these different value type".to_string())
+    );
+    assert_eq!(global(&vm, "caught"), "TypeError ArgumentError");
+}
+
+#[test]
+fn run_all_13() {
+    let vm = run("\
+            fn parse_digit(s) {\
+                if s == \"1\" {\
+                    return ok(1);\
+                }\
+                if s == \"2\" {\
+                    return ok(2);\
+                }\
+                return err(\"not a digit: \" + s);\
+            }\
+            fn sum(a, b) {\
+                return ok(parse_digit(a)? + parse_digit(b)?);\
+            }\
+            let a = sum(\"1\", \"2\");\
+            let b = sum(\"1\", \"x\");\
+            let c = unwrap(a);\
+            let d = unwrap_or(b, 0);\
+            let e = is_err(b);\
+            let f = is_ok(b);\
+            let g = \"\";\
+            try {\
+                unwrap(b);\
+            } catch (e) {\
+                g = error_message(e);\
+            }\
+            ");
+    assert_eq!(global(&vm, "a"), "ok(3)");
+    assert_eq!(global(&vm, "b"), "err(not a digit: x)");
+    assert_eq!(global(&vm, "c"), "3");
+    assert_eq!(global(&vm, "d"), "0");
+    assert_eq!(global(&vm, "e"), "true");
+    assert_eq!(global(&vm, "f"), "false");
+    assert_eq!(global(&vm, "g"), "called unwrap() on err(not a digit: x)");
+}
+
+#[test]
+fn run_all_14() {
+    let s = String::from("let a = ok(1)?;");
+    let lex_result = Lexer::lex_tokens(s.as_str());
+    let mut parser = Parser::new(&lex_result);
+    let parse_result = parser.parse();
+    assert_eq!(parser.errors.len(), 0);
+    let mut compiler = Compiler::new();
+    compiler.compile(&parse_result);
+    assert_eq!(compiler.errors.len(), 1);
+    assert_eq!(
+        compiler.errors[0].message,
+        "The '?' operator can only be used inside a function"
+    );
+}
+
+#[test]
+fn run_all_15() {
+    let vm = run("\
+            fn fib() {\
+                let a = 0;\
+                let b = 1;\
+                while true {\
+                    yield a;\
+                    let c = a + b;\
+                    a = b;\
+                    b = c;\
+                }\
+            }\
+            fn take(n) {\
+                let i = 0;\
+                while i < n {\
+                    try {\
+                        yield i;\
+                    } finally {\
+                        i = i + 1;\
+                    }\
+                }\
+            }\
+            let gen = fib();\
+            let s = 0;\
+            let i = 0;\
+            while i < 10 {\
+                s = s + next(gen);\
+                i = i + 1;\
+            }\
+            let t = take(2);\
+            let x = next(t) + next(t);\
+            let before = done(t);\
+            let y = next(t);\
+            let after = done(t);\
+            let z = next(t);\
+            ");
+    assert_eq!(global(&vm, "s"), "88");
+    assert_eq!(global(&vm, "i"), "10");
+    assert_eq!(global(&vm, "x"), "1");
+    assert_eq!(global(&vm, "before"), "false");
+    assert_eq!(global(&vm, "y"), "nil");
+    assert_eq!(global(&vm, "after"), "true");
+    assert_eq!(global(&vm, "z"), "nil");
+}
+
+#[test]
+fn run_all_16() {
+    let vm = run("\
+            fn producer(out, from, count) {\
+                let i = 0;\
+                while i < count {\
+                    send(out, from + i);\
+                    i = i + 1;\
+                }\
+            }\
+            fn summer(input, count, result) {\
+                let total = 0;\
+                while count > 0 {\
+                    total = total + recv(input);\
+                    count = count - 1;\
+                }\
+                send(result, total);\
+            }\
+            let numbers = channel(2);\
+            let result = channel();\
+            spawn producer(numbers, 1, 50);\
+            spawn producer(numbers, 1000, 50);\
+            spawn summer(numbers, 100, result);\
+            let total = recv(result);\
+            ");
+    assert_eq!(global(&vm, "total"), "52500");
+}
+
+#[test]
+fn run_all_17() {
+    let err = run_err(
+        "\
+            fn worker(input, output) {\
+                send(output, recv(input));\
+            }\
+            let a = channel();\
+            let b = channel();\
+            spawn worker(a, b);\
+            spawn worker(b, a);\
+            recv(a);\
+            ",
+    );
+    assert_eq!(err, "Deadlock: all 3 tasks are blocked");
+    let err = run_err("let ch = channel(); send(ch, 1);");
+    assert_eq!(err, "Deadlock: the only task is blocked");
+    let vm = run("\
+            fn sender(ch) {\
+                send(ch, 1);\
+                state = \"sent\";\
+            }\
+            let state = \"waiting\";\
+            let ch = channel();\
+            spawn sender(ch);\
+            let value = recv(ch);\
+            let during = state;\
+            ");
+    assert_eq!(global(&vm, "value"), "1");
+    assert_eq!(global(&vm, "during"), "waiting");
+}
+
+#[test]
+fn run_all_18() {
+    let vm = run("\
+            fn add(a: int, b: int = 1) -> int {\
+                return a + b;\
+            }\
+            fn greet(name: string, ...rest) -> string {\
+                return \"hello \" + name;\
+            }\
+            fn twice(f, x) {\
+                return f(f(x));\
+            }\
+            let a: int = add(1, b: 2);\
+            let b = greet(\"drop\", 1, true);\
+            let c = 0;\
+            while c < 3 {\
+                c = c + add(1);\
+            }\
+            let d = twice(add, 1);\
+            ");
+    assert_eq!(global(&vm, "a"), "3");
+    assert_eq!(global(&vm, "b"), "hello drop");
+    assert_eq!(global(&vm, "c"), "4");
+    assert_eq!(global(&vm, "d"), "3");
+}
+
+#[test]
+fn run_all_19() {
+    let errors = check_err(
+        "\
+            fn add(a: int, b: int) -> int {\
+                return a + b;\
+            }\
+            fn name() -> string {\
+                return 1;\
+            }\
+            let s: string = 1 + 2;\
+            let x = add(1, \"2\");\
+            let y: bool = add(b: true, a: 1);\
+            let z = \"a\" - 1;\
+            if \"a\" {\
+                print(s);\
+            }\
+            ",
+    );
+    assert_eq!(
+        errors,
+        vec![
+            "Function 'name' should return string, found int",
+            "Cannot initialize variable 's' of type string with int",
+            "Argument 'b' of 'add' should be int, found string",
+            "Argument 'b' of 'add' should be int, found bool",
+            "Cannot initialize variable 'y' of type bool with int",
+            "Cannot apply Minus to string and int",
+            "Expression in condition should be bool or int, found string",
+        ]
+    );
+}
+
+#[test]
+fn run_all_20() {
+    let vm = run("\
+            fn find(n) {\
+                if n > 0 {\
+                    return n;\
+                }\
+            }\
+            fn double(x) {\
+                return x * 2;\
+            }\
+            fn maybe(flag) {\
+                if flag {\
+                    return double;\
+                }\
+                return nil;\
+            }\
+            let a = find(0) ?? 7;\
+            let b = find(3) ?? find(0) ?? 7;\
+            let c = find(0) == nil;\
+            let d = nil != find(1);\
+            let e = maybe(true)?.(21);\
+            let f = maybe(false)?.(double(1));\
+            let g = f ?? \"fallback\";\
+            let h = nil;\
+            ");
+    assert_eq!(global(&vm, "a"), "7");
+    assert_eq!(global(&vm, "b"), "3");
+    assert_eq!(global(&vm, "c"), "true");
+    assert_eq!(global(&vm, "d"), "true");
+    assert_eq!(global(&vm, "e"), "42");
+    assert_eq!(global(&vm, "f"), "nil");
+    assert_eq!(global(&vm, "g"), "fallback");
+    assert_eq!(global(&vm, "h"), "nil");
+}
+
+#[test]
+fn run_all_21() {
+    let vm = run("\
+            fn divmod(x, y) {\
+                return (x / y, x - x / y * y);\
+            }\
+            fn swap((a, b)) {\
+                return (b, a);\
+            }\
+            fn sum((a, (b, c)), d = 4) {\
+                return a + b + c + d;\
+            }\
+            let (q, r) = divmod(17, 5);\
+            let (x, y) = swap((1, 2));\
+            (x, y) = (y, x);\
+            let s = sum((1, (2, 3)));\
+            let t = (\"one\",);\
+            let u = (q, (r, nil));\
+            ");
+    assert_eq!(global(&vm, "q"), "3");
+    assert_eq!(global(&vm, "r"), "2");
+    assert_eq!(global(&vm, "x"), "1");
+    assert_eq!(global(&vm, "y"), "2");
+    assert_eq!(global(&vm, "s"), "10");
+    assert_eq!(global(&vm, "t"), "(one,)");
+    assert_eq!(global(&vm, "u"), "(3, (2, nil))");
+}
+
+#[test]
+fn run_all_22() {
+    let err = run_err(
+        "\
+            fn triple() {\
+                return (1, 2, 3);\
+            }\
+            let (a, b) = triple();\
+            ",
+    );
+    assert_eq!(
+        err,
+        "Uncaught TypeError: Cannot destructure a tuple of 3 elements into 2 names"
+    );
+    let errors = check_err("let (a, b) = (1, 2, 3);");
+    assert_eq!(
+        errors,
+        vec!["Cannot destructure a tuple of 3 elements into 2 names"]
+    );
+}
+
+#[test]
+fn run_all_23() {
+    let vm = run("\
+            let total = 0;\
+            for i in 1..=10 {\
+                total = total + i;\
+            }\
+            let evens = 0;\
+            for i in 0..10 step 2 {\
+                evens = evens + i;\
+            }\
+            let countdown = \"\";\
+            for i in 3..0 step -1 {\
+                countdown = countdown + \"x\";\
+            }\
+            let pairs = 0;\
+            for (a, b) in ((1, 2), (3, 4)) {\
+                pairs = pairs + a * b;\
+            }\
+            let s = \"hello world\";\
+            let a = s[1..4];\
+            let b = s[6..=10];\
+            let c = s[0..11 step 2];\
+            let d = s[4];\
+            let e = (1, 2, 3, 4)[1..3];\
+            let f = 2..=8 step 3;\
+            ");
+    assert_eq!(global(&vm, "total"), "55");
+    assert_eq!(global(&vm, "evens"), "20");
+    assert_eq!(global(&vm, "countdown"), "xxx");
+    assert_eq!(global(&vm, "pairs"), "14");
+    assert_eq!(global(&vm, "a"), "ell");
+    assert_eq!(global(&vm, "b"), "world");
+    assert_eq!(global(&vm, "c"), "hlowrd");
+    assert_eq!(global(&vm, "d"), "o");
+    assert_eq!(global(&vm, "e"), "(2, 3)");
+    assert_eq!(global(&vm, "f"), "2..=8 step 3");
+}
+
+#[test]
+fn run_all_24() {
+    let vm = run("\
+            let s = \"drop\";\
+            let a = \"\";\
+            let b = \"\";\
+            let c = \"\";\
+            try {\
+                s[3..1];\
+            } catch (e) {\
+                a = error_message(e);\
+            }\
+            try {\
+                s[2..9];\
+            } catch (e) {\
+                b = error_message(e);\
+            }\
+            try {\
+                s[4];\
+            } catch (e) {\
+                c = error_message(e);\
+            }\
+            ");
+    assert_eq!(global(&vm, "a"), "Range 3..1 is inverted");
+    assert_eq!(global(&vm, "b"), "Range 2..9 is out of bounds for length 4");
+    assert_eq!(global(&vm, "c"), "Index 4 is out of bounds for length 4");
+}
+
+#[test]
+fn run_all_25() {
+    let vm = run("\
+            fn numbers() {\
+                yield 1;\
+                yield 2;\
+            }\
+            let name = \" Drop \";\
+            let a = name.trim().upper();\
+            let b = name.trim().len() + (-5).abs() + 2.pow(3);\
+            let c = \"a,b,c\".split(\",\").reverse().join(\"-\");\
+            let d = (1..=10 step 3).to_list();\
+            let e = (1, 2).to_list().push(3).len();\
+            let f = error(\"boom\", \"IndexError\").kind();\
+            let g = nil?.len();\
+            let gen = numbers();\
+            let h = gen.next() + gen.next();\
+            let ch = channel(1);\
+            ch.send(\"hi\");\
+            let i = ch.recv();\
+            let j = ok(5).unwrap_or(0) + err(1).unwrap_or(10);\
+            ");
+    assert_eq!(global(&vm, "a"), "DROP");
+    assert_eq!(global(&vm, "b"), "17");
+    assert_eq!(global(&vm, "c"), "c-b-a");
+    assert_eq!(global(&vm, "d"), "[1, 4, 7, 10]");
+    assert_eq!(global(&vm, "e"), "3");
+    assert_eq!(global(&vm, "f"), "IndexError");
+    assert_eq!(global(&vm, "g"), "nil");
+    assert_eq!(global(&vm, "h"), "3");
+    assert_eq!(global(&vm, "i"), "hi");
+    assert_eq!(global(&vm, "j"), "15");
+}
+
+#[test]
+fn run_all_26() {
+    let err = run_err("let n = 3.upper();");
+    assert_eq!(
+        err,
+        "Uncaught TypeError: int has no method 'upper' (available methods: abs, max, min, pow, to_string)"
+    );
+    let err = run_err("let n = \"x\".repeat();");
+    assert_eq!(
+        err,
+        "Uncaught ArgumentError: string.repeat() takes 1 arguments but 0 were given"
+    );
+}
+
+#[test]
+fn run_all_27() {
+    let vm = run("\
+            let log = \"\";\
+            fn note(s) {\
+                log = log + s;\
+            }\
+            fn work() {\
+                defer note(\"a\");\
+                {\
+                    defer note(\"b\");\
+                    defer note(\"c\");\
+                    if true {\
+                        return 1;\
+                    }\
+                }\
+                note(\"x\");\
+            }\
+            fn fail() {\
+                defer note(\"f\");\
+                {\
+                    defer note(\"g\");\
+                    throw error(\"boom\");\
+                }\
+            }\
+            let a = work();\
+            {\
+                defer note(\"1\");\
+                note(\"0\");\
+            }\
+            try {\
+                fail();\
+            } catch (e) {\
+                note(\"h\");\
+            }\
+            for i in 0..3 {\
+                defer note(i.to_string());\
+            }\
+            ");
+    assert_eq!(global(&vm, "a"), "1");
+    assert_eq!(global(&vm, "log"), "cba01gfh012");
+}
+
+#[test]
+fn run_all_28() {
+    let s = String::from("defer print(1);");
+    let lex_result = Lexer::lex_tokens(s.as_str());
+    let mut parser = Parser::new(&lex_result);
+    let parse_result = parser.parse();
+    assert_eq!(parser.errors.len(), 0);
+    let mut compiler = Compiler::new();
+    compiler.compile(&parse_result);
+    assert_eq!(compiler.errors.len(), 1);
+    assert_eq!(
+        compiler.errors[0].message,
+        "Defer should live in a block or a function"
+    );
+}
+
+#[test]
+fn run_all_29() {
+    let vm = run("\
+            let a = twice(4);\
+            let b = 0;\
+            {\
+                b = is_even(10) + is_odd(7) * 2;\
+                fn is_even(n) {\
+                    if n == 0 {\
+                        return true;\
+                    }\
+                    return is_odd(n - 1);\
+                }\
+                fn is_odd(n) {\
+                    if n == 0 {\
+                        return false;\
+                    }\
+                    return is_even(n - 1);\
+                }\
+            }\
+            fn twice(n: int) -> int {\
+                return n * 2;\
+            }\
+            ");
+    assert_eq!(global(&vm, "a"), "8");
+    assert_eq!(global(&vm, "b"), "3");
+    let errors = check_err(
+        "\
+            let s: string = twice(1);\
+            fn twice(n: int) -> int {\
+                return n * 2;\
+            }\
+            ",
+    );
+    assert_eq!(
+        errors,
+        vec!["Cannot initialize variable 's' of type string with int"]
+    );
+}
+
+#[test]
+fn run_all_30() {
+    let errors = resolve_err(
+        "let a = 1;
+fn add(a, b, a) {
+    return a + c;
+}
+add(1);
+add(1, 2, 3, 4);
+add(1, d: 2);
+return a;
+",
+    );
+    assert_eq!(
+        errors,
+        vec![
+            "2:14: Duplicate parameter 'a' in function 'add'",
+            "3:16: No such variable: c",
+            "5:1: add(a, b, a) missing required argument 'b'",
+            "6:1: add(a, b, a) takes at most 3 positional arguments but 4 were given",
+            "7:8: add(a, b, a) got an unexpected named argument 'd'",
+            "8:1: Return should live in a function",
+        ]
+    );
+}
+
+#[test]
+fn run_all_31() {
+    let errors = resolve_err(
+        "let a;
+let b;
+let c;
+if true {
+    a = 1;
+    b = 2;
+} else {
+    a = 3;
+}
+print(a);
+print(b);
+while false {
+    c = 1;
+}
+print(c);
+fn later() {
+    return c;
+}
+let d;
+try {
+    d = 1;
+} catch (e) {
+    throw e;
+}
+print(d);
+let e;
+fn set() {
+    e = 1;
+}
+set();
+print(e);
+let f;
+fn get() {
+    return f;
+}
+print(f);
+",
+    );
+    assert_eq!(
+        errors,
+        vec![
+            "11:7: Can not use variable that is not given value: b",
+            "15:7: Can not use variable that is not given value: c",
+            "36:7: Can not use variable that is not given value: f",
+        ]
+    );
+    let vm = run("let x; fn set() { x = 1; } set(); let y = x;");
+    assert_eq!(global(&vm, "y"), "1");
+}
+
+#[test]
+fn run_all_32() {
+    let vm = run("\
+            fn twice(f, times = 2) {\
+                fn wrapper(x) {\
+                    let i = 0;\
+                    while i < times {\
+                        x = f(x);\
+                        i = i + 1;\
+                    }\
+                    return x;\
+                }\
+                return wrapper;\
+            }\
+            @twice \
+            fn inc(x) {\
+                return x + 1;\
+            }\
+            @twice(times: 3) \
+            @twice \
+            fn double(x) {\
+                return x * 2;\
+            }\
+            let a = inc(1);\
+            let b = double(1);\
+            ");
+    assert_eq!(global(&vm, "a"), "3");
+    assert_eq!(global(&vm, "b"), "64");
+}
+
+#[test]
+fn run_all_33() {
+    let vm = run("\
+            let calls = 0;\
+            @memo \
+            fn fib(n) {\
+                calls = calls + 1;\
+                if n <= 1 {\
+                    return n;\
+                }\
+                return fib(n - 1) + fib(n - 2);\
+            }\
+            @trace \
+            fn add(a, b = 10) {\
+                return a + b;\
+            }\
+            @deprecated(\"use add instead\") \
+            fn plus(a, b) {\
+                return a + b;\
+            }\
+            let a = fib(80);\
+            let b = add(1);\
+            let c = add(1, b: 2);\
+            let d = plus(2, 3) + plus(4, 5);\
+            ");
+    assert_eq!(global(&vm, "a"), "23416728348467685");
+    assert_eq!(global(&vm, "calls"), "81");
+    assert_eq!(global(&vm, "b"), "11");
+    assert_eq!(global(&vm, "c"), "3");
+    assert_eq!(global(&vm, "d"), "14");
+    let err = run_err(
+        "\
+            @deprecated(42) \
+            fn old() {\
+                return 1;\
+            }\
+            ",
+    );
+    assert_eq!(
+        err,
+        "Uncaught TypeError: @deprecated expects a string message, found int"
+    );
+}
+
+#[test]
+fn run_all_34() {
+    let source = "\
+            fn withdraw(balance, amount) requires amount > 0 ensures result >= 0 {\
+                return balance - amount;\
+            }\
+            fn clamp(x, limit = 10) ensures result <= limit {\
+                if x > limit {\
+                    return limit;\
+                }\
+                return x;\
+            }\
+            fn refund(balance, amount) ensures result >= 0 {\
+                amount = 0 - amount;\
+                return balance + amount;\
+            }\
+            let a = withdraw(10, 5);\
+            let b = clamp(42) + clamp(3);\
+            ";
+    let vm = run(source);
+    assert_eq!(global(&vm, "a"), "5");
+    assert_eq!(global(&vm, "b"), "13");
+    let err = run_err(&format!("{}{}", source, "withdraw(10, 0);"));
+    assert_eq!(
+        err,
+        "Uncaught ContractError: withdraw(balance, amount) requires amount > 0 failed (balance = 10, amount = 0)"
+    );
+    let err = run_err(&format!("{}{}", source, "withdraw(10, 20);"));
+    assert_eq!(
+        err,
+        "Uncaught ContractError: withdraw(balance, amount) ensures result >= 0 failed (balance = 10, amount = 20, result = -10)"
+    );
+    let err = run_err(&format!("{}{}", source, "refund(5, 10);"));
+    assert_eq!(
+        err,
+        "Uncaught ContractError: refund(balance, amount) ensures result >= 0 failed (balance = 5, amount = 10, result = -5)"
+    );
+    let lex_result = Lexer::lex_tokens(&format!("{}{}", source, "let c = withdraw(10, 20);"));
+    let mut parser = Parser::new(&lex_result);
+    let parse_result = parser.parse();
+    let mut compiler = Compiler::new();
+    compiler.compile(&parse_result);
+    let mut vm = VM::new(compiler.codes);
+    vm.set_contracts(false);
+    assert_eq!(vm.run(), Ok(()));
+    assert_eq!(global(&vm, "c"), "-10");
+}
+
+#[test]
+fn run_all_35() {
+    let vm = run("\
+            macro swap(a, b) {
+                let tmp = a;
+                a = b;
+                b = tmp;
+            }
+            macro square(x) {
+                x * x;
+            }
+            macro unless(condition, body) {
+                if !condition {
+                    body;
+                }
+            }
+            macro repeat(n, body) {
+                for i in 0..n {
+                    body;
+                }
+            }
+            let tmp = 1;
+            let other = 2;
+            swap(tmp, other);
+            let s = square(3 + 1);
+            let log = \"\";
+            unless(s > 20, log = log + \"small\");
+            let i = 0;
+            let count = 0;
+            repeat(square(2), count = count + i + 1);
+            fn f(n) {
+                let tmp = n + 1;
+                swap(tmp, n);
+                return square(tmp);
+            }
+            let r = f(5);
+            ");
+    assert_eq!(global(&vm, "tmp"), "2");
+    assert_eq!(global(&vm, "other"), "1");
+    assert_eq!(global(&vm, "s"), "16");
+    assert_eq!(global(&vm, "log"), "small");
+    assert_eq!(global(&vm, "count"), "4");
+    assert_eq!(global(&vm, "r"), "25");
+    assert_eq!(
+        expand_err(
+            "\
+            macro square(x) {
+                x * x;
+            }
+            macro twice(s) {
+                s;
+                s;
+            }
+            fn g() {
+                macro inner() {}
+            }
+            let a = square(1, 2);
+            let b = twice(print(1));
+            square(x: 1);
+            macro forever(x) {
+                forever(x);
+            }
+            forever(1);
+            macro bind(n) {
+                let n = 1;
+            }
+            bind(1 + 2);
+            macro square(y) {
+                y;
+            }
+            "
+        ),
+        [
+            "22:19: Macro 'square' is already defined (macro 'square' defined at 1:7)",
+            "9:23: Macros can only be defined at the top level (macro 'inner' defined at 9:23)",
+            "11:21: Macro 'square' expects 1 arguments, found 2 (macro 'square' defined at 1:7)",
+            "12:21: Macro 'twice' expands to statements and cannot be used as an expression (macro 'twice' defined at 4:19)",
+            "13:13: Macro 'square' does not take named argument 'x' (macro 'square' defined at 1:7)",
+            "15:17: Macro expansion of 'forever' exceeded the maximum depth of 64 (macro 'forever' defined at 14:19)",
+            "21:13: Macro argument for 'n' must be a name because it is bound in the macro body (macro 'bind' defined at 18:19)",
+        ]
+    );
+}
+
+#[test]
+fn run_all_36() {
+    let vm = run("\
+            let x = 1;
+            fn outer(n) {
+                let total = 0;
+                fn add(v) {
+                    total = total + v;
+                    return total;
+                }
+                let i = 0;
+                while i < n {
+                    let x = i * 2;
+                    add(x);
+                    i = i + 1;
+                }
+                {
+                    let total = 100;
+                    add(total);
+                }
+                return (total, x);
+            }
+            fn shadow() {
+                let y = x + 1;
+                let x = y * 10;
+                let x = x + 1;
+                return x;
+            }
+            fn deferred() {
+                let log = 0;
+                for i in 0..3 {
+                    defer log = log * 10 + i + 1;
+                }
+                return log;
+            }
+            let result = outer(4);
+            let shadowed = shadow();
+            let log = deferred();
+            ");
+    assert_eq!(global(&vm, "result"), "(112, 1)");
+    assert_eq!(global(&vm, "shadowed"), "21");
+    assert_eq!(global(&vm, "log"), "123");
+    assert_eq!(
+        run_err("fn late() { let a; return a; } late();"),
+        "Uncaught NameError: Can not use variable that is not given value: a"
+    );
+    let lex_result =
+        Lexer::lex_tokens("let g = 1; fn f(a, (b, c)) { let d = a + b; return d + c + g; }");
+    let mut parser = Parser::new(&lex_result);
+    let mut compiler = Compiler::new();
+    compiler.compile(&parser.parse());
+    let body = compiler
+        .codes
+        .iter()
+        .find_map(|code| match code {
+            Opcode::MakeFunction(_, _, codes, _, _) => Some(codes),
+            _ => None,
+        })
+        .unwrap();
+    let names: Vec<&str> = body
+        .iter()
+        .filter_map(|code| match code {
+            Opcode::Load(name) | Opcode::Store(name) | Opcode::Register(name) => {
+                Some(name.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, ["g"]);
+}
+
+#[test]
+fn run_all_37() {
+    let vm = run("\
+            fn counter(start) {
+                let n = start;
+                fn next() {
+                    n = n + 1;
+                    return n;
+                }
+                return next;
+            }
+            let a = counter(0);
+            a();
+            let first = a();
+            let b = counter(10);
+            let second = b();
+            let first_fn = nil;
+            let second_fn = nil;
+            for i in 0..2 {
+                fn get() {
+                    return i;
+                }
+                if i == 0 {
+                    first_fn = get;
+                } else {
+                    second_fn = get;
+                }
+            }
+            let captured = (first_fn(), second_fn());
+            fn late() {
+                let v = 1;
+                fn get() {
+                    return v;
+                }
+                v = 5;
+                return get();
+            }
+            fn pair() {
+                let shared = 0;
+                fn inc() {
+                    shared = shared + 1;
+                }
+                fn get() {
+                    return shared;
+                }
+                inc();
+                inc();
+                return get();
+            }
+            fn chain() {
+                let a = 1;
+                fn b() {
+                    fn c() {
+                        a = a + 1;
+                        return a;
+                    }
+                    c();
+                    return c();
+                }
+                return b();
+            }
+            fn walk(n) {
+                fn down(i) {
+                    if i == 0 {
+                        return 0;
+                    }
+                    return i + down(i - 1);
+                }
+                return down(n);
+            }
+            fn memoized(n) {
+                @memo
+                fn square(x) {
+                    return x * x;
+                }
+                return square(n) + square(n);
+            }
+            let mutated = late();
+            let paired = pair();
+            let chained = chain();
+            let walked = walk(4);
+            let memo = memoized(4);
+            ");
+    assert_eq!(global(&vm, "first"), "2");
+    assert_eq!(global(&vm, "second"), "11");
+    assert_eq!(global(&vm, "captured"), "(0, 1)");
+    assert_eq!(global(&vm, "mutated"), "5");
+    assert_eq!(global(&vm, "paired"), "2");
+    assert_eq!(global(&vm, "chained"), "3");
+    assert_eq!(global(&vm, "walked"), "10");
+    assert_eq!(global(&vm, "memo"), "32");
+}
+
+#[cfg(test)]
+struct Counting;
+
+#[cfg(test)]
+thread_local! {
+    static LIVE: std::cell::Cell<isize> = const { std::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+unsafe impl std::alloc::GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let _ = LIVE.try_with(|live| live.set(live.get() + layout.size() as isize));
+        std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        let _ = LIVE.try_with(|live| live.set(live.get() - layout.size() as isize));
+        std::alloc::System.dealloc(ptr, layout)
+    }
+}
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[test]
+fn run_all_38() {
+    let lex_result = Lexer::lex_tokens(
+        "\
+            fn counter(start) {
+                let n = start;
+                fn next() {
+                    n = n + 1;
+                    return n;
+                }
+                return next;
+            }
+            fn walk(n) {
+                fn down(i) {
+                    if i == 0 {
+                        return 0;
+                    }
+                    return i + down(i - 1);
+                }
+                return down(n);
+            }
+            fn numbers() {
+                yield 1;
+                yield 2;
+            }
+            @memo
+            fn fib(n) {
+                if n < 2 {
+                    return n;
+                }
+                return fib(n - 1) + fib(n - 2);
+            }
+            fn parity(n) {
+                fn even(i) {
+                    if i == 0 {
+                        return true;
+                    }
+                    return odd(i - 1);
+                }
+                fn odd(i) {
+                    if i == 0 {
+                        return false;
+                    }
+                    return even(i - 1);
+                }
+                return even(n);
+            }
+            fn bounce() {
+                fn ping(i) {
+                    if i == 0 {
+                        return 0;
+                    }
+                    return pong(i - 1) + 1;
+                }
+                fn pong(i) {
+                    if i == 0 {
+                        return 0;
+                    }
+                    fn back() {
+                        return ping(i - 1);
+                    }
+                    return back() + 1;
+                }
+                return ping;
+            }
+            fn fast(n) {
+                @memo
+                fn fib(i) {
+                    if i < 2 {
+                        return i;
+                    }
+                    return fib(i - 1) + fib(i - 2);
+                }
+                return fib(n);
+            }
+            let total = 0;
+            for i in 0..50 {
+                total = total + parity(i) + bounce()(5) + fast(i);
+                let c = counter(i);
+                c();
+                total = total + c() + walk(3);
+                {
+                    let base = i;
+                    fn add(v) {
+                        return base + v;
+                    }
+                    total = total + add(1);
+                }
+                defer c();
+                let gen = numbers();
+                next(gen);
+            }
+            let f = fib(20);
+            let p = parity(7);
+            let b = bounce()(9);
+            let q = fast(60);
+            ",
+    );
+    let mut parser = Parser::new(&lex_result);
+    let mut compiler = Compiler::new();
+    compiler.compile(&parser.parse());
+    assert_eq!(compiler.errors.len(), 0);
+    let live = || LIVE.with(|live| live.get());
+    for round in 0..2 {
+        let before = live();
+        {
+            let mut vm = VM::new(compiler.codes.clone());
+            assert_eq!(vm.run(), Ok(()));
+            assert_eq!(global(&vm, "f"), "6765");
+            assert_eq!(global(&vm, "p"), "false");
+            assert_eq!(global(&vm, "b"), "9");
+            assert_eq!(global(&vm, "q"), "1548008755920");
+        }
+        if round > 0 {
+            assert_eq!(live(), before);
+        }
+    }
+}
+
+#[test]
+fn run_all_39() {
+    let vm = run("\
+            let day = 60 * 60 * 24;
+            let coerced = true + 1 * 2;
+            let negated = -true;
+            let truthy = !0;
+            let greeting = \"drop\" + \"let\";
+            let ordered = \"a\" < \"b\";
+            let nils = (nil == nil, nil != 1);
+            let x = 7;
+            let same = (x * 2 + 0, 1 * (x - 0), !(x < 3));
+            let taken = 0;
+            if true {
+                taken = 1;
+            } else {
+                taken = 2;
+            }
+            if 0 {
+                taken = taken + 10;
+            }
+            while false {
+                taken = 100;
+            }
+            fn never() {
+                if false {
+                    yield 1;
+                }
+            }
+            let gen = never();
+            let finished = next(gen);
+            ");
+    assert_eq!(global(&vm, "day"), "86400");
+    assert_eq!(global(&vm, "coerced"), "3");
+    assert_eq!(global(&vm, "negated"), "-1");
+    assert_eq!(global(&vm, "truthy"), "true");
+    assert_eq!(global(&vm, "greeting"), "droplet");
+    assert_eq!(global(&vm, "ordered"), "true");
+    assert_eq!(global(&vm, "nils"), "(true, true)");
+    assert_eq!(global(&vm, "same"), "(14, 7, true)");
+    assert_eq!(global(&vm, "taken"), "1");
+    assert_eq!(global(&vm, "finished"), "nil");
+    assert_eq!(
+        run_err("let n = 10 / (2 - 2);"),
+        "Uncaught ZeroDivisionError: You cannot divide by zero"
+    );
+    let optimized = |s: &str| {
+        let lex_result = Lexer::lex_tokens(s);
+        let mut parser = Parser::new(&lex_result);
+        let program = Optimizer::new().optimize(parser.parse());
+        let mut compiler = Compiler::new();
+        compiler.compile(&program);
+        compiler.codes
+    };
+    let codes = optimized("let day = 60 * 60 * 24; let s = \"a\" + \"b\";");
+    assert!(matches!(
+        codes.as_slice(),
+        [
+            Opcode::Push(Value::Int(86400)),
+            Opcode::Register(_),
+            Opcode::Store(_),
+            Opcode::Pop,
+            Opcode::Push(Value::String(s)),
+            Opcode::Register(_),
+            Opcode::Store(_),
+            Opcode::Pop,
+        ] if s == "ab"
+    ));
+    let codes = optimized("let a = 1; if true { a = 2; } while 1 { a = a + 1; }");
+    assert!(!codes
+        .iter()
+        .any(|code| matches!(code, Opcode::JumpIfFalse(_))));
+    let codes = optimized("let a = 1 / 0; let b = 9223372036854775807 + 1; let c = \"a\" - \"b\";");
+    assert!(codes.iter().any(|code| matches!(code, Opcode::Divide)));
+    assert!(codes.iter().any(|code| matches!(code, Opcode::Add)));
+    assert!(codes.iter().any(|code| matches!(code, Opcode::Subtract)));
+}
+
+#[test]
+fn run_all_40() {
+    let vm = run("\
+            let max = 9223372036854775807;
+            let min = 0 - max - 1;
+            let kinds = \"\";
+            try {
+                max + 1;
+            } catch (e) {
+                kinds = kinds + e.kind() + \" \";
+            }
+            try {
+                min - 1;
+            } catch (e) {
+                kinds = kinds + e.kind() + \" \";
+            }
+            try {
+                max * 2;
+            } catch (e) {
+                kinds = kinds + e.kind() + \" \";
+            }
+            try {
+                min / -1;
+            } catch (e) {
+                kinds = kinds + e.kind() + \" \";
+            }
+            try {
+                -min;
+            } catch (e) {
+                kinds = kinds + e.kind();
+            }
+            ");
+    assert_eq!(
+        global(&vm, "kinds"),
+        "ArithmeticError ArithmeticError ArithmeticError ArithmeticError ArithmeticError"
+    );
+    assert_eq!(
+        run_err("let a = 9223372036854775807 + 1;"),
+        "Uncaught ArithmeticError: Integer overflow when you add these values"
+    );
+}
+
+#[test]
+fn run_all_41() {
+    let vm = run("\
+            let calls = 0;
+            @memo
+            fn join(a, b) {
+                calls = calls + 1;
+                return a + \"|\" + b;
+            }
+            let first = join(\"x,string:y\", \"z\");
+            let second = join(\"x\", \"y,string:z\");
+            let again = join(\"x\", \"y,string:z\");
+            fn one() {
+                return 1;
+            }
+            fn two() {
+                return 2;
+            }
+            @memo
+            fn apply(f) {
+                return f();
+            }
+            let applied = (apply(one), apply(two), apply(one));
+            @memo
+            fn kind(v) {
+                return v;
+            }
+            let kinds = (kind(1), kind(true), kind(\"1\"));
+            ");
+    assert_eq!(global(&vm, "first"), "x,string:y|z");
+    assert_eq!(global(&vm, "second"), "x|y,string:z");
+    assert_eq!(global(&vm, "again"), "x|y,string:z");
+    assert_eq!(global(&vm, "calls"), "2");
+    assert_eq!(global(&vm, "applied"), "(1, 2, 1)");
+    assert_eq!(global(&vm, "kinds"), "(1, true, 1)");
+}
+
+#[test]
+fn run_all_42() {
+    let source = "\
+            fn twice(f, times = 2) {
+                fn wrapper(x) {
+                    let i = 0;
+                    while i < times {
+                        x = f(x);
+                        i = i + 1;
+                    }
+                    return x;
+                }
+                return wrapper;
+            }
+            let n = 3;
+            let repeat = twice;
+            let early = inc(1);
+            @twice(times: n)
+            fn inc(x) {
+                return x + 1;
+            }
+            @repeat
+            fn double(x) {
+                return x * 2;
+            }
+            let a = inc(1);
+            let b = double(1);
+            ";
+    assert_eq!(resolve_err(source), Vec::<String>::new());
+    let vm = run(source);
+    assert_eq!(global(&vm, "early"), "2");
+    assert_eq!(global(&vm, "a"), "4");
+    assert_eq!(global(&vm, "b"), "4");
+}
+
+#[test]
+fn run_all_43() {
+    let source = "\
+            let limit = 5;
+            let one = 1;
+            let seen = 0;
+            macro shadow(v) {
+                seen = limit;
+                let limit = v;
+                seen = seen + limit;
+            }
+            macro inc(v) {
+                v = v + one;
+            }
+            shadow(2);
+            fn g() {
+                let y = 1;
+                inc(y);
+                {
+                    shadow(10);
+                }
+                return y;
+            }
+            let r = g();
+            ";
+    let vm = run(source);
+    assert_eq!(global(&vm, "limit"), "5");
+    assert_eq!(global(&vm, "seen"), "15");
+    assert_eq!(global(&vm, "r"), "2");
+    let lex_result = Lexer::lex_tokens(source);
+    let program = expand(Parser::new(&lex_result).parse());
+    let expanded = Formatter::new().format_program(&program);
+    assert_eq!(
+        expanded,
+        "\
+let limit = 5;
+let one = 1;
+let seen = 0;
+seen = limit;
+let limit_1 = 2;
+seen = seen + limit_1;
+fn g() {
+    let y = 1;
+    y = y + one;
+    {
+        seen = limit;
+        let limit_2 = 10;
+        seen = seen + limit_2;
+    }
+    return y;
+}
+let r = g();
+"
+    );
+    let vm = run(&expanded);
+    assert_eq!(global(&vm, "seen"), "15");
+    assert_eq!(global(&vm, "r"), "2");
+    assert_eq!(
+        expand_err(
+            "\
+            let one = 1;
+            macro inc(v) {
+                v = v + one;
+            }
+            fn f() {
+                let one = 100;
+                let y = 1;
+                inc(y);
+                return y;
+            }
+            "
+        ),
+        ["8:17: Macro 'inc' refers to 'one', which is shadowed at the call site (macro 'inc' defined at 2:19)"]
+    );
+}
+
+#[test]
+fn run_all_44() {
+    let vm = run("\
+            let max = 9223372036854775807;
+            let kinds = \"\";
+            try {
+                \"abc\"[0..=max];
+            } catch (e) {
+                kinds = kinds + e.kind() + \" \";
+            }
+            try {
+                (-5..max).len();
+            } catch (e) {
+                kinds = kinds + e.kind();
+            }
+            let counted = (0..max).len();
+            let contained = ((0..10).contains(max), (0..10).contains(-2), (-5..max).contains(max - 1));
+            ");
+    assert_eq!(global(&vm, "kinds"), "IndexError IndexError");
+    assert_eq!(global(&vm, "counted"), "9223372036854775807");
+    assert_eq!(global(&vm, "contained"), "(false, false, true)");
+}
+
+#[test]
+fn run_all_45() {
+    let vm = run("\
+            let min = -9223372036854775807 - 1;
+            let kinds = \"\";
+            try {
+                2.pow(100);
+            } catch (e) {
+                kinds = kinds + e.kind() + \" \";
+            }
+            try {
+                2.pow(4294967297);
+            } catch (e) {
+                kinds = kinds + e.kind() + \" \";
+            }
+            try {
+                min.abs();
+            } catch (e) {
+                kinds = kinds + e.message();
+            }
+            let repeated = \"\";
+            try {
+                \"abc\".repeat(9223372036854775807);
+            } catch (e) {
+                repeated = e.kind() + \" \" + e.message();
+            }
+            let fits = (2.pow(62), (-5).abs());
+            ");
+    assert_eq!(
+        global(&vm, "kinds"),
+        "ArithmeticError ArithmeticError Integer overflow in abs()"
+    );
+    assert_eq!(
+        global(&vm, "repeated"),
+        "ArithmeticError repeat() result exceeds 1073741824 bytes"
+    );
+    assert_eq!(global(&vm, "fits"), "(4611686018427387904, 5)");
+}
+
+#[test]
+fn run_all_46() {
+    let vm = run("\
+            let calls = 0;
+            fn count() {
+                calls = calls + 1;
+                return 1;
+            }
+            fn maker() {
+                fn make(n) {
+                    return \"abc\";
+                }
+                return make;
+            }
+            let s = nil;
+            let a = s?.len().len();
+            let b = s?.len().pow(count());
+            let c = s?.split(\",\")[0].len();
+            let f = nil;
+            let d = f?.(count())(count()).len();
+            let e = maker()?.(1).len();
+            let g = (s?.len() ?? 7) + 1;
+            let h = (\"a,b\"?.split(\",\"), s?.len());
+            ");
+    assert_eq!(global(&vm, "a"), "nil");
+    assert_eq!(global(&vm, "b"), "nil");
+    assert_eq!(global(&vm, "c"), "nil");
+    assert_eq!(global(&vm, "d"), "nil");
+    assert_eq!(global(&vm, "calls"), "0");
+    assert_eq!(global(&vm, "e"), "3");
+    assert_eq!(global(&vm, "g"), "8");
+    assert_eq!(global(&vm, "h"), "([a, b], nil)");
+}
+
+#[test]
+fn run_all_47() {
+    let vm = run("\
+            let log = \"\";
+            fn f() {
+                try {
+                    return 1;
+                } finally {
+                    return 2;
+                }
+            }
+            fn g() {
+                try {
+                    try {
+                        return 1;
+                    } finally {
+                        log = log + \"i\";
+                        return 2;
+                    }
+                } finally {
+                    log = log + \"o\";
+                }
+            }
+            fn h() {
+                try {
+                    try {
+                        return 1;
+                    } finally {
+                        log = log + \"i\";
+                    }
+                } finally {
+                    log = log + \"o\";
+                    return 3;
+                }
+            }
+            let a = f();
+            let b = g();
+            let c = h();
+            ");
+    assert_eq!(global(&vm, "a"), "2");
+    assert_eq!(global(&vm, "b"), "2");
+    assert_eq!(global(&vm, "c"), "3");
+    assert_eq!(global(&vm, "log"), "ioio");
+}
@@ -0,0 +1,691 @@
+use crate::vm::opcode::{
+    Channel, ErrorKind, ErrorObject, FunctionObject, GeneratorState, Local, NativeFunction, Opcode,
+    Parameter, Scope, Upvalue, Value,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+fn define_native(
+    scope: &Rc<RefCell<Scope>>,
+    native: NativeFunction,
+    params: Vec<(&str, Option<Value>)>,
+) {
+    let mut codes = vec![];
+    let mut parameters = vec![];
+    for (slot, (name, default)) in params.iter().enumerate() {
+        let mut parameter = Parameter::new(name.to_string());
+        if let Some(value) = default {
+            parameter.has_default = true;
+            codes.push(Opcode::JumpIfAssigned(slot, codes.len() + 4));
+            codes.push(Opcode::Push(value.clone()));
+            codes.push(Opcode::StoreLocal(Local::new(0, slot, name)));
+            codes.push(Opcode::Pop);
+        }
+        parameters.push(parameter);
+    }
+    for (slot, (name, _)) in params.iter().enumerate() {
+        codes.push(Opcode::LoadLocal(Local::new(0, slot, name)));
+    }
+    codes.push(Opcode::Native(native));
+    codes.push(Opcode::Return);
+    let func = FunctionObject::new(native.name.to_string(), parameters, codes.into(), vec![]);
+    scope.borrow_mut().variables.insert(
+        native.name.to_string(),
+        Some(Value::Function(Rc::new(func))),
+    );
+}
+
+pub fn define_natives(scope: &Rc<RefCell<Scope>>) {
+    define_native(
+        scope,
+        NativeFunction {
+            name: "error",
+            arity: 2,
+            func: error,
+        },
+        vec![
+            ("message", None),
+            ("kind", Some(Value::String("Error".to_string()))),
+        ],
+    );
+    define_native(
+        scope,
+        NativeFunction {
+            name: "error_kind",
+            arity: 1,
+            func: error_kind,
+        },
+        vec![("error", None)],
+    );
+    define_native(
+        scope,
+        NativeFunction {
+            name: "error_message",
+            arity: 1,
+            func: error_message,
+        },
+        vec![("error", None)],
+    );
+}
+
+pub fn define_generator_natives(scope: &Rc<RefCell<Scope>>) {
+    let next_func = FunctionObject::new(
+        "next".to_string(),
+        vec![Parameter::new("generator".to_string())],
+        Rc::new([
+            Opcode::LoadLocal(Local::new(0, 0, "generator")),
+            Opcode::Resume,
+            Opcode::Return,
+        ]),
+        vec![],
+    );
+    scope.borrow_mut().variables.insert(
+        "next".to_string(),
+        Some(Value::Function(Rc::new(next_func))),
+    );
+    define_native(
+        scope,
+        NativeFunction {
+            name: "done",
+            arity: 1,
+            func: done,
+        },
+        vec![("generator", None)],
+    );
+}
+
+pub fn define_channel_natives(scope: &Rc<RefCell<Scope>>) {
+    define_native(
+        scope,
+        NativeFunction {
+            name: "channel",
+            arity: 1,
+            func: channel,
+        },
+        vec![("capacity", Some(Value::Int(0)))],
+    );
+    let send_func = FunctionObject::new(
+        "send".to_string(),
+        vec![
+            Parameter::new("channel".to_string()),
+            Parameter::new("value".to_string()),
+        ],
+        Rc::new([
+            Opcode::LoadLocal(Local::new(0, 0, "channel")),
+            Opcode::LoadLocal(Local::new(0, 1, "value")),
+            Opcode::Send,
+            Opcode::Return,
+        ]),
+        vec![],
+    );
+    let recv_func = FunctionObject::new(
+        "recv".to_string(),
+        vec![Parameter::new("channel".to_string())],
+        Rc::new([
+            Opcode::LoadLocal(Local::new(0, 0, "channel")),
+            Opcode::Recv,
+            Opcode::Return,
+        ]),
+        vec![],
+    );
+    let mut scope = scope.borrow_mut();
+    scope.variables.insert(
+        "send".to_string(),
+        Some(Value::Function(Rc::new(send_func))),
+    );
+    scope.variables.insert(
+        "recv".to_string(),
+        Some(Value::Function(Rc::new(recv_func))),
+    );
+}
+
+pub fn define_result_natives(scope: &Rc<RefCell<Scope>>) {
+    define_native(
+        scope,
+        NativeFunction {
+            name: "ok",
+            arity: 1,
+            func: ok,
+        },
+        vec![("value", None)],
+    );
+    define_native(
+        scope,
+        NativeFunction {
+            name: "err",
+            arity: 1,
+            func: err,
+        },
+        vec![("error", None)],
+    );
+    define_native(
+        scope,
+        NativeFunction {
+            name: "is_ok",
+            arity: 1,
+            func: is_ok,
+        },
+        vec![("result", None)],
+    );
+    define_native(
+        scope,
+        NativeFunction {
+            name: "is_err",
+            arity: 1,
+            func: is_err,
+        },
+        vec![("result", None)],
+    );
+    define_native(
+        scope,
+        NativeFunction {
+            name: "unwrap",
+            arity: 1,
+            func: unwrap,
+        },
+        vec![("result", None)],
+    );
+    define_native(
+        scope,
+        NativeFunction {
+            name: "unwrap_or",
+            arity: 2,
+            func: unwrap_or,
+        },
+        vec![("result", None), ("default", None)],
+    );
+}
+
+const MAX_STRING_LEN: usize = 1 << 30;
+
+fn type_error(message: &str) -> Value {
+    Value::Error(ErrorObject::new(ErrorKind::TypeError, message))
+}
+
+fn overflow_error(method: &str) -> Value {
+    Value::Error(ErrorObject::new(
+        ErrorKind::ArithmeticError,
+        &format!("Integer overflow in {}()", method),
+    ))
+}
+
+fn error(args: Vec<Value>) -> Result<Value, Value> {
+    match (&args[0], &args[1]) {
+        (Value::String(message), Value::String(kind)) => match ErrorKind::from_name(kind) {
+            Some(kind) => Ok(Value::Error(ErrorObject::new(kind, message))),
+            None => Err(type_error(&format!("Unknown error kind: {}", kind))),
+        },
+        _ => Err(type_error("error() expects a string message and kind")),
+    }
+}
+
+fn error_kind(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::Error(error) => Ok(Value::String(error.kind.to_string())),
+        _ => Err(type_error("error_kind() expects an error value")),
+    }
+}
+
+fn error_message(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::Error(error) => Ok(Value::String(error.message.clone())),
+        _ => Err(type_error("error_message() expects an error value")),
+    }
+}
+
+fn ok(mut args: Vec<Value>) -> Result<Value, Value> {
+    Ok(Value::Ok(Box::new(args.remove(0))))
+}
+
+fn err(mut args: Vec<Value>) -> Result<Value, Value> {
+    Ok(Value::Err(Box::new(args.remove(0))))
+}
+
+fn is_ok(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::Ok(_) => Ok(Value::Boolean(true)),
+        Value::Err(_) => Ok(Value::Boolean(false)),
+        _ => Err(type_error("is_ok() expects an ok or err value")),
+    }
+}
+
+fn is_err(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::Ok(_) => Ok(Value::Boolean(false)),
+        Value::Err(_) => Ok(Value::Boolean(true)),
+        _ => Err(type_error("is_err() expects an ok or err value")),
+    }
+}
+
+fn unwrap(mut args: Vec<Value>) -> Result<Value, Value> {
+    match args.remove(0) {
+        Value::Ok(value) => Ok(*value),
+        Value::Err(value) => Err(Value::Error(ErrorObject::new(
+            ErrorKind::Error,
+            &format!("called unwrap() on err({})", value),
+        ))),
+        _ => Err(type_error("unwrap() expects an ok or err value")),
+    }
+}
+
+fn unwrap_or(mut args: Vec<Value>) -> Result<Value, Value> {
+    let default = args.remove(1);
+    match args.remove(0) {
+        Value::Ok(value) => Ok(*value),
+        Value::Err(_) => Ok(default),
+        _ => Err(type_error("unwrap_or() expects an ok or err value")),
+    }
+}
+
+fn done(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::Generator(generator) => Ok(Value::Boolean(
+            generator.borrow().state == GeneratorState::Done,
+        )),
+        _ => Err(type_error("done() expects a generator")),
+    }
+}
+
+fn channel(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::Int(capacity) if *capacity >= 0 => Ok(Value::Channel(Rc::new(RefCell::new(
+            Channel::new(*capacity as usize),
+        )))),
+        _ => Err(type_error("channel() expects a non-negative capacity")),
+    }
+}
+
+pub fn methods(value: &Value) -> &'static [NativeFunction] {
+    match value {
+        Value::String(_) => STRING_METHODS,
+        Value::Int(_) => INT_METHODS,
+        Value::Boolean(_) => BOOL_METHODS,
+        Value::List(_) => LIST_METHODS,
+        Value::Tuple(_) => TUPLE_METHODS,
+        Value::Range(_) => RANGE_METHODS,
+        Value::Error(_) => ERROR_METHODS,
+        Value::Ok(_) | Value::Err(_) => RESULT_METHODS,
+        Value::Generator(_) => GENERATOR_METHODS,
+        _ => &[],
+    }
+}
+
+pub fn opcode_method(value: &Value, name: &str) -> Option<(usize, Opcode)> {
+    match (value, name) {
+        (Value::Generator(_), "next") => Some((0, Opcode::Resume)),
+        (Value::Channel(_), "send") => Some((1, Opcode::Send)),
+        (Value::Channel(_), "recv") => Some((0, Opcode::Recv)),
+        _ => None,
+    }
+}
+
+pub fn method_names(value: &Value) -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = methods(value).iter().map(|method| method.name).collect();
+    match value {
+        Value::Generator(_) => names.push("next"),
+        Value::Channel(_) => names.extend(["send", "recv"]),
+        _ => (),
+    }
+    names.sort();
+    names
+}
+
+const fn method(
+    name: &'static str,
+    arity: usize,
+    func: fn(Vec<Value>) -> Result<Value, Value>,
+) -> NativeFunction {
+    NativeFunction {
+        name,
+        arity: arity + 1,
+        func,
+    }
+}
+
+const STRING_METHODS: &[NativeFunction] = &[
+    method("len", 0, string_len),
+    method("upper", 0, string_upper),
+    method("lower", 0, string_lower),
+    method("trim", 0, string_trim),
+    method("contains", 1, string_contains),
+    method("starts_with", 1, string_starts_with),
+    method("ends_with", 1, string_ends_with),
+    method("split", 1, string_split),
+    method("chars", 0, string_chars),
+    method("repeat", 1, string_repeat),
+    method("reverse", 0, string_reverse),
+];
+
+const INT_METHODS: &[NativeFunction] = &[
+    method("abs", 0, int_abs),
+    method("pow", 1, int_pow),
+    method("min", 1, int_min),
+    method("max", 1, int_max),
+    method("to_string", 0, to_string),
+];
+
+const BOOL_METHODS: &[NativeFunction] = &[method("to_string", 0, to_string)];
+
+const LIST_METHODS: &[NativeFunction] = &[
+    method("len", 0, sequence_len),
+    method("first", 0, list_first),
+    method("last", 0, list_last),
+    method("push", 1, list_push),
+    method("reverse", 0, list_reverse),
+    method("join", 1, list_join),
+];
+
+const TUPLE_METHODS: &[NativeFunction] = &[
+    method("len", 0, sequence_len),
+    method("to_list", 0, tuple_to_list),
+];
+
+const RANGE_METHODS: &[NativeFunction] = &[
+    method("len", 0, range_len),
+    method("contains", 1, range_contains),
+    method("to_list", 0, range_to_list),
+];
+
+const ERROR_METHODS: &[NativeFunction] = &[
+    method("kind", 0, error_kind),
+    method("message", 0, error_message),
+];
+
+const RESULT_METHODS: &[NativeFunction] = &[
+    method("is_ok", 0, is_ok),
+    method("is_err", 0, is_err),
+    method("unwrap", 0, unwrap),
+    method("unwrap_or", 1, unwrap_or),
+];
+
+const GENERATOR_METHODS: &[NativeFunction] = &[method("done", 0, done)];
+
+fn string_args(args: &[Value]) -> (&str, Option<&Value>) {
+    match &args[0] {
+        Value::String(value) => (value, args.get(1)),
+        _ => unreachable!(),
+    }
+}
+
+fn string_arg<'a>(arg: Option<&'a Value>, method: &str) -> Result<&'a str, Value> {
+    match arg {
+        Some(Value::String(value)) => Ok(value),
+        _ => Err(type_error(&format!(
+            "{}() expects a string argument",
+            method
+        ))),
+    }
+}
+
+fn int_arg(arg: Option<&Value>, method: &str) -> Result<i64, Value> {
+    match arg {
+        Some(Value::Int(value)) => Ok(*value),
+        _ => Err(type_error(&format!("{}() expects an int argument", method))),
+    }
+}
+
+fn string_len(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, _) = string_args(&args);
+    Ok(Value::Int(value.chars().count() as i64))
+}
+
+fn string_upper(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, _) = string_args(&args);
+    Ok(Value::String(value.to_uppercase()))
+}
+
+fn string_lower(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, _) = string_args(&args);
+    Ok(Value::String(value.to_lowercase()))
+}
+
+fn string_trim(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, _) = string_args(&args);
+    Ok(Value::String(value.trim().to_string()))
+}
+
+fn string_contains(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, arg) = string_args(&args);
+    let pattern = string_arg(arg, "contains")?;
+    Ok(Value::Boolean(value.contains(pattern)))
+}
+
+fn string_starts_with(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, arg) = string_args(&args);
+    let prefix = string_arg(arg, "starts_with")?;
+    Ok(Value::Boolean(value.starts_with(prefix)))
+}
+
+fn string_ends_with(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, arg) = string_args(&args);
+    let suffix = string_arg(arg, "ends_with")?;
+    Ok(Value::Boolean(value.ends_with(suffix)))
+}
+
+fn string_split(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, arg) = string_args(&args);
+    let separator = string_arg(arg, "split")?;
+    if separator.is_empty() {
+        return Err(type_error("split() expects a non-empty separator"));
+    }
+    Ok(Value::List(
+        value
+            .split(separator)
+            .map(|part| Value::String(part.to_string()))
+            .collect(),
+    ))
+}
+
+fn string_chars(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, _) = string_args(&args);
+    Ok(Value::List(
+        value
+            .chars()
+            .map(|c| Value::String(c.to_string()))
+            .collect(),
+    ))
+}
+
+fn string_repeat(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, arg) = string_args(&args);
+    match int_arg(arg, "repeat")? {
+        count if count >= 0 => match value.len().checked_mul(count as usize) {
+            Some(len) if len <= MAX_STRING_LEN => Ok(Value::String(value.repeat(count as usize))),
+            _ => Err(Value::Error(ErrorObject::new(
+                ErrorKind::ArithmeticError,
+                &format!("repeat() result exceeds {} bytes", MAX_STRING_LEN),
+            ))),
+        },
+        _ => Err(type_error("repeat() expects a non-negative count")),
+    }
+}
+
+fn string_reverse(args: Vec<Value>) -> Result<Value, Value> {
+    let (value, _) = string_args(&args);
+    Ok(Value::String(value.chars().rev().collect()))
+}
+
+fn int_abs(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::Int(value) => value
+            .checked_abs()
+            .map(Value::Int)
+            .ok_or_else(|| overflow_error("abs")),
+        _ => unreachable!(),
+    }
+}
+
+fn int_pow(args: Vec<Value>) -> Result<Value, Value> {
+    let exponent = int_arg(args.get(1), "pow")?;
+    match &args[0] {
+        Value::Int(_) if exponent < 0 => Err(type_error("pow() expects a non-negative exponent")),
+        Value::Int(value) => u32::try_from(exponent)
+            .ok()
+            .and_then(|exponent| value.checked_pow(exponent))
+            .map(Value::Int)
+            .ok_or_else(|| overflow_error("pow")),
+        _ => unreachable!(),
+    }
+}
+
+fn int_min(args: Vec<Value>) -> Result<Value, Value> {
+    let other = int_arg(args.get(1), "min")?;
+    match &args[0] {
+        Value::Int(value) => Ok(Value::Int(*value.min(&other))),
+        _ => unreachable!(),
+    }
+}
+
+fn int_max(args: Vec<Value>) -> Result<Value, Value> {
+    let other = int_arg(args.get(1), "max")?;
+    match &args[0] {
+        Value::Int(value) => Ok(Value::Int(*value.max(&other))),
+        _ => unreachable!(),
+    }
+}
+
+fn to_string(args: Vec<Value>) -> Result<Value, Value> {
+    Ok(Value::String(args[0].to_string()))
+}
+
+fn sequence_len(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::List(values) | Value::Tuple(values) => Ok(Value::Int(values.len() as i64)),
+        _ => unreachable!(),
+    }
+}
+
+fn list_first(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::List(values) => Ok(values.first().cloned().unwrap_or(Value::None)),
+        _ => unreachable!(),
+    }
+}
+
+fn list_last(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::List(values) => Ok(values.last().cloned().unwrap_or(Value::None)),
+        _ => unreachable!(),
+    }
+}
+
+fn list_push(mut args: Vec<Value>) -> Result<Value, Value> {
+    let value = args.remove(1);
+    match args.remove(0) {
+        Value::List(mut values) => {
+            values.push(value);
+            Ok(Value::List(values))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn list_reverse(mut args: Vec<Value>) -> Result<Value, Value> {
+    match args.remove(0) {
+        Value::List(mut values) => {
+            values.reverse();
+            Ok(Value::List(values))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn list_join(args: Vec<Value>) -> Result<Value, Value> {
+    let separator = string_arg(args.get(1), "join")?;
+    match &args[0] {
+        Value::List(values) => Ok(Value::String(
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(separator),
+        )),
+        _ => unreachable!(),
+    }
+}
+
+fn tuple_to_list(mut args: Vec<Value>) -> Result<Value, Value> {
+    match args.remove(0) {
+        Value::Tuple(values) => Ok(Value::List(values)),
+        _ => unreachable!(),
+    }
+}
+
+fn range_len(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::Range(range) => match range.len() {
+            Some(len) => Ok(Value::Int(len)),
+            None => Err(Value::Error(ErrorObject::new(
+                ErrorKind::IndexError,
+                &format!("Range {} has too many elements to count", range),
+            ))),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn range_contains(args: Vec<Value>) -> Result<Value, Value> {
+    let value = int_arg(args.get(1), "contains")?;
+    match &args[0] {
+        Value::Range(range) => Ok(Value::Boolean(range.contains(value))),
+        _ => unreachable!(),
+    }
+}
+
+fn range_to_list(args: Vec<Value>) -> Result<Value, Value> {
+    match &args[0] {
+        Value::Range(range) => Ok(Value::List(
+            (0..).map_while(|n| range.nth(n)).map(Value::Int).collect(),
+        )),
+        _ => unreachable!(),
+    }
+}
+
+pub fn decorate(
+    name: &str,
+    func: Rc<FunctionObject>,
+    args: Vec<Value>,
+) -> Result<FunctionObject, String> {
+    let mut cache = None;
+    let codes = match name {
+        "memo" => {
+            cache = Some(RefCell::new(HashMap::new()));
+            vec![
+                Opcode::Memo(3),
+                Opcode::Forward,
+                Opcode::Memoize,
+                Opcode::Return,
+            ]
+        }
+        "trace" => vec![
+            Opcode::Trace(false),
+            Opcode::Forward,
+            Opcode::Trace(true),
+            Opcode::Return,
+        ],
+        "deprecated" => {
+            let message = match args.first() {
+                None => format!("Warning: {}() is deprecated", func.name),
+                Some(Value::String(message)) => {
+                    format!("Warning: {}() is deprecated: {}", func.name, message)
+                }
+                Some(value) => {
+                    return Err(format!(
+                        "@deprecated expects a string message, found {}",
+                        value.type_name()
+                    ))
+                }
+            };
+            vec![Opcode::Warn(message), Opcode::Forward, Opcode::Return]
+        }
+        _ => return Err(format!("Unknown decorator '@{}'", name)),
+    };
+    let name = func.name.clone();
+    let params = func.params.clone();
+    let upvalues = vec![
+        Rc::new(RefCell::new(Upvalue::Closed(Some(Value::Function(func))))),
+        Rc::new(RefCell::new(Upvalue::Closed(None))),
+    ];
+    let mut wrapper = FunctionObject::new(name, params, codes.into(), upvalues);
+    wrapper.cache = cache;
+    Ok(wrapper)
+}
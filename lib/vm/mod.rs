@@ -1,541 +1,1702 @@
-use crate::vm::opcode::{FunctionObject, Opcode, Scope, Value};
-use std::{cell::RefCell, rc::Rc};
+use crate::vm::opcode::{
+    Capture, ErrorKind, ErrorObject, FunctionObject, Generator, GeneratorState, Key, Local, Opcode,
+    Parameter, Range, Scope, Siblings, Upvalue, Value,
+};
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
 
+mod builtin;
 pub mod opcode;
 
-
-
 pub struct VM {
-    code: Vec<Opcode>,
+    code: Rc<[Opcode]>,
     stack: Vec<Value>,
     scope: Rc<RefCell<Scope>>,
+    globals: Rc<RefCell<Scope>>,
+    closure: Option<Rc<FunctionObject>>,
     frames: Vec<CallFrame>,
+    handlers: Vec<Handler>,
+
+    ip: usize,
+
+    main: bool,
+    blocked: bool,
+    sending: Option<usize>,
+    tasks: VecDeque<Task>,
+    contracts: bool,
+}
 
+const TIME_SLICE: usize = 100;
+
+pub struct Task {
+    code: Rc<[Opcode]>,
+    stack: Vec<Value>,
+    scope: Rc<RefCell<Scope>>,
+    closure: Option<Rc<FunctionObject>>,
+    frames: Vec<CallFrame>,
+    handlers: Vec<Handler>,
     ip: usize,
+    main: bool,
+    sending: Option<usize>,
 }
 
 pub struct CallFrame {
-    code: Vec<Opcode>,
+    code: Rc<[Opcode]>,
     scope: Rc<RefCell<Scope>>,
+    locals: Rc<RefCell<Scope>>,
+    closure: Option<Rc<FunctionObject>>,
     top: usize,
     ip: usize,
+    generator: Option<Rc<RefCell<Generator>>>,
+    deferred: Option<Exit>,
 }
 
 impl CallFrame {
-    pub fn new(code: Vec<Opcode>, scope: Rc<RefCell<Scope>>,top: usize, ip: usize) -> Self {
-        Self { code, scope, top, ip }
+    pub fn new(
+        code: Rc<[Opcode]>,
+        scope: Rc<RefCell<Scope>>,
+        locals: Rc<RefCell<Scope>>,
+        closure: Option<Rc<FunctionObject>>,
+        top: usize,
+        ip: usize,
+    ) -> Self {
+        Self {
+            code,
+            scope,
+            locals,
+            closure,
+            top,
+            ip,
+            generator: None,
+            deferred: None,
+        }
     }
 }
 
+enum Exit {
+    Resume,
+    Throw(Value),
+}
 
+#[derive(Debug)]
+pub struct Handler {
+    frames: usize,
+    scope: Rc<RefCell<Scope>>,
+    top: usize,
+    ip: usize,
+}
 
 impl VM {
     pub fn new(code: Vec<Opcode>) -> Self {
         let scope = Rc::new(RefCell::new(Scope::new()));
         let print_func = FunctionObject::new(
-            vec!["value".to_string()],
-            vec![
-                Opcode::Load("value".to_string()),
+            "print".to_string(),
+            vec![Parameter::new("value".to_string())],
+            Rc::new([
+                Opcode::LoadLocal(Local::new(0, 0, "value")),
                 Opcode::Print,
                 Opcode::Return,
-            ],
-            Rc::new(RefCell::new(Scope::new())),
+            ]),
+            vec![],
+        );
+        scope.borrow_mut().variables.insert(
+            "print".to_string(),
+            Some(Value::Function(Rc::new(print_func))),
         );
-        scope
-            .borrow_mut()
-            .variables
-            .insert("print".to_string(), Some(Value::Function(print_func)));
+        builtin::define_natives(&scope);
+        builtin::define_result_natives(&scope);
+        builtin::define_generator_natives(&scope);
+        builtin::define_channel_natives(&scope);
         Self {
-            code,
+            code: code.into(),
             stack: vec![],
-            scope,
+            scope: scope.clone(),
+            globals: scope,
+            closure: None,
             frames: vec![],
+            handlers: vec![],
             ip: 0,
+            main: true,
+            blocked: false,
+            sending: None,
+            tasks: VecDeque::new(),
+            contracts: true,
         }
     }
 
     pub fn run(&mut self) -> Result<(), String> {
+        let mut slice = 0;
+        let mut stalled = 0;
         loop {
-            if self.ip == self.code.len() {
-                break;
+            if self.ip == self.code.len() && self.frames.is_empty() {
+                if self.main {
+                    break;
+                }
+                let task = self.tasks.pop_front().unwrap();
+                self.load_task(task);
+                slice = 0;
+                continue;
             }
             if self.ip > self.code.len() {
                 return Err("Unknown Error: ip exceed the code length".to_string());
             }
-            self.ip = self.execute(self.code.get(self.ip).unwrap().clone());
+            // Holding the Rc keeps the opcode alive while a call or return swaps `self.code`.
+            let code = self.code.clone();
+            self.ip = match self.execute(&code[self.ip]) {
+                Ok(ip) => ip,
+                Err(error) => self.throw(error)?,
+            };
+            if self.blocked {
+                self.blocked = false;
+                stalled += 1;
+                if stalled > self.tasks.len() {
+                    return Err(match self.tasks.len() {
+                        0 => "Deadlock: the only task is blocked".to_string(),
+                        n => format!("Deadlock: all {} tasks are blocked", n + 1),
+                    });
+                }
+                self.switch_task();
+                slice = 0;
+                continue;
+            }
+            stalled = 0;
+            slice += 1;
+            if slice >= TIME_SLICE && !self.tasks.is_empty() {
+                self.switch_task();
+                slice = 0;
+            }
         }
         Ok(())
     }
 
-    fn execute(&mut self, code: Opcode) -> usize {
-        match code {
-            Opcode::Push(value) => {
-                self.stack.push(value);
+    fn switch_task(&mut self) {
+        let next = match self.tasks.pop_front() {
+            Some(task) => task,
+            None => return,
+        };
+        let current = self.load_task(next);
+        self.tasks.push_back(current);
+    }
+
+    fn load_task(&mut self, task: Task) -> Task {
+        Task {
+            code: std::mem::replace(&mut self.code, task.code),
+            stack: std::mem::replace(&mut self.stack, task.stack),
+            scope: std::mem::replace(&mut self.scope, task.scope),
+            closure: std::mem::replace(&mut self.closure, task.closure),
+            frames: std::mem::replace(&mut self.frames, task.frames),
+            handlers: std::mem::replace(&mut self.handlers, task.handlers),
+            ip: std::mem::replace(&mut self.ip, task.ip),
+            main: std::mem::replace(&mut self.main, task.main),
+            sending: std::mem::replace(&mut self.sending, task.sending),
+        }
+    }
+
+    fn throw(&mut self, error: Value) -> Result<usize, String> {
+        let depth = self.handlers.last().map_or(0, |handler| handler.frames);
+        loop {
+            let stop = match self.frames.last() {
+                Some(frame) if self.frames.len() > depth => frame.locals.borrow().parent.clone(),
+                _ => self.handlers.last().map(|handler| handler.scope.clone()),
+            };
+            if let Some(action) = self.take_deferred(stop) {
+                return Ok(self.call_deferred(action, Exit::Throw(error)));
+            }
+            if self.frames.len() <= depth {
+                break;
+            }
+            let frame = self.frames.pop().unwrap();
+            if let Some(generator) = frame.generator {
+                generator.borrow_mut().state = GeneratorState::Done;
+            }
+            self.code = frame.code;
+            self.scope = frame.scope;
+            self.closure = frame.closure;
+            self.stack.truncate(frame.top);
+        }
+        let handler = match self.handlers.pop() {
+            Some(handler) => handler,
+            None => return Err(format!("Uncaught {}", error)),
+        };
+        self.stack.truncate(handler.top);
+        self.stack.push(error);
+        self.scope = handler.scope;
+        Ok(handler.ip)
+    }
+
+    fn take_deferred(&self, stop: Option<Rc<RefCell<Scope>>>) -> Option<Rc<FunctionObject>> {
+        let mut scope = self.scope.clone();
+        loop {
+            if matches!(&stop, Some(stop) if Rc::ptr_eq(stop, &scope)) {
+                return None;
+            }
+            if let Some(action) = scope.borrow_mut().deferred.pop() {
+                return Some(action);
+            }
+            let parent = scope.borrow().parent.clone();
+            scope = parent?;
+        }
+    }
+
+    fn call_deferred(&mut self, action: Rc<FunctionObject>, exit: Exit) -> usize {
+        let locals = Rc::new(RefCell::new(Scope::new()));
+        let mut callframe = CallFrame::new(
+            std::mem::replace(&mut self.code, action.codes.clone()),
+            std::mem::replace(&mut self.scope, locals.clone()),
+            locals,
+            self.closure.replace(action),
+            self.stack.len(),
+            self.ip,
+        );
+        callframe.deferred = Some(exit);
+        self.frames.push(callframe);
+        0
+    }
+
+    fn invoke(&mut self, func: Rc<FunctionObject>, bindings: Vec<Option<Value>>) -> usize {
+        let new_scope = Rc::new(RefCell::new(Scope::with_slots(bindings)));
+        if func.generator {
+            let generator = Generator::new(func, new_scope);
+            self.stack
+                .push(Value::Generator(Rc::new(RefCell::new(generator))));
+            return self.ip + 1;
+        }
+        let callframe = CallFrame::new(
+            std::mem::replace(&mut self.code, func.codes.clone()),
+            std::mem::replace(&mut self.scope, new_scope.clone()),
+            new_scope,
+            self.closure.replace(func),
+            self.stack.len(),
+            self.ip,
+        );
+        self.frames.push(callframe);
+        0
+    }
+
+    fn upvalue(&self, index: usize) -> Result<Rc<RefCell<Upvalue>>, Value> {
+        match self
+            .closure
+            .as_ref()
+            .and_then(|closure| closure.upvalues.get(index))
+        {
+            Some(upvalue) => Ok(upvalue.clone()),
+            None => Err(Self::error(
+                ErrorKind::InternalError,
+                "Upvalue outside of a closure",
+            )),
+        }
+    }
+
+    fn capture(&self, capture: &Capture) -> Result<Rc<RefCell<Upvalue>>, Value> {
+        match capture {
+            Capture::Local(local) => {
+                let scope = self.enclosing(local.depth)?;
+                let mut scope_borrow = scope.borrow_mut();
+                if let Some((_, upvalue)) = scope_borrow
+                    .open
+                    .iter()
+                    .find(|(slot, _)| *slot == local.slot)
+                {
+                    return Ok(upvalue.clone());
+                }
+                let upvalue = Rc::new(RefCell::new(Upvalue::Open(
+                    Rc::downgrade(&scope),
+                    local.slot,
+                )));
+                scope_borrow.open.push((local.slot, upvalue.clone()));
+                Ok(upvalue)
+            }
+            Capture::Upvalue(index) => self.upvalue(*index),
+            Capture::Sibling(index) => Ok(Rc::new(RefCell::new(Upvalue::Closed(Some(
+                Value::Function(self.sibling(*index)?),
+            ))))),
+        }
+    }
+
+    fn sibling(&self, index: usize) -> Result<Rc<FunctionObject>, Value> {
+        match self
+            .closure
+            .as_ref()
+            .and_then(|closure| closure.siblings.as_ref())
+        {
+            Some(siblings) => Ok(siblings.get(index)),
+            None => Err(Self::error(
+                ErrorKind::InternalError,
+                "Sibling reference outside of a function",
+            )),
+        }
+    }
+
+    fn make_closure(
+        &self,
+        name: String,
+        params: Vec<Parameter>,
+        codes: Rc<[Opcode]>,
+        captures: &[Capture],
+    ) -> Result<FunctionObject, Value> {
+        let upvalues = captures
+            .iter()
+            .map(|capture| self.capture(capture))
+            .collect::<Result<_, _>>()?;
+        Ok(FunctionObject::new(name, params, codes, upvalues))
+    }
+
+    fn enclosing(&self, depth: usize) -> Result<Rc<RefCell<Scope>>, Value> {
+        let mut scope = self.scope.clone();
+        for _ in 0..depth {
+            let parent = scope.borrow().parent.clone();
+            scope = match parent {
+                Some(parent) => parent,
+                None => {
+                    return Err(Self::error(
+                        ErrorKind::InternalError,
+                        "Local variable is deeper than the scope chain",
+                    ))
+                }
+            };
+        }
+        Ok(scope)
+    }
+
+    fn lookup(&self, local: &Local) -> Option<Value> {
+        let scope = self.enclosing(local.depth).ok()?;
+        let scope = scope.borrow();
+        scope.slots.get(local.slot).cloned().flatten()
+    }
+
+    fn decorated(&self) -> Result<(Rc<RefCell<Upvalue>>, Rc<FunctionObject>), Value> {
+        if let Some(closure) = &self.closure {
+            if let [wrapped, state] = closure.upvalues.as_slice() {
+                if let Upvalue::Closed(Some(Value::Function(func))) = &*wrapped.borrow() {
+                    return Ok((state.clone(), func.clone()));
+                }
+            }
+        }
+        Err(Self::error(
+            ErrorKind::InternalError,
+            "Decorator opcode outside of a decorated function",
+        ))
+    }
+
+    fn arguments(&self, func: &FunctionObject) -> Vec<Value> {
+        let locals = self.scope.borrow();
+        (0..func.params.len())
+            .map(|slot| match locals.slots.get(slot) {
+                Some(Some(value)) => value.clone(),
+                _ => Value::None,
+            })
+            .collect()
+    }
+
+    fn memo_key(&self, func: &FunctionObject) -> Vec<Key> {
+        self.arguments(func).iter().map(Key::from).collect()
+    }
+
+    fn error(kind: ErrorKind, message: &str) -> Value {
+        Value::Error(ErrorObject::new(kind, message))
+    }
+
+    fn arithmetic(
+        value1: Value,
+        value2: Value,
+        verb: &str,
+        op: fn(i64, i64) -> Option<i64>,
+    ) -> Result<Value, Value> {
+        let operand = |value: &Value| match value {
+            Value::Boolean(value) => Some(*value as i64),
+            Value::Int(value) => Some(*value),
+            _ => None,
+        };
+        match (operand(&value1), operand(&value2)) {
+            (Some(value1), Some(value2)) => op(value1, value2).map(Value::Int).ok_or_else(|| {
+                Self::error(
+                    ErrorKind::ArithmeticError,
+                    &format!("Integer overflow when you {} these values", verb),
+                )
+            }),
+            _ => Err(Self::error(
+                ErrorKind::TypeError,
+                &format!("You cannot {} these different value type", verb),
+            )),
+        }
+    }
+
+    fn indices(index: Value, len: usize) -> Result<Vec<usize>, Value> {
+        match index {
+            Value::Int(index) if index >= 0 && (index as usize) < len => Ok(vec![index as usize]),
+            Value::Int(index) => Err(Self::error(
+                ErrorKind::IndexError,
+                &format!("Index {} is out of bounds for length {}", index, len),
+            )),
+            Value::Range(range) => range
+                .slice(len)
+                .map_err(|message| Self::error(ErrorKind::IndexError, &message)),
+            index => Err(Self::error(
+                ErrorKind::TypeError,
+                &format!("Cannot index with {}", index),
+            )),
+        }
+    }
+
+    fn pop(&mut self) -> Result<Value, Value> {
+        match self.stack.pop() {
+            Some(value) => Ok(value),
+            None => Err(Self::error(
+                ErrorKind::InternalError,
+                "Unknown Error: stack empty",
+            )),
+        }
+    }
+
+    pub fn set_contracts(&mut self, enabled: bool) {
+        self.contracts = enabled;
+    }
+
+    pub fn builtins() -> Vec<String> {
+        let vm = VM::new(vec![]);
+        let globals = vm.globals.borrow();
+        globals.variables.keys().cloned().collect()
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        let globals = self.globals.borrow();
+        globals.variables.get(name).cloned().flatten()
+    }
+
+    fn bind_args(
+        func: &FunctionObject,
+        positional: Vec<Value>,
+        named: Vec<(String, Value)>,
+    ) -> Result<Vec<Option<Value>>, String> {
+        if named.is_empty()
+            && positional.len() == func.params.len()
+            && func.params.iter().all(|param| !param.variadic)
+        {
+            return Ok(positional.into_iter().map(Some).collect());
+        }
+        let fixed: Vec<&Parameter> = func.params.iter().filter(|param| !param.variadic).collect();
+        let rest = func.params.iter().find(|param| param.variadic);
+        if rest.is_none() && positional.len() > fixed.len() {
+            return Err(format!(
+                "{} takes at most {} positional arguments but {} were given",
+                func.signature(),
+                fixed.len(),
+                positional.len()
+            ));
+        }
+        let mut values: Vec<Option<Value>> = vec![None; fixed.len()];
+        let mut extra = vec![];
+        for (i, arg) in positional.into_iter().enumerate() {
+            if i < fixed.len() {
+                values[i] = Some(arg);
+            } else {
+                extra.push(arg);
+            }
+        }
+        for (name, arg) in named {
+            let index = match fixed.iter().position(|param| param.name == name) {
+                Some(index) => index,
+                None => {
+                    return Err(format!(
+                        "{} got an unexpected named argument '{}'",
+                        func.signature(),
+                        name
+                    ))
+                }
+            };
+            if values[index].is_some() {
+                return Err(format!(
+                    "{} got multiple values for argument '{}'",
+                    func.signature(),
+                    name
+                ));
+            }
+            values[index] = Some(arg);
+        }
+        for (param, value) in fixed.iter().zip(values.iter()) {
+            if value.is_none() && !param.has_default {
+                return Err(format!(
+                    "{} missing required argument '{}'",
+                    func.signature(),
+                    param.name
+                ));
+            }
+        }
+        let mut values = values.into_iter();
+        let mut extra = Some(Value::List(extra));
+        Ok(func
+            .params
+            .iter()
+            .map(|param| match param.variadic {
+                true => extra.take(),
+                false => values.next().flatten(),
+            })
+            .collect())
+    }
+
+    fn execute(&mut self, code: &Opcode) -> Result<usize, Value> {
+        let next = match *code {
+            Opcode::Push(ref value) => {
+                self.stack.push(value.clone());
                 self.ip + 1
             }
             Opcode::Pop => {
                 self.stack.pop();
                 self.ip + 1
             }
-            Opcode::Load(name) => {
-                let mut scope = self.scope.clone();
-                loop {
-                    let scope_borrow = scope.borrow();
-                    if scope_borrow.variables.contains_key(&name) {
-                        let value = scope_borrow.variables.get(&name).unwrap();
-                        if value.is_none() {
-                            panic!("Can not use variable that is not given value: {}", name);
-                        }
-                        self.stack.push(value.as_ref().unwrap().clone());
-                        break;
+            Opcode::Dup => {
+                let value = self.stack.last().unwrap().clone();
+                self.stack.push(value);
+                self.ip + 1
+            }
+            Opcode::Unpack(arity) => match self.pop()? {
+                Value::Tuple(values) if values.len() == arity => {
+                    self.stack.extend(values.into_iter().rev());
+                    self.ip + 1
+                }
+                Value::Tuple(values) => {
+                    return Err(Self::error(
+                        ErrorKind::TypeError,
+                        &format!(
+                            "Cannot destructure a tuple of {} elements into {} names",
+                            values.len(),
+                            arity
+                        ),
+                    ))
+                }
+                value => {
+                    return Err(Self::error(
+                        ErrorKind::TypeError,
+                        &format!(
+                            "Cannot destructure {} into {} names, expected a tuple",
+                            value, arity
+                        ),
+                    ))
+                }
+            },
+            Opcode::BuildTuple(len) => {
+                let values = self.stack.split_off(self.stack.len() - len);
+                self.stack.push(Value::Tuple(values));
+                self.ip + 1
+            }
+            Opcode::BuildRange(inclusive, has_step) => {
+                let step = match has_step {
+                    true => self.pop()?,
+                    false => Value::Int(1),
+                };
+                let end = self.pop()?;
+                let start = self.pop()?;
+                let range = match (start, end, step) {
+                    (_, _, Value::Int(0)) => {
+                        return Err(Self::error(
+                            ErrorKind::ArgumentError,
+                            "Range step cannot be zero",
+                        ))
                     }
-                    if scope_borrow.upvalues.is_none() {
-                        panic!("No such variable: {}", name);
+                    (Value::Int(start), Value::Int(end), Value::Int(step)) => Range {
+                        start,
+                        end,
+                        step,
+                        inclusive,
+                    },
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "Range bounds and step should be int",
+                        ))
                     }
-                    let parent = scope_borrow.upvalues.clone();
-                    drop(scope_borrow);
-                    scope = parent.unwrap();
-                }
+                };
+                self.stack.push(Value::Range(range));
                 self.ip + 1
             }
-            Opcode::Store(name) => {
-                let mut scope = self.scope.clone();
-                loop {
-                    let mut scope_borrow = scope.borrow_mut();
-                    if scope_borrow.variables.contains_key(&name) {
-                        let value = self.stack.last().unwrap().clone();
-                        scope_borrow.variables.insert(name, Some(value));
-                        break;
+            Opcode::Index => {
+                let index = self.pop()?;
+                let target = self.pop()?;
+                let value = match (target, index) {
+                    (Value::String(value), index) => {
+                        let chars: Vec<char> = value.chars().collect();
+                        let indices = Self::indices(index, chars.len())?;
+                        Value::String(indices.into_iter().map(|i| chars[i]).collect())
                     }
-                    if scope_borrow.upvalues.is_none() {
-                        panic!("No such variable: {}", name);
+                    (Value::List(values), Value::Range(range)) => {
+                        let indices = Self::indices(Value::Range(range), values.len())?;
+                        Value::List(indices.into_iter().map(|i| values[i].clone()).collect())
                     }
-                    let parent = scope_borrow.upvalues.clone();
-                    drop(scope_borrow);
-                    scope = parent.unwrap();
-                }
+                    (Value::Tuple(values), Value::Range(range)) => {
+                        let indices = Self::indices(Value::Range(range), values.len())?;
+                        Value::Tuple(indices.into_iter().map(|i| values[i].clone()).collect())
+                    }
+                    (Value::List(mut values) | Value::Tuple(mut values), index) => {
+                        let indices = Self::indices(index, values.len())?;
+                        values.swap_remove(indices[0])
+                    }
+                    (target, _) => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            &format!("Cannot index into {}", target),
+                        ))
+                    }
+                };
+                self.stack.push(value);
                 self.ip + 1
             }
-            Opcode::Register(name) => {
-                self.scope.borrow_mut().variables.insert(name, None);
+            Opcode::Roll(depth) => {
+                let value = self.stack.remove(self.stack.len() - 1 - depth);
+                self.stack.push(value);
                 self.ip + 1
             }
-            Opcode::StoreFunction(name, params, codes) => {
-                let func = FunctionObject::new(params, codes, self.scope.clone());
-                self.scope
+            Opcode::Load(ref name) => {
+                let value = match self.globals.borrow().variables.get(name) {
+                    Some(Some(value)) => value.clone(),
+                    Some(None) => {
+                        return Err(Self::error(
+                            ErrorKind::NameError,
+                            &format!("Can not use variable that is not given value: {}", name),
+                        ))
+                    }
+                    None => {
+                        return Err(Self::error(
+                            ErrorKind::NameError,
+                            &format!("No such variable: {}", name),
+                        ))
+                    }
+                };
+                self.stack.push(value);
+                self.ip + 1
+            }
+            Opcode::Store(ref name) => {
+                let value = self.stack.last().unwrap().clone();
+                match self.globals.borrow_mut().variables.get_mut(name) {
+                    Some(variable) => *variable = Some(value),
+                    None => {
+                        return Err(Self::error(
+                            ErrorKind::NameError,
+                            &format!("No such variable: {}", name),
+                        ))
+                    }
+                }
+                self.ip + 1
+            }
+            Opcode::Register(ref name) => {
+                self.globals
                     .borrow_mut()
                     .variables
-                    .insert(name, Some(Value::Function(func)));
+                    .insert(name.clone(), None);
                 self.ip + 1
             }
-            Opcode::Call(num_args) => {
-                let func = match self.stack.pop() {
-                    Some(val) => match val {
-                        Value::Function(func) => func,
-                        _ => panic!("Can only call a function variable"),
-                    },
-                    None => unreachable!(),
+            Opcode::LoadLocal(ref local) => {
+                let value = match local.depth {
+                    0 => self.scope.borrow().slots.get(local.slot).cloned().flatten(),
+                    _ => self.lookup(local),
                 };
-                if num_args != func.params.len() {
-                    panic!("The number of args is not true")
-                }
-                let new_scope = Rc::new(RefCell::new(Scope::new_child(func.up_scope.clone())));
-                for param in func.params.iter().rev() {
-                    let arg = match self.stack.pop() {
-                        Some(val) => val,
-                        None => unreachable!(),
-                    };
-                    new_scope
-                        .borrow_mut()
-                        .variables
-                        .insert(param.clone(), Some(arg));
-                }
-                let mut old_code = func.codes;
-                std::mem::swap(&mut self.code, &mut old_code);
-                let callframe = CallFrame::new(old_code, self.scope.clone(), self.stack.len(), self.ip);
-                self.scope = new_scope;
-                self.frames.push(callframe);
-
-                0
+                match value {
+                    Some(value) => self.stack.push(value),
+                    None => {
+                        return Err(Self::error(
+                            ErrorKind::NameError,
+                            &format!(
+                                "Can not use variable that is not given value: {}",
+                                local.name
+                            ),
+                        ))
+                    }
+                }
+                self.ip + 1
+            }
+            Opcode::StoreLocal(ref local) => {
+                let value = self.stack.last().unwrap().clone();
+                let scope = self.enclosing(local.depth)?;
+                scope.borrow_mut().store(local.slot, Some(value));
+                self.ip + 1
+            }
+            Opcode::RegisterLocal(slot) => {
+                self.scope.borrow_mut().store(slot, None);
+                self.ip + 1
+            }
+            Opcode::LoadUpvalue(index, ref name) => match self.upvalue(index)?.borrow().get() {
+                Some(value) => {
+                    self.stack.push(value);
+                    self.ip + 1
+                }
+                None => {
+                    return Err(Self::error(
+                        ErrorKind::NameError,
+                        &format!("Can not use variable that is not given value: {}", name),
+                    ))
+                }
+            },
+            Opcode::StoreUpvalue(index) => {
+                let value = self.stack.last().unwrap().clone();
+                self.upvalue(index)?.borrow_mut().set(value);
+                self.ip + 1
+            }
+            Opcode::LoadSibling(index) => {
+                let func = self.sibling(index)?;
+                self.stack.push(Value::Function(func));
+                self.ip + 1
+            }
+            Opcode::MakeFunction(ref name, ref params, ref codes, generator, ref captures) => {
+                let mut func =
+                    self.make_closure(name.clone(), params.clone(), codes.clone(), captures)?;
+                func.generator = generator;
+                self.stack.push(Value::Function(Rc::new(func)));
+                self.ip + 1
+            }
+            Opcode::MakeSiblings(count) => {
+                let mut functions = vec![];
+                for _ in 0..count {
+                    match self.pop()? {
+                        Value::Function(func) => functions
+                            .push(Rc::try_unwrap(func).unwrap_or_else(|func| (*func).clone())),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::InternalError,
+                                "Siblings should be functions",
+                            ))
+                        }
+                    }
+                }
+                functions.reverse();
+                let siblings = Rc::new(Siblings::new(functions));
+                for index in 0..count {
+                    self.stack.push(Value::Function(siblings.get(index)));
+                }
+                self.ip + 1
+            }
+            Opcode::BindSibling(index) => {
+                let decorated = self.pop()?;
+                if let (Value::Function(func), Value::Function(decorated)) =
+                    (self.pop()?, decorated)
+                {
+                    if let Some(siblings) = &func.siblings {
+                        siblings.instances.borrow_mut()[index] = Rc::downgrade(&decorated);
+                    }
+                }
+                self.ip + 1
+            }
+            Opcode::Call(num_args, ref names) => {
+                let func = match self.pop()? {
+                    Value::Function(func) => func,
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "Can only call a function variable",
+                        ))
+                    }
+                };
+                let named_values = self.stack.split_off(self.stack.len() - names.len());
+                let positional = self.stack.split_off(self.stack.len() - num_args);
+                let named = names.iter().cloned().zip(named_values).collect();
+                let bindings = match Self::bind_args(&func, positional, named) {
+                    Ok(bindings) => bindings,
+                    Err(message) => return Err(Self::error(ErrorKind::ArgumentError, &message)),
+                };
+                self.invoke(func, bindings)
+            }
+            Opcode::CallMethod(ref name, num_args) => {
+                let args = self.stack.split_off(self.stack.len() - num_args);
+                let receiver = self.pop()?;
+                if let Some((arity, opcode)) = builtin::opcode_method(&receiver, name) {
+                    if num_args != arity {
+                        return Err(Self::error(
+                            ErrorKind::ArgumentError,
+                            &format!(
+                                "{}.{}() takes {} arguments but {} were given",
+                                receiver.type_name(),
+                                name,
+                                arity,
+                                num_args
+                            ),
+                        ));
+                    }
+                    self.stack.push(receiver);
+                    self.stack.extend(args);
+                    return self.execute(&opcode);
+                }
+                let method = match builtin::methods(&receiver)
+                    .iter()
+                    .find(|method| method.name == name)
+                {
+                    Some(method) => *method,
+                    None => {
+                        let names = builtin::method_names(&receiver);
+                        let available = match names.is_empty() {
+                            true => "none".to_string(),
+                            false => names.join(", "),
+                        };
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            &format!(
+                                "{} has no method '{}' (available methods: {})",
+                                receiver.type_name(),
+                                name,
+                                available
+                            ),
+                        ));
+                    }
+                };
+                if num_args + 1 != method.arity {
+                    return Err(Self::error(
+                        ErrorKind::ArgumentError,
+                        &format!(
+                            "{}.{}() takes {} arguments but {} were given",
+                            receiver.type_name(),
+                            name,
+                            method.arity - 1,
+                            num_args
+                        ),
+                    ));
+                }
+                let mut call_args = vec![receiver];
+                call_args.extend(args);
+                let result = (method.func)(call_args)?;
+                self.stack.push(result);
+                self.ip + 1
             }
             Opcode::Return => {
-                let frame = match self.frames.pop() {
-                    Some(frame) => frame,
-                    None => panic!("Return should live in a function"),
+                let stop = match self.frames.last() {
+                    Some(frame) => frame.locals.borrow().parent.clone(),
+                    None => {
+                        return Err(Self::error(
+                            ErrorKind::InternalError,
+                            "Return should live in a function",
+                        ))
+                    }
                 };
+                if let Some(action) = self.take_deferred(stop) {
+                    return Ok(self.call_deferred(action, Exit::Resume));
+                }
+                let frame = self.frames.pop().unwrap();
+                let depth = self.frames.len();
+                self.handlers.retain(|handler| handler.frames <= depth);
+                if let Some(exit) = frame.deferred {
+                    self.stack.truncate(frame.top);
+                    self.scope = frame.scope;
+                    self.closure = frame.closure;
+                    self.code = frame.code;
+                    return match exit {
+                        Exit::Resume => Ok(frame.ip),
+                        Exit::Throw(error) => Err(error),
+                    };
+                }
                 if self.stack.len() > frame.top + 1 {
-                    panic!("Unknown Error: the call stack overflow (from a function return)")
+                    let value = self.pop()?;
+                    self.stack.truncate(frame.top);
+                    self.stack.push(value);
                 }
                 if self.stack.len() < frame.top {
-                    panic!("Unknown Error: the call stack underflow (from a function return)")
+                    return Err(Self::error(
+                        ErrorKind::InternalError,
+                        "Unknown Error: the call stack underflow (from a function return)",
+                    ));
                 }
                 if self.stack.len() == frame.top {
                     self.stack.push(Value::None);
                 }
+                if let Some(generator) = &frame.generator {
+                    generator.borrow_mut().state = GeneratorState::Done;
+                    self.pop()?;
+                    self.stack.push(Value::None);
+                }
                 // println!("{:?}", self.stack);
                 // println!("{:?}", self.scope);
                 self.scope = frame.scope.clone();
+                self.closure = frame.closure;
                 let _ = std::mem::replace(&mut self.code, frame.code);
                 frame.ip + 1
             }
+            Opcode::Yield => {
+                let value = self.pop()?;
+                let frame = match self.frames.pop() {
+                    Some(frame) if frame.generator.is_some() => frame,
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::InternalError,
+                            "Yield should live in a generator",
+                        ))
+                    }
+                };
+                let depth = self.frames.len();
+                let generator = frame.generator.unwrap();
+                let mut generator_borrow = generator.borrow_mut();
+                generator_borrow.state = GeneratorState::Suspended;
+                generator_borrow.ip = self.ip + 1;
+                generator_borrow.stack = self.stack.split_off(frame.top);
+                generator_borrow.code = std::mem::replace(&mut self.code, frame.code);
+                generator_borrow.scope = std::mem::replace(&mut self.scope, frame.scope);
+                self.closure = frame.closure;
+                while matches!(self.handlers.last(), Some(handler) if handler.frames > depth) {
+                    let mut handler = self.handlers.pop().unwrap();
+                    handler.top -= frame.top;
+                    generator_borrow.handlers.insert(0, handler);
+                }
+                self.stack.push(value);
+                frame.ip + 1
+            }
+            Opcode::Resume => {
+                let generator = match self.pop()? {
+                    Value::Generator(generator) => generator,
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "next() expects a generator",
+                        ))
+                    }
+                };
+                let mut generator_borrow = generator.borrow_mut();
+                match generator_borrow.state {
+                    GeneratorState::Done => {
+                        self.stack.push(Value::None);
+                        return Ok(self.ip + 1);
+                    }
+                    GeneratorState::Running => {
+                        return Err(Self::error(
+                            ErrorKind::Error,
+                            "The generator is already running",
+                        ))
+                    }
+                    GeneratorState::Suspended => (),
+                }
+                generator_borrow.state = GeneratorState::Running;
+                let top = self.stack.len();
+                let depth = self.frames.len() + 1;
+                for mut handler in generator_borrow.handlers.drain(..) {
+                    handler.top += top;
+                    handler.frames = depth;
+                    self.handlers.push(handler);
+                }
+                self.stack.append(&mut generator_borrow.stack);
+                let code = std::mem::take(&mut generator_borrow.code);
+                let scope = generator_borrow.scope.clone();
+                let closure = generator_borrow.closure.clone();
+                let ip = generator_borrow.ip;
+                drop(generator_borrow);
+                let mut callframe = CallFrame::new(
+                    std::mem::replace(&mut self.code, code),
+                    std::mem::replace(&mut self.scope, scope.clone()),
+                    scope,
+                    self.closure.replace(closure),
+                    top,
+                    self.ip,
+                );
+                callframe.generator = Some(generator);
+                self.frames.push(callframe);
+                ip
+            }
+            Opcode::Spawn(num_args, ref names) => {
+                let func = self.pop()?;
+                let mut stack = self
+                    .stack
+                    .split_off(self.stack.len() - num_args - names.len());
+                stack.push(func);
+                self.tasks.push_back(Task {
+                    code: Rc::new([Opcode::Call(num_args, names.clone()), Opcode::Pop]),
+                    stack,
+                    scope: self.scope.clone(),
+                    closure: None,
+                    frames: vec![],
+                    handlers: vec![],
+                    ip: 0,
+                    main: false,
+                    sending: None,
+                });
+                self.stack.push(Value::None);
+                self.ip + 1
+            }
+            Opcode::Send => {
+                let value = self.pop()?;
+                let channel = match self.pop()? {
+                    Value::Channel(channel) => channel,
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "send() expects a channel",
+                        ))
+                    }
+                };
+                if let Some(ticket) = self.sending {
+                    if channel.borrow().received < ticket {
+                        self.stack.push(Value::Channel(channel));
+                        self.stack.push(value);
+                        self.blocked = true;
+                        return Ok(self.ip);
+                    }
+                    self.sending = None;
+                    self.stack.push(Value::None);
+                    return Ok(self.ip + 1);
+                }
+                if channel.borrow().is_full() {
+                    self.stack.push(Value::Channel(channel));
+                    self.stack.push(value);
+                    self.blocked = true;
+                    return Ok(self.ip);
+                }
+                let mut inner = channel.borrow_mut();
+                inner.queue.push_back(value.clone());
+                inner.sent += 1;
+                if inner.capacity == 0 {
+                    // Wait for a receiver to take the value before completing the send.
+                    self.sending = Some(inner.sent);
+                    drop(inner);
+                    self.stack.push(Value::Channel(channel));
+                    self.stack.push(value);
+                    self.blocked = true;
+                    return Ok(self.ip);
+                }
+                drop(inner);
+                self.stack.push(Value::None);
+                self.ip + 1
+            }
+            Opcode::Recv => {
+                let channel = match self.pop()? {
+                    Value::Channel(channel) => channel,
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "recv() expects a channel",
+                        ))
+                    }
+                };
+                let value = channel.borrow_mut().queue.pop_front();
+                match value {
+                    Some(value) => {
+                        channel.borrow_mut().received += 1;
+                        self.stack.push(value)
+                    }
+                    None => {
+                        self.stack.push(Value::Channel(channel));
+                        self.blocked = true;
+                        return Ok(self.ip);
+                    }
+                }
+                self.ip + 1
+            }
             Opcode::BeginScope => {
                 let new_scope = Rc::new(RefCell::new(Scope::new_child(self.scope.clone())));
                 self.scope = new_scope;
                 self.ip + 1
             }
             Opcode::EndScope => {
+                let action = self.scope.borrow_mut().deferred.pop();
+                if let Some(action) = action {
+                    return Ok(self.call_deferred(action, Exit::Resume));
+                }
                 let old_scope = self.scope.clone();
-                let new_scope = old_scope.borrow().upvalues.clone();
+                let new_scope = old_scope.borrow().parent.clone();
                 let new_scope = match new_scope {
                     Some(parent) => parent,
-                    _ => panic!("Cannot end the root scope"),
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::InternalError,
+                            "Cannot end the root scope",
+                        ))
+                    }
                 };
                 self.scope = new_scope;
                 self.ip + 1
             }
             Opcode::Jump(pos) => pos,
-            Opcode::JumpIfFalse(pos) => match self.stack.pop() {
-                Some(value) => match value {
-                    Value::Boolean(val) => match val {
-                        true => self.ip + 1,
-                        false => pos,
-                    },
-                    Value::Int(val) => match val {
-                        0 => pos,
-                        _ => self.ip + 1,
-                    },
+            Opcode::JumpIfAssigned(slot, pos) => {
+                let assigned = matches!(self.scope.borrow().slots.get(slot), Some(Some(_)));
+                if assigned {
+                    pos
+                } else {
+                    self.ip + 1
+                }
+            }
+            Opcode::JumpIfNil(pos) => match self.stack.last() {
+                Some(Value::None) => pos,
+                _ => self.ip + 1,
+            },
+            Opcode::JumpIfNotNil(pos) => match self.pop()? {
+                Value::None => self.ip + 1,
+                value => {
+                    self.stack.push(value);
+                    pos
+                }
+            },
+            Opcode::IterNext(pos) => {
+                let index = match self.stack.last() {
+                    Some(Value::Int(index)) => *index,
                     _ => {
-                        panic!("Expression in if condition should be boolean or int")
+                        return Err(Self::error(
+                            ErrorKind::InternalError,
+                            "Iteration index should be an int",
+                        ))
+                    }
+                };
+                let item = match &self.stack[self.stack.len() - 2] {
+                    Value::Range(range) => range.nth(index).map(Value::Int),
+                    Value::List(values) | Value::Tuple(values) => {
+                        values.get(index as usize).cloned()
+                    }
+                    Value::String(value) => value
+                        .chars()
+                        .nth(index as usize)
+                        .map(|c| Value::String(c.to_string())),
+                    value => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            &format!("Cannot iterate over {}", value),
+                        ))
+                    }
+                };
+                match item {
+                    Some(item) => {
+                        *self.stack.last_mut().unwrap() = Value::Int(index + 1);
+                        self.stack.push(item);
+                        self.ip + 1
                     }
+                    None => {
+                        self.stack.truncate(self.stack.len() - 2);
+                        pos
+                    }
+                }
+            }
+            Opcode::JumpIfFalse(pos) => match self.pop()? {
+                Value::Boolean(val) => match val {
+                    true => self.ip + 1,
+                    false => pos,
                 },
-                _ => panic!("Unknown Error: stack empty"),
+                Value::Int(val) => match val {
+                    0 => pos,
+                    _ => self.ip + 1,
+                },
+                _ => {
+                    return Err(Self::error(
+                        ErrorKind::TypeError,
+                        "Expression in if condition should be boolean or int",
+                    ))
+                }
             },
-            Opcode::Add => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
+            Opcode::Propagate(pos) => match self.pop()? {
+                Value::Ok(value) => {
+                    self.stack.push(*value);
+                    pos
+                }
+                Value::Err(value) => {
+                    self.stack.push(Value::Err(value));
+                    self.ip + 1
+                }
+                _ => {
+                    return Err(Self::error(
+                        ErrorKind::TypeError,
+                        "The '?' operator can only be applied to ok or err values",
+                    ))
+                }
+            },
+            Opcode::Defer(ref codes, ref captures) => {
+                let action =
+                    self.make_closure("defer".to_string(), vec![], codes.clone(), captures)?;
+                self.scope.borrow_mut().deferred.push(Rc::new(action));
+                self.ip + 1
+            }
+            Opcode::Decorate(ref name, num_args) => {
+                let args = self.stack.split_off(self.stack.len() - num_args);
+                let func = match self.pop()? {
+                    Value::Function(func) => func,
+                    value => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            &format!("Can only decorate a function, found {}", value.type_name()),
+                        ))
+                    }
                 };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
+                match builtin::decorate(name, func, args) {
+                    Ok(wrapper) => self.stack.push(Value::Function(Rc::new(wrapper))),
+                    Err(message) => return Err(Self::error(ErrorKind::TypeError, &message)),
+                }
+                self.ip + 1
+            }
+            Opcode::Forward => {
+                let (_, func) = self.decorated()?;
+                let bindings = {
+                    let locals = self.scope.borrow();
+                    (0..func.params.len())
+                        .map(|slot| locals.slots.get(slot).cloned().flatten())
+                        .collect()
                 };
-                let sum = match value1 {
-                    Value::Boolean(value1) => match value2 {
-                        Value::Boolean(value2) => Value::Int((value1 as i64) + (value2 as i64)),
-                        Value::Int(value2) => Value::Int((value1 as i64) + value2),
-                        _ => panic!("You cannot add these different value type"),
-                    },
-                    Value::Int(value1) => match value2 {
-                        Value::Boolean(value2) => Value::Int(value1 + (value2 as i64)),
-                        Value::Int(value2) => Value::Int(value1 + value2),
-                        _ => panic!("You cannot add these different value type"),
-                    },
-                    Value::String(mut value1) => match value2 {
-                        Value::String(value2) => {
-                            value1.push_str(value2.as_str());
-                            Value::String(value1)
-                        }
-                        _ => panic!("You cannot add these different value type"),
-                    },
-                    _ => panic!("You cannot add these different value type"),
+                self.invoke(func, bindings)
+            }
+            Opcode::Memo(pos) => {
+                let (_, func) = self.decorated()?;
+                let key = self.memo_key(&func);
+                let hit = self
+                    .closure
+                    .as_ref()
+                    .and_then(|closure| closure.cache.as_ref())
+                    .and_then(|cache| cache.borrow().get(&key).cloned());
+                match hit {
+                    Some(value) => {
+                        self.stack.push(value);
+                        pos
+                    }
+                    None => self.ip + 1,
+                }
+            }
+            Opcode::Memoize => {
+                let (_, func) = self.decorated()?;
+                let key = self.memo_key(&func);
+                let value = match self.stack.last() {
+                    Some(value) => value.clone(),
+                    None => Value::None,
                 };
-                self.stack.push(sum);
+                if let Some(cache) = self
+                    .closure
+                    .as_ref()
+                    .and_then(|closure| closure.cache.as_ref())
+                {
+                    cache.borrow_mut().insert(key, value);
+                }
                 self.ip + 1
             }
-            Opcode::Subtract => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
+            Opcode::Trace(returned) => {
+                let (_, func) = self.decorated()?;
+                let args: Vec<String> = {
+                    let locals = self.scope.borrow();
+                    (0..func.params.len())
+                        .filter_map(|slot| match locals.slots.get(slot) {
+                            Some(Some(value)) => Some(value.to_string()),
+                            _ => None,
+                        })
+                        .collect()
                 };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
+                match returned {
+                    false => println!("[trace] {}({})", func.name, args.join(", ")),
+                    true => {
+                        let value = match self.stack.last() {
+                            Some(value) => value.to_string(),
+                            None => Value::None.to_string(),
+                        };
+                        println!("[trace] {}({}) -> {}", func.name, args.join(", "), value)
+                    }
+                }
+                self.ip + 1
+            }
+            Opcode::Warn(ref message) => {
+                let (warned, _) = self.decorated()?;
+                let mut warned = warned.borrow_mut();
+                if warned.get().is_none() {
+                    eprintln!("{}", message);
+                    warned.set(Value::Boolean(true));
+                }
+                self.ip + 1
+            }
+            Opcode::SkipContracts(pos) => match self.contracts {
+                true => self.ip + 1,
+                false => pos,
+            },
+            Opcode::Contract(ref message, ref locals) => {
+                let holds = match self.pop()? {
+                    Value::Boolean(value) => value,
+                    Value::Int(value) => value != 0,
+                    value => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            &format!(
+                                "Contract clause should be bool or int, found {}",
+                                value.type_name()
+                            ),
+                        ))
+                    }
                 };
-                let diff = match value1 {
-                    Value::Boolean(value1) => match value2 {
-                        Value::Boolean(value2) => Value::Int((value1 as i64) - (value2 as i64)),
-                        Value::Int(value2) => Value::Int((value1 as i64) - value2),
-                        _ => panic!("You cannot substract these different value type"),
-                    },
-                    Value::Int(value1) => match value2 {
-                        Value::Boolean(value2) => Value::Int(value1 - (value2 as i64)),
-                        Value::Int(value2) => Value::Int(value1 - value2),
-                        _ => panic!("You cannot substract these different value type"),
-                    },
-                    _ => panic!("You cannot substract these different value type"),
+                if !holds {
+                    let values: Vec<String> = locals
+                        .iter()
+                        .map(|local| match self.lookup(local) {
+                            Some(value) => format!("{} = {}", local.name, value),
+                            None => format!("{} = nil", local.name),
+                        })
+                        .collect();
+                    return Err(Self::error(
+                        ErrorKind::ContractError,
+                        &format!("{} ({})", message, values.join(", ")),
+                    ));
+                }
+                self.ip + 1
+            }
+            Opcode::PushHandler(pos) => {
+                self.handlers.push(Handler {
+                    frames: self.frames.len(),
+                    scope: self.scope.clone(),
+                    top: self.stack.len(),
+                    ip: pos,
+                });
+                self.ip + 1
+            }
+            Opcode::PopHandler => {
+                self.handlers.pop();
+                self.ip + 1
+            }
+            Opcode::Throw => return Err(self.pop()?),
+            Opcode::Add => {
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
+                let sum = match (value1, value2) {
+                    (Value::String(mut value1), Value::String(value2)) => {
+                        value1.push_str(value2.as_str());
+                        Value::String(value1)
+                    }
+                    (value1, value2) => Self::arithmetic(value1, value2, "add", i64::checked_add)?,
                 };
+                self.stack.push(sum);
+                self.ip + 1
+            }
+            Opcode::Subtract => {
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
+                let diff = Self::arithmetic(value1, value2, "substract", i64::checked_sub)?;
                 self.stack.push(diff);
                 self.ip + 1
             }
             Opcode::Multiply => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let mul = match value1 {
-                    Value::Boolean(value1) => match value2 {
-                        Value::Boolean(value2) => Value::Int((value1 as i64) * (value2 as i64)),
-                        Value::Int(value2) => Value::Int((value1 as i64) * value2),
-                        _ => panic!("You cannot multiply these different value type"),
-                    },
-                    Value::Int(value1) => match value2 {
-                        Value::Boolean(value2) => Value::Int(value1 * (value2 as i64)),
-                        Value::Int(value2) => Value::Int(value1 * value2),
-                        _ => panic!("You cannot multiply these different value type"),
-                    },
-                    _ => panic!("You cannot multiply these different value type"),
-                };
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
+                let mul = Self::arithmetic(value1, value2, "multiply", i64::checked_mul)?;
                 self.stack.push(mul);
                 self.ip + 1
             }
             Opcode::Divide => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let div = match value1 {
-                    Value::Boolean(value1) => match value2 {
-                        Value::Boolean(value2) => Value::Int((value1 as i64) / (value2 as i64)),
-                        Value::Int(value2) => Value::Int((value1 as i64) / value2),
-                        _ => panic!("You cannot divide these different value type"),
-                    },
-                    Value::Int(value1) => match value2 {
-                        Value::Boolean(value2) => Value::Int(value1 / (value2 as i64)),
-                        Value::Int(value2) => Value::Int(value1 / value2),
-                        _ => panic!("You cannot divide these different value type"),
-                    },
-                    _ => panic!("You cannot divide these different value type"),
-                };
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
+                if matches!(value2, Value::Int(0) | Value::Boolean(false)) {
+                    return Err(Self::error(
+                        ErrorKind::ZeroDivisionError,
+                        "You cannot divide by zero",
+                    ));
+                }
+                let div = Self::arithmetic(value1, value2, "divide", i64::checked_div)?;
                 self.stack.push(div);
                 self.ip + 1
             }
             Opcode::Less => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
                 let result = match value1 {
                     Value::Boolean(value1) => match value2 {
                         Value::Boolean(value2) => Value::Boolean((value1 as i64) < (value2 as i64)),
                         Value::Int(value2) => Value::Boolean((value1 as i64) < value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::Int(value1) => match value2 {
                         Value::Boolean(value2) => Value::Boolean(value1 < (value2 as i64)),
                         Value::Int(value2) => Value::Boolean(value1 < value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::String(value1) => match value2 {
                         Value::String(value2) => Value::Boolean(value1.lt(&value2)),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
-                    _ => panic!("You cannot compare these different value type"),
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "You cannot compare these different value type",
+                        ))
+                    }
                 };
                 self.stack.push(result);
                 self.ip + 1
             }
             Opcode::Greater => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
                 let result = match value1 {
                     Value::Boolean(value1) => match value2 {
                         Value::Boolean(value2) => Value::Boolean((value1 as i64) > (value2 as i64)),
                         Value::Int(value2) => Value::Boolean((value1 as i64) > value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::Int(value1) => match value2 {
                         Value::Boolean(value2) => Value::Boolean(value1 > (value2 as i64)),
                         Value::Int(value2) => Value::Boolean(value1 > value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::String(value1) => match value2 {
                         Value::String(value2) => Value::Boolean(value1.gt(&value2)),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
-                    _ => panic!("You cannot compare these different value type"),
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "You cannot compare these different value type",
+                        ))
+                    }
                 };
                 self.stack.push(result);
                 self.ip + 1
             }
             Opcode::LessEqual => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
                 let result = match value1 {
                     Value::Boolean(value1) => match value2 {
                         Value::Boolean(value2) => {
                             Value::Boolean((value1 as i64) <= (value2 as i64))
                         }
                         Value::Int(value2) => Value::Boolean((value1 as i64) <= value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::Int(value1) => match value2 {
                         Value::Boolean(value2) => Value::Boolean(value1 <= (value2 as i64)),
                         Value::Int(value2) => Value::Boolean(value1 <= value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::String(value1) => match value2 {
                         Value::String(value2) => Value::Boolean(value1.le(&value2)),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
-                    _ => panic!("You cannot compare these different value type"),
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "You cannot compare these different value type",
+                        ))
+                    }
                 };
                 self.stack.push(result);
                 self.ip + 1
             }
             Opcode::GreaterEqual => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
                 let result = match value1 {
                     Value::Boolean(value1) => match value2 {
                         Value::Boolean(value2) => {
                             Value::Boolean((value1 as i64) >= (value2 as i64))
                         }
                         Value::Int(value2) => Value::Boolean((value1 as i64) >= value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::Int(value1) => match value2 {
                         Value::Boolean(value2) => Value::Boolean(value1 >= (value2 as i64)),
                         Value::Int(value2) => Value::Boolean(value1 >= value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::String(value1) => match value2 {
                         Value::String(value2) => Value::Boolean(value1.ge(&value2)),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
-                    _ => panic!("You cannot compare these different value type"),
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "You cannot compare these different value type",
+                        ))
+                    }
                 };
                 self.stack.push(result);
                 self.ip + 1
             }
             Opcode::Equal => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
+                if matches!(value1, Value::None) || matches!(value2, Value::None) {
+                    let both = matches!((&value1, &value2), (Value::None, Value::None));
+                    self.stack.push(Value::Boolean(both));
+                    return Ok(self.ip + 1);
+                }
                 let result = match value1 {
                     Value::Boolean(value1) => match value2 {
                         Value::Boolean(value2) => {
                             Value::Boolean((value1 as i64) == (value2 as i64))
                         }
                         Value::Int(value2) => Value::Boolean((value1 as i64) == value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::Int(value1) => match value2 {
                         Value::Boolean(value2) => Value::Boolean(value1 == (value2 as i64)),
                         Value::Int(value2) => Value::Boolean(value1 == value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::String(value1) => match value2 {
                         Value::String(value2) => Value::Boolean(value1.eq(&value2)),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
-                    _ => panic!("You cannot compare these different value type"),
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "You cannot compare these different value type",
+                        ))
+                    }
                 };
                 self.stack.push(result);
                 self.ip + 1
             }
             Opcode::NotEqual => {
-                let value2 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
-                let value1 = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
+                let value2 = self.pop()?;
+                let value1 = self.pop()?;
+                if matches!(value1, Value::None) || matches!(value2, Value::None) {
+                    let both = matches!((&value1, &value2), (Value::None, Value::None));
+                    self.stack.push(Value::Boolean(!both));
+                    return Ok(self.ip + 1);
+                }
                 let result = match value1 {
                     Value::Boolean(value1) => match value2 {
                         Value::Boolean(value2) => {
                             Value::Boolean((value1 as i64) != (value2 as i64))
                         }
                         Value::Int(value2) => Value::Boolean((value1 as i64) != value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::Int(value1) => match value2 {
                         Value::Boolean(value2) => Value::Boolean(value1 != (value2 as i64)),
                         Value::Int(value2) => Value::Boolean(value1 != value2),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
                     Value::String(value1) => match value2 {
                         Value::String(value2) => Value::Boolean(value1.ne(&value2)),
-                        _ => panic!("You cannot compare these different value type"),
+                        _ => {
+                            return Err(Self::error(
+                                ErrorKind::TypeError,
+                                "You cannot compare these different value type",
+                            ))
+                        }
                     },
-                    _ => panic!("You cannot compare these different value type"),
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "You cannot compare these different value type",
+                        ))
+                    }
                 };
                 self.stack.push(result);
                 self.ip + 1
             }
             Opcode::Negate => {
-                let value = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
+                let value = self.pop()?;
                 let result = match value {
                     Value::Boolean(value) => Value::Int(-(value as i64)),
-                    Value::Int(value) => Value::Int(-value),
-                    _ => panic!("You can only negate a number"),
+                    Value::Int(value) => match value.checked_neg() {
+                        Some(value) => Value::Int(value),
+                        None => {
+                            return Err(Self::error(
+                                ErrorKind::ArithmeticError,
+                                "Integer overflow in negation",
+                            ))
+                        }
+                    },
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "You can only negate a number",
+                        ))
+                    }
                 };
                 self.stack.push(result);
                 self.ip + 1
             }
             Opcode::Not => {
-                let value = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
+                let value = self.pop()?;
                 let result = match value {
                     Value::Boolean(value) => Value::Boolean(!value),
                     Value::Int(value) => match value {
                         0 => Value::Boolean(true),
                         _ => Value::Boolean(false),
                     },
-                    _ => panic!("You can only Not a Boolean or Number"),
+                    _ => {
+                        return Err(Self::error(
+                            ErrorKind::TypeError,
+                            "You can only Not a Boolean or Number",
+                        ))
+                    }
                 };
                 self.stack.push(result);
                 self.ip + 1
             }
             Opcode::Print => {
-                let value = match self.stack.pop() {
-                    Some(value) => value,
-                    _ => panic!("Unknown Error: stack empty"),
-                };
+                let value = self.pop()?;
                 println!("{}", value);
                 self.ip + 1
             }
-        }
+            Opcode::Native(native) => {
+                let args = self.stack.split_off(self.stack.len() - native.arity);
+                let result = (native.func)(args)?;
+                self.stack.push(result);
+                self.ip + 1
+            }
+        };
+        Ok(next)
     }
 }
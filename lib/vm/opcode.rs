@@ -1,20 +1,66 @@
-use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+use crate::vm::Handler;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    hash::{Hash, Hasher},
+    rc::{Rc, Weak},
+};
 
 #[derive(Clone, Debug)]
 pub enum Opcode {
     Push(Value),
     Pop,
+    Dup,
+    Roll(usize),
+    Unpack(usize),
+    BuildTuple(usize),
+    BuildRange(bool, bool),
+    Index,
 
     Load(String),
     Store(String),
     Register(String),
-    StoreFunction(String, Vec<String>, Vec<Opcode>),
+    LoadLocal(Local),
+    StoreLocal(Local),
+    RegisterLocal(usize),
+    LoadUpvalue(usize, Rc<str>),
+    StoreUpvalue(usize),
+    LoadSibling(usize),
+    MakeFunction(String, Vec<Parameter>, Rc<[Opcode]>, bool, Vec<Capture>),
+    MakeSiblings(usize),
+    BindSibling(usize),
 
-    Call(usize),
+    Call(usize, Vec<String>),
+    CallMethod(String, usize),
     Return,
+    Yield,
+    Resume,
+    Spawn(usize, Vec<String>),
+    Send,
+    Recv,
 
     Jump(usize),
     JumpIfFalse(usize),
+    JumpIfAssigned(usize, usize),
+    JumpIfNil(usize),
+    JumpIfNotNil(usize),
+    IterNext(usize),
+    Propagate(usize),
+
+    Defer(Rc<[Opcode]>, Vec<Capture>),
+    Decorate(String, usize),
+    Forward,
+    Memo(usize),
+    Memoize,
+    Trace(bool),
+    Warn(String),
+    SkipContracts(usize),
+    Contract(String, Vec<Local>),
+
+    PushHandler(usize),
+    PopHandler,
+    Throw,
 
     Add,
     Subtract,
@@ -34,6 +80,31 @@ pub enum Opcode {
     EndScope,
 
     Print,
+    Native(NativeFunction),
+}
+
+#[derive(Clone, Debug)]
+pub struct Local {
+    pub depth: usize,
+    pub slot: usize,
+    pub name: Rc<str>,
+}
+
+impl Local {
+    pub fn new(depth: usize, slot: usize, name: &str) -> Self {
+        Self {
+            depth,
+            slot,
+            name: Rc::from(name),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Capture {
+    Local(Local),
+    Upvalue(usize),
+    Sibling(usize),
 }
 
 #[derive(Clone, Debug)]
@@ -41,10 +112,97 @@ pub enum Value {
     Int(i64),
     String(String),
     Boolean(bool),
-    Function(FunctionObject),
+    List(Vec<Value>),
+    Tuple(Vec<Value>),
+    Range(Range),
+    Function(Rc<FunctionObject>),
+    Generator(Rc<RefCell<Generator>>),
+    Channel(Rc<RefCell<Channel>>),
+    Error(ErrorObject),
+    Ok(Box<Value>),
+    Err(Box<Value>),
+    None,
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "bool",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Range(_) => "range",
+            Value::Function(_) => "function",
+            Value::Generator(_) => "generator",
+            Value::Channel(_) => "channel",
+            Value::Error(_) => "error",
+            Value::Ok(_) | Value::Err(_) => "result",
+            Value::None => "nil",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Key {
+    Int(i64),
+    String(String),
+    Boolean(bool),
+    List(Vec<Key>),
+    Tuple(Vec<Key>),
+    Range(Range),
+    Function(Identity<FunctionObject>),
+    Generator(Identity<RefCell<Generator>>),
+    Channel(Identity<RefCell<Channel>>),
+    Error(ErrorKind, String),
+    Ok(Box<Key>),
+    Err(Box<Key>),
     None,
 }
 
+impl From<&Value> for Key {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Int(value) => Key::Int(*value),
+            Value::String(value) => Key::String(value.clone()),
+            Value::Boolean(value) => Key::Boolean(*value),
+            Value::List(values) => Key::List(values.iter().map(Key::from).collect()),
+            Value::Tuple(values) => Key::Tuple(values.iter().map(Key::from).collect()),
+            Value::Range(range) => Key::Range(*range),
+            Value::Function(func) => Key::Function(Identity(Rc::downgrade(func))),
+            Value::Generator(generator) => Key::Generator(Identity(Rc::downgrade(generator))),
+            Value::Channel(channel) => Key::Channel(Identity(Rc::downgrade(channel))),
+            Value::Error(error) => Key::Error(error.kind, error.message.clone()),
+            Value::Ok(value) => Key::Ok(Box::new(Key::from(value.as_ref()))),
+            Value::Err(value) => Key::Err(Box::new(Key::from(value.as_ref()))),
+            Value::None => Key::None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Identity<T>(Weak<T>);
+
+impl<T> Clone for Identity<T> {
+    fn clone(&self) -> Self {
+        Identity(self.0.clone())
+    }
+}
+
+impl<T> PartialEq for Identity<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Weak::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Eq for Identity<T> {}
+
+impl<T> Hash for Identity<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0.as_ptr() as *const () as usize).hash(state)
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
@@ -54,51 +212,453 @@ impl Display for Value {
             Value::Boolean(value) => {
                 write!(f, "{}", value)?;
             }
+            Value::List(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")?;
+            }
+            Value::Tuple(values) => {
+                write!(f, "(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                if values.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")?;
+            }
+            Value::Range(range) => {
+                write!(f, "{}", range)?;
+            }
             Value::Function(_) => {
                 write!(f, "FunctionObject")?;
             }
+            Value::Generator(_) => {
+                write!(f, "Generator")?;
+            }
+            Value::Channel(_) => {
+                write!(f, "Channel")?;
+            }
+            Value::Error(error) => {
+                write!(f, "{}", error)?;
+            }
+            Value::Ok(value) => {
+                write!(f, "ok({})", value)?;
+            }
+            Value::Err(value) => {
+                write!(f, "err({})", value)?;
+            }
             Value::String(value) => {
                 write!(f, "{}", value)?;
             }
             Value::None => {
-                write!(f, "None")?;
+                write!(f, "nil")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub step: i64,
+    pub inclusive: bool,
+}
+
+impl Range {
+    pub fn nth(&self, n: i64) -> Option<i64> {
+        let value = self.start.checked_add(self.step.checked_mul(n)?)?;
+        self.before_end(value).then_some(value)
+    }
+
+    fn before_end(&self, value: i64) -> bool {
+        match (self.step > 0, self.inclusive) {
+            (true, true) => value <= self.end,
+            (true, false) => value < self.end,
+            (false, true) => value >= self.end,
+            (false, false) => value > self.end,
+        }
+    }
+
+    pub fn len(&self) -> Option<i64> {
+        let (start, end, step) = (self.start as i128, self.end as i128, self.step as i128);
+        let (distance, step) = match step > 0 {
+            true => (end - start, step),
+            false => (start - end, -step),
+        };
+        let distance = if self.inclusive {
+            distance + 1
+        } else {
+            distance
+        };
+        match distance > 0 {
+            true => i64::try_from((distance + step - 1) / step).ok(),
+            false => Some(0),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nth(0).is_none()
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        let offset = value as i128 - self.start as i128;
+        let step = self.step as i128;
+        offset % step == 0 && offset / step >= 0 && self.before_end(value)
+    }
+
+    pub fn slice(&self, len: usize) -> Result<Vec<usize>, String> {
+        if self.step <= 0 {
+            return Err(format!("Slice step must be positive, found {}", self.step));
+        }
+        let end = match self.inclusive {
+            true => self.end.checked_add(1),
+            false => Some(self.end),
+        };
+        let end = match end {
+            Some(end) => end,
+            None => {
+                return Err(format!(
+                    "Range {} is out of bounds for length {}",
+                    self, len
+                ))
             }
+        };
+        if self.start > end {
+            return Err(format!("Range {} is inverted", self));
+        }
+        if self.start < 0 || end > len as i64 {
+            return Err(format!(
+                "Range {} is out of bounds for length {}",
+                self, len
+            ));
+        }
+        Ok((self.start as usize..end as usize)
+            .step_by(self.step as usize)
+            .collect())
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.inclusive {
+            true => write!(f, "{}..={}", self.start, self.end)?,
+            false => write!(f, "{}..{}", self.start, self.end)?,
+        }
+        if self.step != 1 {
+            write!(f, " step {}", self.step)?;
         }
         Ok(())
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ErrorKind {
+    Error,
+    TypeError,
+    NameError,
+    ArgumentError,
+    ZeroDivisionError,
+    ArithmeticError,
+    IndexError,
+    ContractError,
+    InternalError,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl ErrorKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Error" => Some(ErrorKind::Error),
+            "TypeError" => Some(ErrorKind::TypeError),
+            "NameError" => Some(ErrorKind::NameError),
+            "ArgumentError" => Some(ErrorKind::ArgumentError),
+            "ZeroDivisionError" => Some(ErrorKind::ZeroDivisionError),
+            "ArithmeticError" => Some(ErrorKind::ArithmeticError),
+            "IndexError" => Some(ErrorKind::IndexError),
+            "ContractError" => Some(ErrorKind::ContractError),
+            "InternalError" => Some(ErrorKind::InternalError),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
+pub struct ErrorObject {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl ErrorObject {
+    pub fn new(kind: ErrorKind, message: &str) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for ErrorObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub func: fn(Vec<Value>) -> Result<Value, Value>,
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Native({})", self.name)
+    }
+}
+
+#[derive(Debug)]
 pub struct Scope {
     pub variables: HashMap<String, Option<Value>>,
-    pub upvalues: Option<Rc<RefCell<Scope>>>,
+    pub slots: Vec<Option<Value>>,
+    pub parent: Option<Rc<RefCell<Scope>>>,
+    pub deferred: Vec<Rc<FunctionObject>>,
+    pub open: Vec<(usize, Rc<RefCell<Upvalue>>)>,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scope {
     pub fn new() -> Self {
+        Self::with_slots(vec![])
+    }
+
+    pub fn new_child(parent: Rc<RefCell<Scope>>) -> Self {
+        let mut scope = Self::new();
+        scope.parent = Some(parent);
+        scope
+    }
+
+    pub fn with_slots(slots: Vec<Option<Value>>) -> Self {
         Self {
             variables: HashMap::new(),
-            upvalues: None,
+            slots,
+            parent: None,
+            deferred: vec![],
+            open: vec![],
+        }
+    }
+
+    pub fn store(&mut self, slot: usize, value: Option<Value>) {
+        if self.slots.len() <= slot {
+            self.slots.resize(slot + 1, None);
+        }
+        self.slots[slot] = value;
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        for (slot, upvalue) in self.open.drain(..) {
+            let value = self.slots.get_mut(slot).and_then(Option::take);
+            *upvalue.borrow_mut() = Upvalue::Closed(value);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(Weak<RefCell<Scope>>, usize),
+    Closed(Option<Value>),
+}
+
+impl Upvalue {
+    pub fn get(&self) -> Option<Value> {
+        match self {
+            Upvalue::Open(scope, slot) => {
+                let scope = scope.upgrade()?;
+                let value = scope.borrow().slots.get(*slot).cloned().flatten();
+                value
+            }
+            Upvalue::Closed(value) => value.clone(),
+        }
+    }
+
+    pub fn set(&mut self, value: Value) {
+        match self {
+            Upvalue::Open(scope, slot) => {
+                if let Some(scope) = scope.upgrade() {
+                    scope.borrow_mut().store(*slot, Some(value));
+                }
+            }
+            Upvalue::Closed(closed) => *closed = Some(value),
         }
     }
+}
+
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    pub name: String,
+    pub has_default: bool,
+    pub variadic: bool,
+}
 
-    pub fn new_child(upvalues: Rc<RefCell<Scope>>) -> Self {
+impl Parameter {
+    pub fn new(name: String) -> Self {
         Self {
-            variables: HashMap::new(),
-            upvalues: Some(upvalues),
+            name,
+            has_default: false,
+            variadic: false,
+        }
+    }
+}
+
+impl Display for Parameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.variadic {
+            write!(f, "...{}", self.name)
+        } else if self.has_default {
+            write!(f, "{}?", self.name)
+        } else {
+            write!(f, "{}", self.name)
         }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct FunctionObject {
-    pub params: Vec<String>,
-    pub codes: Vec<Opcode>,
-    pub up_scope: Rc<RefCell<Scope>>
+    pub name: String,
+    pub params: Vec<Parameter>,
+    pub codes: Rc<[Opcode]>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    pub generator: bool,
+    pub cache: Option<RefCell<HashMap<Vec<Key>, Value>>>,
+    pub siblings: Option<Rc<Siblings>>,
 }
 
 impl FunctionObject {
-    pub fn new(params: Vec<String>, codes: Vec<Opcode>, scope: Rc<RefCell<Scope>>) -> Self {
-        Self { params, codes, up_scope: scope }
+    pub fn new(
+        name: String,
+        params: Vec<Parameter>,
+        codes: Rc<[Opcode]>,
+        upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            codes,
+            upvalues,
+            generator: false,
+            cache: None,
+            siblings: None,
+        }
+    }
+
+    pub fn signature(&self) -> String {
+        let params: Vec<String> = self.params.iter().map(|param| param.to_string()).collect();
+        format!("{}({})", self.name, params.join(", "))
+    }
+}
+
+#[derive(Debug)]
+pub struct Siblings {
+    pub functions: Vec<FunctionObject>,
+    pub instances: RefCell<Vec<Weak<FunctionObject>>>,
+}
+
+impl Siblings {
+    pub fn new(functions: Vec<FunctionObject>) -> Self {
+        let instances = RefCell::new(functions.iter().map(|_| Weak::new()).collect());
+        Self {
+            functions,
+            instances,
+        }
+    }
+
+    pub fn get(self: &Rc<Self>, index: usize) -> Rc<FunctionObject> {
+        if let Some(instance) = self.instances.borrow()[index].upgrade() {
+            return instance;
+        }
+        let instance = Rc::new(FunctionObject {
+            siblings: Some(self.clone()),
+            ..self.functions[index].clone()
+        });
+        self.instances.borrow_mut()[index] = Rc::downgrade(&instance);
+        instance
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GeneratorState {
+    Suspended,
+    Running,
+    Done,
+}
+
+#[derive(Debug)]
+pub struct Generator {
+    pub state: GeneratorState,
+    pub code: Rc<[Opcode]>,
+    pub closure: Rc<FunctionObject>,
+    pub scope: Rc<RefCell<Scope>>,
+    pub stack: Vec<Value>,
+    pub handlers: Vec<Handler>,
+    pub ip: usize,
+}
+
+impl Generator {
+    pub fn new(closure: Rc<FunctionObject>, scope: Rc<RefCell<Scope>>) -> Self {
+        Self {
+            state: GeneratorState::Suspended,
+            code: closure.codes.clone(),
+            closure,
+            scope,
+            stack: vec![],
+            handlers: vec![],
+            ip: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Channel {
+    pub queue: VecDeque<Value>,
+    pub capacity: usize,
+    pub sent: usize,
+    pub received: usize,
+}
+
+impl Channel {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            capacity,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    /// An unbuffered channel holds at most the one value being handed over.
+    pub fn is_full(&self) -> bool {
+        self.queue.len() >= self.capacity.max(1)
     }
 }
@@ -1,31 +1,212 @@
-use std::{env, fs};
+use std::{env, fs, process};
 
-use drop_lib::{compiler::Compiler, lexer::Lexer, parser::Parser, vm::VM};
+use drop_lib::{
+    checker::Checker,
+    compiler::Compiler,
+    expander::Expander,
+    formatter::Formatter,
+    json::{tokens_to_json, FromJson, Json, ToJson},
+    lexer::{
+        token::{Span, Token},
+        Lexer,
+    },
+    optimizer::Optimizer,
+    parser::{ast::Program, fast::ArenaParser},
+    resolver::Resolver,
+    vm::VM,
+};
 
 extern crate drop_lib;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("fmt") => {
+            format_files(&args[2..]);
+            return;
+        }
+        Some("tokens") => {
+            dump_tokens(args.split_off(2));
+            return;
+        }
+        Some("ast") => {
+            dump_ast(args.split_off(2));
+            return;
+        }
+        Some("expand") => {
+            dump_expanded(args.split_off(2));
+            return;
+        }
+        _ => (),
+    }
+    let contracts = !take_flag(&mut args, "--no-contracts");
+    let json = take_flag(&mut args, "--json");
     if args.len() != 2 {
         panic!("You should pass exactly 2 args")
     }
     let file_name = args.last().unwrap().clone();
-    let file_content = match fs::read_to_string(&file_name) {
+    let file_content = read_file(&file_name);
+
+    let parse_result = match json {
+        true => match Json::parse(&file_content).and_then(|json| Program::from_json(&json)) {
+            Ok(program) => program,
+            Err(err) => {
+                eprintln!("{}: {}", file_name, err);
+                process::exit(1);
+            }
+        },
+        false => parse_file(&file_name, &file_content),
+    };
+    let parse_result = expand_program(&file_name, parse_result);
+    let mut resolver = Resolver::new();
+    resolver.resolve(&parse_result);
+    for err in resolver.errors.iter() {
+        eprintln!("{}: {}", file_name, err);
+    }
+    if !resolver.errors.is_empty() {
+        process::exit(1);
+    }
+    let mut checker = Checker::new();
+    checker.check(&parse_result);
+    for err in checker.errors.iter() {
+        eprintln!("{}: {}", file_name, err);
+    }
+    if !checker.errors.is_empty() {
+        process::exit(1);
+    }
+    let parse_result = Optimizer::new().optimize(parse_result);
+    let mut compiler = Compiler::new();
+    compiler.compile(&parse_result);
+    for err in compiler.errors.iter() {
+        eprintln!("{}: {}", file_name, err);
+    }
+    if !compiler.errors.is_empty() {
+        process::exit(1);
+    }
+    let mut vm = VM::new(compiler.codes);
+    vm.set_contracts(contracts);
+    if let Err(err) = vm.run() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn format_files(args: &[String]) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if files.is_empty() {
+        panic!("You should pass at least one file to format")
+    }
+    let mut failed = false;
+    for file_name in files {
+        let file_content = read_file(file_name);
+        let mut formatter = Formatter::new();
+        let formatted = formatter.format(&file_content);
+        for err in formatter.errors.iter() {
+            eprintln!("{}: {}", file_name, err);
+        }
+        if !formatter.errors.is_empty() || formatted == file_content {
+            failed |= !formatter.errors.is_empty();
+            continue;
+        }
+        if check {
+            println!("{}: would be reformatted", file_name);
+            failed = true;
+        } else if let Err(e) = fs::write(file_name, formatted) {
+            panic!("{:?}", e);
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|arg| arg == flag) {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+fn read_file(file_name: &str) -> String {
+    match fs::read_to_string(file_name) {
         Ok(content) => content,
         Err(e) => {
             panic!("{:?}", e);
-        } 
-    };
+        }
+    }
+}
 
-    let lex_result = Lexer::lex_tokens(file_content.as_str());
-    let mut parser = Parser::new(&lex_result);
-    let parse_result = parser.parse();
+fn parse_file(file_name: &str, file_content: &str) -> Program {
+    let lexemes = Lexer::scan(file_content);
+    let mut parser = ArenaParser::new(&lexemes);
+    let parse_result = parser.parse().lower();
     for err in parser.errors.iter() {
-        println!("{:?}", err);
+        eprintln!("{}: {}", file_name, err);
+    }
+    if !parser.errors.is_empty() {
+        process::exit(1);
+    }
+    parse_result
+}
+
+fn expand_program(file_name: &str, program: Program) -> Program {
+    let mut expander = Expander::new();
+    let program = expander.expand(program);
+    for err in expander.errors.iter() {
+        eprintln!("{}: {}", file_name, err);
+    }
+    if !expander.errors.is_empty() {
+        process::exit(1);
+    }
+    program
+}
+
+fn dump_tokens(mut args: Vec<String>) {
+    let json = take_flag(&mut args, "--json");
+    if args.len() != 1 {
+        panic!("You should pass exactly one file to tokenize")
+    }
+    let file_content = read_file(&args[0]);
+    let lexemes = Lexer::scan(&file_content);
+    let tokens: Vec<Token> = lexemes.iter().map(|lexeme| lexeme.to_token()).collect();
+    let spans: Vec<Span> = lexemes.iter().map(|lexeme| lexeme.span).collect();
+    match json {
+        true => println!("{}", tokens_to_json(&tokens, &spans).pretty()),
+        false => {
+            for (token, span) in tokens.iter().zip(spans.iter()) {
+                println!("{}: {:?}", span, token);
+            }
+        }
+    }
+}
+
+fn dump_ast(mut args: Vec<String>) {
+    let json = take_flag(&mut args, "--json");
+    if args.len() != 1 {
+        panic!("You should pass exactly one file to parse")
+    }
+    let file_content = read_file(&args[0]);
+    let program = parse_file(&args[0], &file_content);
+    match json {
+        true => println!("{}", program.to_json().pretty()),
+        false => println!("{:#?}", program),
+    }
+}
+
+fn dump_expanded(args: Vec<String>) {
+    if args.len() != 1 {
+        panic!("You should pass exactly one file to expand")
+    }
+    let file_content = read_file(&args[0]);
+    let program = parse_file(&args[0], &file_content);
+    let program = expand_program(&args[0], program);
+    let mut formatter = Formatter::new();
+    print!("{}", formatter.format_program(&program));
+    for err in formatter.errors.iter() {
+        eprintln!("{}: {}", args[0], err);
     }
-    assert_eq!(parser.errors.len(), 0);
-    let mut compiler = Compiler::new();
-    compiler.compile(&parse_result);
-    let mut vm = VM::new(compiler.codes);
-    assert_eq!(vm.run(), Ok(()));
 }